@@ -4,7 +4,7 @@ import { redirect } from "next/navigation";
 
 import { ReactNode } from "react";
 
-import { Cookies } from "../_util/cookies";
+import { logout as endSession } from "../_util/sessionActions";
 
 type LogoutButtonProps = {
     children?: ReactNode;
@@ -14,10 +14,8 @@ type LogoutButtonProps = {
 export function LogoutButton({ children, onLogout }: LogoutButtonProps) {
 
     async function logout() {
-        // At the time of writing,
-        // CookieStore API has 90% support, according to
-        // https://caniuse.com/cookie-store-api
-        await window.cookieStore.delete(Cookies.ACCESS_TOKEN);
+        // The refresh token cookie is httpOnly, so only the frontend-server can delete it
+        await endSession();
         onLogout?.();
         redirect("/login");
     }
@@ -4,16 +4,22 @@ import { jwtDecode } from "jwt-decode";
 
 export enum Cookies {
     ACCESS_TOKEN = "access_token",
+    // httpOnly, so it's only available on the frontend-server, see `_util/session.ts`
+    REFRESH_TOKEN = "refresh_token",
 }
 
-const CookieMetadata = {
+const CookieMetadata: Record<Cookies, { kind: "jwt" | "opaque" }> = {
     [Cookies.ACCESS_TOKEN]: {
         kind: "jwt",
-    }
+    },
+    [Cookies.REFRESH_TOKEN]: {
+        kind: "opaque",
+    },
 };
 
 type CookieTypeMap = {
     [Cookies.ACCESS_TOKEN]: JwtClaims;
+    [Cookies.REFRESH_TOKEN]: string;
 }
 
 export function getWrappedTypedCookie<
@@ -65,6 +71,10 @@ export function getWrappedTypedCookie<
             }
 
             wrappedTypedCookie = jwtDecode<CookieTypeMap[typeof cookie]>(wrappedStrCookie.value);
+            break;
+        }
+        case "opaque": {
+            wrappedTypedCookie = wrappedStrCookie.value as CookieTypeMap[typeof cookie] | undefined | Promise<CookieTypeMap[typeof cookie] | undefined>;
         }
     }
 
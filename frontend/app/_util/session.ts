@@ -0,0 +1,64 @@
+import { jwtDecode } from "jwt-decode";
+
+import { JwtClaims, JwtString, RefreshTokenString } from "api-client/build/gen_shared_types";
+
+import { Cookies } from "./cookies";
+
+// Matches the lifetime of the refresh tokens issued by the backend.
+const REFRESH_TOKEN_MAX_AGE_SECS = 14 * 24 * 60 * 60;
+
+// The access token cookie expires a bit earlier than the access token itself,
+// so that it's refreshed before the backend starts rejecting it.
+const ACCESS_TOKEN_EXPIRY_MARGIN_MS = 30 * 1000;
+
+export type SessionTokens = {
+    jwt: JwtString;
+    refresh_token: RefreshTokenString;
+};
+
+type CookieOptions = {
+    expires?: Date;
+    maxAge?: number;
+    httpOnly?: boolean;
+    secure?: boolean;
+    sameSite?: "lax" | "strict" | "none";
+    path?: string;
+};
+
+// Both the `cookies()` of server actions and the cookies of `NextResponse` in the middleware.
+export type WritableCookies = {
+    set(name: string, value: string, options?: CookieOptions): unknown;
+    delete(name: string): unknown;
+};
+
+function accessTokenExpiry(jwt: JwtString): Date | undefined {
+    const claims = jwtDecode<JwtClaims>(jwt);
+    // `exp` is a UNIX timestamp in seconds
+    const expMs = Number(claims.exp) * 1000;
+    if (!Number.isSafeInteger(expMs)) {
+        console.warn("JWT exp claim is too large to be represented as a JavaScript number.");
+        return undefined;
+    }
+    return new Date(expMs - ACCESS_TOKEN_EXPIRY_MARGIN_MS);
+}
+
+// The access token is readable by the browser, which sends it in the `Authorization` header,
+// while the refresh token is only ever used by the frontend-server.
+export function setSessionCookies(cookieStore: WritableCookies, tokens: SessionTokens) {
+    cookieStore.set(Cookies.ACCESS_TOKEN, tokens.jwt, {
+        expires: accessTokenExpiry(tokens.jwt),
+        path: "/",
+    });
+    cookieStore.set(Cookies.REFRESH_TOKEN, tokens.refresh_token, {
+        maxAge: REFRESH_TOKEN_MAX_AGE_SECS,
+        httpOnly: true,
+        secure: process.env.NODE_ENV === "production",
+        sameSite: "lax",
+        path: "/",
+    });
+}
+
+export function clearSessionCookies(cookieStore: WritableCookies) {
+    cookieStore.delete(Cookies.ACCESS_TOKEN);
+    cookieStore.delete(Cookies.REFRESH_TOKEN);
+}
@@ -0,0 +1,50 @@
+"use server";
+
+import { cookies } from "next/headers";
+
+import { postLogout, postRefresh } from "api-client";
+
+import { Cookies } from "./cookies";
+import { clearSessionCookies, setSessionCookies } from "./session";
+
+// Exchanges the refresh token for new tokens, e.g. after the backend rejected the access token.
+// Returns whether the user is still logged in.
+export async function refreshAccessToken(): Promise<boolean> {
+    const cookieStore = await cookies();
+    const refreshToken = cookieStore.get(Cookies.REFRESH_TOKEN)?.value;
+    if (!refreshToken) {
+        return false;
+    }
+
+    const res = await postRefresh({ body: { refresh_token: refreshToken } });
+    switch (res.kind) {
+        case "Success": {
+            setSessionCookies(cookieStore, res);
+            return true;
+        }
+        case "InvalidToken": {
+            clearSessionCookies(cookieStore);
+            return false;
+        }
+        case "InternalServerError": return false;
+    }
+}
+
+// Ends the session on the backend as well, since the refresh token would outlive the cookies otherwise.
+export async function logout(): Promise<void> {
+    const cookieStore = await cookies();
+    const jwt = cookieStore.get(Cookies.ACCESS_TOKEN)?.value;
+    const refreshToken = cookieStore.get(Cookies.REFRESH_TOKEN)?.value;
+
+    if (jwt) {
+        const res = await postLogout({
+            body: { refresh_token: refreshToken ?? null },
+            headers: { "Authorization": `Bearer ${jwt}` },
+        });
+        if (res.kind !== "Success") {
+            console.error(`Failed to end the session on the backend: ${res.kind}`);
+        }
+    }
+
+    clearSessionCookies(cookieStore);
+}
@@ -1,15 +1,15 @@
 "use server";
 
-import { cookies } from "next/headers";
+import { cookies, headers } from "next/headers";
 import { redirect } from "next/navigation";
 
 import { z } from "zod/v4";
-import { jwtDecode } from "jwt-decode";
 
 import { postLogin, postRegister } from "api-client";
-import { JwtClaims, JwtString } from "api-client/build/gen_shared_types";
+import { JwtString } from "api-client/build/gen_shared_types";
 
 import { Cookies } from "@/app/_util/cookies";
+import { SessionTokens, setSessionCookies } from "@/app/_util/session";
 
 import { MAX_CHESS_DOT_COM_USERNAME_LENGTH, MAX_LICHESS_USERNAME_LENGTH, MAX_PASSWORD_LENGTH, MAX_USERNAME_LENGTH, MIN_CHESS_DOT_COM_USERNAME_LENGTH, MIN_LICHESS_USERNAME_LENGTH, MIN_PASSWORD_LENGTH, MIN_USERNAME_LENGTH } from "./config";
 import { LoginInfo } from "./LoginForm";
@@ -89,21 +89,18 @@ export type LoginOutcome = {
 }
 
 async function handleSuccessfulLogin(
-    jwt: JwtString,
+    tokens: SessionTokens,
 ): Promise<{ kind: "Success"; jwt: JwtString; }> {
-    const ret = { kind: "Success", jwt } as const;
-    const claims = jwtDecode<JwtClaims>(jwt);
-    let exp: number | undefined = undefined;
-    if (BigInt(Number.MAX_SAFE_INTEGER) > BigInt(claims.exp as any)) {
-        exp = Number(claims.exp);
-    } else {
-        console.warn("JWT exp claim is too large to be represented as a JavaScript number.");
-    }
-    const cookieStore = await cookies();
-    cookieStore.set(Cookies.ACCESS_TOKEN, jwt, {
-        expires: new Date().setUTCMilliseconds(exp ?? 0)
-    });
-    return ret;
+    // The refresh token stays in an httpOnly cookie, out of reach of the browser
+    setSessionCookies(await cookies(), tokens);
+    return { kind: "Success", jwt: tokens.jwt };
+}
+
+// The backend throttles failed logins per client IP address, so it must know the address of
+// the browser rather than ours. It trusts the header only if we're among its trusted proxies.
+async function forwardedForHeaders(): Promise<Record<string, string>> {
+    const forwardedFor = (await headers()).get("x-forwarded-for");
+    return forwardedFor ? { "X-Forwarded-For": forwardedFor } : {};
 }
 
 async function performServerLoginAttempt(loginInfo: LoginInfo<"server">): Promise<LoginOutcome> {
@@ -111,16 +108,20 @@ async function performServerLoginAttempt(loginInfo: LoginInfo<"server">): Promis
         body: {
             username: loginInfo.username,
             password_hash: loginInfo.password_hash,
-        }
+        },
+        headers: await forwardedForHeaders(),
     });
     switch (res.kind) {
-        case "Success": return handleSuccessfulLogin(res.jwt);
+        case "Success": return handleSuccessfulLogin(res);
         case "InvalidCredentials": {
             if (loginInfo.tab === "signUp") {
                 console.error("Just registered user, but got invalid credentials when trying to log in.");
             }
             return { kind: "Error", error: LoginError.INVALID_CREDENTIALS };
         };
+        // The login form doesn't ask for TOTP codes yet.
+        case "SecondFactorRequired": return { kind: "Error", error: LoginError.SECOND_FACTOR_REQUIRED };
+        case "TooManyRequests": return { kind: "Error", error: LoginError.TOO_MANY_ATTEMPTS };
         case "InternalServerError": return { kind: "Error", error: LoginError.INTERNAL_SERVER_ERROR };
     };
 }
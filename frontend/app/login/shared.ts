@@ -3,7 +3,8 @@ export enum LoginError {
     ALREADY_LOGGED_IN = "ALREADY_LOGGED_IN",
     ILLFORMED_CREDENTIALS = "ILLFORMED_CREDENTIALS",
     INVALID_CREDENTIALS = "INVALID_CREDENTIALS",
-    USER_NOT_FOUND = "USER_NOT_FOUND",
+    TOO_MANY_ATTEMPTS = "TOO_MANY_ATTEMPTS",
+    SECOND_FACTOR_REQUIRED = "SECOND_FACTOR_REQUIRED",
     USER_ALREADY_EXISTS = "USER_ALREADY_EXISTS",
     TOO_SHORT_PASSWORD = "TOO_SHORT_PASSWORD",
     TOO_LONG_PASSWORD = "TOO_LONG_PASSWORD",
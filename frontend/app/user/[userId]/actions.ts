@@ -1,5 +1,7 @@
 import { postUploadUserAvatar } from "api-client";
 
+import { refreshAccessToken } from "@/app/_util/sessionActions";
+
 export async function handleUploadAvatarToServer(previousState: unknown, formData: FormData):
     Promise<ReturnType<typeof postUploadUserAvatar>> {
 
@@ -19,5 +21,13 @@ export async function handleUploadAvatarToServer(previousState: unknown, formDat
         body: newFormData
     });
 
+    // The access token may have expired while the page was open. The refreshed one is read
+    // from the cookie again when retrying.
+    if (res.kind === "Unauthorized" && await refreshAccessToken()) {
+        return postUploadUserAvatar({
+            body: newFormData
+        });
+    }
+
     return res;
 }
\ No newline at end of file
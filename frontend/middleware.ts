@@ -0,0 +1,45 @@
+import { NextRequest, NextResponse } from "next/server";
+
+import { postRefresh } from "api-client";
+
+import { Cookies } from "@/app/_util/cookies";
+import { clearSessionCookies, setSessionCookies } from "@/app/_util/session";
+
+// The access token cookie expires along with the access token, so once it's gone,
+// the refresh token is exchanged for new tokens before the page is rendered.
+export async function middleware(request: NextRequest) {
+    const refreshToken = request.cookies.get(Cookies.REFRESH_TOKEN)?.value;
+    if (request.cookies.has(Cookies.ACCESS_TOKEN) || !refreshToken) {
+        return NextResponse.next();
+    }
+    // Prefetches run alongside the navigation, which refreshes the tokens itself.
+    // The parallel requests that still do get the same successor within the backend's grace period.
+    if (request.headers.has("next-router-prefetch")) {
+        return NextResponse.next();
+    }
+
+    const res = await postRefresh({ body: { refresh_token: refreshToken } });
+    switch (res.kind) {
+        case "Success": {
+            // So that the page rendered for this very request sees the new access token
+            request.cookies.set(Cookies.ACCESS_TOKEN, res.jwt);
+            const response = NextResponse.next({ request: { headers: request.headers } });
+            setSessionCookies(response.cookies, res);
+            return response;
+        }
+        case "InvalidToken": {
+            request.cookies.delete(Cookies.REFRESH_TOKEN);
+            const response = NextResponse.next({ request: { headers: request.headers } });
+            clearSessionCookies(response.cookies);
+            return response;
+        }
+        case "InternalServerError": {
+            console.error("Failed to refresh the access token.");
+            return NextResponse.next();
+        }
+    }
+}
+
+export const config = {
+    matcher: ["/((?!_next/static|_next/image|static|favicon.ico).*)"],
+};
@@ -5,6 +5,10 @@ import {
     GetUserPageDataResponses,
     PostLoginErrors,
     PostLoginResponses,
+    PostLogoutErrors,
+    PostLogoutResponses,
+    PostRefreshErrors,
+    PostRefreshResponses,
     PostRegisterErrors, PostRegisterResponses,
     PostSaltErrors, PostSaltResponses,
     PostUploadUserAvatarErrors,
@@ -14,11 +18,13 @@ import {
     postRegister as postRegisterInner,
     postSalt as postSaltInner,
     postLogin as postLoginInner,
+    postRefresh as postRefreshInner,
+    postLogout as postLogoutInner,
     getSupportedImgFormats as getSupportedImgFormatsInner,
     postUploadUserAvatar as postUploadUserAvatarInner,
     getUserPageData as getUserPageDataInner,
 } from "./gen-client/sdk.gen";
-import { GetUserPageDataResponse, JwtString, LikelyResponse, PostLoginResponse, PostRegisterResponse, PostSaltResponse, PostUploadUserAvatarResponse } from "./gen_shared_types";
+import { GetUserPageDataResponse, JwtString, LikelyResponse, PostLoginResponse, PostLogoutResponse, PostRefreshResponse, PostRegisterResponse, PostSaltResponse, PostUploadUserAvatarResponse } from "./gen_shared_types";
 
 export * as "gen_shared_types" from "./gen_shared_types";
 
@@ -37,6 +43,10 @@ export async function postRegister(
     }
 }
 
+function retryAfterSecs(response: Response): number {
+    return Number(response.headers.get("Retry-After") ?? 0);
+}
+
 export async function postSalt(
     options: HttpMethodCallOptions<typeof postSaltInner>
 ): Promise<PostSaltResponse> {
@@ -48,7 +58,7 @@ export async function postSalt(
             const saltResponseSuccess: PostSaltResponses[200] = result.data!;
             return { "kind": "Success", "salt": saltResponseSuccess.salt };
         }
-        case 404: return { "kind": "UserNotFound" };
+        case 429: return { "kind": "TooManyRequests", "retry_after_secs": retryAfterSecs(result.response) };
         case 500: return { "kind": "InternalServerError" };
     };
 }
@@ -62,9 +72,43 @@ export async function postLogin(
     switch (statusCode) {
         case 200: {
             const loginResponseSuccess: PostLoginResponses[200] = result.data!;
-            return { "kind": "Success", "jwt": loginResponseSuccess.jwt };
+            return { "kind": "Success", ...loginResponseSuccess };
+        }
+        case 202: {
+            const challenge: PostLoginResponses[202] = result.data!;
+            return { "kind": "SecondFactorRequired", ...challenge };
         }
         case 401: return { "kind": "InvalidCredentials" };
+        case 429: return { "kind": "TooManyRequests", "retry_after_secs": retryAfterSecs(result.response) };
+        case 500: return { "kind": "InternalServerError" };
+    }
+}
+
+export async function postRefresh(
+    options: HttpMethodCallOptions<typeof postRefreshInner>
+): Promise<PostRefreshResponse> {
+    modifyOptions(options);
+    const result = await postRefreshInner(options);
+    const statusCode = result.response.status as keyof PostRefreshResponses | keyof PostRefreshErrors;
+    switch (statusCode) {
+        case 200: {
+            const refreshResponseSuccess: PostRefreshResponses[200] = result.data!;
+            return { "kind": "Success", ...refreshResponseSuccess };
+        }
+        case 401: return { "kind": "InvalidToken" };
+        case 500: return { "kind": "InternalServerError" };
+    }
+}
+
+export async function postLogout(
+    options: HttpMethodCallOptions<typeof postLogoutInner>
+): Promise<PostLogoutResponse> {
+    modifyOptions(options);
+    const result = await postLogoutInner(options);
+    const statusCode = result.response.status as keyof PostLogoutResponses | keyof PostLogoutErrors;
+    switch (statusCode) {
+        case 200: return { "kind": "Success" };
+        case 401: return { "kind": "Unauthorized" };
         case 500: return { "kind": "InternalServerError" };
     }
 }
@@ -0,0 +1,4 @@
+-- Note: the server-side hashes can't be turned back into the client-side ones,
+-- so the affected users won't be able to log in after reverting this migration.
+ALTER TABLE users DROP COLUMN IF EXISTS client_salt;
+ALTER TABLE users DROP COLUMN IF EXISTS legacy_password_hash;
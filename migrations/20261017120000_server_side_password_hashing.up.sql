@@ -0,0 +1,10 @@
+-- Until now `password_hash` held the client-side Argon2id hash verbatim and was compared as-is.
+-- Such rows are flagged so that they can be rehashed on the server on the next successful login.
+ALTER TABLE users ADD COLUMN legacy_password_hash BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE users SET legacy_password_hash = TRUE;
+
+-- The salt that the client uses for pre-hashing the password (see `/api/user/salt`).
+-- For the legacy rows, it is the salt of the stored PHC string.
+ALTER TABLE users ADD COLUMN client_salt VARCHAR(64);
+UPDATE users SET client_salt = split_part(password_hash, '$', 5);
+ALTER TABLE users ALTER COLUMN client_salt SET NOT NULL;
@@ -0,0 +1,20 @@
+-- Every row is a single-use refresh token. Rotating a refresh token marks the row as rotated
+-- and inserts a new row with the same `family_id`, so all tokens descending from one login
+-- share a family that can be revoked at once when a rotated token is replayed.
+CREATE TABLE IF NOT EXISTS sessions (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    family_id UUID NOT NULL DEFAULT gen_random_uuid(),
+    -- Hex-encoded SHA-256 of the refresh token. The token itself is never stored.
+    refresh_token_hash CHAR(64) UNIQUE NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMPTZ NOT NULL,
+    rotated_at TIMESTAMPTZ,
+    -- The seed the successor token was derived from on rotation, so that presenting the token again
+    -- shortly after, e.g. from a parallel request, yields the same successor instead of a reuse.
+    successor_seed VARCHAR(64),
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_id_idx ON sessions (user_id);
+CREATE INDEX IF NOT EXISTS sessions_family_id_idx ON sessions (family_id);
@@ -0,0 +1,12 @@
+-- Access tokens revoked before their expiration, identified by their `jti` claim.
+-- Rows are useless once `expires_at` has passed and can be pruned.
+CREATE TABLE IF NOT EXISTS revoked_access_tokens (
+    jti VARCHAR(64) PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS revoked_access_tokens_expires_at_idx ON revoked_access_tokens (expires_at);
+
+-- Set by "log out everywhere": access tokens issued at or before this moment are rejected.
+ALTER TABLE users ADD COLUMN sessions_revoked_at TIMESTAMPTZ;
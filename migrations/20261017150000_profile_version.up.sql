@@ -0,0 +1,3 @@
+-- Incremented on every profile edit, so that edits based on a stale read are rejected
+-- instead of silently overwriting each other (optimistic concurrency).
+ALTER TABLE users ADD COLUMN profile_version INTEGER NOT NULL DEFAULT 0;
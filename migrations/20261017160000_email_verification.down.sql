@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS email_outbox;
+ALTER TABLE users DROP COLUMN IF EXISTS email_verification_jti;
+ALTER TABLE users DROP COLUMN IF EXISTS email_verified_at;
@@ -0,0 +1,23 @@
+-- NULL until the user proves that they own `email`; reset whenever `email` changes.
+ALTER TABLE users ADD COLUMN email_verified_at TIMESTAMPTZ;
+-- The `jti` of the only email verification token that can still be redeemed.
+-- Issuing a new token or redeeming it replaces it, which makes the tokens single-use.
+ALTER TABLE users ADD COLUMN email_verification_jti VARCHAR(64);
+
+-- Transactional outbox: mails are inserted in the same transaction as the change that
+-- triggers them and delivered by a background dispatcher, so that they are neither lost
+-- when the transaction commits nor sent when it rolls back.
+CREATE TABLE IF NOT EXISTS email_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    recipient VARCHAR(100) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Also serves as a lease: a dispatcher claiming the mail pushes it into the future.
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    sent_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS email_outbox_pending_idx ON email_outbox (next_attempt_at) WHERE sent_at IS NULL;
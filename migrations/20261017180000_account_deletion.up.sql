@@ -0,0 +1,5 @@
+-- When the account and everything it owns will be purged, NULL unless the deletion was requested.
+-- Until then, the deletion can be cancelled.
+ALTER TABLE users ADD COLUMN deletion_scheduled_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS users_deletion_scheduled_at_idx ON users (deletion_scheduled_at) WHERE deletion_scheduled_at IS NOT NULL;
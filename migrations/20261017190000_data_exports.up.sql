@@ -0,0 +1,20 @@
+CREATE TYPE data_export_status AS ENUM ('pending', 'running', 'ready', 'failed');
+
+-- Personal data exports, assembled by a background worker into a ZIP in the export bucket.
+CREATE TABLE IF NOT EXISTS data_exports (
+    id BIGSERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status data_export_status NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Also serves as a lease: a running export that started long ago is assumed to be abandoned.
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ,
+    -- The key of the archive in the export bucket, once it's ready
+    object_key VARCHAR(255),
+    -- When the archive will be deleted
+    expires_at TIMESTAMPTZ,
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS data_exports_user_id_idx ON data_exports (user_id);
+CREATE INDEX IF NOT EXISTS data_exports_pending_idx ON data_exports (created_at) WHERE status IN ('pending', 'running');
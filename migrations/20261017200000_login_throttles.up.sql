@@ -0,0 +1,16 @@
+CREATE TYPE login_throttle_scope AS ENUM ('username', 'ip');
+
+-- Failed logins per username and per client IP address, shared by every backend replica.
+CREATE TABLE IF NOT EXISTS login_throttles (
+    scope login_throttle_scope NOT NULL,
+    -- The lowercased username or the IP address
+    key VARCHAR(255) NOT NULL,
+    -- Consecutive failures, counted from scratch when the last one is old enough
+    failures INTEGER NOT NULL,
+    last_failure_at TIMESTAMPTZ NOT NULL,
+    -- Until when logins are rejected without checking the password, NULL unless locked out
+    locked_until TIMESTAMPTZ,
+    PRIMARY KEY (scope, key)
+);
+
+CREATE INDEX IF NOT EXISTS login_throttles_last_failure_at_idx ON login_throttles (last_failure_at);
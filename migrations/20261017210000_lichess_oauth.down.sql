@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS lichess_oauth_states;
+ALTER TABLE users DROP COLUMN IF EXISTS lichess_id;
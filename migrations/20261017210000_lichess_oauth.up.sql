@@ -0,0 +1,16 @@
+-- The ID of the Lichess account that the user signed in with, which, unlike the self-declared
+-- `lichess_username`, is verified through OAuth. Lichess IDs are the lowercased usernames.
+ALTER TABLE users ADD COLUMN lichess_id VARCHAR(50) UNIQUE;
+
+-- Pending "Sign in with Lichess" authorizations, consumed by the callback.
+CREATE TABLE IF NOT EXISTS lichess_oauth_states (
+    -- Hex-encoded SHA-256 of the `state` parameter
+    state_hash CHAR(64) PRIMARY KEY,
+    -- The PKCE code verifier, sent along with the authorization code
+    code_verifier VARCHAR(128) NOT NULL,
+    -- The user to link the Lichess account to, NULL to sign in or up
+    link_user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS lichess_oauth_states_expires_at_idx ON lichess_oauth_states (expires_at);
@@ -0,0 +1,8 @@
+ALTER TABLE sessions DROP COLUMN IF EXISTS second_factor;
+
+DROP TABLE IF EXISTS totp_recovery_codes;
+
+ALTER TABLE users
+    DROP COLUMN IF EXISTS totp_last_used_step,
+    DROP COLUMN IF EXISTS totp_enabled_at,
+    DROP COLUMN IF EXISTS totp_secret;
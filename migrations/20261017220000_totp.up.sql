@@ -0,0 +1,20 @@
+-- The TOTP secret is set when the user starts enrolling and `totp_enabled_at` once they confirm
+-- it with a code. `totp_last_used_step` is the time step of the last accepted code, so a code
+-- can't be replayed within its validity window.
+ALTER TABLE users
+    ADD COLUMN totp_secret BYTEA,
+    ADD COLUMN totp_enabled_at TIMESTAMPTZ,
+    ADD COLUMN totp_last_used_step BIGINT;
+
+-- Single-use codes for logging in without the authenticator app, replaced on every enrollment.
+CREATE TABLE IF NOT EXISTS totp_recovery_codes (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Hex-encoded SHA-256 of the normalized code. The code itself is never stored.
+    code_hash CHAR(64) NOT NULL,
+    used_at TIMESTAMPTZ,
+    UNIQUE (user_id, code_hash)
+);
+
+-- Whether the token family started with the second factor, which the access tokens carry over.
+ALTER TABLE sessions ADD COLUMN second_factor BOOLEAN NOT NULL DEFAULT FALSE;
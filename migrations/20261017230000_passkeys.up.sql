@@ -0,0 +1,25 @@
+-- WebAuthn credentials for logging in without the password. A user may have several, e.g. one
+-- per device or password manager.
+CREATE TABLE IF NOT EXISTS passkeys (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    credential_id BYTEA NOT NULL UNIQUE,
+    -- In the COSE_Key format, as attested by the authenticator
+    public_key BYTEA NOT NULL,
+    -- The signature counter of the last ceremony, which must increase unless it stays 0
+    sign_count BIGINT NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS passkeys_user_id_idx ON passkeys (user_id);
+
+-- Pending ceremonies. The challenges of the registrations belong to the user who asked for them,
+-- while those of the logins have no user yet.
+CREATE TABLE IF NOT EXISTS webauthn_challenges (
+    -- Hex-encoded SHA-256 of the challenge
+    challenge_hash CHAR(64) PRIMARY KEY,
+    user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,11 @@
+DROP INDEX IF EXISTS users_restriction_idx;
+DROP INDEX IF EXISTS users_created_at_idx;
+
+ALTER TABLE users DROP COLUMN IF EXISTS password_reset_required;
+
+ALTER TABLE users
+    DROP COLUMN IF EXISTS restricted_until,
+    DROP COLUMN IF EXISTS restriction_reason,
+    DROP COLUMN IF EXISTS restriction;
+
+DROP TYPE IF EXISTS account_restriction;
@@ -0,0 +1,15 @@
+CREATE TYPE account_restriction AS ENUM ('suspended', 'banned');
+
+-- An active restriction keeps the user from logging in and invalidates their access tokens.
+-- Suspensions always expire, while bans expire only if `restricted_until` is set.
+ALTER TABLE users
+    ADD COLUMN restriction account_restriction,
+    ADD COLUMN restriction_reason VARCHAR(500),
+    ADD COLUMN restricted_until TIMESTAMPTZ;
+
+-- Set by an admin to keep the user from logging in with the password until they reset it
+-- through the emailed link.
+ALTER TABLE users ADD COLUMN password_reset_required BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS users_created_at_idx ON users (created_at);
+CREATE INDEX IF NOT EXISTS users_restriction_idx ON users (id) WHERE restriction IS NOT NULL;
@@ -0,0 +1,11 @@
+DROP TRIGGER IF EXISTS audit_events_no_truncate ON audit_events;
+DROP TRIGGER IF EXISTS audit_events_no_update_or_delete ON audit_events;
+DROP FUNCTION IF EXISTS audit_events_append_only();
+
+DROP INDEX IF EXISTS audit_events_target_id_idx;
+DROP INDEX IF EXISTS audit_events_actor_id_idx;
+DROP INDEX IF EXISTS audit_events_occurred_at_idx;
+
+DROP TABLE IF EXISTS audit_events;
+
+DROP TYPE IF EXISTS audit_event_kind;
@@ -0,0 +1,45 @@
+CREATE TYPE audit_event_kind AS ENUM (
+    'registered',
+    'login_succeeded',
+    'login_failed',
+    'avatar_changed',
+    'role_changed',
+    'restricted',
+    'restriction_lifted',
+    'password_reset_forced'
+);
+
+-- Security-relevant events, never updated or deleted.
+--
+-- The user IDs aren't foreign keys, so that the events outlive the accounts they're about.
+CREATE TABLE IF NOT EXISTS audit_events (
+    id BIGSERIAL PRIMARY KEY,
+    kind audit_event_kind NOT NULL,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Who did it, NULL if nobody is logged in, e.g. for a failed login with an unknown username
+    actor_id INTEGER,
+    -- Whose account it's about
+    target_id INTEGER,
+    ip INET,
+    user_agent VARCHAR(512),
+    -- The serialized `AuditEventDetails` of shared_items_lib, including the kind
+    details JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS audit_events_occurred_at_idx ON audit_events (occurred_at);
+CREATE INDEX IF NOT EXISTS audit_events_actor_id_idx ON audit_events (actor_id, occurred_at);
+CREATE INDEX IF NOT EXISTS audit_events_target_id_idx ON audit_events (target_id, occurred_at);
+
+CREATE FUNCTION audit_events_append_only() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_no_update_or_delete
+    BEFORE UPDATE OR DELETE ON audit_events
+    FOR EACH ROW EXECUTE FUNCTION audit_events_append_only();
+
+CREATE TRIGGER audit_events_no_truncate
+    BEFORE TRUNCATE ON audit_events
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_events_append_only();
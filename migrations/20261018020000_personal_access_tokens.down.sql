@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS personal_access_tokens_user_id_idx;
+
+DROP TABLE IF EXISTS personal_access_tokens;
+
+DROP TYPE IF EXISTS personal_access_token_scope;
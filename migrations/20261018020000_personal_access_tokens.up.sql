@@ -0,0 +1,17 @@
+CREATE TYPE personal_access_token_scope AS ENUM ('avatar_write', 'games_read', 'profile_read');
+
+-- Long-lived bearer tokens for scripts, each limited to the endpoints of its scopes.
+CREATE TABLE IF NOT EXISTS personal_access_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Hex-encoded SHA-256 of the token, which is shown to the user only once
+    token_hash CHAR(64) NOT NULL UNIQUE,
+    name VARCHAR(64) NOT NULL,
+    scopes personal_access_token_scope[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ,
+    -- NULL for the tokens that are valid until revoked
+    expires_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS personal_access_tokens_user_id_idx ON personal_access_tokens (user_id);
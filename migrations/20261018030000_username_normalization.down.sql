@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS users_username_normalized_idx;
+
+ALTER TABLE users DROP COLUMN IF EXISTS username_normalized;
@@ -0,0 +1,7 @@
+-- The normalized username, unique so that the usernames differing only by case can't coexist.
+-- The backend fills it in for the existing accounts on startup, since `LOWER` doesn't lowercase
+-- every username like it does. The accounts whose normalized username an older account claimed
+-- keep NULL, which the unique index allows, and log in by their exact username.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS username_normalized VARCHAR(50);
+
+CREATE UNIQUE INDEX IF NOT EXISTS users_username_normalized_idx ON users (username_normalized);
@@ -0,0 +1,3 @@
+-- Whether the WebP renditions of the avatar are stored next to it. The avatars uploaded before
+-- the renditions existed, as well as the SVG ones, are served as they are at any size.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_has_renditions BOOLEAN NOT NULL DEFAULT FALSE;
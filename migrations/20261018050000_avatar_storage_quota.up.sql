@@ -0,0 +1,4 @@
+-- The bytes taken by the avatars of the user and their renditions in the object storage, which
+-- are limited by a quota. The avatars uploaded before it was tracked aren't counted.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_storage_bytes BIGINT NOT NULL DEFAULT 0
+    CHECK (avatar_storage_bytes >= 0);
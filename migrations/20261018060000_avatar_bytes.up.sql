@@ -0,0 +1,4 @@
+-- The bytes taken by the current avatar of the user and its renditions, which stop counting
+-- against `avatar_storage_bytes` once the avatar is replaced.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_bytes BIGINT NOT NULL DEFAULT 0
+    CHECK (avatar_bytes >= 0);
@@ -0,0 +1,303 @@
+//! Extractors that authenticate the request with the bearer access token or personal access
+//! token.
+//!
+//! Handlers that take [`AuthUser`] are documented with `security(("bearerAuth" = []))` and
+//! respond with 401 when the token is missing, invalid or revoked, or the user is suspended
+//! or banned, and with 403 for a personal access token. Handlers that take
+//! [`RequireRole`] are documented with the required role as the scope, e.g.
+//! `security(("bearerAuth" = ["admin"]))`, and additionally respond with 403, or with 401 when
+//! the role also requires logging in with the second factor and the user didn't. Handlers that
+//! take [`RequireScope`] also accept the personal access tokens with the scope, and are
+//! documented with it, e.g. `security(("bearerAuth" = []), ("personalAccessToken" = ["avatar:write"]))`.
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+use mnln_env::Env;
+use shared_items_lib::service_responses::PersonalAccessTokenScope;
+use shared_items_lib::{JwtClaims, Role};
+
+use crate::Context;
+use crate::service;
+use crate::util::{self, JwtVerificationError};
+
+/// See <https://www.rfc-editor.org/rfc/rfc6750#section-3>
+pub(crate) enum AuthRejection {
+    MissingToken,
+    InvalidToken(&'static str),
+    InsufficientRole,
+    InsufficientScope(&'static str),
+    /// See <https://www.rfc-editor.org/rfc/rfc9470>
+    SecondFactorRequired,
+    InternalServerError,
+}
+
+impl IntoResponse for AuthRejection {
+    fn into_response(self) -> Response {
+        match self {
+            AuthRejection::MissingToken => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+            )
+                .into_response(),
+            AuthRejection::InvalidToken(description) => {
+                let www_authenticate =
+                    format!(r#"Bearer error="invalid_token", error_description="{description}""#);
+                (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::WWW_AUTHENTICATE, www_authenticate)],
+                )
+                    .into_response()
+            }
+            AuthRejection::InsufficientRole => (
+                StatusCode::FORBIDDEN,
+                [(
+                    header::WWW_AUTHENTICATE,
+                    r#"Bearer error="insufficient_scope""#,
+                )],
+            )
+                .into_response(),
+            AuthRejection::InsufficientScope(description) => {
+                let www_authenticate = format!(
+                    r#"Bearer error="insufficient_scope", error_description="{description}""#
+                );
+                (
+                    StatusCode::FORBIDDEN,
+                    [(header::WWW_AUTHENTICATE, www_authenticate)],
+                )
+                    .into_response()
+            }
+            AuthRejection::SecondFactorRequired => (
+                StatusCode::UNAUTHORIZED,
+                [(
+                    header::WWW_AUTHENTICATE,
+                    r#"Bearer error="insufficient_user_authentication", error_description="Log in with the second factor""#,
+                )],
+            )
+                .into_response(),
+            AuthRejection::InternalServerError => {
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+fn jwt_error_description(err: &JwtVerificationError) -> &'static str {
+    match err {
+        JwtVerificationError::Malformed(_) => "The access token is malformed",
+        JwtVerificationError::UnknownKey(_) => "The access token was signed with an unknown key",
+        JwtVerificationError::BadSignature => "The access token signature is invalid",
+        JwtVerificationError::Expired => "The access token expired",
+        JwtVerificationError::NotYetValid => "The access token is not valid yet",
+        JwtVerificationError::WrongIssuer => "The access token was issued by someone else",
+        JwtVerificationError::WrongAudience => "The access token is meant for someone else",
+    }
+}
+
+/// The user that the bearer token of the request belongs to.
+#[derive(Clone)]
+struct Authenticated {
+    claims: JwtClaims,
+    /// The scopes of the personal access token, or `None` for an access token
+    scopes: Option<Vec<PersonalAccessTokenScope>>,
+}
+
+impl Authenticated {
+    async fn authenticate(parts: &Parts, ctx: &Context) -> Result<Self, AuthRejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|header_value| header_value.to_str().ok())
+            .and_then(|header_value| header_value.strip_prefix("Bearer "))
+            .ok_or(AuthRejection::MissingToken)?;
+
+        let (claims, scopes) = if service::personal_access_token::is_personal_access_token(token) {
+            match service::personal_access_token::authenticate(ctx, token).await {
+                Ok(Some((claims, scopes))) => (claims, Some(scopes)),
+                Ok(None) => {
+                    tracing::warn!("Rejected an unknown, expired or revoked personal access token");
+                    return Err(AuthRejection::InvalidToken(
+                        "The personal access token is unknown, expired or revoked",
+                    ));
+                }
+                Err(e) => {
+                    tracing::error!("Failed to verify a personal access token: {e}");
+                    return Err(AuthRejection::InternalServerError);
+                }
+            }
+        } else {
+            let claims = util::verify_jwt(token, ctx).map_err(|err| {
+                tracing::warn!("Failed to verify JWT: {err}");
+                AuthRejection::InvalidToken(jwt_error_description(&err))
+            })?;
+
+            if ctx.revocations.is_revoked(&claims) {
+                tracing::warn!("Rejected a revoked JWT: {claims:?}");
+                return Err(AuthRejection::InvalidToken(
+                    "The access token has been revoked",
+                ));
+            }
+            (claims, None)
+        };
+
+        if ctx.revocations.is_restricted(&claims) {
+            tracing::warn!("Rejected a token of a suspended or banned user: {claims:?}");
+            return Err(AuthRejection::InvalidToken(
+                "The account is suspended or banned",
+            ));
+        }
+
+        tracing::info!("Verified claims: {claims:?}, scopes: {scopes:?}");
+        Ok(Authenticated { claims, scopes })
+    }
+}
+
+impl FromRequestParts<Arc<Context>> for Authenticated {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        ctx: &Arc<Context>,
+    ) -> Result<Self, Self::Rejection> {
+        // Several extractors of the same handler may need the user, so the token is verified once.
+        if let Some(authenticated) = parts.extensions.get::<Authenticated>() {
+            return Ok(authenticated.clone());
+        }
+        let authenticated = Self::authenticate(parts, ctx).await?;
+        parts.extensions.insert(authenticated.clone());
+        Ok(authenticated)
+    }
+}
+
+/// The user that the verified, non-revoked access token of the request was issued to.
+#[derive(Clone)]
+pub(crate) struct AuthUser(pub(crate) JwtClaims);
+
+impl FromRequestParts<Arc<Context>> for AuthUser {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        ctx: &Arc<Context>,
+    ) -> Result<Self, Self::Rejection> {
+        let Authenticated { claims, scopes } =
+            Authenticated::from_request_parts(parts, ctx).await?;
+        if scopes.is_some() {
+            tracing::warn!("Rejected a personal access token without a suitable scope: {claims:?}");
+            return Err(AuthRejection::InsufficientScope(
+                "The endpoint doesn't accept personal access tokens",
+            ));
+        }
+        Ok(AuthUser(claims))
+    }
+}
+
+/// A scope that [`RequireScope`] can demand from a personal access token.
+pub(crate) trait RequiredScope {
+    const SCOPE: PersonalAccessTokenScope;
+}
+
+pub(crate) enum AvatarWrite {}
+
+impl RequiredScope for AvatarWrite {
+    const SCOPE: PersonalAccessTokenScope = PersonalAccessTokenScope::AvatarWrite;
+}
+
+pub(crate) enum ProfileRead {}
+
+impl RequiredScope for ProfileRead {
+    const SCOPE: PersonalAccessTokenScope = PersonalAccessTokenScope::ProfileRead;
+}
+
+/// The user of an access token, or of a personal access token with the scope `S`,
+/// e.g. `RequireScope<AvatarWrite>`.
+pub(crate) struct RequireScope<S> {
+    pub(crate) user: AuthUser,
+    _scope: PhantomData<S>,
+}
+
+impl<S: RequiredScope> FromRequestParts<Arc<Context>> for RequireScope<S> {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        ctx: &Arc<Context>,
+    ) -> Result<Self, Self::Rejection> {
+        let Authenticated { claims, scopes } =
+            Authenticated::from_request_parts(parts, ctx).await?;
+        if let Some(scopes) = scopes
+            && !scopes.contains(&S::SCOPE)
+        {
+            tracing::warn!(
+                "Rejected a personal access token without the scope {:?}: {claims:?}",
+                S::SCOPE
+            );
+            return Err(AuthRejection::InsufficientScope(
+                "The personal access token lacks the scope of the endpoint",
+            ));
+        }
+        Ok(RequireScope {
+            user: AuthUser(claims),
+            _scope: PhantomData,
+        })
+    }
+}
+
+/// A role that [`RequireRole`] can demand from the [`AuthUser`].
+pub(crate) trait RequiredRole {
+    fn is_satisfied_by(role: Role) -> bool;
+
+    /// Whether the access token must come from a login with the second factor.
+    fn requires_second_factor(_env: &Env) -> bool {
+        false
+    }
+}
+
+pub(crate) enum Admin {}
+
+impl RequiredRole for Admin {
+    fn is_satisfied_by(role: Role) -> bool {
+        matches!(role, Role::Admin)
+    }
+
+    fn requires_second_factor(env: &Env) -> bool {
+        env.totp.require_for_admins
+    }
+}
+
+/// An [`AuthUser`] whose role satisfies `R`, e.g. `RequireRole<Admin>`.
+pub(crate) struct RequireRole<R> {
+    pub(crate) user: AuthUser,
+    _role: PhantomData<R>,
+}
+
+impl<R: RequiredRole> FromRequestParts<Arc<Context>> for RequireRole<R> {
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        ctx: &Arc<Context>,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, ctx).await?;
+        if !R::is_satisfied_by(user.0.role) {
+            tracing::warn!("Rejected a JWT with an insufficient role: {:?}", user.0);
+            return Err(AuthRejection::InsufficientRole);
+        }
+        if R::requires_second_factor(&ctx.env) && !user.0.second_factor {
+            tracing::warn!(
+                "Rejected a JWT issued without the second factor: {:?}",
+                user.0
+            );
+            return Err(AuthRejection::SecondFactorRequired);
+        }
+        Ok(RequireRole {
+            user,
+            _role: PhantomData,
+        })
+    }
+}
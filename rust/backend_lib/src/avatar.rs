@@ -0,0 +1,275 @@
+//! Decoding of the uploaded avatars into the WebP renditions served by
+//! `GET /api/user/{user_id}/avatar?size=`.
+//!
+//! The renditions are square, cropped around the center, and upright regardless of the EXIF
+//! orientation of the original. SVG avatars scale on their own, so they have no renditions,
+//! unless they are rasterized, see [`mnln_env::SvgAvatarPolicy`].
+
+use std::io::Cursor;
+
+use browser_supported_img_format::BrowserSupportedImgFormat;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder as _, ImageFormat, ImageReader, Limits};
+use mnln_env::SvgAvatarPolicy;
+
+use crate::svg;
+
+/// The side lengths (px) of the renditions, in ascending order.
+pub(crate) const RENDITION_SIZES: [u32; 3] = [64, 128, 256];
+
+pub(crate) struct Rendition {
+    pub size: u32,
+    pub webp: Vec<u8>,
+}
+
+fn image_format(format: BrowserSupportedImgFormat) -> Option<ImageFormat> {
+    match format {
+        BrowserSupportedImgFormat::Bmp => Some(ImageFormat::Bmp),
+        BrowserSupportedImgFormat::Png => Some(ImageFormat::Png),
+        BrowserSupportedImgFormat::Jpeg => Some(ImageFormat::Jpeg),
+        BrowserSupportedImgFormat::Gif => Some(ImageFormat::Gif),
+        BrowserSupportedImgFormat::Webp => Some(ImageFormat::WebP),
+        BrowserSupportedImgFormat::Svg => None,
+    }
+}
+
+pub(crate) fn has_renditions(format: BrowserSupportedImgFormat) -> bool {
+    image_format(format).is_some()
+}
+
+/// Fails with [`image::ImageError::Limits`] if either side exceeds `max_dimension`, before the
+/// pixels, which take far more memory than the encoded file, are decoded.
+fn decode(
+    bytes: &[u8],
+    format: ImageFormat,
+    max_dimension: u32,
+) -> image::ImageResult<DynamicImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(max_dimension);
+    limits.max_image_height = Some(max_dimension);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+/// Decodes the avatar and encodes its renditions of every size in [`RENDITION_SIZES`].
+///
+/// Returns no renditions for the formats without them, see [`has_renditions`]. Only the first
+/// frame of an animated image is kept. CPU-bound, so it's meant for
+/// [`tokio::task::spawn_blocking`].
+pub(crate) fn renditions(
+    bytes: &[u8],
+    format: BrowserSupportedImgFormat,
+    max_dimension: u32,
+) -> image::ImageResult<Vec<Rendition>> {
+    let Some(format) = image_format(format) else {
+        return Ok(Vec::new());
+    };
+    let image = decode(bytes, format, max_dimension)?;
+
+    RENDITION_SIZES
+        .into_iter()
+        .map(|size| {
+            let resized = image.resize_to_fill(size, size, FilterType::Lanczos3);
+            let resized = DynamicImage::ImageRgba8(resized.into_rgba8());
+            let mut webp = Vec::new();
+            resized.write_to(&mut Cursor::new(&mut webp), ImageFormat::WebP)?;
+            Ok(Rendition { size, webp })
+        })
+        .collect()
+}
+
+pub(crate) struct Processed {
+    /// What is stored and served instead of the upload
+    pub avatar: bytes::Bytes,
+    pub format: BrowserSupportedImgFormat,
+    pub renditions: Vec<Rendition>,
+}
+
+/// Makes the uploaded avatar safe to serve according to the policy, see [`crate::svg`],
+/// and encodes its renditions.
+///
+/// Fails if the upload can't be decoded, or with [`image::ImageError::Limits`] if it's larger
+/// than `max_dimension`. CPU-bound, see [`renditions`].
+pub(crate) fn process(
+    avatar: bytes::Bytes,
+    format: BrowserSupportedImgFormat,
+    svg_policy: SvgAvatarPolicy,
+    max_dimension: u32,
+) -> anyhow::Result<Processed> {
+    let (avatar, format) = match (format, svg_policy) {
+        (BrowserSupportedImgFormat::Svg, SvgAvatarPolicy::Sanitize) => {
+            (svg::sanitize(&avatar)?.into(), format)
+        }
+        (BrowserSupportedImgFormat::Svg, SvgAvatarPolicy::Rasterize) => (
+            svg::rasterize(&avatar)?.into(),
+            BrowserSupportedImgFormat::Png,
+        ),
+        _ => (avatar, format),
+    };
+    let renditions = renditions(&avatar, format, max_dimension)?;
+    Ok(Processed {
+        avatar,
+        format,
+        renditions,
+    })
+}
+
+/// The bytes that the avatars of a user take in the object storage, renditions included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Storage {
+    /// Counted against the quota: the current avatar, the uploads in progress and the avatars
+    /// that are being deleted
+    pub storage_bytes: i64,
+    /// Taken by the current avatar
+    pub avatar_bytes: i64,
+}
+
+impl Storage {
+    /// Counts `bytes` more, unless that would exceed `quota_bytes`.
+    pub(crate) fn reserve(self, bytes: i64, quota_bytes: i64) -> Option<Self> {
+        let storage_bytes = self
+            .storage_bytes
+            .checked_add(bytes)
+            .filter(|&storage_bytes| storage_bytes <= quota_bytes)?;
+        Some(Storage {
+            storage_bytes,
+            ..self
+        })
+    }
+
+    /// Stops counting `bytes`. The avatars uploaded before the storage was tracked were never
+    /// counted, so the count doesn't go below zero.
+    pub(crate) fn release(self, bytes: i64) -> Self {
+        Storage {
+            storage_bytes: self.storage_bytes.saturating_sub(bytes).max(0),
+            ..self
+        }
+    }
+
+    /// Makes the reserved `avatar_bytes` the current avatar, so that the replaced one stops
+    /// counting.
+    pub(crate) fn replace(self, avatar_bytes: i64) -> Self {
+        Storage {
+            avatar_bytes,
+            ..self.release(self.avatar_bytes)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView as _, Rgb, RgbImage};
+
+    use super::*;
+
+    #[test]
+    fn renditions_are_square_webp_images() {
+        let image = RgbImage::from_fn(300, 200, |x, _| Rgb([(x % 256) as u8, 0, 0]));
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        let renditions = renditions(&png, BrowserSupportedImgFormat::Png, 300).unwrap();
+        assert_eq!(renditions.len(), RENDITION_SIZES.len());
+        for Rendition { size, webp } in renditions {
+            assert_eq!(
+                BrowserSupportedImgFormat::sniff(&webp),
+                Some(BrowserSupportedImgFormat::Webp)
+            );
+            let decoded = image::load_from_memory_with_format(&webp, ImageFormat::WebP).unwrap();
+            assert_eq!(decoded.dimensions(), (size, size));
+        }
+    }
+
+    #[test]
+    fn oversized_avatars_are_rejected_before_decoding() {
+        let image = RgbImage::new(300, 200);
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        let res = renditions(&png, BrowserSupportedImgFormat::Png, 299);
+        assert!(matches!(res, Err(image::ImageError::Limits(_))));
+    }
+
+    #[test]
+    fn undecodable_avatars_are_rejected() {
+        let truncated = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert!(renditions(truncated, BrowserSupportedImgFormat::Png, 8192).is_err());
+        assert!(
+            renditions(b"<svg/>", BrowserSupportedImgFormat::Svg, 8192)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn storage_is_reserved_up_to_the_quota() {
+        let storage = Storage {
+            storage_bytes: 100,
+            avatar_bytes: 100,
+        };
+        assert_eq!(
+            storage.reserve(900, 1000),
+            Some(Storage {
+                storage_bytes: 1000,
+                avatar_bytes: 100,
+            })
+        );
+        assert_eq!(storage.reserve(901, 1000), None);
+        assert_eq!(storage.reserve(i64::MAX, i64::MAX), None);
+    }
+
+    #[test]
+    fn released_storage_doesnt_go_below_zero() {
+        let storage = Storage {
+            storage_bytes: 300,
+            avatar_bytes: 100,
+        };
+        assert_eq!(storage.release(200).storage_bytes, 100);
+        assert_eq!(storage.release(400).storage_bytes, 0);
+    }
+
+    #[test]
+    fn the_replaced_avatar_stops_counting() {
+        // The current avatar of 100 bytes and the reserved 200 bytes of the new one
+        let storage = Storage {
+            storage_bytes: 300,
+            avatar_bytes: 100,
+        };
+        assert_eq!(
+            storage.replace(200),
+            Storage {
+                storage_bytes: 200,
+                avatar_bytes: 200,
+            }
+        );
+
+        // An avatar uploaded before the storage was tracked counts as nothing
+        let storage = Storage {
+            storage_bytes: 200,
+            avatar_bytes: 0,
+        };
+        assert_eq!(storage.replace(200).storage_bytes, 200);
+
+        // Replacing avatars over and over doesn't exhaust the quota, though while an avatar is
+        // uploaded, both it and the current one count
+        let mut storage = Storage {
+            storage_bytes: 0,
+            avatar_bytes: 0,
+        };
+        for _ in 0..10 {
+            storage = storage.reserve(600, 1200).unwrap().replace(600);
+        }
+        assert_eq!(storage.storage_bytes, 600);
+        assert_eq!(storage.reserve(600, 1000), None);
+    }
+}
@@ -0,0 +1,71 @@
+//! The extractor of the IP address of the client, as opposed to that of a reverse proxy.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::StatusCode;
+use axum::http::request::Parts;
+
+use crate::Context;
+
+/// The IP address of the client.
+///
+/// It starts as the peer address of the connection, so the router must be served with
+/// `into_make_service_with_connect_info`. While the address belongs to a trusted proxy,
+/// it's replaced by the rightmost remaining `X-Forwarded-For` entry, i.e. by the peer of that
+/// proxy. The entries to the left of the first untrusted address are provided by the client
+/// and are ignored.
+#[derive(Clone, Copy)]
+pub(crate) struct ClientIp(pub(crate) IpAddr);
+
+impl ClientIp {
+    fn resolve(parts: &Parts, peer: IpAddr, ctx: &Context) -> Option<IpAddr> {
+        let is_trusted = |ip: &IpAddr| ctx.env.trusted_proxies.iter().any(|net| net.contains(ip));
+
+        let mut forwarded_for = parts
+            .headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|header_value| header_value.to_str().ok())
+            .flat_map(|header_value| header_value.split(','))
+            .map(str::trim)
+            .rev();
+
+        let mut ip = peer;
+        while is_trusted(&ip) {
+            let Some(entry) = forwarded_for.next() else {
+                break;
+            };
+            ip = entry.parse::<IpAddr>().ok()?.to_canonical();
+        }
+        Some(ip)
+    }
+}
+
+impl FromRequestParts<Arc<Context>> for ClientIp {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        ctx: &Arc<Context>,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(ConnectInfo(peer)) = parts.extensions.get::<ConnectInfo<SocketAddr>>() else {
+            tracing::error!("The router is served without the connection info");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        };
+        // An IPv4 client of a dual-stack listener must be counted like any other IPv4 client
+        let peer = peer.ip().to_canonical();
+
+        match Self::resolve(parts, peer, ctx) {
+            Some(ip) => Ok(ClientIp(ip)),
+            None => {
+                tracing::warn!(
+                    "Malformed X-Forwarded-For from the trusted proxy {peer}: {:?}",
+                    parts.headers.get_all("x-forwarded-for")
+                );
+                Err(StatusCode::BAD_REQUEST)
+            }
+        }
+    }
+}
@@ -1,21 +1,56 @@
+use std::sync::Arc;
+
 use mnln_env::Env;
 
 use crate::db::Db;
+use crate::key_ring::KeyRing;
+use crate::mail;
+use crate::revocation::RevocationCache;
+use crate::service;
+
+const HTTP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 #[derive(Clone)]
 pub struct Context {
     pub env: Env,
     pub db: Db,
+    pub(crate) key_ring: Arc<KeyRing>,
+    pub(crate) revocations: RevocationCache,
+    /// The client of the third-party APIs, e.g. of Lichess
+    pub(crate) http: reqwest::Client,
 }
 
 impl Context {
     pub async fn new() -> anyhow::Result<Self> {
         let env = Env::from_env()?;
+        let key_ring = Arc::new(KeyRing::new(&env.jwt.key_ring)?);
         let db = Db::new(&env.pg).await?;
+        service::user::normalize_legacy_usernames(&db).await?;
 
         object_storage::init(&env).await?;
 
-        let ctx = Self { env, db };
+        let revocations = RevocationCache::default();
+        revocations.spawn_reloader(db.clone()).await?;
+
+        mail::spawn_dispatcher(&env.mail, db.clone())?;
+
+        service::account_deletion::spawn_purger(env.clone(), db.clone());
+        service::avatar_gc::spawn_reconciler(env.clone(), db.clone());
+        service::data_export::spawn_worker(env.clone(), db.clone());
+        service::login_throttle::spawn_pruner(env.clone(), db.clone());
+
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("main-line/", env!("CARGO_PKG_VERSION")))
+            .timeout(HTTP_TIMEOUT)
+            .build()?;
+
+        let ctx = Self {
+            env,
+            db,
+            key_ring,
+            revocations,
+            http,
+        };
         Ok(ctx)
     }
 }
@@ -0,0 +1,106 @@
+use tracing::trace;
+
+use crate::db::id::UserId;
+
+/// Schedules the deletion of the user in `grace_period`, unless it's already scheduled.
+///
+/// Returns the moment of the deletion as a UNIX timestamp in milliseconds,
+/// or `None` if the user doesn't exist.
+pub(crate) async fn schedule(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    grace_period: chrono::Duration,
+) -> sqlx::Result<Option<i64>> {
+    let scheduled_at_ms: Option<i64> = sqlx::query_scalar!(
+        r#"
+        UPDATE users
+        SET deletion_scheduled_at = COALESCE(
+            deletion_scheduled_at,
+            NOW() + make_interval(secs => $2)
+        )
+        WHERE id = $1
+        RETURNING (EXTRACT(EPOCH FROM deletion_scheduled_at) * 1000)::BIGINT as "scheduled_at_ms!"
+        "#,
+        user_id.0,
+        grace_period.num_seconds() as f64,
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) finished: scheduled the deletion of user with ID {user_id} at {scheduled_at_ms:?}",
+        mod_path = module_path!(),
+        fn_name = stringify!(schedule),
+    );
+
+    Ok(scheduled_at_ms)
+}
+
+/// Returns `false` if the user doesn't exist or no deletion was scheduled.
+pub(crate) async fn cancel(pg_pool: &sqlx::PgPool, user_id: UserId) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET deletion_scheduled_at = NULL
+        WHERE id = $1 AND deletion_scheduled_at IS NOT NULL
+        "#,
+        user_id.0,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+/// Locks the user whose deletion is overdue the most, if any.
+///
+/// The lock is held until the end of the transaction, so the deletion can't be cancelled
+/// halfway through and several replicas can purge accounts concurrently.
+pub(crate) async fn lock_next_due(conn: &mut sqlx::PgConnection) -> sqlx::Result<Option<UserId>> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT id as "id: UserId"
+        FROM users
+        WHERE deletion_scheduled_at <= NOW()
+        ORDER BY deletion_scheduled_at
+        LIMIT 1
+        FOR UPDATE SKIP LOCKED
+        "#,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Deletes the user and, through `ON DELETE CASCADE`, every row that belongs to them.
+pub(crate) async fn delete(conn: &mut sqlx::PgConnection, user_id: UserId) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM users
+        WHERE id = $1
+        "#,
+        user_id.0,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Moves the scheduled deletion of the user `delay` into the future, e.g. after it failed,
+/// so that the accounts due after it aren't held up.
+pub(crate) async fn postpone(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    delay: chrono::Duration,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET deletion_scheduled_at = NOW() + make_interval(secs => $2)
+        WHERE id = $1 AND deletion_scheduled_at IS NOT NULL
+        "#,
+        user_id.0,
+        delay.num_seconds() as f64,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
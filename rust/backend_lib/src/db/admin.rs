@@ -0,0 +1,319 @@
+use tracing::trace;
+
+use crate::db::id::UserId;
+use crate::db::user::Role;
+
+#[derive(sqlx::Type, Clone, Copy)]
+#[sqlx(type_name = "account_restriction")]
+#[sqlx(rename_all = "lowercase")]
+pub(crate) enum Restriction {
+    Suspended,
+    Banned,
+}
+
+impl From<shared_items_lib::service_responses::AccountRestrictionKind> for Restriction {
+    fn from(value: shared_items_lib::service_responses::AccountRestrictionKind) -> Self {
+        use shared_items_lib::service_responses::AccountRestrictionKind;
+        match value {
+            AccountRestrictionKind::Suspended => Restriction::Suspended,
+            AccountRestrictionKind::Banned => Restriction::Banned,
+        }
+    }
+}
+
+impl From<Restriction> for shared_items_lib::service_responses::AccountRestrictionKind {
+    fn from(value: Restriction) -> Self {
+        use shared_items_lib::service_responses::AccountRestrictionKind;
+        match value {
+            Restriction::Suspended => AccountRestrictionKind::Suspended,
+            Restriction::Banned => AccountRestrictionKind::Banned,
+        }
+    }
+}
+
+pub(crate) mod get_user {
+    use crate::db::id::UserId;
+    use crate::db::user::Role;
+
+    use super::Restriction;
+
+    pub(crate) struct User {
+        pub id: UserId,
+        pub username: String,
+        pub email: Option<String>,
+        pub email_verified: bool,
+        pub role: Role,
+        pub created_at_ms: Option<i64>,
+        /// Possibly expired, see `restricted_until_ms`
+        pub restriction: Option<Restriction>,
+        pub restriction_reason: Option<String>,
+        pub restricted_until_ms: Option<i64>,
+        pub password_reset_required: bool,
+        pub totp_enabled: bool,
+        pub passkey_count: i64,
+        pub deletion_scheduled_at_ms: Option<i64>,
+    }
+}
+
+pub(crate) async fn get_user(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Option<get_user::User>> {
+    sqlx::query_as!(
+        get_user::User,
+        r#"
+        SELECT
+            id as "id!: UserId",
+            username,
+            email,
+            email_verified_at IS NOT NULL as "email_verified!",
+            role as "role: Role",
+            (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT as created_at_ms,
+            restriction as "restriction: Restriction",
+            restriction_reason,
+            (EXTRACT(EPOCH FROM restricted_until) * 1000)::BIGINT as restricted_until_ms,
+            password_reset_required,
+            totp_enabled_at IS NOT NULL as "totp_enabled!",
+            (SELECT COUNT(*) FROM passkeys WHERE passkeys.user_id = users.id) as "passkey_count!",
+            (EXTRACT(EPOCH FROM deletion_scheduled_at) * 1000)::BIGINT as deletion_scheduled_at_ms
+        FROM users
+        WHERE id = $1
+        "#,
+        user_id.0,
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
+
+pub(crate) mod list_users {
+    use crate::db::user::Role;
+
+    pub(crate) struct Filter<'a> {
+        pub role: Option<Role>,
+        /// UNIX timestamp (ms), inclusive
+        pub created_after_ms: Option<i64>,
+        /// UNIX timestamp (ms), exclusive
+        pub created_before_ms: Option<i64>,
+        /// Matched case-insensitively
+        pub username_prefix: Option<&'a str>,
+    }
+
+    pub(crate) struct Page {
+        pub users: Vec<super::get_user::User>,
+        /// The number of users matching the filter across all pages
+        pub total: i64,
+    }
+}
+
+/// The users matching the filter, ordered by ID.
+pub(crate) async fn list_users(
+    pg_pool: &sqlx::PgPool,
+    filter: list_users::Filter<'_>,
+    limit: i64,
+    offset: i64,
+) -> sqlx::Result<list_users::Page> {
+    let list_users::Filter {
+        role,
+        created_after_ms,
+        created_before_ms,
+        username_prefix,
+    } = filter;
+
+    let mut tx = pg_pool.begin().await?;
+
+    let users = sqlx::query_as!(
+        get_user::User,
+        r#"
+        SELECT
+            id as "id!: UserId",
+            username,
+            email,
+            email_verified_at IS NOT NULL as "email_verified!",
+            role as "role: Role",
+            (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT as created_at_ms,
+            restriction as "restriction: Restriction",
+            restriction_reason,
+            (EXTRACT(EPOCH FROM restricted_until) * 1000)::BIGINT as restricted_until_ms,
+            password_reset_required,
+            totp_enabled_at IS NOT NULL as "totp_enabled!",
+            (SELECT COUNT(*) FROM passkeys WHERE passkeys.user_id = users.id) as "passkey_count!",
+            (EXTRACT(EPOCH FROM deletion_scheduled_at) * 1000)::BIGINT as deletion_scheduled_at_ms
+        FROM users
+        WHERE ($1::role IS NULL OR role = $1)
+            AND ($2::BIGINT IS NULL OR created_at >= to_timestamp($2 / 1000.0))
+            AND ($3::BIGINT IS NULL OR created_at < to_timestamp($3 / 1000.0))
+            AND ($4::TEXT IS NULL OR starts_with(LOWER(username), LOWER($4)))
+        ORDER BY id
+        LIMIT $5 OFFSET $6
+        "#,
+        role as _,
+        created_after_ms,
+        created_before_ms,
+        username_prefix,
+        limit,
+        offset,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total: i64 = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "total!"
+        FROM users
+        WHERE ($1::role IS NULL OR role = $1)
+            AND ($2::BIGINT IS NULL OR created_at >= to_timestamp($2 / 1000.0))
+            AND ($3::BIGINT IS NULL OR created_at < to_timestamp($3 / 1000.0))
+            AND ($4::TEXT IS NULL OR starts_with(LOWER(username), LOWER($4)))
+        "#,
+        role as _,
+        created_after_ms,
+        created_before_ms,
+        username_prefix,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(list_users::Page { users, total })
+}
+
+/// Changes the role of the user and revokes their sessions, whose access tokens carry the old one.
+///
+/// Returns the moment of the revocation, see [`crate::db::revocation::revoke_all_sessions`],
+/// or `None` if the user doesn't exist.
+pub(crate) async fn set_role(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    role: Role,
+) -> sqlx::Result<Option<i64>> {
+    let mut tx = pg_pool.begin().await?;
+
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET role = $2
+        WHERE id = $1
+        "#,
+        user_id.0,
+        role as _,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        tx.rollback().await?;
+        return Ok(None);
+    }
+
+    let revoked_at_ms = crate::db::revocation::revoke_all_sessions_in(&mut tx, user_id).await?;
+    tx.commit().await?;
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) succeeded: changed the role of user with ID {user_id}",
+        mod_path = module_path!(),
+        fn_name = stringify!(set_role),
+    );
+
+    Ok(Some(revoked_at_ms))
+}
+
+/// Suspends or bans the user, replacing the previous restriction, and revokes their sessions.
+///
+/// Returns the moment of the revocation, see [`crate::db::revocation::revoke_all_sessions`],
+/// or `None` if the user doesn't exist.
+pub(crate) async fn restrict(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    restriction: Restriction,
+    reason: &str,
+    restricted_until_ms: Option<i64>,
+) -> sqlx::Result<Option<i64>> {
+    let mut tx = pg_pool.begin().await?;
+
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET
+            restriction = $2,
+            restriction_reason = $3,
+            restricted_until = to_timestamp($4::BIGINT / 1000.0)
+        WHERE id = $1
+        "#,
+        user_id.0,
+        restriction as _,
+        reason,
+        restricted_until_ms,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        tx.rollback().await?;
+        return Ok(None);
+    }
+
+    let revoked_at_ms = crate::db::revocation::revoke_all_sessions_in(&mut tx, user_id).await?;
+    tx.commit().await?;
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) succeeded: restricted user with ID {user_id}",
+        mod_path = module_path!(),
+        fn_name = stringify!(restrict),
+    );
+
+    Ok(Some(revoked_at_ms))
+}
+
+/// Lifts the active restriction of the user. Returns `false` if there's none.
+pub(crate) async fn lift_restriction(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET restriction = NULL, restriction_reason = NULL, restricted_until = NULL
+        WHERE id = $1
+            AND restriction IS NOT NULL
+            AND (restricted_until IS NULL OR restricted_until > NOW())
+        "#,
+        user_id.0,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+pub(crate) mod active_restriction {
+    use super::Restriction;
+
+    pub(crate) struct ActiveRestriction {
+        pub restriction: Restriction,
+        pub reason: String,
+        pub restricted_until_ms: Option<i64>,
+    }
+}
+
+/// The restriction that currently keeps the user from logging in, if any.
+pub(crate) async fn active_restriction(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Option<active_restriction::ActiveRestriction>> {
+    sqlx::query_as!(
+        active_restriction::ActiveRestriction,
+        r#"
+        SELECT
+            restriction as "restriction!: Restriction",
+            COALESCE(restriction_reason, '') as "reason!",
+            (EXTRACT(EPOCH FROM restricted_until) * 1000)::BIGINT as restricted_until_ms
+        FROM users
+        WHERE id = $1
+            AND restriction IS NOT NULL
+            AND (restricted_until IS NULL OR restricted_until > NOW())
+        "#,
+        user_id.0,
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
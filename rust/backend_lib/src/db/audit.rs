@@ -0,0 +1,211 @@
+use crate::db::id::UserId;
+
+#[derive(sqlx::Type, Clone, Copy)]
+#[sqlx(type_name = "audit_event_kind")]
+#[sqlx(rename_all = "snake_case")]
+pub(crate) enum Kind {
+    Registered,
+    LoginSucceeded,
+    LoginFailed,
+    AvatarChanged,
+    RoleChanged,
+    Restricted,
+    RestrictionLifted,
+    PasswordResetForced,
+}
+
+impl From<shared_items_lib::service_responses::AuditEventKind> for Kind {
+    fn from(value: shared_items_lib::service_responses::AuditEventKind) -> Self {
+        use shared_items_lib::service_responses::AuditEventKind;
+        match value {
+            AuditEventKind::Registered => Kind::Registered,
+            AuditEventKind::LoginSucceeded => Kind::LoginSucceeded,
+            AuditEventKind::LoginFailed => Kind::LoginFailed,
+            AuditEventKind::AvatarChanged => Kind::AvatarChanged,
+            AuditEventKind::RoleChanged => Kind::RoleChanged,
+            AuditEventKind::Restricted => Kind::Restricted,
+            AuditEventKind::RestrictionLifted => Kind::RestrictionLifted,
+            AuditEventKind::PasswordResetForced => Kind::PasswordResetForced,
+        }
+    }
+}
+
+pub(crate) mod append {
+    use crate::db::id::UserId;
+
+    use super::Kind;
+
+    pub(crate) struct Event<'a> {
+        pub kind: Kind,
+        pub actor_id: Option<UserId>,
+        pub target_id: Option<UserId>,
+        pub ip: Option<String>,
+        pub user_agent: Option<&'a str>,
+        /// The serialized `AuditEventDetails`
+        pub details_json: String,
+    }
+}
+
+pub(crate) async fn append(pg_pool: &sqlx::PgPool, event: append::Event<'_>) -> sqlx::Result<()> {
+    let append::Event {
+        kind,
+        actor_id,
+        target_id,
+        ip,
+        user_agent,
+        details_json,
+    } = event;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_events (kind, actor_id, target_id, ip, user_agent, details)
+        VALUES ($1, $2, $3, $4::TEXT::INET, $5, $6::TEXT::JSONB)
+        "#,
+        kind as _,
+        actor_id.map(|actor_id| actor_id.0),
+        target_id.map(|target_id| target_id.0),
+        ip,
+        user_agent,
+        details_json,
+    )
+    .execute(pg_pool)
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) mod list {
+    use crate::db::id::UserId;
+
+    use super::Kind;
+
+    pub(crate) struct Filter {
+        pub actor_id: Option<UserId>,
+        pub target_id: Option<UserId>,
+        pub kind: Option<Kind>,
+        /// UNIX timestamp (ms), inclusive
+        pub occurred_after_ms: Option<i64>,
+        /// UNIX timestamp (ms), exclusive
+        pub occurred_before_ms: Option<i64>,
+    }
+
+    pub(crate) struct Event {
+        pub id: i64,
+        pub occurred_at_ms: i64,
+        pub actor_id: Option<UserId>,
+        pub target_id: Option<UserId>,
+        pub ip: Option<String>,
+        pub user_agent: Option<String>,
+        /// The serialized `AuditEventDetails`
+        pub details_json: String,
+    }
+
+    pub(crate) struct Page {
+        pub events: Vec<Event>,
+        /// The number of events matching the filter across all pages
+        pub total: i64,
+    }
+}
+
+/// The events matching the filter, the most recent first.
+pub(crate) async fn list(
+    pg_pool: &sqlx::PgPool,
+    filter: list::Filter,
+    limit: i64,
+    offset: i64,
+) -> sqlx::Result<list::Page> {
+    let list::Filter {
+        actor_id,
+        target_id,
+        kind,
+        occurred_after_ms,
+        occurred_before_ms,
+    } = filter;
+    let actor_id = actor_id.map(|actor_id| actor_id.0);
+    let target_id = target_id.map(|target_id| target_id.0);
+
+    let mut tx = pg_pool.begin().await?;
+
+    let events = sqlx::query_as!(
+        list::Event,
+        r#"
+        SELECT
+            id,
+            (EXTRACT(EPOCH FROM occurred_at) * 1000)::BIGINT as "occurred_at_ms!",
+            actor_id as "actor_id: UserId",
+            target_id as "target_id: UserId",
+            HOST(ip) as ip,
+            user_agent,
+            details::TEXT as "details_json!"
+        FROM audit_events
+        WHERE ($1::INTEGER IS NULL OR actor_id = $1)
+            AND ($2::INTEGER IS NULL OR target_id = $2)
+            AND ($3::audit_event_kind IS NULL OR kind = $3)
+            AND ($4::BIGINT IS NULL OR occurred_at >= to_timestamp($4 / 1000.0))
+            AND ($5::BIGINT IS NULL OR occurred_at < to_timestamp($5 / 1000.0))
+        ORDER BY occurred_at DESC, id DESC
+        LIMIT $6 OFFSET $7
+        "#,
+        actor_id,
+        target_id,
+        kind as _,
+        occurred_after_ms,
+        occurred_before_ms,
+        limit,
+        offset,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let total: i64 = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "total!"
+        FROM audit_events
+        WHERE ($1::INTEGER IS NULL OR actor_id = $1)
+            AND ($2::INTEGER IS NULL OR target_id = $2)
+            AND ($3::audit_event_kind IS NULL OR kind = $3)
+            AND ($4::BIGINT IS NULL OR occurred_at >= to_timestamp($4 / 1000.0))
+            AND ($5::BIGINT IS NULL OR occurred_at < to_timestamp($5 / 1000.0))
+        "#,
+        actor_id,
+        target_id,
+        kind as _,
+        occurred_after_ms,
+        occurred_before_ms,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(list::Page { events, total })
+}
+
+/// The most recent events about the user.
+pub(crate) async fn recent_for_target(
+    pg_pool: &sqlx::PgPool,
+    target_id: UserId,
+    limit: i64,
+) -> sqlx::Result<Vec<list::Event>> {
+    sqlx::query_as!(
+        list::Event,
+        r#"
+        SELECT
+            id,
+            (EXTRACT(EPOCH FROM occurred_at) * 1000)::BIGINT as "occurred_at_ms!",
+            actor_id as "actor_id: UserId",
+            target_id as "target_id: UserId",
+            HOST(ip) as ip,
+            user_agent,
+            details::TEXT as "details_json!"
+        FROM audit_events
+        WHERE target_id = $1
+        ORDER BY occurred_at DESC, id DESC
+        LIMIT $2
+        "#,
+        target_id.0,
+        limit,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
@@ -19,7 +19,8 @@ pub(crate) async fn get_user_page_data(
         SELECT
             avatar_s3_key,
             username,
-            email,
+            -- Only email addresses whose ownership has been proven are public
+            CASE WHEN email_verified_at IS NOT NULL THEN email END as email,
             chess_dot_com_username,
             lichess_username
         FROM users
@@ -0,0 +1,419 @@
+use crate::db::id::UserId;
+
+#[derive(sqlx::Type, Clone, Copy)]
+#[sqlx(type_name = "data_export_status")]
+#[sqlx(rename_all = "lowercase")]
+pub(crate) enum Status {
+    Pending,
+    Running,
+    Ready,
+    Failed,
+}
+
+/// Requests an export for the user, unless one is already pending or running.
+///
+/// Returns the ID of the requested or the already pending export.
+pub(crate) async fn create(pg_pool: &sqlx::PgPool, user_id: UserId) -> sqlx::Result<i64> {
+    sqlx::query_scalar!(
+        r#"
+        WITH existing AS (
+            SELECT id
+            FROM data_exports
+            WHERE user_id = $1 AND status IN ('pending', 'running')
+            LIMIT 1
+        ), inserted AS (
+            INSERT INTO data_exports (user_id)
+            SELECT $1
+            WHERE NOT EXISTS (SELECT 1 FROM existing)
+            RETURNING id
+        )
+        SELECT id as "id!" FROM existing
+        UNION ALL
+        SELECT id as "id!" FROM inserted
+        "#,
+        user_id.0,
+    )
+    .fetch_one(pg_pool)
+    .await
+}
+
+pub(crate) mod get {
+    use super::Status;
+
+    pub(crate) struct Export {
+        pub id: i64,
+        pub status: Status,
+        pub created_at_ms: i64,
+        pub expires_at_ms: Option<i64>,
+    }
+}
+
+pub(crate) async fn get(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    export_id: i64,
+) -> sqlx::Result<Option<get::Export>> {
+    sqlx::query_as!(
+        get::Export,
+        r#"
+        SELECT
+            id,
+            status as "status: Status",
+            (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT as "created_at_ms!",
+            (EXTRACT(EPOCH FROM expires_at) * 1000)::BIGINT as expires_at_ms
+        FROM data_exports
+        WHERE id = $1 AND user_id = $2
+        "#,
+        export_id,
+        user_id.0,
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
+
+/// The key of the archive, if the export is ready and hasn't expired.
+pub(crate) async fn get_archive_key(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    export_id: i64,
+) -> sqlx::Result<Option<String>> {
+    let key: Option<Option<String>> = sqlx::query_scalar!(
+        r#"
+        SELECT object_key
+        FROM data_exports
+        WHERE id = $1 AND user_id = $2 AND status = 'ready' AND expires_at > NOW()
+        "#,
+        export_id,
+        user_id.0,
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+    Ok(key.flatten())
+}
+
+pub(crate) mod claim {
+    use crate::db::id::UserId;
+
+    pub(crate) struct ClaimedExport {
+        pub id: i64,
+        pub user_id: UserId,
+    }
+}
+
+/// Claims the oldest pending export, or a running one that was started more than `lease` ago
+/// and is therefore assumed to be abandoned.
+pub(crate) async fn claim(
+    pg_pool: &sqlx::PgPool,
+    lease: chrono::Duration,
+) -> sqlx::Result<Option<claim::ClaimedExport>> {
+    sqlx::query_as!(
+        claim::ClaimedExport,
+        r#"
+        UPDATE data_exports
+        SET status = 'running', started_at = NOW()
+        WHERE id = (
+            SELECT id
+            FROM data_exports
+            WHERE status = 'pending'
+                OR (status = 'running' AND started_at < NOW() - make_interval(secs => $1))
+            ORDER BY created_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, user_id as "user_id: UserId"
+        "#,
+        lease.num_seconds() as f64,
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
+
+pub(crate) async fn mark_ready(
+    pg_pool: &sqlx::PgPool,
+    export_id: i64,
+    object_key: &str,
+    retention: chrono::Duration,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE data_exports
+        SET
+            status = 'ready',
+            finished_at = NOW(),
+            object_key = $2,
+            expires_at = NOW() + make_interval(secs => $3)
+        WHERE id = $1
+        "#,
+        export_id,
+        object_key,
+        retention.num_seconds() as f64,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn mark_failed(
+    pg_pool: &sqlx::PgPool,
+    export_id: i64,
+    error: &str,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE data_exports
+        SET status = 'failed', finished_at = NOW(), error = $2
+        WHERE id = $1
+        "#,
+        export_id,
+        error,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+pub(crate) mod take_expired {
+    pub(crate) struct ExpiredExport {
+        pub id: i64,
+        pub object_key: Option<String>,
+    }
+}
+
+/// Exports whose archive can be deleted, including the failed ones past their retention.
+pub(crate) async fn take_expired(
+    pg_pool: &sqlx::PgPool,
+    failed_retention: chrono::Duration,
+    limit: i64,
+) -> sqlx::Result<Vec<take_expired::ExpiredExport>> {
+    sqlx::query_as!(
+        take_expired::ExpiredExport,
+        r#"
+        SELECT id, object_key
+        FROM data_exports
+        WHERE expires_at <= NOW()
+            OR (status = 'failed' AND finished_at < NOW() - make_interval(secs => $1))
+        ORDER BY id
+        LIMIT $2
+        "#,
+        failed_retention.num_seconds() as f64,
+        limit,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+pub(crate) async fn delete(pg_pool: &sqlx::PgPool, export_id: i64) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM data_exports
+        WHERE id = $1
+        "#,
+        export_id,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+pub(crate) mod user {
+    /// The exported columns of the user. The credentials and other secrets are left out,
+    /// so a column is only exported once it's listed here.
+    #[derive(serde::Serialize)]
+    #[cfg_attr(test, derive(Default))]
+    pub(crate) struct User {
+        pub id: i32,
+        pub username: String,
+        pub email: Option<String>,
+        pub email_verified_at: Option<i64>,
+        pub avatar_s3_key: Option<String>,
+        pub avatar_bytes: i64,
+        pub avatar_storage_bytes: i64,
+        pub lichess_username: Option<String>,
+        pub lichess_id: Option<String>,
+        pub chess_dot_com_username: Option<String>,
+        pub role: String,
+        pub created_at: Option<i64>,
+        pub sessions_revoked_at: Option<i64>,
+        pub deletion_scheduled_at: Option<i64>,
+        pub totp_enabled_at: Option<i64>,
+        pub restriction: Option<String>,
+        pub restriction_reason: Option<String>,
+        pub restricted_until: Option<i64>,
+        pub password_reset_required: bool,
+    }
+}
+
+/// The user as exported, with the times as UNIX timestamps (ms).
+pub(crate) async fn user(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Option<user::User>> {
+    sqlx::query_as!(
+        user::User,
+        r#"
+        SELECT
+            id,
+            username,
+            email,
+            (EXTRACT(EPOCH FROM email_verified_at) * 1000)::BIGINT as email_verified_at,
+            avatar_s3_key,
+            avatar_bytes,
+            avatar_storage_bytes,
+            lichess_username,
+            lichess_id,
+            chess_dot_com_username,
+            role::TEXT as "role!",
+            (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT as created_at,
+            (EXTRACT(EPOCH FROM sessions_revoked_at) * 1000)::BIGINT as sessions_revoked_at,
+            (EXTRACT(EPOCH FROM deletion_scheduled_at) * 1000)::BIGINT as deletion_scheduled_at,
+            (EXTRACT(EPOCH FROM totp_enabled_at) * 1000)::BIGINT as totp_enabled_at,
+            restriction::TEXT as restriction,
+            restriction_reason,
+            (EXTRACT(EPOCH FROM restricted_until) * 1000)::BIGINT as restricted_until,
+            password_reset_required
+        FROM users
+        WHERE id = $1
+        "#,
+        user_id.0,
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
+
+pub(crate) mod sessions {
+    /// The exported columns of a session, i.e. all but the refresh token hash and its successor seed.
+    #[derive(serde::Serialize)]
+    pub(crate) struct Session {
+        pub id: i32,
+        pub family_id: String,
+        pub created_at: i64,
+        pub expires_at: i64,
+        pub rotated_at: Option<i64>,
+        pub revoked_at: Option<i64>,
+        pub second_factor: bool,
+    }
+}
+
+/// The sessions of the user as exported, with the times as UNIX timestamps (ms).
+pub(crate) async fn sessions(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Vec<sessions::Session>> {
+    sqlx::query_as!(
+        sessions::Session,
+        r#"
+        SELECT
+            id,
+            family_id::TEXT as "family_id!",
+            (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT as "created_at!",
+            (EXTRACT(EPOCH FROM expires_at) * 1000)::BIGINT as "expires_at!",
+            (EXTRACT(EPOCH FROM rotated_at) * 1000)::BIGINT as rotated_at,
+            (EXTRACT(EPOCH FROM revoked_at) * 1000)::BIGINT as revoked_at,
+            second_factor
+        FROM sessions
+        WHERE user_id = $1
+        ORDER BY id
+        "#,
+        user_id.0,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+pub(crate) mod passkeys {
+    /// The exported columns of a passkey, i.e. all but the credential itself.
+    #[derive(serde::Serialize)]
+    pub(crate) struct Passkey {
+        pub name: String,
+        pub created_at: i64,
+        pub last_used_at: Option<i64>,
+    }
+}
+
+/// The passkeys of the user as exported, with the times as UNIX timestamps (ms).
+pub(crate) async fn passkeys(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Vec<passkeys::Passkey>> {
+    sqlx::query_as!(
+        passkeys::Passkey,
+        r#"
+        SELECT
+            name,
+            (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT as "created_at!",
+            (EXTRACT(EPOCH FROM last_used_at) * 1000)::BIGINT as last_used_at
+        FROM passkeys
+        WHERE user_id = $1
+        ORDER BY id
+        "#,
+        user_id.0,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+pub(crate) mod personal_access_tokens {
+    /// The exported columns of a personal access token, i.e. all but the token hash.
+    #[derive(serde::Serialize)]
+    pub(crate) struct PersonalAccessToken {
+        pub name: String,
+        pub scopes: Vec<String>,
+        pub created_at: i64,
+        pub last_used_at: Option<i64>,
+        pub expires_at: Option<i64>,
+    }
+}
+
+/// The personal access tokens of the user as exported, with the times as UNIX timestamps (ms).
+pub(crate) async fn personal_access_tokens(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Vec<personal_access_tokens::PersonalAccessToken>> {
+    sqlx::query_as!(
+        personal_access_tokens::PersonalAccessToken,
+        r#"
+        SELECT
+            name,
+            scopes::TEXT[] as "scopes!",
+            (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT as "created_at!",
+            (EXTRACT(EPOCH FROM last_used_at) * 1000)::BIGINT as last_used_at,
+            (EXTRACT(EPOCH FROM expires_at) * 1000)::BIGINT as expires_at
+        FROM personal_access_tokens
+        WHERE user_id = $1
+        ORDER BY id
+        "#,
+        user_id.0,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    /// The columns that hold credentials or other secrets.
+    const SECRET_COLUMNS: &[&str] = &[
+        "password_hash",
+        "legacy_password_hash",
+        "client_salt",
+        "email_verification_jti",
+        "totp_secret",
+        "totp_last_used_step",
+        "refresh_token_hash",
+        "successor_seed",
+        "credential_id",
+        "public_key",
+        "token_hash",
+    ];
+
+    #[test]
+    fn no_secret_column_reaches_user_json() {
+        let json = serde_json::to_value(super::user::User::default()).unwrap();
+        let keys: Vec<&String> = json.as_object().unwrap().keys().collect();
+        for key in keys {
+            assert!(!SECRET_COLUMNS.contains(&key.as_str()), "{key} is exported");
+            for part in ["hash", "salt", "secret", "jti", "token"] {
+                assert!(!key.contains(part), "{key} looks like a secret");
+            }
+        }
+    }
+}
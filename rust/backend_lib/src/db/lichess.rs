@@ -0,0 +1,249 @@
+use tracing::trace;
+
+use crate::db::id::UserId;
+use crate::db::user::{PHCString, Role};
+
+/// Stores a pending authorization, pruning the expired ones.
+pub(crate) async fn create_state(
+    pg_pool: &sqlx::PgPool,
+    state_hash: &str,
+    code_verifier: &str,
+    link_user_id: Option<UserId>,
+    ttl: chrono::Duration,
+) -> sqlx::Result<()> {
+    let mut tx = pg_pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM lichess_oauth_states
+        WHERE expires_at <= NOW()
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO lichess_oauth_states (state_hash, code_verifier, link_user_id, expires_at)
+        VALUES ($1, $2, $3, NOW() + make_interval(secs => $4))
+        "#,
+        state_hash,
+        code_verifier,
+        link_user_id.map(|user_id| user_id.0),
+        ttl.num_seconds() as f64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+pub(crate) mod take_state {
+    use crate::db::id::UserId;
+
+    pub(crate) struct State {
+        pub code_verifier: String,
+        pub link_user_id: Option<UserId>,
+    }
+}
+
+/// Consumes the pending authorization. Returns `None` if it's unknown, used or expired.
+pub(crate) async fn take_state(
+    pg_pool: &sqlx::PgPool,
+    state_hash: &str,
+) -> sqlx::Result<Option<take_state::State>> {
+    sqlx::query_as!(
+        take_state::State,
+        r#"
+        WITH taken AS (
+            DELETE FROM lichess_oauth_states
+            WHERE state_hash = $1
+            RETURNING code_verifier, link_user_id, expires_at
+        )
+        SELECT
+            code_verifier as "code_verifier!",
+            link_user_id as "link_user_id?: UserId"
+        FROM taken
+        WHERE expires_at > NOW()
+        "#,
+        state_hash,
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
+
+pub(crate) mod find_user {
+    use crate::db::id::UserId;
+    use crate::db::user::Role;
+
+    pub(crate) struct User {
+        pub id: UserId,
+        pub role: Role,
+    }
+}
+
+/// The user who signed in with the Lichess account `lichess_id` before, if any.
+pub(crate) async fn find_user(
+    pg_pool: &sqlx::PgPool,
+    lichess_id: &str,
+) -> sqlx::Result<Option<find_user::User>> {
+    sqlx::query_as!(
+        find_user::User,
+        r#"
+        SELECT id as "id: UserId", role as "role: Role"
+        FROM users
+        WHERE lichess_id = $1
+        "#,
+        lichess_id,
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
+
+/// The Lichess username of the user, if it's verified.
+pub(crate) async fn linked_username(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Option<String>> {
+    let username: Option<Option<String>> = sqlx::query_scalar!(
+        r#"
+        SELECT lichess_username
+        FROM users
+        WHERE id = $1 AND lichess_id IS NOT NULL
+        "#,
+        user_id.0,
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+    Ok(username.flatten())
+}
+
+/// Clears the Lichess username that other users merely claim to have, since it's verified now.
+async fn release_claims(
+    conn: &mut sqlx::PgConnection,
+    lichess_username: &str,
+    except: Option<UserId>,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET lichess_username = NULL, profile_version = profile_version + 1
+        WHERE LOWER(lichess_username) = LOWER($1)
+            AND lichess_id IS NULL
+            AND id IS DISTINCT FROM $2
+        "#,
+        lichess_username,
+        except.map(|user_id| user_id.0),
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+fn is_unique_violation(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .is_some_and(|db_err| matches!(db_err.kind(), sqlx::error::ErrorKind::UniqueViolation))
+}
+
+pub(crate) mod link {
+    pub(crate) enum Output {
+        Success,
+        /// The Lichess account is linked to another user
+        AlreadyLinked,
+        NotFound,
+    }
+}
+
+/// Links the verified Lichess account to the user, replacing the Lichess username.
+pub(crate) async fn link(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    lichess_id: &str,
+    lichess_username: &str,
+) -> sqlx::Result<link::Output> {
+    let mut tx = pg_pool.begin().await?;
+    release_claims(&mut tx, lichess_username, Some(user_id)).await?;
+
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET lichess_id = $2, lichess_username = $3, profile_version = profile_version + 1
+        WHERE id = $1
+        "#,
+        user_id.0,
+        lichess_id,
+        lichess_username,
+    )
+    .execute(&mut *tx)
+    .await;
+
+    let output = match res {
+        Ok(res) if res.rows_affected() == 0 => link::Output::NotFound,
+        Ok(_) => link::Output::Success,
+        Err(err) if is_unique_violation(&err) => link::Output::AlreadyLinked,
+        Err(err) => return Err(err),
+    };
+
+    match output {
+        link::Output::Success => tx.commit().await?,
+        link::Output::AlreadyLinked | link::Output::NotFound => tx.rollback().await?,
+    }
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) finished: linked {lichess_id} to user with ID {user_id}: {linked}",
+        mod_path = module_path!(),
+        fn_name = stringify!(link),
+        linked = matches!(output, link::Output::Success),
+    );
+
+    Ok(output)
+}
+
+/// Registers a user who signed up with the verified Lichess account.
+///
+/// The password is random, so it can only be set through a password reset.
+pub(crate) async fn register(
+    pg_pool: &sqlx::PgPool,
+    username: &str,
+    username_normalized: &str,
+    password_hash: &PHCString,
+    client_salt: &str,
+    lichess_id: &str,
+) -> crate::db::user::register::Output {
+    let res: sqlx::Result<UserId> = async {
+        let mut tx = pg_pool.begin().await?;
+        release_claims(&mut tx, username, None).await?;
+        let user_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO users (
+                username, username_normalized, password_hash, client_salt, lichess_id,
+                lichess_username
+            )
+            VALUES ($1, $2, $3, $4, $5, $1)
+            RETURNING id as "id!: UserId"
+            "#,
+            username,
+            username_normalized,
+            password_hash.as_str(),
+            client_salt,
+            lichess_id,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(user_id)
+    }
+    .await;
+
+    let output = crate::db::user::register::Output::from(res);
+
+    if let crate::db::user::register::Output::Success { user_id } = &output {
+        trace!(
+            "The function {mod_path}::{fn_name}(...) succeeded: registered user with ID {user_id} through Lichess",
+            mod_path = module_path!(),
+            fn_name = stringify!(register),
+        );
+    }
+
+    output
+}
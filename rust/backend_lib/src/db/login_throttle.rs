@@ -0,0 +1,116 @@
+#[derive(sqlx::Type, Clone, Copy, Debug)]
+#[sqlx(type_name = "login_throttle_scope")]
+#[sqlx(rename_all = "lowercase")]
+pub(crate) enum Scope {
+    Username,
+    Ip,
+}
+
+/// How many seconds are left until the later of the lockouts of the username and of the IP
+/// address is over, if either is locked out.
+pub(crate) async fn retry_after_secs(
+    pg_pool: &sqlx::PgPool,
+    username: &str,
+    ip: &str,
+) -> sqlx::Result<Option<i64>> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT CEIL(EXTRACT(EPOCH FROM MAX(locked_until) - NOW()))::BIGINT
+        FROM login_throttles
+        WHERE locked_until > NOW()
+            AND ((scope = 'username' AND key = $1) OR (scope = 'ip' AND key = $2))
+        "#,
+        username,
+        ip,
+    )
+    .fetch_one(pg_pool)
+    .await
+}
+
+/// Counts a failure, starting over if the previous one was more than `reset_after` ago.
+///
+/// Returns the number of consecutive failures, including this one. The row stays locked until
+/// the end of the transaction, so concurrent failures are counted one after another.
+pub(crate) async fn record_failure(
+    conn: &mut sqlx::PgConnection,
+    scope: Scope,
+    key: &str,
+    reset_after: chrono::Duration,
+) -> sqlx::Result<i32> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO login_throttles (scope, key, failures, last_failure_at)
+        VALUES ($1, $2, 1, NOW())
+        ON CONFLICT (scope, key) DO UPDATE
+        SET
+            failures = CASE
+                WHEN login_throttles.last_failure_at < NOW() - make_interval(secs => $3) THEN 1
+                ELSE login_throttles.failures + 1
+            END,
+            last_failure_at = NOW()
+        RETURNING failures
+        "#,
+        scope as Scope,
+        key,
+        reset_after.num_seconds() as f64,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Locks the username or the IP address out for `lockout`, unless it's locked out for longer.
+pub(crate) async fn lock_out(
+    conn: &mut sqlx::PgConnection,
+    scope: Scope,
+    key: &str,
+    lockout: chrono::Duration,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE login_throttles
+        SET locked_until = GREATEST(locked_until, NOW() + make_interval(secs => $3))
+        WHERE scope = $1 AND key = $2
+        "#,
+        scope as Scope,
+        key,
+        lockout.num_seconds() as f64,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Forgets the failures, e.g. of the username after a successful login.
+pub(crate) async fn reset(pg_pool: &sqlx::PgPool, scope: Scope, key: &str) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        DELETE FROM login_throttles
+        WHERE scope = $1 AND key = $2
+        "#,
+        scope as Scope,
+        key,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Deletes the counters that would start over anyway and aren't locked out.
+///
+/// Returns the number of deleted counters.
+pub(crate) async fn prune(
+    pg_pool: &sqlx::PgPool,
+    reset_after: chrono::Duration,
+) -> sqlx::Result<u64> {
+    let res = sqlx::query!(
+        r#"
+        DELETE FROM login_throttles
+        WHERE last_failure_at < NOW() - make_interval(secs => $1)
+            AND (locked_until IS NULL OR locked_until <= NOW())
+        "#,
+        reset_after.num_seconds() as f64,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected())
+}
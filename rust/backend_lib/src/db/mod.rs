@@ -4,8 +4,21 @@ use tracing::info;
 
 use mnln_env::PgEnv;
 
+pub(crate) mod account_deletion;
+pub(crate) mod admin;
+pub(crate) mod audit;
 pub(crate) mod bff;
+pub(crate) mod data_export;
 pub(crate) mod id;
+pub(crate) mod lichess;
+pub(crate) mod login_throttle;
+pub(crate) mod outbox;
+pub(crate) mod passkey;
+pub(crate) mod password_reset;
+pub(crate) mod personal_access_token;
+pub(crate) mod revocation;
+pub(crate) mod session;
+pub(crate) mod totp;
 pub(crate) mod user;
 
 #[derive(Clone)]
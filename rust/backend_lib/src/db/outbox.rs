@@ -0,0 +1,123 @@
+pub(crate) mod enqueue {
+    pub(crate) struct NewMail {
+        pub recipient: String,
+        pub subject: String,
+        pub body: String,
+    }
+}
+
+/// Meant to be called in the transaction of the change that triggers the mail.
+pub(crate) async fn enqueue(
+    conn: &mut sqlx::PgConnection,
+    mail: &enqueue::NewMail,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_outbox (recipient, subject, body)
+        VALUES ($1, $2, $3)
+        "#,
+        mail.recipient,
+        mail.subject,
+        mail.body,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub(crate) mod claim {
+    pub(crate) struct ClaimedMail {
+        pub id: i64,
+        pub recipient: String,
+        pub subject: String,
+        pub body: String,
+        /// Including the current one
+        pub attempts: i32,
+    }
+}
+
+/// Claims up to `limit` due mails for `lease`, after which they become due again
+/// unless they are marked as sent or failed.
+///
+/// `SKIP LOCKED` lets several replicas dispatch the outbox without sending a mail twice.
+pub(crate) async fn claim(
+    pg_pool: &sqlx::PgPool,
+    limit: i64,
+    max_attempts: i32,
+    lease: chrono::Duration,
+) -> sqlx::Result<Vec<claim::ClaimedMail>> {
+    sqlx::query_as!(
+        claim::ClaimedMail,
+        r#"
+        UPDATE email_outbox
+        SET
+            next_attempt_at = NOW() + make_interval(secs => $3),
+            attempts = attempts + 1
+        WHERE id IN (
+            SELECT id
+            FROM email_outbox
+            WHERE sent_at IS NULL AND next_attempt_at <= NOW() AND attempts < $2
+            ORDER BY id
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, recipient, subject, body, attempts
+        "#,
+        limit,
+        max_attempts,
+        lease.num_seconds() as f64,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+pub(crate) async fn mark_sent(pg_pool: &sqlx::PgPool, id: i64) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE email_outbox
+        SET sent_at = NOW(), last_error = NULL
+        WHERE id = $1
+        "#,
+        id,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn mark_failed(
+    pg_pool: &sqlx::PgPool,
+    id: i64,
+    error: &str,
+    retry_in: chrono::Duration,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE email_outbox
+        SET next_attempt_at = NOW() + make_interval(secs => $3), last_error = $2
+        WHERE id = $1
+        "#,
+        id,
+        error,
+        retry_in.num_seconds() as f64,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn prune_sent(
+    pg_pool: &sqlx::PgPool,
+    older_than: chrono::Duration,
+) -> sqlx::Result<u64> {
+    let res = sqlx::query!(
+        r#"
+        DELETE FROM email_outbox
+        WHERE sent_at < NOW() - make_interval(secs => $1)
+        "#,
+        older_than.num_seconds() as f64,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected())
+}
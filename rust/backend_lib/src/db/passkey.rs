@@ -0,0 +1,248 @@
+use tracing::trace;
+
+use crate::db::id::UserId;
+use crate::db::user::Role;
+
+/// Stores the challenge of a pending ceremony, pruning the expired ones.
+///
+/// The challenges of the registrations belong to `user_id`, while those of the logins don't.
+pub(crate) async fn create_challenge(
+    pg_pool: &sqlx::PgPool,
+    challenge_hash: &str,
+    user_id: Option<UserId>,
+    ttl: chrono::Duration,
+) -> sqlx::Result<()> {
+    let mut tx = pg_pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM webauthn_challenges
+        WHERE expires_at <= NOW()
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO webauthn_challenges (challenge_hash, user_id, expires_at)
+        VALUES ($1, $2, NOW() + make_interval(secs => $3))
+        "#,
+        challenge_hash,
+        user_id.map(|user_id| user_id.0),
+        ttl.num_seconds() as f64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
+
+/// Consumes the challenge of a pending ceremony of `user_id`, or of a login if it's `None`.
+///
+/// Returns `false` if it's unknown, used, expired or issued for another ceremony.
+pub(crate) async fn take_challenge(
+    pg_pool: &sqlx::PgPool,
+    challenge_hash: &str,
+    user_id: Option<UserId>,
+) -> sqlx::Result<bool> {
+    let taken: Option<bool> = sqlx::query_scalar!(
+        r#"
+        WITH taken AS (
+            DELETE FROM webauthn_challenges
+            WHERE challenge_hash = $1
+            RETURNING user_id, expires_at
+        )
+        SELECT (expires_at > NOW() AND user_id IS NOT DISTINCT FROM $2) as "valid!"
+        FROM taken
+        "#,
+        challenge_hash,
+        user_id.map(|user_id| user_id.0),
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+    Ok(taken.unwrap_or(false))
+}
+
+/// The IDs of the credentials of the user, so that the authenticator doesn't register one twice.
+pub(crate) async fn credential_ids(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Vec<Vec<u8>>> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT credential_id
+        FROM passkeys
+        WHERE user_id = $1
+        ORDER BY id
+        "#,
+        user_id.0,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+pub(crate) mod list {
+    pub(crate) struct Passkey {
+        pub id: i32,
+        pub name: String,
+        pub created_at_ms: i64,
+        pub last_used_at_ms: Option<i64>,
+    }
+}
+
+pub(crate) async fn list(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Vec<list::Passkey>> {
+    sqlx::query_as!(
+        list::Passkey,
+        r#"
+        SELECT
+            id,
+            name,
+            (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT as "created_at_ms!",
+            (EXTRACT(EPOCH FROM last_used_at) * 1000)::BIGINT as last_used_at_ms
+        FROM passkeys
+        WHERE user_id = $1
+        ORDER BY id
+        "#,
+        user_id.0,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+pub(crate) mod create {
+    use super::list::Passkey;
+
+    pub(crate) enum Output {
+        Success(Passkey),
+        /// The credential is registered already, by this user or another one
+        AlreadyRegistered,
+    }
+}
+
+pub(crate) async fn create(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    credential_id: &[u8],
+    public_key: &[u8],
+    sign_count: u32,
+    name: &str,
+) -> sqlx::Result<create::Output> {
+    let res = sqlx::query_as!(
+        list::Passkey,
+        r#"
+        INSERT INTO passkeys (user_id, credential_id, public_key, sign_count, name)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (credential_id) DO NOTHING
+        RETURNING
+            id,
+            name,
+            (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT as "created_at_ms!",
+            (EXTRACT(EPOCH FROM last_used_at) * 1000)::BIGINT as last_used_at_ms
+        "#,
+        user_id.0,
+        credential_id,
+        public_key,
+        i64::from(sign_count),
+        name,
+    )
+    .fetch_optional(pg_pool)
+    .await?;
+
+    let output = match res {
+        Some(passkey) => create::Output::Success(passkey),
+        None => create::Output::AlreadyRegistered,
+    };
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) finished: registered a passkey for user with ID {user_id}: {registered}",
+        mod_path = module_path!(),
+        fn_name = stringify!(create),
+        registered = matches!(output, create::Output::Success(_)),
+    );
+
+    Ok(output)
+}
+
+/// Deletes the passkey of the user. Returns `false` if the user has no such passkey.
+pub(crate) async fn delete(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    passkey_id: i32,
+) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+        DELETE FROM passkeys
+        WHERE id = $1 AND user_id = $2
+        "#,
+        passkey_id,
+        user_id.0,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+pub(crate) mod find {
+    use crate::db::id::UserId;
+    use crate::db::user::Role;
+
+    pub(crate) struct Credential {
+        pub id: i32,
+        pub user_id: UserId,
+        pub role: Role,
+        /// In the COSE_Key format
+        pub public_key: Vec<u8>,
+        pub sign_count: i64,
+    }
+}
+
+/// The passkey with the credential ID and its owner, if any.
+pub(crate) async fn find(
+    pg_pool: &sqlx::PgPool,
+    credential_id: &[u8],
+) -> sqlx::Result<Option<find::Credential>> {
+    sqlx::query_as!(
+        find::Credential,
+        r#"
+        SELECT
+            passkeys.id,
+            passkeys.user_id as "user_id: UserId",
+            users.role as "role: Role",
+            passkeys.public_key,
+            passkeys.sign_count
+        FROM passkeys
+        JOIN users ON users.id = passkeys.user_id
+        WHERE passkeys.credential_id = $1
+        "#,
+        credential_id,
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
+
+/// Stores the signature counter of the login, unless another login with the same counter
+/// got there first. Returns `false` in that case.
+pub(crate) async fn record_use(
+    pg_pool: &sqlx::PgPool,
+    passkey_id: i32,
+    old_sign_count: i64,
+    new_sign_count: u32,
+) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE passkeys
+        SET sign_count = $3, last_used_at = NOW()
+        WHERE id = $1 AND sign_count = $2
+        "#,
+        passkey_id,
+        old_sign_count,
+        i64::from(new_sign_count),
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
@@ -0,0 +1,200 @@
+use tracing::trace;
+
+use crate::db::id::UserId;
+use crate::db::outbox::enqueue::NewMail;
+use crate::db::user::PHCString;
+
+/// Stores the reset token for the user with the verified email address `email`, if any,
+/// and enqueues the mail delivering it.
+///
+/// No token is issued if the user was sent one less than `throttle` ago, so the endpoint
+/// can't be used to flood someone's inbox. Returns whether the token was issued.
+pub(crate) async fn create(
+    pg_pool: &sqlx::PgPool,
+    email: &str,
+    token_hash: &str,
+    ttl: chrono::Duration,
+    throttle: chrono::Duration,
+    mail: &NewMail,
+) -> sqlx::Result<bool> {
+    let mut tx = pg_pool.begin().await?;
+
+    let user_id: Option<UserId> = sqlx::query_scalar!(
+        r#"
+        INSERT INTO password_reset_tokens (user_id, token_hash, expires_at)
+        SELECT id, $2, NOW() + make_interval(secs => $3)
+        FROM users
+        WHERE email = $1
+            AND email_verified_at IS NOT NULL
+            AND NOT EXISTS (
+                SELECT 1
+                FROM password_reset_tokens
+                WHERE user_id = users.id AND created_at > NOW() - make_interval(secs => $4)
+            )
+        RETURNING user_id as "user_id: UserId"
+        "#,
+        email,
+        token_hash,
+        ttl.num_seconds() as f64,
+        throttle.num_seconds() as f64,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(user_id) = user_id else {
+        tx.rollback().await?;
+        return Ok(false);
+    };
+
+    sqlx::query!(
+        r#"
+        DELETE FROM password_reset_tokens
+        WHERE user_id = $1 AND expires_at <= NOW()
+        "#,
+        user_id.0,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    crate::db::outbox::enqueue(&mut tx, mail).await?;
+    tx.commit().await?;
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) succeeded: issued a password reset token for user with ID {user_id}",
+        mod_path = module_path!(),
+        fn_name = stringify!(create),
+    );
+
+    Ok(true)
+}
+
+/// Requires the user to reset the password before logging in with it, revokes their sessions,
+/// stores the reset token and enqueues the mail delivering it, regardless of the throttle.
+///
+/// Returns the moment of the revocation, see [`crate::db::revocation::revoke_all_sessions`],
+/// or `None` if the user doesn't exist.
+pub(crate) async fn force(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    token_hash: &str,
+    ttl: chrono::Duration,
+    mail: &NewMail,
+) -> sqlx::Result<Option<i64>> {
+    let mut tx = pg_pool.begin().await?;
+
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_reset_required = TRUE
+        WHERE id = $1
+        "#,
+        user_id.0,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        tx.rollback().await?;
+        return Ok(None);
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (user_id, token_hash, expires_at)
+        VALUES ($1, $2, NOW() + make_interval(secs => $3))
+        "#,
+        user_id.0,
+        token_hash,
+        ttl.num_seconds() as f64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    crate::db::outbox::enqueue(&mut tx, mail).await?;
+    let revoked_at_ms = crate::db::revocation::revoke_all_sessions_in(&mut tx, user_id).await?;
+    tx.commit().await?;
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) succeeded: forced a password reset for user with ID {user_id}",
+        mod_path = module_path!(),
+        fn_name = stringify!(force),
+    );
+
+    Ok(Some(revoked_at_ms))
+}
+
+pub(crate) mod redeem {
+    use crate::db::id::UserId;
+
+    pub(crate) struct Redeemed {
+        pub user_id: UserId,
+        /// See [`crate::db::revocation::revoke_all_sessions`]
+        pub sessions_revoked_at_ms: i64,
+    }
+}
+
+/// Consumes the reset token, sets the new password and revokes every session of the user.
+///
+/// Returns `None` if the token is unknown, expired or already used.
+pub(crate) async fn redeem(
+    pg_pool: &sqlx::PgPool,
+    token_hash: &str,
+    password_hash: &PHCString,
+    client_salt: &str,
+) -> sqlx::Result<Option<redeem::Redeemed>> {
+    let mut tx = pg_pool.begin().await?;
+
+    let user_id: Option<UserId> = sqlx::query_scalar!(
+        r#"
+        DELETE FROM password_reset_tokens
+        WHERE token_hash = $1 AND expires_at > NOW()
+        RETURNING user_id as "user_id: UserId"
+        "#,
+        token_hash,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(user_id) = user_id else {
+        tx.rollback().await?;
+        return Ok(None);
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET
+            password_hash = $2,
+            client_salt = $3,
+            legacy_password_hash = FALSE,
+            password_reset_required = FALSE
+        WHERE id = $1
+        "#,
+        user_id.0,
+        password_hash.0,
+        client_salt,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // The other links sent before the reset must not work after it.
+    sqlx::query!(
+        r#"
+        DELETE FROM password_reset_tokens
+        WHERE user_id = $1
+        "#,
+        user_id.0,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let sessions_revoked_at_ms =
+        crate::db::revocation::revoke_all_sessions_in(&mut tx, user_id).await?;
+
+    tx.commit().await?;
+
+    Ok(Some(redeem::Redeemed {
+        user_id,
+        sessions_revoked_at_ms,
+    }))
+}
@@ -0,0 +1,222 @@
+use tracing::trace;
+
+use crate::db::id::UserId;
+use crate::db::user::Role;
+
+#[derive(sqlx::Type, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "personal_access_token_scope")]
+#[sqlx(rename_all = "snake_case")]
+pub(crate) enum Scope {
+    AvatarWrite,
+    GamesRead,
+    ProfileRead,
+}
+
+impl From<shared_items_lib::service_responses::PersonalAccessTokenScope> for Scope {
+    fn from(value: shared_items_lib::service_responses::PersonalAccessTokenScope) -> Self {
+        use shared_items_lib::service_responses::PersonalAccessTokenScope;
+        match value {
+            PersonalAccessTokenScope::AvatarWrite => Scope::AvatarWrite,
+            PersonalAccessTokenScope::GamesRead => Scope::GamesRead,
+            PersonalAccessTokenScope::ProfileRead => Scope::ProfileRead,
+        }
+    }
+}
+
+impl From<Scope> for shared_items_lib::service_responses::PersonalAccessTokenScope {
+    fn from(value: Scope) -> Self {
+        use shared_items_lib::service_responses::PersonalAccessTokenScope;
+        match value {
+            Scope::AvatarWrite => PersonalAccessTokenScope::AvatarWrite,
+            Scope::GamesRead => PersonalAccessTokenScope::GamesRead,
+            Scope::ProfileRead => PersonalAccessTokenScope::ProfileRead,
+        }
+    }
+}
+
+pub(crate) mod list {
+    use super::Scope;
+
+    pub(crate) struct Token {
+        pub id: i32,
+        pub name: String,
+        pub scopes: Vec<Scope>,
+        pub created_at_ms: i64,
+        pub last_used_at_ms: Option<i64>,
+        pub expires_at_ms: Option<i64>,
+    }
+}
+
+/// The tokens of the user, including the expired ones.
+pub(crate) async fn list(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Vec<list::Token>> {
+    sqlx::query_as!(
+        list::Token,
+        r#"
+        SELECT
+            id,
+            name,
+            scopes as "scopes: Vec<Scope>",
+            (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT as "created_at_ms!",
+            (EXTRACT(EPOCH FROM last_used_at) * 1000)::BIGINT as last_used_at_ms,
+            (EXTRACT(EPOCH FROM expires_at) * 1000)::BIGINT as expires_at_ms
+        FROM personal_access_tokens
+        WHERE user_id = $1
+        ORDER BY id
+        "#,
+        user_id.0,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+pub(crate) mod create {
+    use super::list::Token;
+
+    pub(crate) enum Output {
+        Success(Token),
+        /// The user has `max_tokens` tokens already
+        TooManyTokens,
+    }
+}
+
+/// Stores the token, unless the user has `max_tokens` of them already.
+pub(crate) async fn create(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    token_hash: &str,
+    name: &str,
+    scopes: &[Scope],
+    expires_at_ms: Option<i64>,
+    max_tokens: i64,
+) -> sqlx::Result<create::Output> {
+    let mut tx = pg_pool.begin().await?;
+
+    // Serializes the concurrent creations by the same user, so that they can't exceed the limit.
+    sqlx::query!(
+        r#"
+        SELECT id
+        FROM users
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        user_id.0,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let count: i64 = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM personal_access_tokens
+        WHERE user_id = $1
+        "#,
+        user_id.0,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if count >= max_tokens {
+        tx.rollback().await?;
+        return Ok(create::Output::TooManyTokens);
+    }
+
+    let token = sqlx::query_as!(
+        list::Token,
+        r#"
+        INSERT INTO personal_access_tokens (user_id, token_hash, name, scopes, expires_at)
+        VALUES ($1, $2, $3, $4, to_timestamp($5::BIGINT / 1000.0))
+        RETURNING
+            id,
+            name,
+            scopes as "scopes: Vec<Scope>",
+            (EXTRACT(EPOCH FROM created_at) * 1000)::BIGINT as "created_at_ms!",
+            (EXTRACT(EPOCH FROM last_used_at) * 1000)::BIGINT as last_used_at_ms,
+            (EXTRACT(EPOCH FROM expires_at) * 1000)::BIGINT as expires_at_ms
+        "#,
+        user_id.0,
+        token_hash,
+        name,
+        scopes as &[Scope],
+        expires_at_ms,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) succeeded: created the personal access token with ID {token_id} for user with ID {user_id}",
+        mod_path = module_path!(),
+        fn_name = stringify!(create),
+        token_id = token.id,
+    );
+
+    Ok(create::Output::Success(token))
+}
+
+/// Deletes the token of the user. Returns `false` if the user has no such token.
+pub(crate) async fn delete(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    token_id: i32,
+) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+        DELETE FROM personal_access_tokens
+        WHERE id = $1 AND user_id = $2
+        "#,
+        token_id,
+        user_id.0,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+pub(crate) mod authenticate {
+    use crate::db::id::UserId;
+    use crate::db::user::Role;
+
+    use super::Scope;
+
+    pub(crate) struct Token {
+        pub id: i32,
+        pub user_id: UserId,
+        pub role: Role,
+        pub scopes: Vec<Scope>,
+        pub created_at_ms: i64,
+        pub expires_at_ms: Option<i64>,
+    }
+}
+
+/// The unexpired token with the hash and its owner, if any, recording that it's used.
+pub(crate) async fn authenticate(
+    pg_pool: &sqlx::PgPool,
+    token_hash: &str,
+) -> sqlx::Result<Option<authenticate::Token>> {
+    sqlx::query_as!(
+        authenticate::Token,
+        r#"
+        UPDATE personal_access_tokens
+        SET last_used_at = NOW()
+        FROM users
+        WHERE personal_access_tokens.token_hash = $1
+            AND users.id = personal_access_tokens.user_id
+            AND (personal_access_tokens.expires_at IS NULL
+                OR personal_access_tokens.expires_at > NOW())
+        RETURNING
+            personal_access_tokens.id,
+            personal_access_tokens.user_id as "user_id: UserId",
+            users.role as "role: Role",
+            personal_access_tokens.scopes as "scopes: Vec<Scope>",
+            (EXTRACT(EPOCH FROM personal_access_tokens.created_at) * 1000)::BIGINT as "created_at_ms!",
+            (EXTRACT(EPOCH FROM personal_access_tokens.expires_at) * 1000)::BIGINT as expires_at_ms
+        "#,
+        token_hash,
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
@@ -0,0 +1,199 @@
+use tracing::trace;
+
+use crate::db::id::UserId;
+
+pub(crate) async fn revoke_access_token(
+    pg_pool: &sqlx::PgPool,
+    jti: &str,
+    user_id: UserId,
+    expires_at_ms: i64,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO revoked_access_tokens (jti, user_id, expires_at)
+        VALUES ($1, $2, to_timestamp($3::BIGINT / 1000.0))
+        ON CONFLICT (jti) DO NOTHING
+        "#,
+        jti,
+        user_id.0,
+        expires_at_ms,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+/// Revokes the token family that the refresh token belongs to.
+///
+/// Only the families of `user_id` are affected, so a leaked refresh token of another user
+/// can't be used to log them out.
+pub(crate) async fn revoke_session_family(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    refresh_token_hash: &str,
+) -> sqlx::Result<()> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE sessions
+        SET revoked_at = NOW()
+        WHERE revoked_at IS NULL
+            AND user_id = $1
+            AND family_id = (SELECT family_id FROM sessions WHERE refresh_token_hash = $2)
+        "#,
+        user_id.0,
+        refresh_token_hash,
+    )
+    .execute(pg_pool)
+    .await?;
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) succeeded: revoked {n} sessions of user with ID {user_id}",
+        mod_path = module_path!(),
+        fn_name = stringify!(revoke_session_family),
+        n = res.rows_affected(),
+    );
+
+    Ok(())
+}
+
+/// Revokes every session of the user and every access token issued so far, and deletes the
+/// personal access tokens of the user.
+///
+/// Returns the moment of the revocation as a UNIX timestamp in milliseconds. It's taken from
+/// [`crate::util::now`] rather than `NOW()`, like the `iat` of the access tokens, so that
+/// [`crate::revocation::RevocationCache::is_revoked`] compares the readings of the same clock.
+pub(crate) async fn revoke_all_sessions(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<i64> {
+    let mut tx = pg_pool.begin().await?;
+    let revoked_at_ms = revoke_all_sessions_in(&mut tx, user_id).await?;
+    tx.commit().await?;
+    Ok(revoked_at_ms)
+}
+
+/// [`revoke_all_sessions`] as a part of the caller's transaction, e.g. of a password change.
+pub(crate) async fn revoke_all_sessions_in(
+    conn: &mut sqlx::PgConnection,
+    user_id: UserId,
+) -> sqlx::Result<i64> {
+    sqlx::query!(
+        r#"
+        UPDATE sessions
+        SET revoked_at = NOW()
+        WHERE revoked_at IS NULL AND user_id = $1
+        "#,
+        user_id.0,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM personal_access_tokens
+        WHERE user_id = $1
+        "#,
+        user_id.0,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let revoked_at_ms = crate::util::now().0 as i64;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET sessions_revoked_at = to_timestamp($2::BIGINT / 1000.0)
+        WHERE id = $1
+        "#,
+        user_id.0,
+        revoked_at_ms,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(revoked_at_ms)
+}
+
+pub(crate) mod load {
+    use crate::db::id::UserId;
+
+    pub(crate) struct SessionsRevokedAt {
+        pub user_id: UserId,
+        pub revoked_at_ms: i64,
+    }
+
+    pub(crate) struct Restricted {
+        pub user_id: UserId,
+        /// `None` for a ban that doesn't expire
+        pub restricted_until_ms: Option<i64>,
+    }
+
+    pub(crate) struct Snapshot {
+        pub jtis: Vec<String>,
+        pub sessions_revoked_at: Vec<SessionsRevokedAt>,
+        pub restricted: Vec<Restricted>,
+    }
+}
+
+/// Loads every revocation that can still affect a non-expired access token, along with the
+/// users whose access tokens are rejected while they're suspended or banned.
+pub(crate) async fn load(
+    pg_pool: &sqlx::PgPool,
+    access_token_ttl: chrono::Duration,
+) -> sqlx::Result<load::Snapshot> {
+    let jtis: Vec<String> = sqlx::query_scalar!(
+        r#"
+        SELECT jti
+        FROM revoked_access_tokens
+        WHERE expires_at > NOW()
+        "#,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    let sessions_revoked_at = sqlx::query_as!(
+        load::SessionsRevokedAt,
+        r#"
+        SELECT
+            id as "user_id!: UserId",
+            (EXTRACT(EPOCH FROM sessions_revoked_at) * 1000)::BIGINT as "revoked_at_ms!"
+        FROM users
+        WHERE sessions_revoked_at > NOW() - make_interval(secs => $1)
+        "#,
+        access_token_ttl.num_seconds() as f64,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    let restricted = sqlx::query_as!(
+        load::Restricted,
+        r#"
+        SELECT
+            id as "user_id!: UserId",
+            (EXTRACT(EPOCH FROM restricted_until) * 1000)::BIGINT as restricted_until_ms
+        FROM users
+        WHERE restriction IS NOT NULL
+            AND (restricted_until IS NULL OR restricted_until > NOW())
+        "#,
+    )
+    .fetch_all(pg_pool)
+    .await?;
+
+    Ok(load::Snapshot {
+        jtis,
+        sessions_revoked_at,
+        restricted,
+    })
+}
+
+pub(crate) async fn prune_expired(pg_pool: &sqlx::PgPool) -> sqlx::Result<u64> {
+    let res = sqlx::query!(
+        r#"
+        DELETE FROM revoked_access_tokens
+        WHERE expires_at <= NOW()
+        "#,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected())
+}
@@ -0,0 +1,223 @@
+use tracing::{error, trace, warn};
+
+use crate::db::id::UserId;
+use crate::db::user::Role;
+
+pub(crate) async fn create(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    refresh_token_hash: &str,
+    ttl: chrono::Duration,
+    second_factor: bool,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO sessions (user_id, refresh_token_hash, expires_at, second_factor)
+        VALUES ($1, $2, NOW() + make_interval(secs => $3), $4)
+        "#,
+        user_id.0,
+        refresh_token_hash,
+        ttl.num_seconds() as f64,
+        second_factor,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
+}
+
+pub(crate) mod rotate {
+    use crate::db::id::UserId;
+    use crate::db::user::Role;
+
+    pub(crate) struct Session {
+        pub id: i32,
+        pub user_id: UserId,
+        pub role: Role,
+        pub second_factor: bool,
+        pub rotated: bool,
+        /// Whether the token was rotated within the reuse grace period
+        pub rotated_recently: bool,
+        pub successor_seed: Option<String>,
+        pub revoked: bool,
+        pub expired: bool,
+    }
+
+    pub(crate) enum Output {
+        /// The successor is derived from the presented token and `successor_seed`,
+        /// see [`crate::token::successor`].
+        Rotated {
+            user_id: UserId,
+            role: Role,
+            second_factor: bool,
+            successor_seed: String,
+        },
+        /// A token that had already been rotated was presented again,
+        /// so its whole family has been revoked.
+        Reused {
+            user_id: UserId,
+        },
+        Invalid,
+        DbError {
+            err: sqlx::Error,
+        },
+    }
+}
+
+async fn try_rotate(
+    pg_pool: &sqlx::PgPool,
+    refresh_token_hash: &str,
+    successor_seed: &str,
+    successor_hash: &str,
+    ttl: chrono::Duration,
+    reuse_grace: chrono::Duration,
+) -> sqlx::Result<rotate::Output> {
+    let mut tx = pg_pool.begin().await?;
+
+    // `FOR UPDATE` serializes concurrent attempts to rotate the same token.
+    let session = sqlx::query_as!(
+        rotate::Session,
+        r#"
+        SELECT
+            sessions.id,
+            sessions.user_id as "user_id!: UserId",
+            users.role as "role!: Role",
+            sessions.second_factor,
+            sessions.rotated_at IS NOT NULL as "rotated!",
+            sessions.rotated_at > NOW() - make_interval(secs => $2) as "rotated_recently!",
+            sessions.successor_seed,
+            sessions.revoked_at IS NOT NULL as "revoked!",
+            sessions.expires_at <= NOW() as "expired!"
+        FROM sessions
+        JOIN users ON users.id = sessions.user_id
+        WHERE sessions.refresh_token_hash = $1
+        FOR UPDATE OF sessions
+        "#,
+        refresh_token_hash,
+        reuse_grace.num_seconds() as f64,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(session) = session else {
+        return Ok(rotate::Output::Invalid);
+    };
+
+    if session.revoked || session.expired {
+        return Ok(rotate::Output::Invalid);
+    }
+
+    // E.g. parallel requests or browser tabs that all found the access token expired
+    if let (true, Some(successor_seed)) = (session.rotated_recently, session.successor_seed) {
+        return Ok(rotate::Output::Rotated {
+            user_id: session.user_id,
+            role: session.role,
+            second_factor: session.second_factor,
+            successor_seed,
+        });
+    }
+
+    if session.rotated {
+        sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET revoked_at = NOW()
+            WHERE revoked_at IS NULL
+                AND family_id = (SELECT family_id FROM sessions WHERE id = $1)
+            "#,
+            session.id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        return Ok(rotate::Output::Reused {
+            user_id: session.user_id,
+        });
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE sessions
+        SET rotated_at = NOW(), successor_seed = $2
+        WHERE id = $1
+        "#,
+        session.id,
+        successor_seed,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO sessions (user_id, family_id, refresh_token_hash, expires_at, second_factor)
+        SELECT user_id, family_id, $2, NOW() + make_interval(secs => $3), second_factor
+        FROM sessions
+        WHERE id = $1
+        "#,
+        session.id,
+        successor_hash,
+        ttl.num_seconds() as f64,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(rotate::Output::Rotated {
+        user_id: session.user_id,
+        role: session.role,
+        second_factor: session.second_factor,
+        successor_seed: successor_seed.to_string(),
+    })
+}
+
+/// Marks the session identified by `refresh_token_hash` as rotated and starts a new session
+/// in the same family identified by `successor_hash`.
+///
+/// If the token was rotated less than `reuse_grace` ago, the seed of its existing successor is
+/// returned instead, and only a later presentation counts as a reuse.
+pub(crate) async fn rotate(
+    pg_pool: &sqlx::PgPool,
+    refresh_token_hash: &str,
+    successor_seed: &str,
+    successor_hash: &str,
+    ttl: chrono::Duration,
+    reuse_grace: chrono::Duration,
+) -> rotate::Output {
+    let output = try_rotate(
+        pg_pool,
+        refresh_token_hash,
+        successor_seed,
+        successor_hash,
+        ttl,
+        reuse_grace,
+    )
+    .await
+    .unwrap_or_else(|err| rotate::Output::DbError { err });
+
+    match &output {
+        rotate::Output::Rotated { user_id, .. } => trace!(
+            "The function {mod_path}::{fn_name}(...) succeeded: rotated the refresh token of user with ID {user_id}",
+            mod_path = module_path!(),
+            fn_name = stringify!(rotate),
+        ),
+        rotate::Output::Reused { user_id } => warn!(
+            "The function {mod_path}::{fn_name}(...) failed: a rotated refresh token of user with ID {user_id} \
+            was reused, the token family has been revoked",
+            mod_path = module_path!(),
+            fn_name = stringify!(rotate),
+        ),
+        rotate::Output::Invalid => trace!(
+            "The function {mod_path}::{fn_name}(...) failed: unknown, expired or revoked refresh token",
+            mod_path = module_path!(),
+            fn_name = stringify!(rotate),
+        ),
+        rotate::Output::DbError { err } => error!(
+            "The function {mod_path}::{fn_name}(...) failed: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(rotate),
+            err = err,
+        ),
+    };
+
+    output
+}
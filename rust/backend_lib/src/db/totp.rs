@@ -0,0 +1,197 @@
+use tracing::trace;
+
+use crate::db::id::UserId;
+use crate::db::user::Role;
+
+pub(crate) mod get {
+    use crate::db::user::Role;
+
+    pub(crate) struct Totp {
+        pub username: String,
+        pub role: Role,
+        /// Set since the user started enrolling
+        pub secret: Option<Vec<u8>>,
+        /// Whether the enrollment is confirmed, so the second factor is required
+        pub enabled: bool,
+    }
+}
+
+pub(crate) async fn get(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Option<get::Totp>> {
+    sqlx::query_as!(
+        get::Totp,
+        r#"
+        SELECT
+            username,
+            role as "role: Role",
+            totp_secret as secret,
+            totp_enabled_at IS NOT NULL as "enabled!"
+        FROM users
+        WHERE id = $1
+        "#,
+        user_id.0,
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
+
+/// Stores the secret of a new enrollment, replacing the unconfirmed one, if any.
+///
+/// Returns `false` if the user doesn't exist or has already enabled TOTP.
+pub(crate) async fn begin_enrollment(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    secret: &[u8],
+) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $2
+        WHERE id = $1 AND totp_enabled_at IS NULL
+        "#,
+        user_id.0,
+        secret,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+/// Confirms the enrollment with the code of `step` and replaces the recovery codes.
+///
+/// Returns `false` if the user isn't enrolling.
+pub(crate) async fn enable(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    step: i64,
+    recovery_code_hashes: &[String],
+) -> sqlx::Result<bool> {
+    let mut tx = pg_pool.begin().await?;
+
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_enabled_at = NOW(), totp_last_used_step = $2
+        WHERE id = $1 AND totp_secret IS NOT NULL AND totp_enabled_at IS NULL
+        "#,
+        user_id.0,
+        step,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        tx.rollback().await?;
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"
+        DELETE FROM totp_recovery_codes
+        WHERE user_id = $1
+        "#,
+        user_id.0,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO totp_recovery_codes (user_id, code_hash)
+        SELECT $1, code_hash
+        FROM UNNEST($2::TEXT[]) AS code_hash
+        "#,
+        user_id.0,
+        recovery_code_hashes,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) succeeded: enabled TOTP for user with ID {user_id}",
+        mod_path = module_path!(),
+        fn_name = stringify!(enable),
+    );
+
+    Ok(true)
+}
+
+/// Accepts the code of `step` unless a code of the same or a later step was accepted before.
+pub(crate) async fn use_step(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    step: i64,
+) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_last_used_step = $2
+        WHERE id = $1 AND (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+        "#,
+        user_id.0,
+        step,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+/// Marks the recovery code as used. Returns `false` if it's unknown or already used.
+pub(crate) async fn use_recovery_code(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    code_hash: &str,
+) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE totp_recovery_codes
+        SET used_at = NOW()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id.0,
+        code_hash,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected() == 1)
+}
+
+/// Forgets the secret and the recovery codes, so the second factor is no longer required.
+pub(crate) async fn disable(pg_pool: &sqlx::PgPool, user_id: UserId) -> sqlx::Result<()> {
+    let mut tx = pg_pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_used_step = NULL
+        WHERE id = $1
+        "#,
+        user_id.0,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM totp_recovery_codes
+        WHERE user_id = $1
+        "#,
+        user_id.0,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) succeeded: disabled TOTP for user with ID {user_id}",
+        mod_path = module_path!(),
+        fn_name = stringify!(disable),
+    );
+
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use tracing::{error, trace};
 
+use crate::avatar;
 use crate::db::id::UserId;
 
 #[derive(sqlx::Type)]
@@ -28,6 +29,31 @@ impl From<Role> for shared_items_lib::Role {
     }
 }
 
+/// See <https://docs.rs/argon2/0.5.3/argon2/struct.PasswordHash.html>
+#[derive(sqlx::Type, derive_more::Display)]
+#[sqlx(transparent)]
+pub(crate) struct PHCString(pub(in crate::db) String);
+
+impl PHCString {
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<'a> TryFrom<&'a PHCString> for argon2::PasswordHash<'a> {
+    type Error = argon2::password_hash::Error;
+
+    fn try_from(value: &'a PHCString) -> Result<Self, Self::Error> {
+        argon2::PasswordHash::new(&value.0)
+    }
+}
+
+impl From<argon2::PasswordHash<'_>> for PHCString {
+    fn from(value: argon2::PasswordHash<'_>) -> Self {
+        PHCString(value.to_string())
+    }
+}
+
 pub(crate) mod register {
     use crate::db::id::UserId;
 
@@ -54,19 +80,24 @@ pub(crate) mod register {
     }
 }
 
+/// Registers the user, unless `username` or `username_normalized` is taken.
 pub(crate) async fn register(
     pg_pool: &sqlx::PgPool,
     username: &str,
-    password_hash: &str,
+    username_normalized: &str,
+    password_hash: &PHCString,
+    client_salt: &str,
 ) -> register::Output {
     let res: sqlx::Result<UserId> = sqlx::query_scalar!(
         r#"
-        INSERT INTO users (username, password_hash)
-        VALUES ($1, $2)
+        INSERT INTO users (username, username_normalized, password_hash, client_salt)
+        VALUES ($1, $2, $3, $4)
         RETURNING id as "id!: UserId"
         "#,
         username,
-        password_hash,
+        username_normalized,
+        password_hash.0,
+        client_salt,
     )
     .fetch_one(pg_pool)
     .await;
@@ -95,159 +126,676 @@ pub(crate) async fn register(
     output
 }
 
-pub(crate) mod check_credentials {
+pub(crate) mod get_credentials {
     use crate::db::id::UserId;
 
-    use super::Role;
+    use super::{PHCString, Role};
 
-    pub(crate) struct User {
+    pub(crate) struct Credentials {
         pub id: UserId,
         pub role: Role,
-        pub password_hash: String,
-    }
-
-    pub(crate) enum Output {
-        Valid { user_id: UserId, role: Role },
-        WrongPassword,
-        UserNotFound,
-        DbError { err: sqlx::Error },
+        pub password_hash: PHCString,
+        /// Whether `password_hash` is a client-side hash stored verbatim
+        /// (see the `server_side_password_hashing` migration).
+        pub legacy_password_hash: bool,
+        /// See [`crate::db::password_reset::force`]
+        pub password_reset_required: bool,
     }
 }
 
-pub(crate) async fn check_credentials(
+/// Looks the user up by the [`crate::username::normalize`]d username, or by the exact `username`
+/// for the accounts left without a normalized one, see [`set_username_normalized`].
+pub(crate) async fn get_credentials(
     pg_pool: &sqlx::PgPool,
     username: &str,
-    password_hash: &str,
-) -> check_credentials::Output {
+    username_normalized: &str,
+) -> sqlx::Result<Option<get_credentials::Credentials>> {
     let res = sqlx::query_as!(
-        check_credentials::User,
+        get_credentials::Credentials,
         r#"
-        SELECT id as "id!: UserId", password_hash, role as "role!: Role"
+        SELECT
+            id as "id!: UserId",
+            role as "role!: Role",
+            password_hash as "password_hash!: PHCString",
+            legacy_password_hash,
+            password_reset_required
         FROM users
-        WHERE username = $1
+        WHERE username_normalized = $1 OR (username_normalized IS NULL AND username = $2)
+        ORDER BY username_normalized IS NULL DESC
+        LIMIT 1
         "#,
+        username_normalized,
         username,
     )
     .fetch_optional(pg_pool)
     .await;
 
-    let output = match res {
-        Ok(Some(check_credentials::User {
-            id: user_id,
-            password_hash: stored_hash,
-            role,
-        })) if stored_hash == password_hash => check_credentials::Output::Valid { user_id, role },
-        Ok(Some(check_credentials::User {
-            id: user_id,
-            password_hash: stored_hash,
-            role: _,
-        })) => {
-            error!(
-                "The function {mod_path}::{fn_name}(...) failed: wrong password for user with ID {user_id}.\n\
-                Provided hash: {password_hash}\n\
-                Stored hash:   {stored_hash}",
-                mod_path = module_path!(),
-                fn_name = stringify!(check_credentials),
-            );
-            check_credentials::Output::WrongPassword
-        }
-        Ok(None) => check_credentials::Output::UserNotFound,
-        Err(err) => check_credentials::Output::DbError { err },
-    };
-
-    match &output {
-        check_credentials::Output::Valid { user_id, role: _ } => trace!(
-            "The function {mod_path}::{fn_name}(...) succeeded: valid credentials for user ID {user_id}",
+    match &res {
+        Ok(Some(credentials)) => trace!(
+            "The function {mod_path}::{fn_name}(...) succeeded: found credentials for user ID {user_id}",
             mod_path = module_path!(),
-            fn_name = stringify!(check_credentials),
+            fn_name = stringify!(get_credentials),
+            user_id = credentials.id,
         ),
-        check_credentials::Output::WrongPassword => error!(
-            "The function {mod_path}::{fn_name}(...) failed: wrong password",
+        Ok(None) => trace!(
+            "The function {mod_path}::{fn_name}(...) succeeded: user not found",
             mod_path = module_path!(),
-            fn_name = stringify!(check_credentials),
+            fn_name = stringify!(get_credentials),
         ),
-        check_credentials::Output::UserNotFound => error!(
-            "The function {mod_path}::{fn_name}(...) failed: user not found",
-            mod_path = module_path!(),
-            fn_name = stringify!(check_credentials),
-        ),
-        check_credentials::Output::DbError { err } => error!(
+        Err(err) => error!(
             "The function {mod_path}::{fn_name}(...) failed: {err}",
             mod_path = module_path!(),
-            fn_name = stringify!(check_credentials),
+            fn_name = stringify!(get_credentials),
             err = err,
         ),
     };
 
-    output
+    res
 }
 
-pub(crate) mod get_password_hash {
-    /// See <https://docs.rs/argon2/0.5.3/argon2/struct.PasswordHash.html>
-    #[derive(sqlx::Type, derive_more::Display)]
-    #[sqlx(transparent)]
-    pub(crate) struct PHCString(pub(in crate::db) String);
-
-    impl<'a> TryFrom<&'a PHCString> for argon2::PasswordHash<'a> {
-        type Error = argon2::password_hash::Error;
-
-        fn try_from(value: &'a PHCString) -> Result<Self, Self::Error> {
-            argon2::PasswordHash::new(&value.0)
-        }
-    }
+/// Replaces the stored password hash with a server-side one, e.g. after the Argon2 parameters changed.
+pub(crate) async fn set_password_hash(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    password_hash: &PHCString,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1, legacy_password_hash = FALSE
+        WHERE id = $2
+        "#,
+        password_hash.0,
+        user_id.0,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(())
 }
 
-pub(crate) async fn get_password_hash(
+/// Looks the user up by the [`crate::username::normalize`]d username, or by the exact `username`
+/// for the accounts left without a normalized one, see [`set_username_normalized`].
+pub(crate) async fn get_client_salt(
     pg_pool: &sqlx::PgPool,
     username: &str,
-) -> sqlx::Result<Option<get_password_hash::PHCString>> {
+    username_normalized: &str,
+) -> sqlx::Result<Option<String>> {
     let res: Option<String> = sqlx::query_scalar!(
         r#"
-        SELECT password_hash
+        SELECT client_salt
         FROM users
-        WHERE username = $1
+        WHERE username_normalized = $1 OR (username_normalized IS NULL AND username = $2)
+        ORDER BY username_normalized IS NULL DESC
+        LIMIT 1
         "#,
+        username_normalized,
         username,
     )
     .fetch_optional(pg_pool)
     .await?;
 
-    Ok(res.map(get_password_hash::PHCString))
+    Ok(res)
 }
 
-pub(crate) async fn set_avatar(
+pub(crate) mod without_username_normalized {
+    use crate::db::id::UserId;
+
+    pub(crate) struct User {
+        pub id: UserId,
+        pub username: String,
+    }
+}
+
+/// The users without a normalized username, the oldest first.
+pub(crate) async fn without_username_normalized(
+    pg_pool: &sqlx::PgPool,
+) -> sqlx::Result<Vec<without_username_normalized::User>> {
+    sqlx::query_as!(
+        without_username_normalized::User,
+        r#"
+        SELECT id as "id: UserId", username
+        FROM users
+        WHERE username_normalized IS NULL
+        ORDER BY id
+        "#,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+/// Sets the normalized username of the user who has none, unless another user has it already.
+///
+/// Returns `false` if the user keeps none: the accounts that predate the username policy may
+/// differ only by case, and only the oldest of them gets the normalized username. The rest log
+/// in by their exact usernames.
+pub(crate) async fn set_username_normalized(
     pg_pool: &sqlx::PgPool,
     user_id: UserId,
-    avatar_s3_key: &str,
+    username_normalized: &str,
+) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET username_normalized = $2
+        WHERE id = $1
+            AND username_normalized IS NULL
+            AND NOT EXISTS (SELECT 1 FROM users WHERE username_normalized = $2)
+        "#,
+        user_id.0,
+        username_normalized,
+    )
+    .execute(pg_pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Locks the row of the user until the end of the transaction and reads their avatar storage.
+async fn lock_avatar_storage(
+    conn: &mut sqlx::PgConnection,
+    user_id: UserId,
+) -> sqlx::Result<Option<avatar::Storage>> {
+    sqlx::query_as!(
+        avatar::Storage,
+        r#"
+        SELECT avatar_storage_bytes as storage_bytes, avatar_bytes
+        FROM users
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        user_id.0,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+async fn set_avatar_storage_bytes(
+    conn: &mut sqlx::PgConnection,
+    user_id: UserId,
+    storage_bytes: i64,
 ) -> sqlx::Result<()> {
     sqlx::query!(
         r#"
         UPDATE users
-        SET avatar_s3_key = $1
-        WHERE id = $2
+        SET avatar_storage_bytes = $2
+        WHERE id = $1
         "#,
-        avatar_s3_key,
         user_id.0,
+        storage_bytes,
     )
-    .execute(pg_pool)
+    .execute(conn)
     .await?;
     Ok(())
 }
 
-pub(crate) async fn get_avatar(
+/// Points the user to the avatar stored under `avatar_s3_key`.
+///
+/// The avatar takes `avatar_bytes` with its renditions, and the replaced avatar stops counting
+/// against the storage quota, see [`avatar::Storage::replace`]. Returns the replaced avatar,
+/// whose objects are no longer referenced.
+pub(crate) async fn set_avatar(
     pg_pool: &sqlx::PgPool,
     user_id: UserId,
-) -> sqlx::Result<Option<String>> {
-    let res: Option<String> = sqlx::query_scalar!(
+    avatar_s3_key: &str,
+    has_renditions: bool,
+    avatar_bytes: i64,
+) -> sqlx::Result<Option<get_avatar::Avatar>> {
+    let mut tx = pg_pool.begin().await?;
+
+    let Some(previous) = sqlx::query!(
         r#"
-        SELECT avatar_s3_key
+        SELECT avatar_s3_key, avatar_has_renditions, avatar_storage_bytes, avatar_bytes
         FROM users
         WHERE id = $1
+        FOR UPDATE
         "#,
         user_id.0,
     )
-    .fetch_one(pg_pool)
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let storage = avatar::Storage {
+        storage_bytes: previous.avatar_storage_bytes,
+        avatar_bytes: previous.avatar_bytes,
+    }
+    .replace(avatar_bytes);
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET
+            avatar_s3_key = $2,
+            avatar_has_renditions = $3,
+            avatar_bytes = $4,
+            avatar_storage_bytes = $5
+        WHERE id = $1
+        "#,
+        user_id.0,
+        avatar_s3_key,
+        has_renditions,
+        storage.avatar_bytes,
+        storage.storage_bytes,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let previous = previous.avatar_s3_key.map(|s3_key| get_avatar::Avatar {
+        s3_key,
+        has_renditions: previous.avatar_has_renditions,
+    });
+    Ok(previous)
+}
+
+/// Counts `bytes` more against the avatar storage quota of the user, unless it would exceed
+/// `quota_bytes`, see [`avatar::Storage::reserve`]. Returns whether it fits.
+pub(crate) async fn reserve_avatar_storage(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    bytes: i64,
+    quota_bytes: i64,
+) -> sqlx::Result<bool> {
+    let mut tx = pg_pool.begin().await?;
+    let Some(storage) = lock_avatar_storage(&mut tx, user_id)
+        .await?
+        .and_then(|storage| storage.reserve(bytes, quota_bytes))
+    else {
+        return Ok(false);
+    };
+    set_avatar_storage_bytes(&mut tx, user_id, storage.storage_bytes).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Stops counting `bytes` against the avatar storage quota of the user, e.g. once the objects are
+/// deleted, see [`reserve_avatar_storage`].
+pub(crate) async fn release_avatar_storage(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    bytes: i64,
+) -> sqlx::Result<()> {
+    let mut tx = pg_pool.begin().await?;
+    if let Some(storage) = lock_avatar_storage(&mut tx, user_id).await? {
+        set_avatar_storage_bytes(&mut tx, user_id, storage.release(bytes).storage_bytes).await?;
+    }
+    tx.commit().await
+}
+
+pub(crate) mod get_avatar {
+    pub(crate) struct Avatar {
+        pub s3_key: String,
+        /// Whether the WebP renditions are stored next to the avatar,
+        /// see [`object_storage::avatar_rendition_key`]
+        pub has_renditions: bool,
+    }
+}
+
+pub(crate) async fn get_avatar(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Option<get_avatar::Avatar>> {
+    let res: Option<get_avatar::Avatar> = sqlx::query_as!(
+        get_avatar::Avatar,
+        r#"
+        SELECT avatar_s3_key as "s3_key!", avatar_has_renditions as has_renditions
+        FROM users
+        WHERE id = $1 AND avatar_s3_key IS NOT NULL
+        "#,
+        user_id.0,
+    )
+    .fetch_optional(pg_pool)
     .await?;
 
     Ok(res)
 }
+
+/// Every avatar the users point to, see [`crate::service::avatar_gc`].
+pub(crate) async fn list_avatars(pg_pool: &sqlx::PgPool) -> sqlx::Result<Vec<get_avatar::Avatar>> {
+    sqlx::query_as!(
+        get_avatar::Avatar,
+        r#"
+        SELECT avatar_s3_key as "s3_key!", avatar_has_renditions as has_renditions
+        FROM users
+        WHERE avatar_s3_key IS NOT NULL
+        "#,
+    )
+    .fetch_all(pg_pool)
+    .await
+}
+
+pub(crate) mod get_profile {
+    pub(crate) struct Profile {
+        pub username: String,
+        pub email: Option<String>,
+        pub email_verified: bool,
+        pub lichess_username: Option<String>,
+        pub chess_dot_com_username: Option<String>,
+        pub profile_version: i32,
+        /// UNIX timestamp (ms), see [`crate::db::account_deletion::schedule`]
+        pub deletion_scheduled_at_ms: Option<i64>,
+    }
+}
+
+pub(crate) async fn get_profile(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+) -> sqlx::Result<Option<get_profile::Profile>> {
+    sqlx::query_as!(
+        get_profile::Profile,
+        r#"
+        SELECT
+            username,
+            email,
+            email_verified_at IS NOT NULL as "email_verified!",
+            lichess_username,
+            chess_dot_com_username,
+            profile_version,
+            (EXTRACT(EPOCH FROM deletion_scheduled_at) * 1000)::BIGINT as deletion_scheduled_at_ms
+        FROM users
+        WHERE id = $1
+        "#,
+        user_id.0,
+    )
+    .fetch_optional(pg_pool)
+    .await
+}
+
+/// A single-use email verification token, identified by its `jti`, and the mail delivering it.
+pub(crate) struct EmailVerification {
+    pub jti: String,
+    pub mail: crate::db::outbox::enqueue::NewMail,
+}
+
+pub(crate) mod update_profile {
+    use super::get_profile::Profile;
+
+    pub(crate) struct Row {
+        pub username: String,
+        pub email: Option<String>,
+        pub email_verified: bool,
+        pub lichess_username: Option<String>,
+        pub chess_dot_com_username: Option<String>,
+        pub profile_version: i32,
+        pub deletion_scheduled_at_ms: Option<i64>,
+        pub email_changed: bool,
+    }
+
+    impl From<Row> for Profile {
+        fn from(value: Row) -> Self {
+            Profile {
+                username: value.username,
+                email: value.email,
+                email_verified: value.email_verified,
+                lichess_username: value.lichess_username,
+                chess_dot_com_username: value.chess_dot_com_username,
+                profile_version: value.profile_version,
+                deletion_scheduled_at_ms: value.deletion_scheduled_at_ms,
+            }
+        }
+    }
+
+    /// `None` keeps the current value, `Some(None)` clears it.
+    pub(crate) struct Changes {
+        pub email: Option<Option<String>>,
+        pub lichess_username: Option<Option<String>>,
+        pub chess_dot_com_username: Option<Option<String>>,
+    }
+
+    /// A column with a unique constraint.
+    pub(crate) enum UniqueField {
+        Email,
+        LichessUsername,
+        ChessDotComUsername,
+    }
+
+    impl UniqueField {
+        fn from_constraint(constraint: &str) -> Option<Self> {
+            match constraint {
+                "users_email_key" => Some(Self::Email),
+                "users_lichess_username_key" => Some(Self::LichessUsername),
+                "users_chess_dot_com_username_key" => Some(Self::ChessDotComUsername),
+                _ => None,
+            }
+        }
+    }
+
+    pub(crate) enum Output {
+        Success(Profile),
+        /// The profile has been edited since `expected_version` was read
+        VersionMismatch(Profile),
+        /// The value is already used by another user
+        AlreadyExists {
+            field: UniqueField,
+        },
+        NotFound,
+        UnknownError {
+            err: sqlx::Error,
+        },
+    }
+
+    impl From<sqlx::Error> for Output {
+        fn from(err: sqlx::Error) -> Self {
+            let field = err
+                .as_database_error()
+                .filter(|db_err| matches!(db_err.kind(), sqlx::error::ErrorKind::UniqueViolation))
+                .and_then(|db_err| db_err.constraint())
+                .and_then(UniqueField::from_constraint);
+            match field {
+                Some(field) => Self::AlreadyExists { field },
+                None => Self::UnknownError { err },
+            }
+        }
+    }
+}
+
+async fn try_update_profile(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    expected_version: i32,
+    changes: update_profile::Changes,
+    email_verification: Option<&EmailVerification>,
+) -> sqlx::Result<update_profile::Output> {
+    let update_profile::Changes {
+        email,
+        lichess_username,
+        chess_dot_com_username,
+    } = changes;
+    let (set_email, email) = (email.is_some(), email.flatten());
+    let (set_lichess_username, lichess_username) =
+        (lichess_username.is_some(), lichess_username.flatten());
+    let (set_chess_dot_com_username, chess_dot_com_username) = (
+        chess_dot_com_username.is_some(),
+        chess_dot_com_username.flatten(),
+    );
+
+    let mut tx = pg_pool.begin().await?;
+
+    // A new email address has to be verified again, and the tokens sent to the old one are void.
+    let updated = sqlx::query_as!(
+        update_profile::Row,
+        r#"
+        WITH old AS (
+            SELECT id, email FROM users WHERE id = $1
+        )
+        UPDATE users
+        SET
+            email = CASE WHEN $3 THEN $4 ELSE users.email END,
+            email_verified_at = CASE
+                WHEN $3 AND $4 IS DISTINCT FROM old.email THEN NULL
+                ELSE users.email_verified_at
+            END,
+            email_verification_jti = CASE
+                WHEN $3 AND $4 IS DISTINCT FROM old.email THEN $9
+                ELSE users.email_verification_jti
+            END,
+            lichess_username = CASE WHEN $5 THEN $6 ELSE users.lichess_username END,
+            chess_dot_com_username = CASE WHEN $7 THEN $8 ELSE users.chess_dot_com_username END,
+            profile_version = users.profile_version + 1
+        FROM old
+        WHERE users.id = old.id AND users.profile_version = $2
+        RETURNING
+            users.username,
+            users.email,
+            users.email_verified_at IS NOT NULL as "email_verified!",
+            users.lichess_username,
+            users.chess_dot_com_username,
+            users.profile_version,
+            (EXTRACT(EPOCH FROM users.deletion_scheduled_at) * 1000)::BIGINT as deletion_scheduled_at_ms,
+            ($3 AND $4 IS DISTINCT FROM old.email) as "email_changed!"
+        "#,
+        user_id.0,
+        expected_version,
+        set_email,
+        email,
+        set_lichess_username,
+        lichess_username,
+        set_chess_dot_com_username,
+        chess_dot_com_username,
+        email_verification.map(|verification| verification.jti.as_str()),
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(updated) = updated else {
+        tx.rollback().await?;
+        // Nothing was updated, either because the user is gone or because the version is stale.
+        return Ok(match get_profile(pg_pool, user_id).await? {
+            Some(profile) => update_profile::Output::VersionMismatch(profile),
+            None => update_profile::Output::NotFound,
+        });
+    };
+
+    if updated.email_changed
+        && updated.email.is_some()
+        && let Some(email_verification) = email_verification
+    {
+        crate::db::outbox::enqueue(&mut tx, &email_verification.mail).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(update_profile::Output::Success(updated.into()))
+}
+
+/// Applies the changes if the profile is still at `expected_version`.
+///
+/// If the email address changes to a new one, `email_verification` is started for it.
+pub(crate) async fn update_profile(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    expected_version: i32,
+    changes: update_profile::Changes,
+    email_verification: Option<&EmailVerification>,
+) -> update_profile::Output {
+    let output = try_update_profile(
+        pg_pool,
+        user_id,
+        expected_version,
+        changes,
+        email_verification,
+    )
+    .await
+    .unwrap_or_else(update_profile::Output::from);
+
+    match &output {
+        update_profile::Output::Success(_) => trace!(
+            "The function {mod_path}::{fn_name}(...) succeeded: updated the profile of user with ID {user_id}",
+            mod_path = module_path!(),
+            fn_name = stringify!(update_profile),
+        ),
+        update_profile::Output::VersionMismatch(profile) => trace!(
+            "The function {mod_path}::{fn_name}(...) failed: the profile of user with ID {user_id} is at version {current}, not {expected_version}",
+            mod_path = module_path!(),
+            fn_name = stringify!(update_profile),
+            current = profile.profile_version,
+        ),
+        update_profile::Output::AlreadyExists { field: _ } => trace!(
+            "The function {mod_path}::{fn_name}(...) failed: the value is already used by another user",
+            mod_path = module_path!(),
+            fn_name = stringify!(update_profile),
+        ),
+        update_profile::Output::NotFound => trace!(
+            "The function {mod_path}::{fn_name}(...) failed: no user with ID {user_id}",
+            mod_path = module_path!(),
+            fn_name = stringify!(update_profile),
+        ),
+        update_profile::Output::UnknownError { err } => error!(
+            "The function {mod_path}::{fn_name}(...) failed: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(update_profile),
+            err = err,
+        ),
+    };
+
+    output
+}
+
+/// Replaces the redeemable verification token of `email`, if it is still the unverified
+/// email address of the user, and enqueues the mail delivering the new one.
+///
+/// Returns whether the verification was started.
+pub(crate) async fn start_email_verification(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    email: &str,
+    email_verification: &EmailVerification,
+) -> sqlx::Result<bool> {
+    let mut tx = pg_pool.begin().await?;
+
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET email_verification_jti = $3
+        WHERE id = $1 AND email = $2 AND email_verified_at IS NULL
+        "#,
+        user_id.0,
+        email,
+        email_verification.jti,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if res.rows_affected() == 0 {
+        tx.rollback().await?;
+        return Ok(false);
+    }
+
+    crate::db::outbox::enqueue(&mut tx, &email_verification.mail).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Redeems the verification token identified by `jti`.
+///
+/// Returns `false` if the token was already redeemed or superseded, or if the email address
+/// of the user is no longer `email`.
+pub(crate) async fn verify_email(
+    pg_pool: &sqlx::PgPool,
+    user_id: UserId,
+    email: &str,
+    jti: &str,
+) -> sqlx::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+        UPDATE users
+        SET email_verified_at = NOW(), email_verification_jti = NULL
+        WHERE id = $1 AND email = $2 AND email_verification_jti = $3
+        "#,
+        user_id.0,
+        email,
+        jti,
+    )
+    .execute(pg_pool)
+    .await?;
+
+    trace!(
+        "The function {mod_path}::{fn_name}(...) finished: verified {n} email addresses of user with ID {user_id}",
+        mod_path = module_path!(),
+        fn_name = stringify!(verify_email),
+        n = res.rows_affected(),
+    );
+
+    Ok(res.rows_affected() == 1)
+}
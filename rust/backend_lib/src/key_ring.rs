@@ -0,0 +1,236 @@
+//! Signing and verification keys for the JWTs we issue, built from [`mnln_env::JwtKeyRing`].
+//!
+//! The public halves of the asymmetric keys are published as a JWK Set, so other services
+//! (e.g. the chess-engine broker) can verify the access tokens without holding any secrets.
+//! The registered claims are `NumericDate`s, so any JWT library validates them as we do.
+
+use anyhow::Context as _;
+use base64::Engine as _;
+use ed25519_dalek::pkcs8::DecodePrivateKey as _;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use mnln_env::{JwtAlgorithm, JwtKey};
+
+use crate::util::JwtVerificationError;
+
+struct Key {
+    kid: String,
+    algorithm: Algorithm,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+pub(crate) struct KeyRing {
+    keys: Vec<Key>,
+    active: usize,
+    jwks: JwkSet,
+}
+
+/// The public half of an asymmetric key, `None` for symmetric ones.
+fn public_jwk(key: &JwtKey, encoding: &EncodingKey) -> anyhow::Result<Option<Jwk>> {
+    let common = CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_id: Some(key.kid.clone()),
+        ..Default::default()
+    };
+    let jwk = match key.algorithm {
+        JwtAlgorithm::HS256 => return Ok(None),
+        JwtAlgorithm::ES256 => {
+            let jwk = Jwk::from_encoding_key(encoding, Algorithm::ES256)?;
+            Jwk {
+                common: CommonParameters {
+                    key_algorithm: jwk.common.key_algorithm,
+                    ..common
+                },
+                algorithm: jwk.algorithm,
+            }
+        }
+        // `Jwk::from_encoding_key` doesn't support Ed25519 keys yet.
+        JwtAlgorithm::EdDSA => {
+            let signing_key = ed25519_dalek::SigningKey::from_pkcs8_der(&key.material)
+                .map_err(|err| anyhow::anyhow!("{err}"))?;
+            let x = base64::engine::general_purpose::URL_SAFE_NO_PAD
+                .encode(signing_key.verifying_key().as_bytes());
+            Jwk {
+                common: CommonParameters {
+                    key_algorithm: Some(KeyAlgorithm::EdDSA),
+                    ..common
+                },
+                algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x,
+                }),
+            }
+        }
+    };
+    Ok(Some(jwk))
+}
+
+impl KeyRing {
+    pub(crate) fn new(env: &mnln_env::JwtKeyRing) -> anyhow::Result<Self> {
+        let mut keys = Vec::with_capacity(env.keys.len());
+        let mut jwks = JwkSet { keys: Vec::new() };
+
+        for key in &env.keys {
+            let (algorithm, encoding) = match key.algorithm {
+                JwtAlgorithm::HS256 => (Algorithm::HS256, EncodingKey::from_secret(&key.material)),
+                JwtAlgorithm::ES256 => (Algorithm::ES256, EncodingKey::from_ec_der(&key.material)),
+                JwtAlgorithm::EdDSA => (Algorithm::EdDSA, EncodingKey::from_ed_der(&key.material)),
+            };
+            let jwk = public_jwk(key, &encoding)
+                .with_context(|| format!("Invalid private key for the JWT key `{}`", key.kid))?;
+            let decoding = match &jwk {
+                Some(jwk) => DecodingKey::from_jwk(jwk)?,
+                None => DecodingKey::from_secret(&key.material),
+            };
+            jwks.keys.extend(jwk);
+            keys.push(Key {
+                kid: key.kid.clone(),
+                algorithm,
+                encoding,
+                decoding,
+            });
+        }
+
+        let active = keys
+            .iter()
+            .position(|key| key.kid == env.active_kid)
+            .context("The active JWT key is missing from the key ring")?;
+
+        Ok(KeyRing { keys, active, jwks })
+    }
+
+    /// Signs the claims with the active key, naming it in the `kid` header.
+    pub(crate) fn sign<T: serde::Serialize>(
+        &self,
+        claims: &T,
+    ) -> jsonwebtoken::errors::Result<String> {
+        let key = &self.keys[self.active];
+        let header = jsonwebtoken::Header {
+            kid: Some(key.kid.clone()),
+            ..jsonwebtoken::Header::new(key.algorithm)
+        };
+        jsonwebtoken::encode(&header, claims, &key.encoding)
+    }
+
+    /// Verifies the signature with the key named in the `kid` header, and the registered claims
+    /// according to `validation`, see [`crate::util::jwt_validation`].
+    ///
+    /// The algorithm is taken from the key rather than from the token, so a token can't
+    /// e.g. pass off a public key as an HMAC secret.
+    pub(crate) fn verify<T: serde::de::DeserializeOwned>(
+        &self,
+        token: &str,
+        mut validation: jsonwebtoken::Validation,
+    ) -> Result<T, JwtVerificationError> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let kid = header.kid.ok_or_else(|| {
+            jsonwebtoken::errors::Error::from(jsonwebtoken::errors::ErrorKind::InvalidToken)
+        })?;
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key.kid == kid)
+            .ok_or(JwtVerificationError::UnknownKey(kid))?;
+
+        validation.algorithms = vec![key.algorithm];
+
+        Ok(jsonwebtoken::decode(token, &key.decoding, &validation)?.claims)
+    }
+
+    /// The public keys of the key ring, see <https://www.rfc-editor.org/rfc/rfc7517#section-5>
+    pub(crate) fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::pkcs8::EncodePrivateKey as _;
+    use mnln_env::{JwtEnv, JwtKeyRing};
+
+    use super::*;
+    use crate::util;
+
+    fn jwt_env() -> JwtEnv {
+        let material = ed25519_dalek::SigningKey::from_bytes(&[7; 32])
+            .to_pkcs8_der()
+            .unwrap()
+            .as_bytes()
+            .to_vec();
+        JwtEnv {
+            issuer: "main-line".to_string(),
+            audience: "main-line".to_string(),
+            leeway_secs: 30,
+            key_ring: JwtKeyRing {
+                keys: vec![JwtKey {
+                    kid: "ed".to_string(),
+                    algorithm: JwtAlgorithm::EdDSA,
+                    material,
+                }],
+                active_kid: "ed".to_string(),
+            },
+        }
+    }
+
+    fn claims(exp_from_now_secs: i64) -> serde_json::Value {
+        let now = jsonwebtoken::get_current_timestamp() as i64;
+        serde_json::json!({
+            "iss": "main-line",
+            "aud": "main-line",
+            "sub": 1,
+            "iat": now,
+            "nbf": now,
+            "exp": now + exp_from_now_secs,
+        })
+    }
+
+    /// What a third-party service does with the published JWK Set
+    fn verify_with_jwks(key_ring: &KeyRing, token: &str) -> jsonwebtoken::errors::Result<()> {
+        let kid = jsonwebtoken::decode_header(token)?.kid.unwrap();
+        let jwk = key_ring.jwks().find(&kid).unwrap();
+        let mut validation = jsonwebtoken::Validation::new(Algorithm::EdDSA);
+        validation.set_audience(&["main-line"]);
+        let key = DecodingKey::from_jwk(jwk)?;
+        jsonwebtoken::decode::<serde_json::Value>(token, &key, &validation).map(|_| ())
+    }
+
+    #[test]
+    fn published_keys_verify_the_tokens_with_standard_validation() {
+        let env = jwt_env();
+        let key_ring = KeyRing::new(&env.key_ring).unwrap();
+        let token = key_ring.sign(&claims(15 * 60)).unwrap();
+
+        verify_with_jwks(&key_ring, &token).unwrap();
+        key_ring
+            .verify::<serde_json::Value>(&token, util::jwt_validation(&env, &env.audience))
+            .unwrap();
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let env = jwt_env();
+        let key_ring = KeyRing::new(&env.key_ring).unwrap();
+        let token = key_ring.sign(&claims(-15 * 60)).unwrap();
+
+        assert!(verify_with_jwks(&key_ring, &token).is_err());
+        let res =
+            key_ring.verify::<serde_json::Value>(&token, util::jwt_validation(&env, &env.audience));
+        assert!(matches!(res, Err(JwtVerificationError::Expired)));
+    }
+
+    #[test]
+    fn tokens_for_other_audiences_are_rejected() {
+        let env = jwt_env();
+        let key_ring = KeyRing::new(&env.key_ring).unwrap();
+        let token = key_ring.sign(&claims(15 * 60)).unwrap();
+
+        let res = key_ring
+            .verify::<serde_json::Value>(&token, util::jwt_validation(&env, "second-factor"));
+        assert!(matches!(res, Err(JwtVerificationError::WrongAudience)));
+    }
+}
@@ -1,10 +1,25 @@
+pub(crate) mod auth;
+pub(crate) mod avatar;
+pub(crate) mod client_ip;
 pub(crate) mod context;
 pub(crate) mod db;
+pub(crate) mod key_ring;
+pub(crate) mod lichess;
 pub(crate) mod links;
+pub(crate) mod mail;
 pub(crate) mod middleware;
 pub(crate) mod params;
+pub(crate) mod password;
+pub(crate) mod request_origin;
+pub(crate) mod revocation;
 pub(crate) mod service;
+pub(crate) mod svg;
+pub(crate) mod token;
+pub(crate) mod totp;
+pub(crate) mod username;
 pub(crate) mod util;
+pub(crate) mod validation;
+pub(crate) mod webauthn;
 
 mod requests;
 
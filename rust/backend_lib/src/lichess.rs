@@ -0,0 +1,223 @@
+//! The Lichess side of "Sign in with Lichess": OAuth2 authorization code flow with PKCE.
+//!
+//! See <https://lichess.org/api#tag/OAuth> and <https://www.rfc-editor.org/rfc/rfc7636>.
+
+use anyhow::Context as _;
+use base64::Engine as _;
+use sha2::Digest as _;
+
+use mnln_env::LichessOAuthEnv;
+
+/// The S256 code challenge of the PKCE code verifier.
+pub(crate) fn code_challenge(code_verifier: &str) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .encode(sha2::Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Where the user is sent to let us read their Lichess account.
+///
+/// No scope is requested because reading the account needs none.
+pub(crate) fn authorization_url(
+    env: &LichessOAuthEnv,
+    redirect_uri: &str,
+    state: &str,
+    code_verifier: &str,
+) -> anyhow::Result<String> {
+    let url = reqwest::Url::parse_with_params(
+        &env.authorize_url,
+        [
+            ("response_type", "code"),
+            ("client_id", env.client_id.as_str()),
+            ("redirect_uri", redirect_uri),
+            ("code_challenge_method", "S256"),
+            ("code_challenge", &code_challenge(code_verifier)),
+            ("state", state),
+        ],
+    )
+    .context("Invalid LICHESS_OAUTH_AUTHORIZE_URL")?;
+    Ok(url.into())
+}
+
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// Exchanges the authorization code for an access token.
+pub(crate) async fn exchange_code(
+    http: &reqwest::Client,
+    env: &LichessOAuthEnv,
+    redirect_uri: &str,
+    code: &str,
+    code_verifier: &str,
+) -> anyhow::Result<String> {
+    let TokenResponse { access_token } = http
+        .post(&env.token_url)
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("code_verifier", code_verifier),
+            ("redirect_uri", redirect_uri),
+            ("client_id", env.client_id.as_str()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(access_token)
+}
+
+/// The part of <https://lichess.org/api#tag/Account/operation/accountMe> that we need.
+#[derive(serde::Deserialize)]
+pub(crate) struct Account {
+    /// The lowercased username, which never changes
+    pub(crate) id: String,
+    pub(crate) username: String,
+}
+
+pub(crate) async fn account(
+    http: &reqwest::Client,
+    env: &LichessOAuthEnv,
+    access_token: &str,
+) -> anyhow::Result<Account> {
+    let account = http
+        .get(&env.account_url)
+        .bearer_auth(access_token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(account)
+}
+
+/// Revokes the access token, which is no longer needed once the account is read.
+pub(crate) async fn revoke(
+    http: &reqwest::Client,
+    env: &LichessOAuthEnv,
+    access_token: &str,
+) -> anyhow::Result<()> {
+    http.delete(&env.token_url)
+        .bearer_auth(access_token)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use axum::extract::{Form, State};
+    use axum::http::{HeaderMap, StatusCode, header};
+
+    use super::*;
+
+    const CODE: &str = "the-authorization-code";
+    const ACCESS_TOKEN: &str = "lio_the-access-token";
+    const REDIRECT_URI: &str = "https://main-line.test/lichess/callback";
+
+    /// The code challenge that the mock authorization server got along with [`CODE`]
+    type CodeChallenge = Arc<Mutex<Option<String>>>;
+
+    async fn token(
+        State(challenge_of_code): State<CodeChallenge>,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> Result<axum::Json<serde_json::Value>, StatusCode> {
+        let expected_challenge = challenge_of_code.lock().unwrap().clone();
+        let valid = form["grant_type"] == "authorization_code"
+            && form["code"] == CODE
+            && form["redirect_uri"] == REDIRECT_URI
+            && form["client_id"] == "main-line"
+            && expected_challenge == Some(code_challenge(&form["code_verifier"]));
+        if !valid {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Ok(axum::Json(serde_json::json!({
+            "token_type": "Bearer",
+            "access_token": ACCESS_TOKEN,
+            "expires_in": 31536000,
+        })))
+    }
+
+    async fn account_me(headers: HeaderMap) -> Result<axum::Json<serde_json::Value>, StatusCode> {
+        if headers[header::AUTHORIZATION] != format!("Bearer {ACCESS_TOKEN}") {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        Ok(axum::Json(serde_json::json!({
+            "id": "magnus",
+            "username": "Magnus",
+            "perfs": {},
+        })))
+    }
+
+    /// Serves the Lichess endpoints on a random port and returns the env pointing at them.
+    async fn mock_lichess(challenge_of_code: CodeChallenge) -> LichessOAuthEnv {
+        let router = axum::Router::new()
+            .route(
+                "/api/token",
+                axum::routing::post(token).delete(|| async { StatusCode::NO_CONTENT }),
+            )
+            .route("/api/account", axum::routing::get(account_me))
+            .with_state(challenge_of_code);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        LichessOAuthEnv {
+            client_id: "main-line".to_string(),
+            authorize_url: format!("http://{addr}/oauth"),
+            token_url: format!("http://{addr}/api/token"),
+            account_url: format!("http://{addr}/api/account"),
+        }
+    }
+
+    #[tokio::test]
+    async fn signs_in_through_the_authorization_code_flow_with_pkce() {
+        let challenge_of_code = CodeChallenge::default();
+        let env = mock_lichess(challenge_of_code.clone()).await;
+        let http = reqwest::Client::new();
+
+        let state = crate::token::generate();
+        let code_verifier = crate::token::generate();
+        let url = authorization_url(&env, REDIRECT_URI, &state, &code_verifier).unwrap();
+
+        // The user consents, and Lichess remembers the challenge along with the code
+        let url = reqwest::Url::parse(&url).unwrap();
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(url.path(), "/oauth");
+        assert_eq!(params["state"], state);
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert_eq!(params["redirect_uri"], REDIRECT_URI);
+        *challenge_of_code.lock().unwrap() = Some(params["code_challenge"].clone());
+
+        let wrong_verifier = crate::token::generate();
+        assert!(
+            exchange_code(&http, &env, REDIRECT_URI, CODE, &wrong_verifier)
+                .await
+                .is_err()
+        );
+
+        let access_token = exchange_code(&http, &env, REDIRECT_URI, CODE, &code_verifier)
+            .await
+            .unwrap();
+        assert_eq!(access_token, ACCESS_TOKEN);
+
+        let Account { id, username } = account(&http, &env, &access_token).await.unwrap();
+        assert_eq!((id.as_str(), username.as_str()), ("magnus", "Magnus"));
+        assert!(account(&http, &env, "lio_another-token").await.is_err());
+
+        revoke(&http, &env, &access_token).await.unwrap();
+    }
+
+    // See <https://www.rfc-editor.org/rfc/rfc7636#appendix-B>
+    #[test]
+    fn code_challenge_matches_rfc_7636() {
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+}
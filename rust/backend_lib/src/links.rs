@@ -2,12 +2,54 @@ use mnln_env::Env;
 
 use crate::util;
 
-pub(crate) fn avatar_url(env: &Env, user_id: mnln_core_items::id::UserId) -> String {
+/// The avatar of the user, or its rendition of the `size`, see [`crate::avatar::RENDITION_SIZES`].
+pub(crate) fn avatar_url(
+    env: &Env,
+    user_id: mnln_core_items::id::UserId,
+    size: Option<u32>,
+) -> String {
     let base_api_url = &env.base_api_url;
     let timestamp: shared_items_lib::Timestamp = util::now();
     let timestamp: mnln_core_items::Timestamp = timestamp.into();
     // https://stackoverflow.com/questions/1077041/refresh-image-with-a-new-one-at-the-same-url
-    format!("{base_api_url}/api/user/{user_id}/avatar?ts={timestamp}")
+    match size {
+        Some(size) => {
+            format!("{base_api_url}/api/user/{user_id}/avatar?size={size}&ts={timestamp}")
+        }
+        None => format!("{base_api_url}/api/user/{user_id}/avatar?ts={timestamp}"),
+    }
+}
+
+/// The frontend page that redeems the email verification token.
+pub(crate) fn verify_email_url(env: &Env, token: &str) -> String {
+    let base_frontend_url = &env.base_frontend_url;
+    // The token is a JWT, which is URL-safe
+    format!("{base_frontend_url}/verify-email?token={token}")
+}
+
+/// The frontend page that asks for the new password and redeems the reset token.
+pub(crate) fn reset_password_url(env: &Env, token: &str) -> String {
+    let base_frontend_url = &env.base_frontend_url;
+    // The token is base64url-encoded
+    format!("{base_frontend_url}/reset-password?token={token}")
+}
+
+/// A link to the archive of a personal data export that works until the token expires.
+pub(crate) fn data_export_download_url(env: &Env, token: &str) -> String {
+    let base_api_url = &env.base_api_url;
+    format!("{base_api_url}/api/user/export/download?token={token}")
+}
+
+/// The frontend page that Lichess redirects to after "Sign in with Lichess".
+pub(crate) fn lichess_sign_in_callback_url(env: &Env) -> String {
+    let base_frontend_url = &env.base_frontend_url;
+    format!("{base_frontend_url}/login/lichess/callback")
+}
+
+/// The frontend page that Lichess redirects to after linking the Lichess account.
+pub(crate) fn lichess_link_callback_url(env: &Env) -> String {
+    let base_frontend_url = &env.base_frontend_url;
+    format!("{base_frontend_url}/settings/lichess/callback")
 }
 
 pub(crate) fn chess_dot_com_profile(username: &str) -> String {
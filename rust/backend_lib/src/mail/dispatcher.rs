@@ -0,0 +1,98 @@
+//! Delivers the mails of the outbox.
+//!
+//! Every replica runs a dispatcher. Each mail is claimed by one of them for the [`lease`], so a mail
+//! whose dispatcher died mid-delivery is retried by another one afterwards. Delivery is therefore
+//! at least once, which is fine for the kind of mails we send.
+
+use std::time::Duration;
+
+use super::{Mail, MailTransport};
+use crate::db::{self, Db};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BATCH_SIZE: i64 = 10;
+const MAX_ATTEMPTS: i32 = 8;
+
+fn lease() -> chrono::Duration {
+    chrono::Duration::minutes(5)
+}
+
+/// How long sent mails are kept around for troubleshooting.
+fn retention() -> chrono::Duration {
+    chrono::Duration::days(7)
+}
+
+/// Exponential backoff starting at 1 minute and capped at 6 hours.
+fn retry_in(attempts: i32) -> chrono::Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    std::cmp::min(
+        chrono::Duration::minutes(1) * 2i32.pow(exponent),
+        chrono::Duration::hours(6),
+    )
+}
+
+async fn deliver<T: MailTransport>(
+    transport: &T,
+    from: &str,
+    db: &Db,
+    mail: db::outbox::claim::ClaimedMail,
+) {
+    let db::outbox::claim::ClaimedMail {
+        id,
+        recipient,
+        subject,
+        body,
+        attempts,
+    } = mail;
+    let mail = Mail {
+        from: from.to_string(),
+        to: recipient,
+        subject,
+        body,
+    };
+
+    let res = match transport.send(&mail).await {
+        Ok(()) => db::outbox::mark_sent(db, id).await,
+        Err(err) => {
+            if attempts >= MAX_ATTEMPTS {
+                tracing::error!("Gave up sending the mail {id} after {attempts} attempts: {err:#}");
+            } else {
+                tracing::warn!("Failed to send the mail {id} (attempt {attempts}): {err:#}");
+            }
+            db::outbox::mark_failed(db, id, &format!("{err:#}"), retry_in(attempts)).await
+        }
+    };
+    if let Err(err) = res {
+        tracing::error!("Failed to record the delivery of the mail {id}: {err}");
+    }
+}
+
+async fn dispatch<T: MailTransport>(transport: &T, from: &str, db: &Db) {
+    let mails = match db::outbox::claim(db, BATCH_SIZE, MAX_ATTEMPTS, lease()).await {
+        Ok(mails) => mails,
+        Err(err) => {
+            tracing::error!("Failed to claim the due mails: {err}");
+            return;
+        }
+    };
+    for mail in mails {
+        deliver(transport, from, db, mail).await;
+    }
+
+    match db::outbox::prune_sent(db, retention()).await {
+        Ok(0) => (),
+        Ok(n) => tracing::trace!("Pruned {n} sent mails"),
+        Err(err) => tracing::error!("Failed to prune the sent mails: {err}"),
+    }
+}
+
+pub(super) fn spawn<T: MailTransport>(transport: T, from: String, db: Db) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            dispatch(&transport, &from, &db).await;
+        }
+    });
+}
@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+
+use super::{Mail, MailTransport};
+use crate::{token, util};
+
+/// Writes every mail to a `.eml` file, which most mail clients can open, for local development.
+pub(crate) struct FileTransport {
+    dir: PathBuf,
+}
+
+impl FileTransport {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        FileTransport { dir }
+    }
+}
+
+impl MailTransport for FileTransport {
+    async fn send(&self, mail: &Mail) -> anyhow::Result<()> {
+        let message = mail.to_rfc5322()?;
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // The timestamp keeps the files in the order they were sent
+        let file_name = format!("{}-{}.eml", util::now().0, token::generate());
+        let path = self.dir.join(file_name);
+        tokio::fs::write(&path, message).await?;
+        tracing::info!("Wrote the mail to {} to {}", mail.to, path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+use std::sync::{Arc, Mutex};
+
+use super::{Mail, MailTransport};
+
+/// Keeps every mail in memory and logs it, for tests and for trying out the flows locally.
+#[derive(Clone, Default)]
+pub(crate) struct MemoryTransport {
+    mails: Arc<Mutex<Vec<Mail>>>,
+}
+
+impl MemoryTransport {
+    #[cfg(test)]
+    pub(crate) fn mails(&self) -> Vec<Mail> {
+        self.mails.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl MailTransport for MemoryTransport {
+    async fn send(&self, mail: &Mail) -> anyhow::Result<()> {
+        tracing::info!("Sent mail: {mail:?}");
+        self.mails
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(mail.clone());
+        Ok(())
+    }
+}
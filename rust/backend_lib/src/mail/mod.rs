@@ -0,0 +1,163 @@
+//! Outgoing mail.
+//!
+//! Mails are never sent from request handlers. They are enqueued in the `email_outbox` table in
+//! the same transaction as the change that triggers them (see [`crate::db::outbox`]) and the
+//! [`dispatcher`] hands them to the configured [`MailTransport`], retrying failed deliveries.
+
+use base64::Engine as _;
+use mnln_env::{MailEnv, MailTransportEnv};
+
+use crate::db::Db;
+use crate::token;
+
+mod dispatcher;
+mod file;
+mod memory;
+mod smtp;
+
+use file::FileTransport;
+use memory::MemoryTransport;
+use smtp::SmtpTransport;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Mail {
+    /// E.g. `main-line <no-reply@example.com>`
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    /// Plain text
+    pub body: String,
+}
+
+/// The address part of e.g. `main-line <no-reply@example.com>`.
+pub(crate) fn envelope_address(mailbox: &str) -> &str {
+    match (mailbox.rfind('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+/// See <https://www.rfc-editor.org/rfc/rfc2047>
+fn encode_header_value(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    let encoded = base64::engine::general_purpose::STANDARD.encode(value);
+    format!("=?utf-8?B?{encoded}?=")
+}
+
+impl Mail {
+    /// Renders the mail as an Internet Message, see <https://www.rfc-editor.org/rfc/rfc5322>.
+    ///
+    /// The body is base64-encoded, so its lines can't be mistaken for the end of an SMTP
+    /// `DATA` section or exceed the line length limit.
+    pub(crate) fn to_rfc5322(&self) -> anyhow::Result<String> {
+        let Mail {
+            from,
+            to,
+            subject,
+            body,
+        } = self;
+
+        // Otherwise, the values could inject headers
+        for (name, value) in [("From", from), ("To", to), ("Subject", subject)] {
+            anyhow::ensure!(
+                !value.contains(['\r', '\n']),
+                "The {name} header contains a line break"
+            );
+        }
+
+        let domain = envelope_address(from)
+            .rsplit_once('@')
+            .map_or("localhost", |(_, domain)| domain);
+        let message_id = format!("<{}@{domain}>", token::generate());
+        let date = chrono::Utc::now().to_rfc2822();
+        let subject = encode_header_value(subject);
+
+        let body = base64::engine::general_purpose::STANDARD.encode(body);
+        let body = body
+            .as_bytes()
+            .chunks(76)
+            // The chunks of a base64 string are ASCII
+            .map(|line| std::str::from_utf8(line).unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join("\r\n");
+
+        Ok(format!(
+            "From: {from}\r\n\
+             To: {to}\r\n\
+             Subject: {subject}\r\n\
+             Date: {date}\r\n\
+             Message-ID: {message_id}\r\n\
+             MIME-Version: 1.0\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             Content-Transfer-Encoding: base64\r\n\
+             \r\n\
+             {body}\r\n"
+        ))
+    }
+}
+
+pub(crate) trait MailTransport: Send + Sync + 'static {
+    fn send(&self, mail: &Mail) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+/// Starts dispatching the outbox with the transport configured in [`MailEnv`].
+pub(crate) fn spawn_dispatcher(env: &MailEnv, db: Db) -> anyhow::Result<()> {
+    let from = env.from.clone();
+    match &env.transport {
+        MailTransportEnv::Smtp(smtp_env) => {
+            dispatcher::spawn(SmtpTransport::new(smtp_env.clone())?, from, db)
+        }
+        MailTransportEnv::File { dir } => {
+            dispatcher::spawn(FileTransport::new(dir.clone()), from, db)
+        }
+        MailTransportEnv::Memory => dispatcher::spawn(MemoryTransport::default(), from, db),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mail() -> Mail {
+        Mail {
+            from: "main-line <no-reply@example.com>".to_string(),
+            to: "player@example.com".to_string(),
+            subject: "Vérifiez votre adresse".to_string(),
+            body: "Hi,\n.\nBye".to_string(),
+        }
+    }
+
+    #[test]
+    fn renders_headers_and_body() {
+        let rendered = mail().to_rfc5322().unwrap();
+        let (headers, body) = rendered.split_once("\r\n\r\n").unwrap();
+
+        assert!(headers.contains("Subject: =?utf-8?B?"));
+        assert!(headers.contains("@example.com>\r\n"));
+        let body = base64::engine::general_purpose::STANDARD
+            .decode(body.trim_end().replace("\r\n", ""))
+            .unwrap();
+        assert_eq!(body, b"Hi,\n.\nBye");
+    }
+
+    #[test]
+    fn rejects_header_injection() {
+        let mail = Mail {
+            subject: "Hi\r\nBcc: everyone@example.com".to_string(),
+            ..mail()
+        };
+        assert!(mail.to_rfc5322().is_err());
+    }
+
+    #[tokio::test]
+    async fn memory_transport_keeps_mails() {
+        let transport = MemoryTransport::default();
+        transport.send(&mail()).await.unwrap();
+        let mails = transport.mails();
+        assert_eq!(mails.len(), 1);
+        assert_eq!(mails[0].to, "player@example.com");
+    }
+}
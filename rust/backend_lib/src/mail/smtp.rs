@@ -0,0 +1,56 @@
+//! Delivery over SMTP, see <https://www.rfc-editor.org/rfc/rfc5321>.
+//!
+//! A new connection is opened for every mail, which is plenty for the volume of mails we send.
+
+use std::time::Duration;
+
+use lettre::AsyncTransport as _;
+use lettre::address::Envelope;
+use lettre::transport::smtp::AsyncSmtpTransport;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use mnln_env::{SmtpEnv, SmtpSecurity};
+
+use super::{Mail, MailTransport, envelope_address};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+pub(crate) struct SmtpTransport {
+    transport: AsyncSmtpTransport<lettre::Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub(crate) fn new(env: SmtpEnv) -> anyhow::Result<Self> {
+        let tls = match env.security {
+            SmtpSecurity::None => Tls::None,
+            SmtpSecurity::StartTls => Tls::Required(TlsParameters::new(env.host.clone())?),
+            SmtpSecurity::Tls => Tls::Wrapper(TlsParameters::new(env.host.clone())?),
+        };
+        // "Dangerous" only because the security isn't inferred from the port but configured,
+        // and `mnln_env` doesn't allow credentials without TLS.
+        let mut builder = AsyncSmtpTransport::<lettre::Tokio1Executor>::builder_dangerous(env.host)
+            .port(env.port)
+            .tls(tls)
+            .timeout(Some(TIMEOUT));
+        if let (Some(username), Some(password)) = (env.username, env.password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(SmtpTransport {
+            transport: builder.build(),
+        })
+    }
+}
+
+impl MailTransport for SmtpTransport {
+    async fn send(&self, mail: &Mail) -> anyhow::Result<()> {
+        let message = mail.to_rfc5322()?;
+        let envelope = Envelope::new(
+            Some(envelope_address(&mail.from).parse()?),
+            vec![envelope_address(&mail.to).parse()?],
+        )?;
+        self.transport
+            .send_raw(&envelope, message.as_bytes())
+            .await?;
+        Ok(())
+    }
+}
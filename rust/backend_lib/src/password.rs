@@ -0,0 +1,204 @@
+//! Server-side password hashing and verification.
+//!
+//! The frontend pre-hashes passwords before sending them (see `docs/practices/password_storage.md`),
+//! so whatever the client sends is treated as an opaque secret and hashed once more with Argon2id.
+//! Storing the client-side hash verbatim would make it equivalent to the password itself.
+
+use argon2::password_hash::{
+    PasswordHasher as _, PasswordVerifier as _, SaltString, rand_core::OsRng,
+};
+use argon2::{Algorithm, Argon2, Params, PasswordHash, Version};
+use hmac::Mac as _;
+use subtle::ConstantTimeEq as _;
+
+use mnln_env::{Argon2Env, FakeSaltEnv};
+
+use crate::db::user::PHCString;
+
+pub(crate) enum Verification {
+    Valid { needs_rehash: bool },
+    Invalid,
+}
+
+fn hasher(env: &Argon2Env) -> anyhow::Result<Argon2<'static>> {
+    let params = Params::new(env.m_cost, env.t_cost, env.p_cost, None)?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+fn needs_rehash(hash: &PasswordHash, env: &Argon2Env) -> bool {
+    if hash.algorithm != Algorithm::Argon2id.ident() || hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+    match Params::try_from(hash) {
+        Ok(params) => {
+            params.m_cost() != env.m_cost
+                || params.t_cost() != env.t_cost
+                || params.p_cost() != env.p_cost
+        }
+        Err(_) => true,
+    }
+}
+
+/// Returns the salt that the client used for pre-hashing `secret`.
+///
+/// If the secret is not a PHC string (i.e. the client didn't pre-hash the password),
+/// a fresh salt is generated so that `/api/user/salt` behaves the same for every user.
+pub(crate) fn client_salt(secret: &str) -> String {
+    match PasswordHash::new(secret) {
+        Ok(PasswordHash {
+            salt: Some(salt), ..
+        }) => salt.as_str().to_string(),
+        _ => SaltString::generate(&mut OsRng).as_str().to_string(),
+    }
+}
+
+/// Returns the salt that `/api/user/salt` responds with for an unknown `username`.
+///
+/// It's an HMAC of the username, so it's the same for every request, and it's encoded like
+/// the 16-byte salts that the clients generate, so it can't be told apart from a real one.
+pub(crate) fn fake_client_salt(env: &FakeSaltEnv, username: &str) -> String {
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(&env.key)
+        .expect("HMAC accepts keys of any length");
+    mac.update(username.as_bytes());
+    let digest = mac.finalize().into_bytes();
+    SaltString::encode_b64(&digest[..16])
+        .expect("16 bytes are a valid salt")
+        .as_str()
+        .to_string()
+}
+
+// Hashing is CPU- and memory-bound, so it must not block the async runtime.
+pub(crate) async fn hash(env: &Argon2Env, secret: String) -> anyhow::Result<PHCString> {
+    let env = env.clone();
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = hasher(&env)?.hash_password(secret.as_bytes(), &salt)?;
+        Ok(PHCString::from(hash))
+    })
+    .await?
+}
+
+/// Verifies `secret` against the stored hash in constant time.
+///
+/// A `legacy` hash is the client-side hash stored verbatim before the server started
+/// hashing passwords. It is compared as-is and always needs a rehash.
+pub(crate) async fn verify(
+    env: &Argon2Env,
+    secret: String,
+    stored: PHCString,
+    legacy: bool,
+) -> anyhow::Result<Verification> {
+    let env = env.clone();
+    tokio::task::spawn_blocking(move || {
+        if legacy {
+            let is_valid: bool = secret.as_bytes().ct_eq(stored.as_str().as_bytes()).into();
+            return Ok(if is_valid {
+                Verification::Valid { needs_rehash: true }
+            } else {
+                Verification::Invalid
+            });
+        }
+
+        let stored: PasswordHash = (&stored).try_into()?;
+        // The parameters are taken from the stored hash, not from `env`.
+        match Argon2::default().verify_password(secret.as_bytes(), &stored) {
+            Ok(()) => Ok(Verification::Valid {
+                needs_rehash: needs_rehash(&stored, &env),
+            }),
+            Err(argon2::password_hash::Error::Password) => Ok(Verification::Invalid),
+            Err(err) => Err(err.into()),
+        }
+    })
+    .await?
+}
+
+/// Spends the same amount of work as [`verify`] so that logging in as a non-existent user
+/// takes as long as logging in with a wrong password.
+pub(crate) async fn verify_dummy(env: &Argon2Env, secret: String) -> anyhow::Result<()> {
+    hash(env, secret).await.map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap parameters, so that the tests don't take long
+    fn env() -> Argon2Env {
+        Argon2Env {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+        }
+    }
+
+    fn needs_rehash_of(verification: Verification) -> Option<bool> {
+        match verification {
+            Verification::Valid { needs_rehash } => Some(needs_rehash),
+            Verification::Invalid => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn verifies_the_hashed_secret() {
+        let env = env();
+        let stored = hash(&env, "secret".to_string()).await.unwrap();
+        let verification = verify(&env, "secret".to_string(), stored, false)
+            .await
+            .unwrap();
+        assert_eq!(needs_rehash_of(verification), Some(false));
+    }
+
+    #[tokio::test]
+    async fn rejects_a_wrong_secret() {
+        let env = env();
+        let stored = hash(&env, "secret".to_string()).await.unwrap();
+        let verification = verify(&env, "Secret".to_string(), stored, false)
+            .await
+            .unwrap();
+        assert_eq!(needs_rehash_of(verification), None);
+    }
+
+    #[tokio::test]
+    async fn legacy_hashes_are_compared_verbatim_and_need_a_rehash() {
+        let env = env();
+        // What the clients used to store, i.e. their own PHC string
+        let client_hash = hash(&env, "password".to_string()).await.unwrap();
+        let secret = client_hash.as_str().to_string();
+
+        let stored = hash(&env, "password".to_string()).await.unwrap();
+        let verification = verify(&env, secret.clone(), client_hash, true)
+            .await
+            .unwrap();
+        assert_eq!(needs_rehash_of(verification), Some(true));
+
+        let verification = verify(&env, secret, stored, true).await.unwrap();
+        assert_eq!(needs_rehash_of(verification), None);
+    }
+
+    #[tokio::test]
+    async fn hashes_with_other_parameters_need_a_rehash() {
+        let stored = hash(&env(), "secret".to_string()).await.unwrap();
+        let env = Argon2Env { t_cost: 2, ..env() };
+        let verification = verify(&env, "secret".to_string(), stored, false)
+            .await
+            .unwrap();
+        assert_eq!(needs_rehash_of(verification), Some(true));
+    }
+
+    #[tokio::test]
+    async fn verify_dummy_succeeds() {
+        verify_dummy(&env(), "secret".to_string()).await.unwrap();
+    }
+
+    #[test]
+    fn fake_client_salt_looks_like_a_generated_one() {
+        let env = FakeSaltEnv {
+            key: b"0123456789abcdef0123456789abcdef".to_vec(),
+        };
+        let salt = fake_client_salt(&env, "magnus");
+        assert_eq!(salt, fake_client_salt(&env, "magnus"));
+        assert_ne!(salt, fake_client_salt(&env, "hikaru"));
+        assert_eq!(salt.len(), SaltString::generate(&mut OsRng).as_str().len());
+    }
+}
@@ -0,0 +1,39 @@
+//! The extractor of where a request comes from, as recorded in the audit log.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::extract::FromRequestParts;
+use axum::http::StatusCode;
+use axum::http::header::USER_AGENT;
+use axum::http::request::Parts;
+
+use crate::Context;
+use crate::client_ip::ClientIp;
+
+/// Longer `User-Agent` headers are truncated.
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// The IP address of the client, see [`ClientIp`], and its `User-Agent`, if any.
+#[derive(Clone)]
+pub(crate) struct RequestOrigin {
+    pub(crate) ip: IpAddr,
+    pub(crate) user_agent: Option<String>,
+}
+
+impl FromRequestParts<Arc<Context>> for RequestOrigin {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        ctx: &Arc<Context>,
+    ) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip) = ClientIp::from_request_parts(parts, ctx).await?;
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|header_value| header_value.to_str().ok())
+            .map(|user_agent| user_agent.chars().take(MAX_USER_AGENT_LEN).collect());
+        Ok(RequestOrigin { ip, user_agent })
+    }
+}
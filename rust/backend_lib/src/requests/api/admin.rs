@@ -0,0 +1,362 @@
+use std::sync::Arc;
+
+use axum::response::IntoResponse as _;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Response,
+    routing::{get, post, put},
+};
+
+use shared_items_lib::service_responses::{
+    AdminUser, DeleteAdminUserRestrictionResponse, DeleteUserResponseScheduled,
+    GetAdminUserResponse, GetAdminUsersResponse, GetAdminUsersResponseSuccess,
+    GetAuditEventsResponse, GetAuditEventsResponseSuccess, PostAdminForcePasswordResetResponse,
+    PutAdminUserRestrictionResponse, PutAdminUserRoleResponse,
+};
+
+use crate::auth::{Admin, AuthUser, RequireRole};
+use crate::context::Context;
+use crate::params::UserIdPathParams;
+use crate::request_origin::RequestOrigin;
+use crate::requests::api::user::{cancel_user_deletion_response, delete_user_response};
+use crate::service;
+use crate::service::admin::{ListUsersQueryParams, RestrictUserRequest, SetRoleRequest};
+use crate::service::audit::AuditEventsQueryParams;
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit-events",
+    tag = "admin",
+    responses(
+        (status = 200, description = "A page of the audit events matching the filters", body = GetAuditEventsResponseSuccess),
+        (status = 400, description = "The filters or the pagination are out of range", body = GetAuditEventsResponse),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 403, description = "The user is not an admin", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(AuditEventsQueryParams),
+    security(
+        ("bearerAuth" = ["admin"])
+    )
+)]
+async fn get_audit_events(
+    State(ctx): State<Arc<Context>>,
+    _: RequireRole<Admin>,
+    Query(params): Query<AuditEventsQueryParams>,
+) -> Response {
+    match service::audit::list(&ctx, params).await {
+        GetAuditEventsResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        resp @ GetAuditEventsResponse::InvalidQuery { .. } => {
+            (StatusCode::BAD_REQUEST, Json(resp)).into_response()
+        }
+        GetAuditEventsResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/users",
+    tag = "admin",
+    responses(
+        (status = 200, description = "A page of the users matching the filters", body = GetAdminUsersResponseSuccess),
+        (status = 400, description = "The filters or the pagination are out of range", body = GetAdminUsersResponse),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 403, description = "The user is not an admin", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(ListUsersQueryParams),
+    security(
+        ("bearerAuth" = ["admin"])
+    )
+)]
+async fn get_users(
+    State(ctx): State<Arc<Context>>,
+    _: RequireRole<Admin>,
+    Query(params): Query<ListUsersQueryParams>,
+) -> Response {
+    match service::admin::list_users(&ctx, params).await {
+        GetAdminUsersResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        resp @ GetAdminUsersResponse::InvalidQuery { .. } => {
+            (StatusCode::BAD_REQUEST, Json(resp)).into_response()
+        }
+        GetAdminUsersResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/users/{user_id}",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The user", body = AdminUser),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 403, description = "The user is not an admin", body = ()),
+        (status = 404, description = "User not found", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(UserIdPathParams),
+    security(
+        ("bearerAuth" = ["admin"])
+    )
+)]
+async fn get_user(
+    State(ctx): State<Arc<Context>>,
+    _: RequireRole<Admin>,
+    Path(params): Path<UserIdPathParams>,
+) -> Response {
+    match service::admin::get_user(&ctx, params.user_id.into()).await {
+        GetAdminUserResponse::Success(user) => (StatusCode::OK, Json(user)).into_response(),
+        GetAdminUserResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        GetAdminUserResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/users/{user_id}/role",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The role is changed and the user is logged out everywhere", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 403, description = "The user is not an admin", body = ()),
+        (status = 404, description = "User not found", body = ()),
+        (status = 409, description = "Admins can't change their own role", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(UserIdPathParams),
+    request_body = SetRoleRequest,
+    security(
+        ("bearerAuth" = ["admin"])
+    )
+)]
+async fn put_user_role(
+    State(ctx): State<Arc<Context>>,
+    origin: RequestOrigin,
+    RequireRole {
+        user: AuthUser(admin),
+        ..
+    }: RequireRole<Admin>,
+    Path(params): Path<UserIdPathParams>,
+    Json(request): Json<SetRoleRequest>,
+) -> Response {
+    match service::admin::set_role(&ctx, admin, params.user_id.into(), request, &origin).await {
+        PutAdminUserRoleResponse::Success => StatusCode::OK.into_response(),
+        PutAdminUserRoleResponse::CannotModifySelf => StatusCode::CONFLICT.into_response(),
+        PutAdminUserRoleResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        PutAdminUserRoleResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/users/{user_id}/restriction",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The user is suspended or banned and logged out everywhere", body = ()),
+        (status = 400, description = "Invalid reason or expiry", body = PutAdminUserRestrictionResponse),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 403, description = "The user is not an admin", body = ()),
+        (status = 404, description = "User not found", body = ()),
+        (status = 409, description = "Admins can't restrict themselves", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(UserIdPathParams),
+    request_body = RestrictUserRequest,
+    security(
+        ("bearerAuth" = ["admin"])
+    )
+)]
+async fn put_user_restriction(
+    State(ctx): State<Arc<Context>>,
+    origin: RequestOrigin,
+    RequireRole {
+        user: AuthUser(admin),
+        ..
+    }: RequireRole<Admin>,
+    Path(params): Path<UserIdPathParams>,
+    Json(request): Json<RestrictUserRequest>,
+) -> Response {
+    match service::admin::restrict(&ctx, admin, params.user_id.into(), request, &origin).await {
+        PutAdminUserRestrictionResponse::Success => StatusCode::OK.into_response(),
+        resp @ PutAdminUserRestrictionResponse::InvalidRestriction { .. } => {
+            (StatusCode::BAD_REQUEST, Json(resp)).into_response()
+        }
+        PutAdminUserRestrictionResponse::CannotModifySelf => StatusCode::CONFLICT.into_response(),
+        PutAdminUserRestrictionResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        PutAdminUserRestrictionResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/users/{user_id}/restriction",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The user can log in again", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 403, description = "The user is not an admin", body = ()),
+        (status = 409, description = "The user doesn't exist or isn't restricted", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(UserIdPathParams),
+    security(
+        ("bearerAuth" = ["admin"])
+    )
+)]
+async fn delete_user_restriction(
+    State(ctx): State<Arc<Context>>,
+    origin: RequestOrigin,
+    RequireRole {
+        user: AuthUser(admin),
+        ..
+    }: RequireRole<Admin>,
+    Path(params): Path<UserIdPathParams>,
+) -> Response {
+    match service::admin::lift_restriction(&ctx, admin, params.user_id.into(), &origin).await {
+        DeleteAdminUserRestrictionResponse::Success => StatusCode::OK.into_response(),
+        DeleteAdminUserRestrictionResponse::NotRestricted => StatusCode::CONFLICT.into_response(),
+        DeleteAdminUserRestrictionResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{user_id}/force-password-reset",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The reset link is sent and the user is logged out everywhere", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 403, description = "The user is not an admin", body = ()),
+        (status = 404, description = "User not found", body = ()),
+        (status = 409, description = "The user has no verified email address to send the link to", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(UserIdPathParams),
+    security(
+        ("bearerAuth" = ["admin"])
+    )
+)]
+async fn post_force_password_reset(
+    State(ctx): State<Arc<Context>>,
+    origin: RequestOrigin,
+    RequireRole {
+        user: AuthUser(admin),
+        ..
+    }: RequireRole<Admin>,
+    Path(params): Path<UserIdPathParams>,
+) -> Response {
+    match service::password_reset::force(&ctx, admin, params.user_id.into(), &origin).await {
+        PostAdminForcePasswordResetResponse::Success => StatusCode::OK.into_response(),
+        PostAdminForcePasswordResetResponse::NoVerifiedEmail => {
+            StatusCode::CONFLICT.into_response()
+        }
+        PostAdminForcePasswordResetResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        PostAdminForcePasswordResetResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/users/{user_id}",
+    tag = "admin",
+    responses(
+        (status = 202, description = "The account will be deleted after the grace period", body = DeleteUserResponseScheduled),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 403, description = "The user is not an admin", body = ()),
+        (status = 404, description = "User not found", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(UserIdPathParams),
+    security(
+        ("bearerAuth" = ["admin"])
+    )
+)]
+async fn delete_user(
+    State(ctx): State<Arc<Context>>,
+    RequireRole {
+        user: AuthUser(admin),
+        ..
+    }: RequireRole<Admin>,
+    Path(params): Path<UserIdPathParams>,
+) -> Response {
+    let user_id: mnln_core_items::id::UserId = params.user_id.into();
+    tracing::info!(
+        "Admin with ID {} requested the deletion of user with ID {user_id}",
+        admin.sub.0
+    );
+    delete_user_response(service::account_deletion::schedule(&ctx, user_id).await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{user_id}/cancel-deletion",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The account will be kept", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 403, description = "The user is not an admin", body = ()),
+        (status = 409, description = "The user doesn't exist or no deletion is scheduled", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(UserIdPathParams),
+    security(
+        ("bearerAuth" = ["admin"])
+    )
+)]
+async fn post_cancel_user_deletion(
+    State(ctx): State<Arc<Context>>,
+    RequireRole {
+        user: AuthUser(admin),
+        ..
+    }: RequireRole<Admin>,
+    Path(params): Path<UserIdPathParams>,
+) -> Response {
+    let user_id: mnln_core_items::id::UserId = params.user_id.into();
+    tracing::info!(
+        "Admin with ID {} cancelled the deletion of user with ID {user_id}",
+        admin.sub.0
+    );
+    cancel_user_deletion_response(service::account_deletion::cancel(&ctx, user_id).await)
+}
+
+fn admin_routes() -> Router<Arc<Context>> {
+    Router::new()
+        .route("/audit-events", get(get_audit_events))
+        .route("/users", get(get_users))
+        .route("/users/{user_id}", get(get_user).delete(delete_user))
+        .route("/users/{user_id}/role", put(put_user_role))
+        .route(
+            "/users/{user_id}/restriction",
+            put(put_user_restriction).delete(delete_user_restriction),
+        )
+        .route(
+            "/users/{user_id}/force-password-reset",
+            post(post_force_password_reset),
+        )
+        .route(
+            "/users/{user_id}/cancel-deletion",
+            post(post_cancel_user_deletion),
+        )
+}
+
+pub(in crate::requests::api) fn add_nested_routes(
+    router: axum::Router<Arc<Context>>,
+) -> axum::Router<Arc<Context>> {
+    router.nest("/admin", admin_routes())
+}
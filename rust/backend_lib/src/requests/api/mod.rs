@@ -4,18 +4,17 @@ use axum::Router;
 
 use crate::context::Context;
 
+pub(crate) mod admin;
 pub(crate) mod bff;
 pub(crate) mod user;
 
-fn api_routes(ctx: Arc<Context>) -> Router<Arc<Context>> {
+fn api_routes() -> Router<Arc<Context>> {
     let router = Router::new();
-    let router = user::add_nested_routes(router, ctx);
+    let router = user::add_nested_routes(router);
+    let router = admin::add_nested_routes(router);
     bff::add_nested_routes(router)
 }
 
-pub(in crate::requests) fn add_nested_routes(
-    router: Router<Arc<Context>>,
-    ctx: Arc<Context>,
-) -> Router<Arc<Context>> {
-    router.nest("/api", api_routes(ctx))
+pub(in crate::requests) fn add_nested_routes(router: Router<Arc<Context>>) -> Router<Arc<Context>> {
+    router.nest("/api", api_routes())
 }
@@ -1,9 +1,8 @@
 use std::sync::Arc;
 
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::response::IntoResponse as _;
 
-use axum::Extension;
 use axum::{
     Router,
     extract::{Json, State},
@@ -13,36 +12,704 @@ use axum::{
 };
 
 use shared_items_lib::service_responses::{
-    PostLoginResponse, PostLoginResponseSuccess, PostRegisterResponse, PostSaltResponse,
-    PostSaltResponseSuccess, PostUploadUserAvatarResponse, PostUploadUserAvatarSuccess,
+    DataExport, DeletePasskeyResponse, DeletePersonalAccessTokenResponse, DeleteUserResponse,
+    DeleteUserResponseScheduled, GetDataExportResponse, GetMeResponse, GetPasskeysResponse,
+    GetPasskeysResponseSuccess, GetPersonalAccessTokensResponse,
+    GetPersonalAccessTokensResponseSuccess, GetSecurityActivityResponse,
+    GetSecurityActivityResponseSuccess, Passkey, PasskeyCreationOptions, PasskeyRequestOptions,
+    PatchMeResponse, PostCancelUserDeletionResponse, PostDataExportResponse,
+    PostLichessAuthorizeResponse, PostLichessAuthorizeResponseSuccess, PostLichessLinkResponse,
+    PostLichessSignInResponse, PostLoginResponse, PostLoginResponseSuccess,
+    PostLoginSecondFactorResponse, PostLogoutEverywhereResponse, PostLogoutResponse,
+    PostPasskeyLoginOptionsResponse, PostPasskeyLoginResponse,
+    PostPasskeyRegistrationOptionsResponse, PostPasskeyRegistrationResponse,
+    PostPasswordResetConfirmResponse, PostPasswordResetRequestResponse,
+    PostPersonalAccessTokenResponse, PostPersonalAccessTokenResponseSuccess, PostRefreshResponse,
+    PostRefreshResponseSuccess, PostRegisterResponse, PostSaltResponse, PostSaltResponseSuccess,
+    PostSendEmailVerificationResponse, PostTotpConfirmResponse, PostTotpConfirmResponseSuccess,
+    PostTotpDisableResponse, PostTotpEnrollResponse, PostTotpEnrollResponseSuccess,
+    PostUploadUserAvatarResponse, PostUploadUserAvatarSuccess, PostVerifyEmailResponse,
+    SecondFactorChallenge, UserProfile,
 };
 
+use crate::auth::{AuthUser, AvatarWrite, ProfileRead, RequireScope};
+use crate::client_ip::ClientIp;
 use crate::context::Context;
 use crate::params::UserIdPathParams;
+use crate::request_origin::RequestOrigin;
 use crate::requests::Binary;
 use crate::service;
-use crate::service::user::{RegisterRequest, SaltRequest, UploadUserAvatarRequest};
+use crate::service::data_export::DownloadQueryParams;
+use crate::service::email_verification::VerifyEmailRequest;
+use crate::service::lichess::LichessCallbackRequest;
+use crate::service::passkey::{PasskeyLoginRequest, PasskeyRegistrationRequest};
+use crate::service::password_reset::{PasswordResetConfirmRequest, PasswordResetRequest};
+use crate::service::personal_access_token::CreatePersonalAccessTokenRequest;
+use crate::service::totp::{SecondFactorLoginRequest, TotpCodeRequest};
+use crate::service::user::{
+    AvatarQueryParams, LogoutRequest, PatchMeRequest, RefreshRequest, RegisterRequest, SaltRequest,
+    UploadUserAvatarRequest,
+};
+
+#[utoipa::path(
+    post,
+    path = "/api/user/register",
+    tag = "user",
+    responses(
+        (status = 200, description = "User registered successfully", body = ()),
+        (status = 400, description = "The username doesn't satisfy the policy", body = PostRegisterResponse),
+        (status = 409, description = "User already exists", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = RegisterRequest,
+)]
+#[axum::debug_handler]
+async fn post_register(
+    State(ctx): State<Arc<Context>>,
+    origin: RequestOrigin,
+    Json(request): Json<RegisterRequest>,
+) -> Response {
+    match service::user::register(&ctx, request, &origin).await {
+        PostRegisterResponse::Success => StatusCode::OK.into_response(),
+        PostRegisterResponse::AlreadyExists => StatusCode::CONFLICT.into_response(),
+        resp @ PostRegisterResponse::InvalidUsername { .. } => {
+            (StatusCode::BAD_REQUEST, Json(resp)).into_response()
+        }
+        PostRegisterResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn too_many_requests(retry_after_secs: u64) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(
+            axum::http::header::RETRY_AFTER,
+            retry_after_secs.to_string(),
+        )],
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/login",
+    tag = "user",
+    responses(
+        (status = 200, description = "Login successful", body = PostLoginResponseSuccess),
+        (
+            status = 202,
+            description = "The password is right, but the login has to be completed with a TOTP code at /api/user/login/second-factor",
+            body = SecondFactorChallenge
+        ),
+        (status = 401, description = "Invalid credentials", body = ()),
+        (
+            status = 403,
+            description = "The password is right, but the account is suspended or banned, or the password has to be reset",
+            body = PostLoginResponse
+        ),
+        (
+            status = 429,
+            description = "Too many failed logins for the username or from the IP address",
+            body = (),
+            headers(("Retry-After" = u64, description = "Seconds until the lockout is over"))
+        ),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = service::user::LoginRequest,
+)]
+async fn post_login(
+    State(ctx): State<Arc<Context>>,
+    origin: RequestOrigin,
+    Json(request): Json<service::user::LoginRequest>,
+) -> Response {
+    match service::user::login(&ctx, request, &origin).await {
+        PostLoginResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        PostLoginResponse::SecondFactorRequired(challenge) => {
+            (StatusCode::ACCEPTED, Json(challenge)).into_response()
+        }
+        PostLoginResponse::InvalidCredentials => StatusCode::UNAUTHORIZED.into_response(),
+        resp @ (PostLoginResponse::Restricted(_) | PostLoginResponse::PasswordResetRequired) => {
+            (StatusCode::FORBIDDEN, Json(resp)).into_response()
+        }
+        PostLoginResponse::TooManyRequests { retry_after_secs } => {
+            too_many_requests(retry_after_secs)
+        }
+        PostLoginResponse::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/login/second-factor",
+    tag = "user",
+    responses(
+        (status = 200, description = "Login successful", body = PostLoginResponseSuccess),
+        (status = 400, description = "Invalid or expired challenge token, so the login has to start over", body = ()),
+        (status = 401, description = "Wrong or already used code", body = ()),
+        (status = 403, description = "The account is suspended or banned", body = PostLoginSecondFactorResponse),
+        (
+            status = 429,
+            description = "Too many failed logins for the username or from the IP address",
+            body = (),
+            headers(("Retry-After" = u64, description = "Seconds until the lockout is over"))
+        ),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = SecondFactorLoginRequest,
+)]
+async fn post_login_second_factor(
+    State(ctx): State<Arc<Context>>,
+    origin: RequestOrigin,
+    Json(request): Json<SecondFactorLoginRequest>,
+) -> Response {
+    match service::totp::login(&ctx, request, &origin).await {
+        PostLoginSecondFactorResponse::Success(resp) => {
+            (StatusCode::OK, Json(resp)).into_response()
+        }
+        PostLoginSecondFactorResponse::InvalidChallenge => StatusCode::BAD_REQUEST.into_response(),
+        PostLoginSecondFactorResponse::InvalidCode => StatusCode::UNAUTHORIZED.into_response(),
+        resp @ PostLoginSecondFactorResponse::Restricted(_) => {
+            (StatusCode::FORBIDDEN, Json(resp)).into_response()
+        }
+        PostLoginSecondFactorResponse::TooManyRequests { retry_after_secs } => {
+            too_many_requests(retry_after_secs)
+        }
+        PostLoginSecondFactorResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/me/totp/enroll",
+    tag = "user",
+    responses(
+        (status = 200, description = "The secret to add to the authenticator app, pending confirmation", body = PostTotpEnrollResponseSuccess),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 404, description = "User not found", body = ()),
+        (status = 409, description = "TOTP is already enabled", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_totp_enroll(State(ctx): State<Arc<Context>>, AuthUser(claims): AuthUser) -> Response {
+    match service::totp::enroll(&ctx, claims).await {
+        PostTotpEnrollResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        PostTotpEnrollResponse::AlreadyEnabled => StatusCode::CONFLICT.into_response(),
+        PostTotpEnrollResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        PostTotpEnrollResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/me/totp/confirm",
+    tag = "user",
+    responses(
+        (status = 200, description = "TOTP is enabled. The recovery codes are shown only this once", body = PostTotpConfirmResponseSuccess),
+        (status = 400, description = "Wrong code", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 409, description = "There's no enrollment to confirm", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = TotpCodeRequest,
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_totp_confirm(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+    Json(request): Json<TotpCodeRequest>,
+) -> Response {
+    match service::totp::confirm(&ctx, claims, request).await {
+        PostTotpConfirmResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        PostTotpConfirmResponse::InvalidCode => StatusCode::BAD_REQUEST.into_response(),
+        PostTotpConfirmResponse::NotEnrolling => StatusCode::CONFLICT.into_response(),
+        PostTotpConfirmResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/me/totp/disable",
+    tag = "user",
+    responses(
+        (status = 200, description = "TOTP is disabled and the recovery codes are deleted", body = ()),
+        (status = 400, description = "Wrong or already used code", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 409, description = "TOTP isn't enabled", body = ()),
+        (
+            status = 429,
+            description = "Too many failed logins for the username or from the IP address",
+            body = (),
+            headers(("Retry-After" = u64, description = "Seconds until the lockout is over"))
+        ),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = TotpCodeRequest,
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_totp_disable(
+    State(ctx): State<Arc<Context>>,
+    ClientIp(ip): ClientIp,
+    AuthUser(claims): AuthUser,
+    Json(request): Json<TotpCodeRequest>,
+) -> Response {
+    match service::totp::disable(&ctx, claims, request, ip).await {
+        PostTotpDisableResponse::Success => StatusCode::OK.into_response(),
+        PostTotpDisableResponse::InvalidCode => StatusCode::BAD_REQUEST.into_response(),
+        PostTotpDisableResponse::NotEnabled => StatusCode::CONFLICT.into_response(),
+        PostTotpDisableResponse::TooManyRequests { retry_after_secs } => {
+            too_many_requests(retry_after_secs)
+        }
+        PostTotpDisableResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/me/passkeys/register/options",
+    tag = "user",
+    responses(
+        (status = 200, description = "The options for `navigator.credentials.create()`", body = PasskeyCreationOptions),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 404, description = "User not found", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_passkey_registration_options(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+) -> Response {
+    match service::passkey::registration_options(&ctx, claims).await {
+        PostPasskeyRegistrationOptionsResponse::Success(options) => {
+            (StatusCode::OK, Json(options)).into_response()
+        }
+        PostPasskeyRegistrationOptionsResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        PostPasskeyRegistrationOptionsResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/me/passkeys/register",
+    tag = "user",
+    responses(
+        (status = 200, description = "The passkey is registered", body = Passkey),
+        (status = 400, description = "Invalid name, challenge or credential", body = PostPasskeyRegistrationResponse),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 409, description = "The passkey is registered already", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = PasskeyRegistrationRequest,
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_passkey_registration(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+    Json(request): Json<PasskeyRegistrationRequest>,
+) -> Response {
+    match service::passkey::register(&ctx, claims, request).await {
+        PostPasskeyRegistrationResponse::Success(passkey) => {
+            (StatusCode::OK, Json(passkey)).into_response()
+        }
+        PostPasskeyRegistrationResponse::AlreadyRegistered => StatusCode::CONFLICT.into_response(),
+        PostPasskeyRegistrationResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        resp => (StatusCode::BAD_REQUEST, Json(resp)).into_response(),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/user/me/passkeys",
+    tag = "user",
+    responses(
+        (status = 200, description = "The passkeys of the current user", body = GetPasskeysResponseSuccess),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn get_passkeys(State(ctx): State<Arc<Context>>, AuthUser(claims): AuthUser) -> Response {
+    match service::passkey::list(&ctx, claims).await {
+        GetPasskeysResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        GetPasskeysResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/user/me/security-activity",
+    tag = "user",
+    responses(
+        (status = 200, description = "The recent logins, failed logins and other security-relevant events of the current user", body = GetSecurityActivityResponseSuccess),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn get_security_activity(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+) -> Response {
+    match service::audit::security_activity(&ctx, claims).await {
+        GetSecurityActivityResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        GetSecurityActivityResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/me/personal-access-tokens",
+    tag = "user",
+    responses(
+        (status = 200, description = "The token is created. It's shown only this once", body = PostPersonalAccessTokenResponseSuccess),
+        (status = 400, description = "Invalid name, scopes or expiry", body = PostPersonalAccessTokenResponse),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 409, description = "The user has as many tokens as allowed", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = CreatePersonalAccessTokenRequest,
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_personal_access_token(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+    Json(request): Json<CreatePersonalAccessTokenRequest>,
+) -> Response {
+    match service::personal_access_token::create(&ctx, claims, request).await {
+        PostPersonalAccessTokenResponse::Success(resp) => {
+            (StatusCode::OK, Json(resp)).into_response()
+        }
+        resp @ PostPersonalAccessTokenResponse::InvalidRequest { .. } => {
+            (StatusCode::BAD_REQUEST, Json(resp)).into_response()
+        }
+        PostPersonalAccessTokenResponse::TooManyTokens => StatusCode::CONFLICT.into_response(),
+        PostPersonalAccessTokenResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/user/me/personal-access-tokens",
+    tag = "user",
+    responses(
+        (status = 200, description = "The personal access tokens of the current user", body = GetPersonalAccessTokensResponseSuccess),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn get_personal_access_tokens(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+) -> Response {
+    match service::personal_access_token::list(&ctx, claims).await {
+        GetPersonalAccessTokensResponse::Success(resp) => {
+            (StatusCode::OK, Json(resp)).into_response()
+        }
+        GetPersonalAccessTokensResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Path)]
+struct PersonalAccessTokenIdPathParams {
+    token_id: i32,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/user/me/personal-access-tokens/{token_id}",
+    tag = "user",
+    responses(
+        (status = 200, description = "The token is revoked", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 404, description = "Personal access token not found", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(PersonalAccessTokenIdPathParams),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn delete_personal_access_token(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+    Path(params): Path<PersonalAccessTokenIdPathParams>,
+) -> Response {
+    match service::personal_access_token::delete(&ctx, claims, params.token_id).await {
+        DeletePersonalAccessTokenResponse::Success => StatusCode::OK.into_response(),
+        DeletePersonalAccessTokenResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        DeletePersonalAccessTokenResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Path)]
+struct PasskeyIdPathParams {
+    passkey_id: i32,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/user/me/passkeys/{passkey_id}",
+    tag = "user",
+    responses(
+        (status = 200, description = "The passkey is deleted", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 404, description = "Passkey not found", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(PasskeyIdPathParams),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn delete_passkey(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+    Path(params): Path<PasskeyIdPathParams>,
+) -> Response {
+    match service::passkey::delete(&ctx, claims, params.passkey_id).await {
+        DeletePasskeyResponse::Success => StatusCode::OK.into_response(),
+        DeletePasskeyResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        DeletePasskeyResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/passkeys/login/options",
+    tag = "user",
+    responses(
+        (status = 200, description = "The options for `navigator.credentials.get()`", body = PasskeyRequestOptions),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+)]
+async fn post_passkey_login_options(State(ctx): State<Arc<Context>>) -> Response {
+    match service::passkey::login_options(&ctx).await {
+        PostPasskeyLoginOptionsResponse::Success(options) => {
+            (StatusCode::OK, Json(options)).into_response()
+        }
+        PostPasskeyLoginOptionsResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/passkeys/login",
+    tag = "user",
+    responses(
+        (status = 200, description = "Login successful", body = PostLoginResponseSuccess),
+        (status = 400, description = "Invalid or expired challenge, so the login has to start over", body = ()),
+        (status = 401, description = "Unknown passkey or failed verification", body = PostPasskeyLoginResponse),
+        (status = 403, description = "The account is suspended or banned", body = PostPasskeyLoginResponse),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = PasskeyLoginRequest,
+)]
+async fn post_passkey_login(
+    State(ctx): State<Arc<Context>>,
+    origin: RequestOrigin,
+    Json(request): Json<PasskeyLoginRequest>,
+) -> Response {
+    match service::passkey::login(&ctx, request, &origin).await {
+        PostPasskeyLoginResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        PostPasskeyLoginResponse::InvalidChallenge => StatusCode::BAD_REQUEST.into_response(),
+        resp @ PostPasskeyLoginResponse::InvalidCredential { .. } => {
+            (StatusCode::UNAUTHORIZED, Json(resp)).into_response()
+        }
+        resp @ PostPasskeyLoginResponse::Restricted(_) => {
+            (StatusCode::FORBIDDEN, Json(resp)).into_response()
+        }
+        PostPasskeyLoginResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+fn lichess_authorize_response(resp: PostLichessAuthorizeResponse) -> Response {
+    match resp {
+        PostLichessAuthorizeResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        PostLichessAuthorizeResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/lichess/authorize",
+    tag = "user",
+    responses(
+        (status = 200, description = "The Lichess URL to send the user to for signing in", body = PostLichessAuthorizeResponseSuccess),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+)]
+async fn post_lichess_authorize(State(ctx): State<Arc<Context>>) -> Response {
+    lichess_authorize_response(service::lichess::authorize(&ctx, None).await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/lichess/callback",
+    tag = "user",
+    responses(
+        (status = 200, description = "Signed in, registering the user on the first sign-in", body = PostLoginResponseSuccess),
+        (
+            status = 202,
+            description = "The sign-in has to be completed with a TOTP code at /api/user/login/second-factor",
+            body = SecondFactorChallenge
+        ),
+        (status = 400, description = "Unknown, expired or already used state", body = ()),
+        (status = 403, description = "The linked account is suspended or banned", body = PostLichessSignInResponse),
+        (status = 409, description = "The Lichess username is taken by a user who isn't linked to the Lichess account", body = ()),
+        (status = 502, description = "Lichess rejected the authorization code or is unavailable", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = LichessCallbackRequest,
+)]
+async fn post_lichess_callback(
+    State(ctx): State<Arc<Context>>,
+    origin: RequestOrigin,
+    Json(request): Json<LichessCallbackRequest>,
+) -> Response {
+    match service::lichess::sign_in(&ctx, request, &origin).await {
+        PostLichessSignInResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        PostLichessSignInResponse::SecondFactorRequired(challenge) => {
+            (StatusCode::ACCEPTED, Json(challenge)).into_response()
+        }
+        PostLichessSignInResponse::InvalidState => StatusCode::BAD_REQUEST.into_response(),
+        resp @ PostLichessSignInResponse::Restricted(_) => {
+            (StatusCode::FORBIDDEN, Json(resp)).into_response()
+        }
+        PostLichessSignInResponse::UsernameTaken => StatusCode::CONFLICT.into_response(),
+        PostLichessSignInResponse::LichessUnavailable => StatusCode::BAD_GATEWAY.into_response(),
+        PostLichessSignInResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/me/lichess/authorize",
+    tag = "user",
+    responses(
+        (status = 200, description = "The Lichess URL to send the user to for linking their Lichess account", body = PostLichessAuthorizeResponseSuccess),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_me_lichess_authorize(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+) -> Response {
+    let resp = service::lichess::authorize(&ctx, Some(claims.sub.into())).await;
+    lichess_authorize_response(resp)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/me/lichess/callback",
+    tag = "user",
+    responses(
+        (status = 200, description = "The Lichess account is linked", body = ()),
+        (status = 400, description = "Unknown, expired or already used state, or one started by another user", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 409, description = "The Lichess account is linked to another user", body = ()),
+        (status = 502, description = "Lichess rejected the authorization code or is unavailable", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = LichessCallbackRequest,
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_me_lichess_callback(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+    Json(request): Json<LichessCallbackRequest>,
+) -> Response {
+    match service::lichess::link(&ctx, claims, request).await {
+        PostLichessLinkResponse::Success => StatusCode::OK.into_response(),
+        PostLichessLinkResponse::InvalidState => StatusCode::BAD_REQUEST.into_response(),
+        PostLichessLinkResponse::AlreadyLinked => StatusCode::CONFLICT.into_response(),
+        PostLichessLinkResponse::LichessUnavailable => StatusCode::BAD_GATEWAY.into_response(),
+        PostLichessLinkResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
 
 #[utoipa::path(
     post,
-    path = "/api/user/register",
+    path = "/api/user/refresh",
     tag = "user",
     responses(
-        (status = 200, description = "User registered successfully", body = ()),
-        (status = 409, description = "User already exists", body = ()),
+        (status = 200, description = "Tokens rotated successfully", body = PostRefreshResponseSuccess),
+        (status = 401, description = "Unknown, expired, revoked or already used refresh token", body = ()),
         (status = 500, description = "Internal server error", body = ()),
     ),
-    request_body = RegisterRequest,
+    request_body = RefreshRequest,
 )]
-#[axum::debug_handler]
-async fn post_register(
+async fn post_refresh(
     State(ctx): State<Arc<Context>>,
-    Json(request): Json<RegisterRequest>,
+    Json(request): Json<RefreshRequest>,
 ) -> Response {
-    match service::user::register(&ctx, request).await {
-        PostRegisterResponse::Success => StatusCode::OK.into_response(),
-        PostRegisterResponse::AlreadyExists => StatusCode::CONFLICT.into_response(),
-        PostRegisterResponse::InternalServerError => {
+    match service::user::refresh(&ctx, request).await {
+        PostRefreshResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
+        PostRefreshResponse::InvalidToken => StatusCode::UNAUTHORIZED.into_response(),
+        PostRefreshResponse::InternalServerError => {
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
     }
@@ -50,44 +717,417 @@ async fn post_register(
 
 #[utoipa::path(
     post,
-    path = "/api/user/login",
+    path = "/api/user/logout",
     tag = "user",
     responses(
-        (status = 200, description = "Login successful", body = PostLoginResponseSuccess),
-        (status = 401, description = "Invalid credentials", body = ()),
+        (status = 200, description = "Logged out successfully", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
         (status = 500, description = "Internal server error", body = ()),
     ),
-    request_body = service::user::LoginRequest,
+    request_body = LogoutRequest,
+    security(
+        ("bearerAuth" = [])
+    )
 )]
-async fn post_login(
+async fn post_logout(
     State(ctx): State<Arc<Context>>,
-    Json(request): Json<service::user::LoginRequest>,
+    AuthUser(claims): AuthUser,
+    Json(request): Json<LogoutRequest>,
 ) -> Response {
-    match service::user::login(&ctx, request).await {
-        PostLoginResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
-        PostLoginResponse::InvalidCredentials => StatusCode::UNAUTHORIZED.into_response(),
-        PostLoginResponse::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    match service::user::logout(&ctx, claims, request).await {
+        PostLogoutResponse::Success => StatusCode::OK.into_response(),
+        PostLogoutResponse::Unauthorized => StatusCode::UNAUTHORIZED.into_response(),
+        PostLogoutResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
     }
 }
 
 #[utoipa::path(
     post,
-    path = "/api/user/salt",
+    path = "/api/user/logout-everywhere",
+    tag = "user",
+    responses(
+        (status = 200, description = "Logged out of every session and deleted the personal access tokens successfully", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_logout_everywhere(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+) -> Response {
+    match service::user::logout_everywhere(&ctx, claims).await {
+        PostLogoutEverywhereResponse::Success => StatusCode::OK.into_response(),
+        PostLogoutEverywhereResponse::Unauthorized => StatusCode::UNAUTHORIZED.into_response(),
+        PostLogoutEverywhereResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/user/me",
+    tag = "user",
+    responses(
+        (status = 200, description = "Profile retrieved successfully", body = UserProfile),
+        (status = 401, description = "Missing or invalid token", body = ()),
+        (status = 403, description = "The personal access token lacks the `profile:read` scope", body = ()),
+        (status = 404, description = "User not found", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = []),
+        ("personalAccessToken" = ["profile:read"])
+    )
+)]
+async fn get_me(
+    State(ctx): State<Arc<Context>>,
+    RequireScope {
+        user: AuthUser(claims),
+        ..
+    }: RequireScope<ProfileRead>,
+) -> Response {
+    match service::user::get_me(&ctx, claims).await {
+        GetMeResponse::Success(profile) => (StatusCode::OK, Json(profile)).into_response(),
+        GetMeResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        GetMeResponse::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/user/me",
+    tag = "user",
+    responses(
+        (status = 200, description = "Profile edited successfully", body = UserProfile),
+        (status = 400, description = "Invalid field value", body = PatchMeResponse),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 404, description = "User not found", body = ()),
+        (status = 409, description = "The value is already used by another user", body = PatchMeResponse),
+        (status = 412, description = "The profile has been edited since `version` was read", body = PatchMeResponse),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = PatchMeRequest,
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn patch_me(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+    Json(request): Json<PatchMeRequest>,
+) -> Response {
+    let resp = service::user::patch_me(&ctx, claims, request).await;
+    let status = match &resp {
+        PatchMeResponse::Success(_) => StatusCode::OK,
+        PatchMeResponse::InvalidField { .. } => StatusCode::BAD_REQUEST,
+        PatchMeResponse::AlreadyTaken { .. } => StatusCode::CONFLICT,
+        PatchMeResponse::VersionMismatch { .. } => StatusCode::PRECONDITION_FAILED,
+        PatchMeResponse::NotFound => StatusCode::NOT_FOUND,
+        PatchMeResponse::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    match resp {
+        PatchMeResponse::Success(profile) => (status, Json(profile)).into_response(),
+        PatchMeResponse::NotFound | PatchMeResponse::InternalServerError => status.into_response(),
+        resp => (status, Json(resp)).into_response(),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/user/me",
+    tag = "user",
+    responses(
+        (status = 202, description = "The account will be deleted after the grace period", body = DeleteUserResponseScheduled),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 404, description = "User not found", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn delete_me(State(ctx): State<Arc<Context>>, AuthUser(claims): AuthUser) -> Response {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    delete_user_response(service::account_deletion::schedule(&ctx, user_id).await)
+}
+
+pub(in crate::requests::api) fn delete_user_response(resp: DeleteUserResponse) -> Response {
+    match resp {
+        DeleteUserResponse::Scheduled(resp) => (StatusCode::ACCEPTED, Json(resp)).into_response(),
+        DeleteUserResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        DeleteUserResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/me/cancel-deletion",
+    tag = "user",
+    responses(
+        (status = 200, description = "The account will be kept", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 409, description = "No deletion is scheduled", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_cancel_deletion(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+) -> Response {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    cancel_user_deletion_response(service::account_deletion::cancel(&ctx, user_id).await)
+}
+
+pub(in crate::requests::api) fn cancel_user_deletion_response(
+    resp: PostCancelUserDeletionResponse,
+) -> Response {
+    match resp {
+        PostCancelUserDeletionResponse::Success => StatusCode::OK.into_response(),
+        PostCancelUserDeletionResponse::NotScheduled => StatusCode::CONFLICT.into_response(),
+        PostCancelUserDeletionResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/me/export",
+    tag = "user",
+    responses(
+        (
+            status = 202,
+            description = "The export has been requested, or was already pending, and its status can be polled at the `Location`",
+            body = DataExport
+        ),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_data_export(State(ctx): State<Arc<Context>>, AuthUser(claims): AuthUser) -> Response {
+    match service::data_export::request(&ctx, claims).await {
+        PostDataExportResponse::Accepted(export) => {
+            let location = format!("/api/user/me/export/{}", export.id);
+            (
+                StatusCode::ACCEPTED,
+                [(axum::http::header::LOCATION, location)],
+                Json(export),
+            )
+                .into_response()
+        }
+        PostDataExportResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Path)]
+struct ExportIdPathParams {
+    export_id: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/user/me/export/{export_id}",
+    tag = "user",
+    responses(
+        (status = 200, description = "The status of the export", body = DataExport),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
+        (status = 404, description = "Export not found", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(ExportIdPathParams),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn get_data_export(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+    Path(params): Path<ExportIdPathParams>,
+) -> Response {
+    match service::data_export::status(&ctx, claims, params.export_id).await {
+        GetDataExportResponse::Success(export) => (StatusCode::OK, Json(export)).into_response(),
+        GetDataExportResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        GetDataExportResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/user/export/download",
+    tag = "user",
+    responses(
+        (
+            status = 200,
+            description = "The ZIP archive of the export",
+            body = Binary,
+            content_type = "application/zip"
+        ),
+        (status = 403, description = "Invalid or expired download link", body = ()),
+        (status = 404, description = "The export has expired", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    params(DownloadQueryParams)
+)]
+async fn get_data_export_download(
+    State(ctx): State<Arc<Context>>,
+    Query(params): Query<DownloadQueryParams>,
+) -> Response {
+    service::data_export::download(&ctx, params).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/me/email-verification",
     tag = "user",
     responses(
-        (status = 200, description = "User salt retrieved successfully", body = PostSaltResponseSuccess),
+        (status = 202, description = "The verification link will be sent shortly", body = ()),
+        (status = 401, description = "Missing or invalid JWT", body = ()),
         (status = 404, description = "User not found", body = ()),
+        (status = 409, description = "There's no unverified email address to verify", body = PostSendEmailVerificationResponse),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    security(
+        ("bearerAuth" = [])
+    )
+)]
+async fn post_send_email_verification(
+    State(ctx): State<Arc<Context>>,
+    AuthUser(claims): AuthUser,
+) -> Response {
+    let resp = service::email_verification::send(&ctx, claims).await;
+    match resp {
+        PostSendEmailVerificationResponse::Success => StatusCode::ACCEPTED.into_response(),
+        PostSendEmailVerificationResponse::NoEmail
+        | PostSendEmailVerificationResponse::AlreadyVerified
+        | PostSendEmailVerificationResponse::Conflict => {
+            (StatusCode::CONFLICT, Json(resp)).into_response()
+        }
+        PostSendEmailVerificationResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+        PostSendEmailVerificationResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/verify-email",
+    tag = "user",
+    responses(
+        (status = 200, description = "The email address is verified", body = ()),
+        (status = 400, description = "Malformed, expired, already redeemed or superseded token", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = VerifyEmailRequest,
+)]
+async fn post_verify_email(
+    State(ctx): State<Arc<Context>>,
+    Json(request): Json<VerifyEmailRequest>,
+) -> Response {
+    match service::email_verification::verify(&ctx, request).await {
+        PostVerifyEmailResponse::Success => StatusCode::OK.into_response(),
+        PostVerifyEmailResponse::InvalidToken => StatusCode::BAD_REQUEST.into_response(),
+        PostVerifyEmailResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/password-reset/request",
+    tag = "user",
+    responses(
+        (status = 202, description = "If the email address belongs to an account and is verified, a reset link will be sent shortly", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = PasswordResetRequest,
+)]
+async fn post_password_reset_request(
+    State(ctx): State<Arc<Context>>,
+    Json(request): Json<PasswordResetRequest>,
+) -> Response {
+    match service::password_reset::request(&ctx, request).await {
+        PostPasswordResetRequestResponse::Success => StatusCode::ACCEPTED.into_response(),
+        PostPasswordResetRequestResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/password-reset/confirm",
+    tag = "user",
+    responses(
+        (status = 200, description = "Password changed and every session logged out", body = ()),
+        (status = 400, description = "Unknown, expired or already used token", body = ()),
+        (status = 500, description = "Internal server error", body = ()),
+    ),
+    request_body = PasswordResetConfirmRequest,
+)]
+async fn post_password_reset_confirm(
+    State(ctx): State<Arc<Context>>,
+    Json(request): Json<PasswordResetConfirmRequest>,
+) -> Response {
+    match service::password_reset::confirm(&ctx, request).await {
+        PostPasswordResetConfirmResponse::Success => StatusCode::OK.into_response(),
+        PostPasswordResetConfirmResponse::InvalidToken => StatusCode::BAD_REQUEST.into_response(),
+        PostPasswordResetConfirmResponse::InternalServerError => {
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/user/salt",
+    tag = "user",
+    responses(
+        (
+            status = 200,
+            description = "The client salt of the user, or an indistinguishable fake one if the username is unknown",
+            body = PostSaltResponseSuccess
+        ),
+        (
+            status = 429,
+            description = "Too many failed logins for the username or from the IP address",
+            body = (),
+            headers(("Retry-After" = u64, description = "Seconds until the lockout is over"))
+        ),
         (status = 500, description = "Internal server error", body = ()),
     ),
     request_body = SaltRequest,
 )]
 async fn post_salt(
     State(ctx): State<Arc<Context>>,
+    ClientIp(ip): ClientIp,
     Json(request): Json<service::user::SaltRequest>,
 ) -> Response {
-    match service::user::salt(&ctx, request).await {
+    match service::user::salt(&ctx, request, ip).await {
         PostSaltResponse::Success(resp) => (StatusCode::OK, Json(resp)).into_response(),
-        PostSaltResponse::UserNotFound => StatusCode::NOT_FOUND.into_response(),
+        PostSaltResponse::TooManyRequests { retry_after_secs } => {
+            too_many_requests(retry_after_secs)
+        }
         PostSaltResponse::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
     }
 }
@@ -99,26 +1139,36 @@ async fn post_salt(
     responses(
         (status = 200, description = "Avatar uploaded successfully", body = String),
         (status = 400, description = "Bad request", body = String),
-        (status = 401, description = "Missing or invalid JWT", body = String),
+        (status = 401, description = "Missing or invalid token", body = ()),
+        (status = 403, description = "The personal access token lacks the `avatar:write` scope", body = ()),
+        (status = 413, description = "The avatar is too large or would exceed the storage quota", body = String),
         (status = 500, description = "Internal server error", body = Option<String>),
     ),
     request_body(content_type = "multipart/form-data", content = UploadUserAvatarRequest),
     security(
-        ("bearer_auth" = [])
+        ("bearerAuth" = []),
+        ("personalAccessToken" = ["avatar:write"])
     )
 )]
 async fn post_upload_user_avatar(
     State(ctx): State<Arc<Context>>,
-    Extension(claims): Extension<Option<shared_items_lib::JwtClaims>>,
+    origin: RequestOrigin,
+    RequireScope {
+        user: AuthUser(claims),
+        ..
+    }: RequireScope<AvatarWrite>,
     multipart: axum::extract::Multipart,
 ) -> Response {
-    match service::user::upload_user_avatar(&ctx, claims, multipart).await {
+    match service::user::upload_user_avatar(&ctx, claims, &origin, multipart).await {
         PostUploadUserAvatarResponse::Success(PostUploadUserAvatarSuccess { url }) => {
             (StatusCode::OK, url).into_response()
         }
         PostUploadUserAvatarResponse::BadRequest { detail } => {
             (StatusCode::BAD_REQUEST, detail).into_response()
         }
+        PostUploadUserAvatarResponse::PayloadTooLarge { detail } => {
+            (StatusCode::PAYLOAD_TOO_LARGE, detail).into_response()
+        }
         PostUploadUserAvatarResponse::InternalServerError { detail } => {
             if let Some(detail) = detail {
                 (StatusCode::INTERNAL_SERVER_ERROR, detail).into_response()
@@ -149,37 +1199,96 @@ async fn post_upload_user_avatar(
             body = Binary,
             content_type = "image/jpeg"
         ),
+        (
+            status = 200,
+            description = "Successfully retrieved the webp rendition of the avatar",
+            body = Binary,
+            content_type = "image/webp"
+        ),
+        (status = 400, description = "Unsupported avatar size", body = String),
+        (status = 404, description = "The user has no avatar", body = ()),
         (status = 500, description = "Internal server error", body = Option<String>),
     ),
-    params(UserIdPathParams)
+    params(UserIdPathParams, AvatarQueryParams)
 )]
 async fn get_user_avatar(
     State(ctx): State<Arc<Context>>,
     Path(params): Path<UserIdPathParams>,
+    Query(query): Query<AvatarQueryParams>,
 ) -> Response {
     let UserIdPathParams { user_id } = params;
     let user_id: mnln_core_items::id::UserId = user_id.into();
-    service::user::get_user_avatar(&ctx, user_id).await
+    service::user::get_user_avatar(&ctx, user_id, query).await
 }
 
-fn user_routes(ctx: Arc<Context>) -> Router<Arc<Context>> {
+fn user_routes() -> Router<Arc<Context>> {
     Router::new()
         .route("/register", post(post_register))
         .route("/login", post(post_login))
+        .route("/login/second-factor", post(post_login_second_factor))
+        .route("/refresh", post(post_refresh))
+        .route("/lichess/authorize", post(post_lichess_authorize))
+        .route("/lichess/callback", post(post_lichess_callback))
+        .route("/logout", post(post_logout))
+        .route("/logout-everywhere", post(post_logout_everywhere))
+        .route(
+            "/me",
+            axum::routing::get(get_me).patch(patch_me).delete(delete_me),
+        )
+        .route("/me/cancel-deletion", post(post_cancel_deletion))
+        .route("/me/lichess/authorize", post(post_me_lichess_authorize))
+        .route("/me/lichess/callback", post(post_me_lichess_callback))
+        .route("/me/totp/enroll", post(post_totp_enroll))
+        .route("/me/totp/confirm", post(post_totp_confirm))
+        .route("/me/totp/disable", post(post_totp_disable))
+        .route(
+            "/me/passkeys/register/options",
+            post(post_passkey_registration_options),
+        )
+        .route("/me/passkeys/register", post(post_passkey_registration))
+        .route("/me/passkeys", axum::routing::get(get_passkeys))
+        .route(
+            "/me/passkeys/{passkey_id}",
+            axum::routing::delete(delete_passkey),
+        )
+        .route(
+            "/me/security-activity",
+            axum::routing::get(get_security_activity),
+        )
+        .route(
+            "/me/personal-access-tokens",
+            axum::routing::get(get_personal_access_tokens).post(post_personal_access_token),
+        )
+        .route(
+            "/me/personal-access-tokens/{token_id}",
+            axum::routing::delete(delete_personal_access_token),
+        )
+        .route("/passkeys/login/options", post(post_passkey_login_options))
+        .route("/passkeys/login", post(post_passkey_login))
+        .route("/me/export", post(post_data_export))
+        .route(
+            "/me/export/{export_id}",
+            axum::routing::get(get_data_export),
+        )
+        .route(
+            "/export/download",
+            axum::routing::get(get_data_export_download),
+        )
+        .route("/me/email-verification", post(post_send_email_verification))
+        .route("/verify-email", post(post_verify_email))
+        .route("/password-reset/request", post(post_password_reset_request))
+        .route("/password-reset/confirm", post(post_password_reset_confirm))
         .route("/salt", post(post_salt))
+        // The size of the avatar is limited while it's streamed, see `AvatarEnv::max_upload_bytes`
         .route(
             "/upload-avatar",
-            post(post_upload_user_avatar).layer(axum::middleware::from_fn_with_state(
-                ctx,
-                crate::middleware::add_jwt_claims_extension,
-            )),
+            post(post_upload_user_avatar).layer(axum::extract::DefaultBodyLimit::disable()),
         )
         .route("/{user_id}/avatar", axum::routing::get(get_user_avatar))
 }
 
 pub(in crate::requests::api) fn add_nested_routes(
     router: axum::Router<Arc<Context>>,
-    ctx: Arc<Context>,
 ) -> axum::Router<Arc<Context>> {
-    router.nest("/user", user_routes(ctx))
+    router.nest("/user", user_routes())
 }
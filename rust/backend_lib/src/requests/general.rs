@@ -1,8 +1,12 @@
+use std::sync::Arc;
+
 use utoipa::OpenApi as _;
 
+use axum::extract::State;
 use axum::routing::get;
 use utoipa_swagger_ui::SwaggerUi;
 
+use crate::Context;
 use crate::requests::ApiDoc;
 
 const HEALTH_CHECK_OK: &str = r#"{
@@ -23,12 +27,28 @@ async fn get_supported_img_formats() -> &'static str {
     browser_supported_img_format::BrowserSupportedImgFormat::accept_str()
 }
 
-pub(in crate::requests) fn add_routes<S>(router: axum::Router<S>) -> axum::Router<S>
-where
-    S: Clone + Send + Sync + 'static,
-{
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    tag = "auth",
+    responses(
+        (status = 200, description = "The public keys that the access tokens are signed with, as a JWK Set (<https://www.rfc-editor.org/rfc/rfc7517#section-5>)", body = Object),
+    )
+)]
+async fn get_jwks(State(ctx): State<Arc<Context>>) -> impl axum::response::IntoResponse {
+    // Verifiers may cache the keys for a while, the key ring only changes on redeploys.
+    (
+        [(axum::http::header::CACHE_CONTROL, "public, max-age=300")],
+        axum::Json(ctx.key_ring.jwks().clone()),
+    )
+}
+
+pub(in crate::requests) fn add_routes(
+    router: axum::Router<Arc<Context>>,
+) -> axum::Router<Arc<Context>> {
     router
         .route("/health-check", get(|| async { HEALTH_CHECK_OK }))
         .route("/supported-img-formats", get(get_supported_img_formats))
+        .route("/.well-known/jwks.json", get(get_jwks))
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
 }
@@ -11,10 +11,10 @@ mod api;
 mod general;
 
 #[derive(ToSchema)]
+#[allow(dead_code)]
 pub(crate) struct Binary {
     /// The uploaded file content
     #[schema(format = "binary")]
-    #[allow(dead_code)]
     pub content: String, // Or Vec<u8> depending on your implementation
 }
 
@@ -35,6 +35,18 @@ impl utoipa::Modify for SecurityAddon {
                     .build(),
             ),
         );
+        openapi.components.as_mut().unwrap().add_security_scheme(
+            "personalAccessToken",
+            utoipa::openapi::security::SecurityScheme::Http(
+                utoipa::openapi::security::HttpBuilder::new()
+                    .scheme(utoipa::openapi::security::HttpAuthScheme::Bearer)
+                    .bearer_format("mnln_pat_...")
+                    .description(Some(
+                        "A personal access token, accepted by the endpoints that list its scope",
+                    ))
+                    .build(),
+            ),
+        );
     }
 }
 
@@ -49,6 +61,35 @@ impl utoipa::Modify for SecurityAddon {
 )]
 pub struct ApiDoc;
 
+/// The URI with the values of the query parameters redacted, since some of them are credentials,
+/// e.g. the `token` of the data export download links.
+fn redacted_uri(uri: &axum::http::Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.path().to_string();
+    };
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) => format!("{name}=[redacted]"),
+            None => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{query}", uri.path())
+}
+
+/// The headers with the credentials redacted, i.e. the access tokens, the personal access tokens
+/// and the cookies.
+fn redacted_headers(headers: &axum::http::HeaderMap) -> axum::http::HeaderMap {
+    let mut headers = headers.clone();
+    for (name, value) in headers.iter_mut() {
+        if name == axum::http::header::AUTHORIZATION || name == axum::http::header::COOKIE {
+            *value = axum::http::HeaderValue::from_static("[redacted]");
+        }
+    }
+    headers
+}
+
 macro_rules! make_trace_layer {
     () => {
         tower_http::trace::TraceLayer::new_for_http()
@@ -56,8 +97,8 @@ macro_rules! make_trace_layer {
                 tracing::info!(
                     "Received request: {} {}. Headers: {:?}",
                     request.method(),
-                    request.uri(),
-                    request.headers()
+                    redacted_uri(request.uri()),
+                    redacted_headers(request.headers())
                 );
             })
             .on_response(
@@ -89,7 +130,7 @@ macro_rules! make_trace_layer {
 pub fn make_router(ctx: Arc<Context>) -> anyhow::Result<axum::Router> {
     let router = axum::Router::new();
     let router = general::add_routes(router);
-    let router = api::add_nested_routes(router, ctx.clone());
+    let router = api::add_nested_routes(router);
 
     let base_frontend_url: axum::http::HeaderValue = ctx.env.base_frontend_url.parse()?;
 
@@ -108,3 +149,42 @@ pub fn make_router(ctx: Arc<Context>) -> anyhow::Result<axum::Router> {
         .with_state(ctx);
     Ok(router)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{redacted_headers, redacted_uri};
+
+    #[test]
+    fn the_query_values_are_redacted() {
+        let uri = "/api/user/export/download?token=eyJ.secret&flag"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            redacted_uri(&uri),
+            "/api/user/export/download?token=[redacted]&flag"
+        );
+        assert_eq!(
+            redacted_uri(&"/api/user/me".parse().unwrap()),
+            "/api/user/me"
+        );
+    }
+
+    #[test]
+    fn the_credentials_are_redacted_from_the_headers() {
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(
+            axum::http::header::AUTHORIZATION,
+            "Bearer mnln_pat_secret".parse().unwrap(),
+        );
+        headers.insert(
+            axum::http::header::COOKIE,
+            "refresh_token=secret".parse().unwrap(),
+        );
+        headers.insert(axum::http::header::USER_AGENT, "curl/8.0".parse().unwrap());
+
+        let headers = redacted_headers(&headers);
+        assert_eq!(headers[axum::http::header::AUTHORIZATION], "[redacted]");
+        assert_eq!(headers[axum::http::header::COOKIE], "[redacted]");
+        assert_eq!(headers[axum::http::header::USER_AGENT], "curl/8.0");
+    }
+}
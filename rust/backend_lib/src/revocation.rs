@@ -0,0 +1,149 @@
+//! In-memory view of the revoked access tokens and of the suspended or banned users.
+//!
+//! Checking every request against Postgres would put a query on the hot path, so each replica
+//! keeps a snapshot of the revocations that can still affect non-expired access tokens and
+//! reloads it periodically. Revocations made by this replica are applied to the snapshot
+//! immediately; those made by other replicas become visible after at most [`RELOAD_INTERVAL`].
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use shared_items_lib::{JwtClaims, Timestamp};
+
+use crate::db::{self, Db};
+use crate::service::session;
+use crate::util;
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Default)]
+struct Snapshot {
+    jtis: HashSet<String>,
+    /// User ID -> UNIX timestamp (ms) before which (inclusive) all access tokens are revoked
+    sessions_revoked_at: HashMap<i32, u64>,
+    /// User ID -> UNIX timestamp (ms) until which the user is restricted, `None` for good
+    restricted_until: HashMap<i32, Option<u64>>,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct RevocationCache {
+    snapshot: Arc<RwLock<Snapshot>>,
+}
+
+impl RevocationCache {
+    /// Both the `iat` and the moments of revocation are stamped by the replicas' clocks,
+    /// see [`db::revocation::revoke_all_sessions`].
+    pub(crate) fn is_revoked(&self, claims: &JwtClaims) -> bool {
+        let snapshot = self.snapshot.read().unwrap_or_else(|e| e.into_inner());
+        if snapshot.jtis.contains(&claims.jti) {
+            return true;
+        }
+        snapshot
+            .sessions_revoked_at
+            .get(&claims.sub.0)
+            // `iat` is in seconds, so the tokens issued later within the second of the revocation
+            // are revoked as well
+            .is_some_and(|revoked_at| Timestamp::from(claims.iat).0 <= *revoked_at)
+    }
+
+    /// Whether the user is suspended or banned, so that none of their access tokens are accepted.
+    pub(crate) fn is_restricted(&self, claims: &JwtClaims) -> bool {
+        let snapshot = self.snapshot.read().unwrap_or_else(|e| e.into_inner());
+        snapshot
+            .restricted_until
+            .get(&claims.sub.0)
+            .is_some_and(|until| until.is_none_or(|until| util::now().0 < until))
+    }
+
+    pub(crate) fn insert_jti(&self, jti: String) {
+        let mut snapshot = self.snapshot.write().unwrap_or_else(|e| e.into_inner());
+        snapshot.jtis.insert(jti);
+    }
+
+    pub(crate) fn insert_sessions_revoked_at(
+        &self,
+        user_id: mnln_core_items::id::UserId,
+        revoked_at_ms: u64,
+    ) {
+        let mut snapshot = self.snapshot.write().unwrap_or_else(|e| e.into_inner());
+        snapshot
+            .sessions_revoked_at
+            .insert(user_id.0, revoked_at_ms);
+    }
+
+    pub(crate) fn insert_restriction(
+        &self,
+        user_id: mnln_core_items::id::UserId,
+        restricted_until_ms: Option<u64>,
+    ) {
+        let mut snapshot = self.snapshot.write().unwrap_or_else(|e| e.into_inner());
+        snapshot
+            .restricted_until
+            .insert(user_id.0, restricted_until_ms);
+    }
+
+    pub(crate) fn remove_restriction(&self, user_id: mnln_core_items::id::UserId) {
+        let mut snapshot = self.snapshot.write().unwrap_or_else(|e| e.into_inner());
+        snapshot.restricted_until.remove(&user_id.0);
+    }
+
+    async fn reload(&self, db: &Db) -> sqlx::Result<()> {
+        let db::revocation::load::Snapshot {
+            jtis,
+            sessions_revoked_at,
+            restricted,
+        } = db::revocation::load(db, session::access_token_ttl()).await?;
+
+        let sessions_revoked_at = sessions_revoked_at
+            .into_iter()
+            .map(|row| {
+                let user_id: mnln_core_items::id::UserId = row.user_id.into();
+                (user_id.0, row.revoked_at_ms as u64)
+            })
+            .collect();
+
+        let restricted_until = restricted
+            .into_iter()
+            .map(|row| {
+                let user_id: mnln_core_items::id::UserId = row.user_id.into();
+                (user_id.0, row.restricted_until_ms.map(|ms| ms as u64))
+            })
+            .collect();
+
+        let snapshot = Snapshot {
+            jtis: jtis.into_iter().collect(),
+            sessions_revoked_at,
+            restricted_until,
+        };
+
+        *self.snapshot.write().unwrap_or_else(|e| e.into_inner()) = snapshot;
+        Ok(())
+    }
+
+    /// Loads the initial snapshot and keeps it up to date in a background task.
+    pub(crate) async fn spawn_reloader(&self, db: Db) -> anyhow::Result<()> {
+        self.reload(&db).await?;
+
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if let Err(err) = cache.reload(&db).await {
+                    tracing::error!("Failed to reload the revoked access tokens: {err}");
+                }
+                match db::revocation::prune_expired(&db).await {
+                    Ok(0) => (),
+                    Ok(n) => tracing::trace!("Pruned {n} expired revoked access tokens"),
+                    Err(err) => {
+                        tracing::error!("Failed to prune the expired revoked access tokens: {err}")
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
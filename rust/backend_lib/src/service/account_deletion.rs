@@ -0,0 +1,135 @@
+//! Deleting accounts after a grace period during which the deletion can be cancelled.
+//!
+//! Deleting an account only schedules it. Once the grace period is over, the purger deletes
+//! the files of the user from the object storage and then the user, whose dependent rows are
+//! deleted through `ON DELETE CASCADE`.
+
+use std::time::Duration;
+
+use mnln_env::Env;
+use shared_items_lib::Timestamp;
+use shared_items_lib::service_responses::{
+    DeleteUserResponse, DeleteUserResponseScheduled, PostCancelUserDeletionResponse,
+};
+
+use crate::Context;
+use crate::db::{self, Db};
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+fn grace_period() -> chrono::Duration {
+    chrono::Duration::days(14)
+}
+
+/// How long a failed deletion waits before it's retried.
+fn retry_delay() -> chrono::Duration {
+    chrono::Duration::hours(1)
+}
+
+pub(crate) async fn schedule(
+    ctx: &Context,
+    user_id: mnln_core_items::id::UserId,
+) -> DeleteUserResponse {
+    match db::account_deletion::schedule(&ctx.db, user_id.into(), grace_period()).await {
+        Ok(Some(scheduled_at_ms)) => {
+            tracing::info!(
+                "The function {mod_path}::{fn_name}(...) succeeded: user with ID {user_id} will be deleted at {scheduled_at_ms}",
+                mod_path = module_path!(),
+                fn_name = stringify!(schedule),
+            );
+            DeleteUserResponse::Scheduled(DeleteUserResponseScheduled {
+                deletion_scheduled_at: Timestamp(scheduled_at_ms as u64),
+            })
+        }
+        Ok(None) => DeleteUserResponse::NotFound,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(schedule),
+                err = e,
+            );
+            DeleteUserResponse::InternalServerError
+        }
+    }
+}
+
+pub(crate) async fn cancel(
+    ctx: &Context,
+    user_id: mnln_core_items::id::UserId,
+) -> PostCancelUserDeletionResponse {
+    match db::account_deletion::cancel(&ctx.db, user_id.into()).await {
+        Ok(true) => PostCancelUserDeletionResponse::Success,
+        Ok(false) => PostCancelUserDeletionResponse::NotScheduled,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(cancel),
+                err = e,
+            );
+            PostCancelUserDeletionResponse::InternalServerError
+        }
+    }
+}
+
+/// Deletes the files of the locked user and then the user.
+///
+/// The files go first: if deleting them fails, the user is still there to retry with.
+async fn purge(
+    env: &Env,
+    tx: &mut sqlx::PgConnection,
+    user_id: db::id::UserId,
+) -> anyhow::Result<usize> {
+    let deleted_objects = object_storage::delete_user_objects(env, user_id.into()).await?;
+    db::account_deletion::delete(tx, user_id).await?;
+    Ok(deleted_objects)
+}
+
+/// Deletes the account whose deletion is overdue the most, or postpones it if that fails.
+///
+/// Returns `false` if there was none.
+async fn purge_next(env: &Env, db: &Db) -> anyhow::Result<bool> {
+    let mut tx = db.begin().await?;
+    let Some(user_id) = db::account_deletion::lock_next_due(&mut tx).await? else {
+        return Ok(false);
+    };
+
+    match purge(env, &mut tx, user_id).await {
+        Ok(deleted_objects) => {
+            tx.commit().await?;
+            tracing::info!("Deleted user with ID {user_id} and their {deleted_objects} files");
+        }
+        Err(err) => {
+            // Otherwise, the user would stay the most overdue one and be retried forever
+            tx.rollback().await?;
+            db::account_deletion::postpone(db, user_id, retry_delay()).await?;
+            tracing::error!(
+                "Failed to delete user with ID {user_id}, retrying in {minutes} min: {err:#}",
+                minutes = retry_delay().num_minutes(),
+            );
+        }
+    }
+    Ok(true)
+}
+
+/// Periodically deletes the accounts whose grace period is over.
+pub(crate) fn spawn_purger(env: Env, db: Db) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            loop {
+                match purge_next(&env, &db).await {
+                    Ok(true) => (),
+                    Ok(false) => break,
+                    Err(err) => {
+                        tracing::error!("Failed to delete an account: {err:#}");
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,385 @@
+//! Managing the users as an admin: listing and viewing them, changing their roles and keeping
+//! them from logging in.
+//!
+//! A suspension or a ban logs the user out everywhere, and the access tokens issued before are
+//! rejected anyway while it's active, see [`crate::revocation`]. The logins check it as well, see
+//! [`login_restriction`].
+
+use shared_items_lib::service_responses::{
+    AccountRestriction, AccountRestrictionKind, AdminUser, AuditEventDetails,
+    DeleteAdminUserRestrictionResponse, GetAdminUserResponse, GetAdminUsersResponse,
+    GetAdminUsersResponseSuccess, PutAdminUserRestrictionResponse, PutAdminUserRoleResponse,
+};
+use shared_items_lib::{JwtClaims, Role, Timestamp};
+
+use crate::request_origin::RequestOrigin;
+use crate::service::audit;
+use crate::{Context, db, util};
+
+const DEFAULT_PER_PAGE: u32 = 50;
+const MAX_PER_PAGE: u32 = 100;
+const MAX_REASON_LEN: usize = 500;
+
+fn restriction(
+    restriction: db::admin::Restriction,
+    reason: String,
+    restricted_until_ms: Option<i64>,
+) -> AccountRestriction {
+    AccountRestriction {
+        kind: restriction.into(),
+        reason,
+        expires_at: restricted_until_ms.map(|ms| Timestamp(ms as u64)),
+    }
+}
+
+impl From<db::admin::get_user::User> for AdminUser {
+    fn from(value: db::admin::get_user::User) -> Self {
+        let db::admin::get_user::User {
+            id,
+            username,
+            email,
+            email_verified,
+            role,
+            created_at_ms,
+            restriction: user_restriction,
+            restriction_reason,
+            restricted_until_ms,
+            password_reset_required,
+            totp_enabled,
+            passkey_count,
+            deletion_scheduled_at_ms,
+        } = value;
+        let id: mnln_core_items::id::UserId = id.into();
+        let is_active = restricted_until_ms.is_none_or(|until| until as u64 > util::now().0);
+        AdminUser {
+            id: id.into(),
+            username,
+            email,
+            email_verified,
+            role: role.into(),
+            created_at: created_at_ms.map(|ms| Timestamp(ms as u64)),
+            restriction: user_restriction
+                .filter(|_| is_active)
+                .map(|user_restriction| {
+                    restriction(
+                        user_restriction,
+                        restriction_reason.unwrap_or_default(),
+                        restricted_until_ms,
+                    )
+                }),
+            password_reset_required,
+            totp_enabled,
+            passkey_count: passkey_count as u32,
+            deletion_scheduled_at: deletion_scheduled_at_ms.map(|ms| Timestamp(ms as u64)),
+        }
+    }
+}
+
+fn admin_id(admin: &JwtClaims) -> db::id::UserId {
+    let admin_id: mnln_core_items::id::UserId = admin.sub.into();
+    admin_id.into()
+}
+
+/// The restriction that keeps the user from logging in, checked by every kind of login right
+/// before starting the session.
+pub(crate) async fn login_restriction(
+    ctx: &Context,
+    user_id: db::id::UserId,
+) -> sqlx::Result<Option<AccountRestriction>> {
+    let active = db::admin::active_restriction(&ctx.db, user_id).await?;
+    Ok(active.map(|active| {
+        let db::admin::active_restriction::ActiveRestriction {
+            restriction: user_restriction,
+            reason,
+            restricted_until_ms,
+        } = active;
+        restriction(user_restriction, reason, restricted_until_ms)
+    }))
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct ListUsersQueryParams {
+    role: Option<Role>,
+    /// UNIX timestamp (ms), inclusive
+    created_after: Option<u64>,
+    /// UNIX timestamp (ms), exclusive
+    created_before: Option<u64>,
+    /// Matched case-insensitively
+    username_prefix: Option<String>,
+    /// Starting from 1, which is the default
+    page: Option<u32>,
+    /// From 1 to 100, 50 by default
+    per_page: Option<u32>,
+}
+
+fn timestamp_ms(timestamp: Option<u64>) -> Result<Option<i64>, &'static str> {
+    timestamp
+        .map(|ms| i64::try_from(ms).map_err(|_| "The timestamp is out of range"))
+        .transpose()
+}
+
+pub(crate) async fn list_users(
+    ctx: &Context,
+    params: ListUsersQueryParams,
+) -> GetAdminUsersResponse {
+    let ListUsersQueryParams {
+        role,
+        created_after,
+        created_before,
+        username_prefix,
+        page,
+        per_page,
+    } = params;
+
+    let invalid_query = |reason: &str| GetAdminUsersResponse::InvalidQuery {
+        reason: reason.to_string(),
+    };
+    let page = page.unwrap_or(1);
+    if page == 0 {
+        return invalid_query("`page` starts from 1");
+    }
+    let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if !(1..=MAX_PER_PAGE).contains(&per_page) {
+        return invalid_query("`per_page` must be from 1 to 100");
+    }
+    let (created_after_ms, created_before_ms) =
+        match (timestamp_ms(created_after), timestamp_ms(created_before)) {
+            (Ok(after), Ok(before)) => (after, before),
+            (Err(reason), _) | (_, Err(reason)) => return invalid_query(reason),
+        };
+    if let (Some(after), Some(before)) = (created_after_ms, created_before_ms)
+        && after > before
+    {
+        return invalid_query("`created_after` is later than `created_before`");
+    }
+    let username_prefix = username_prefix
+        .as_deref()
+        .map(str::trim)
+        .filter(|prefix| !prefix.is_empty());
+
+    let filter = db::admin::list_users::Filter {
+        role: role.map(Into::into),
+        created_after_ms,
+        created_before_ms,
+        username_prefix,
+    };
+    let limit = i64::from(per_page);
+    let offset = i64::from(page - 1) * limit;
+
+    match db::admin::list_users(&ctx.db, filter, limit, offset).await {
+        Ok(db::admin::list_users::Page { users, total }) => {
+            GetAdminUsersResponse::Success(GetAdminUsersResponseSuccess {
+                users: users.into_iter().map(Into::into).collect(),
+                page,
+                per_page,
+                total: total as u64,
+            })
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(list_users),
+                err = e,
+            );
+            GetAdminUsersResponse::InternalServerError
+        }
+    }
+}
+
+pub(crate) async fn get_user(
+    ctx: &Context,
+    user_id: mnln_core_items::id::UserId,
+) -> GetAdminUserResponse {
+    match db::admin::get_user(&ctx.db, user_id.into()).await {
+        Ok(Some(user)) => GetAdminUserResponse::Success(user.into()),
+        Ok(None) => GetAdminUserResponse::NotFound,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(get_user),
+                err = e,
+            );
+            GetAdminUserResponse::InternalServerError
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct SetRoleRequest {
+    role: Role,
+}
+
+pub(crate) async fn set_role(
+    ctx: &Context,
+    admin: JwtClaims,
+    user_id: mnln_core_items::id::UserId,
+    request: SetRoleRequest,
+    origin: &RequestOrigin,
+) -> PutAdminUserRoleResponse {
+    let SetRoleRequest { role } = request;
+    if admin.sub.0 == user_id.0 {
+        return PutAdminUserRoleResponse::CannotModifySelf;
+    }
+
+    match db::admin::set_role(&ctx.db, user_id.into(), role.into()).await {
+        Ok(Some(revoked_at_ms)) => {
+            ctx.revocations
+                .insert_sessions_revoked_at(user_id, revoked_at_ms as u64);
+            tracing::info!(
+                "Admin with ID {} changed the role of user with ID {user_id} to {role:?}",
+                admin.sub.0
+            );
+            let details = AuditEventDetails::RoleChanged { role };
+            audit::record(
+                ctx,
+                Some(admin_id(&admin)),
+                Some(user_id.into()),
+                origin,
+                details,
+            )
+            .await;
+            PutAdminUserRoleResponse::Success
+        }
+        Ok(None) => PutAdminUserRoleResponse::NotFound,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(set_role),
+                err = e,
+            );
+            PutAdminUserRoleResponse::InternalServerError
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct RestrictUserRequest {
+    kind: AccountRestrictionKind,
+    /// Shown to the user when they try to log in
+    reason: String,
+    /// Required for suspensions. A ban without it lasts until it's lifted.
+    expires_at: Option<Timestamp>,
+}
+
+pub(crate) async fn restrict(
+    ctx: &Context,
+    admin: JwtClaims,
+    user_id: mnln_core_items::id::UserId,
+    request: RestrictUserRequest,
+    origin: &RequestOrigin,
+) -> PutAdminUserRestrictionResponse {
+    let RestrictUserRequest {
+        kind,
+        reason,
+        expires_at,
+    } = request;
+
+    let invalid_restriction = |reason: &str| PutAdminUserRestrictionResponse::InvalidRestriction {
+        reason: reason.to_string(),
+    };
+    let reason = reason.trim().to_string();
+    if reason.is_empty() || reason.chars().count() > MAX_REASON_LEN {
+        return invalid_restriction("The reason must be from 1 to 500 characters long");
+    }
+    let restricted_until_ms = match (kind, expires_at) {
+        (AccountRestrictionKind::Suspended, None) => {
+            return invalid_restriction("A suspension must expire");
+        }
+        (_, Some(Timestamp(ms))) if ms <= util::now().0 || i64::try_from(ms).is_err() => {
+            return invalid_restriction("The expiry must be in the future");
+        }
+        (_, expires_at) => expires_at.map(|Timestamp(ms)| ms as i64),
+    };
+    if admin.sub.0 == user_id.0 {
+        return PutAdminUserRestrictionResponse::CannotModifySelf;
+    }
+
+    let res = db::admin::restrict(
+        &ctx.db,
+        user_id.into(),
+        kind.into(),
+        &reason,
+        restricted_until_ms,
+    )
+    .await;
+
+    match res {
+        Ok(Some(revoked_at_ms)) => {
+            ctx.revocations
+                .insert_sessions_revoked_at(user_id, revoked_at_ms as u64);
+            ctx.revocations
+                .insert_restriction(user_id, restricted_until_ms.map(|ms| ms as u64));
+            tracing::info!(
+                "Admin with ID {} restricted user with ID {user_id}: {kind:?} until {restricted_until_ms:?}",
+                admin.sub.0
+            );
+            let details = AuditEventDetails::Restricted {
+                restriction: AccountRestriction {
+                    kind,
+                    reason,
+                    expires_at,
+                },
+            };
+            audit::record(
+                ctx,
+                Some(admin_id(&admin)),
+                Some(user_id.into()),
+                origin,
+                details,
+            )
+            .await;
+            PutAdminUserRestrictionResponse::Success
+        }
+        Ok(None) => PutAdminUserRestrictionResponse::NotFound,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(restrict),
+                err = e,
+            );
+            PutAdminUserRestrictionResponse::InternalServerError
+        }
+    }
+}
+
+pub(crate) async fn lift_restriction(
+    ctx: &Context,
+    admin: JwtClaims,
+    user_id: mnln_core_items::id::UserId,
+    origin: &RequestOrigin,
+) -> DeleteAdminUserRestrictionResponse {
+    match db::admin::lift_restriction(&ctx.db, user_id.into()).await {
+        Ok(true) => {
+            ctx.revocations.remove_restriction(user_id);
+            tracing::info!(
+                "Admin with ID {} lifted the restriction of user with ID {user_id}",
+                admin.sub.0
+            );
+            let details = AuditEventDetails::RestrictionLifted;
+            audit::record(
+                ctx,
+                Some(admin_id(&admin)),
+                Some(user_id.into()),
+                origin,
+                details,
+            )
+            .await;
+            DeleteAdminUserRestrictionResponse::Success
+        }
+        Ok(false) => DeleteAdminUserRestrictionResponse::NotRestricted,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(lift_restriction),
+                err = e,
+            );
+            DeleteAdminUserRestrictionResponse::InternalServerError
+        }
+    }
+}
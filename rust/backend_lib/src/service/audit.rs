@@ -0,0 +1,297 @@
+//! The append-only log of security-relevant events: registrations, logins, avatar changes and the
+//! actions of the admins.
+//!
+//! Failing to record an event is logged but never fails the action itself.
+
+use shared_items_lib::service_responses::{
+    AuditEvent, AuditEventDetails, AuditEventKind, GetAuditEventsResponse,
+    GetAuditEventsResponseSuccess, GetSecurityActivityResponse, GetSecurityActivityResponseSuccess,
+    LoginFailureReason, LoginMethod, SecurityActivityEvent,
+};
+use shared_items_lib::{JwtClaims, Timestamp};
+
+use crate::Context;
+use crate::db::{self, Db};
+use crate::request_origin::RequestOrigin;
+
+const DEFAULT_PER_PAGE: u32 = 50;
+const MAX_PER_PAGE: u32 = 100;
+const SECURITY_ACTIVITY_LIMIT: i64 = 50;
+
+fn kind(details: &AuditEventDetails) -> AuditEventKind {
+    match details {
+        AuditEventDetails::Registered => AuditEventKind::Registered,
+        AuditEventDetails::LoginSucceeded { .. } => AuditEventKind::LoginSucceeded,
+        AuditEventDetails::LoginFailed { .. } => AuditEventKind::LoginFailed,
+        AuditEventDetails::AvatarChanged => AuditEventKind::AvatarChanged,
+        AuditEventDetails::RoleChanged { .. } => AuditEventKind::RoleChanged,
+        AuditEventDetails::Restricted { .. } => AuditEventKind::Restricted,
+        AuditEventDetails::RestrictionLifted => AuditEventKind::RestrictionLifted,
+        AuditEventDetails::PasswordResetForced => AuditEventKind::PasswordResetForced,
+    }
+}
+
+/// Appends the event done by `actor_id` to the account of `target_id`.
+pub(crate) async fn record(
+    ctx: &Context,
+    actor_id: Option<db::id::UserId>,
+    target_id: Option<db::id::UserId>,
+    origin: &RequestOrigin,
+    details: AuditEventDetails,
+) {
+    let res: anyhow::Result<()> = async {
+        let event = db::audit::append::Event {
+            kind: kind(&details).into(),
+            actor_id,
+            target_id,
+            ip: Some(origin.ip.to_string()),
+            user_agent: origin.user_agent.as_deref(),
+            details_json: serde_json::to_string(&details)?,
+        };
+        db::audit::append(&ctx.db, event).await?;
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = res {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed to record {kind:?}: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(record),
+            kind = kind(&details),
+            err = e,
+        );
+    }
+}
+
+pub(crate) async fn login_succeeded(
+    ctx: &Context,
+    user_id: db::id::UserId,
+    origin: &RequestOrigin,
+    method: LoginMethod,
+) {
+    let details = AuditEventDetails::LoginSucceeded { method };
+    record(ctx, Some(user_id), Some(user_id), origin, details).await;
+}
+
+/// Records the failed login into the account of `user_id`, if there's one, by nobody in particular.
+pub(crate) async fn login_failed(
+    ctx: &Context,
+    user_id: Option<db::id::UserId>,
+    origin: &RequestOrigin,
+    method: LoginMethod,
+    reason: LoginFailureReason,
+    username: Option<&str>,
+) {
+    let details = AuditEventDetails::LoginFailed {
+        method,
+        reason,
+        username: username.map(str::to_string),
+    };
+    record(ctx, None, user_id, origin, details).await;
+}
+
+fn user_id(user_id: db::id::UserId) -> shared_items_lib::id::UserId {
+    let user_id: mnln_core_items::id::UserId = user_id.into();
+    user_id.into()
+}
+
+impl TryFrom<db::audit::list::Event> for AuditEvent {
+    type Error = serde_json::Error;
+
+    fn try_from(value: db::audit::list::Event) -> Result<Self, Self::Error> {
+        let db::audit::list::Event {
+            id,
+            occurred_at_ms,
+            actor_id,
+            target_id,
+            ip,
+            user_agent,
+            details_json,
+        } = value;
+        Ok(AuditEvent {
+            id: id as u64,
+            occurred_at: Timestamp(occurred_at_ms as u64),
+            actor_id: actor_id.map(user_id),
+            target_id: target_id.map(user_id),
+            ip,
+            user_agent,
+            details: serde_json::from_str(&details_json)?,
+        })
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct AuditEventsQueryParams {
+    actor_id: Option<i32>,
+    target_id: Option<i32>,
+    kind: Option<AuditEventKind>,
+    /// UNIX timestamp (ms), inclusive
+    occurred_after: Option<u64>,
+    /// UNIX timestamp (ms), exclusive
+    occurred_before: Option<u64>,
+    /// Starting from 1, which is the default
+    page: Option<u32>,
+    /// From 1 to 100, 50 by default
+    per_page: Option<u32>,
+}
+
+fn timestamp_ms(timestamp: Option<u64>) -> Result<Option<i64>, &'static str> {
+    timestamp
+        .map(|ms| i64::try_from(ms).map_err(|_| "The timestamp is out of range"))
+        .transpose()
+}
+
+pub(crate) async fn list(ctx: &Context, params: AuditEventsQueryParams) -> GetAuditEventsResponse {
+    let AuditEventsQueryParams {
+        actor_id,
+        target_id,
+        kind,
+        occurred_after,
+        occurred_before,
+        page,
+        per_page,
+    } = params;
+
+    let invalid_query = |reason: &str| GetAuditEventsResponse::InvalidQuery {
+        reason: reason.to_string(),
+    };
+    let page = page.unwrap_or(1);
+    if page == 0 {
+        return invalid_query("`page` starts from 1");
+    }
+    let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if !(1..=MAX_PER_PAGE).contains(&per_page) {
+        return invalid_query("`per_page` must be from 1 to 100");
+    }
+    let (occurred_after_ms, occurred_before_ms) =
+        match (timestamp_ms(occurred_after), timestamp_ms(occurred_before)) {
+            (Ok(after), Ok(before)) => (after, before),
+            (Err(reason), _) | (_, Err(reason)) => return invalid_query(reason),
+        };
+    if let (Some(after), Some(before)) = (occurred_after_ms, occurred_before_ms)
+        && after > before
+    {
+        return invalid_query("`occurred_after` is later than `occurred_before`");
+    }
+
+    let filter = db::audit::list::Filter {
+        actor_id: actor_id.map(|id| mnln_core_items::id::UserId(id).into()),
+        target_id: target_id.map(|id| mnln_core_items::id::UserId(id).into()),
+        kind: kind.map(Into::into),
+        occurred_after_ms,
+        occurred_before_ms,
+    };
+    let limit = i64::from(per_page);
+    let offset = i64::from(page - 1) * limit;
+
+    let res: anyhow::Result<GetAuditEventsResponseSuccess> = async {
+        let db::audit::list::Page { events, total } =
+            db::audit::list(&ctx.db, filter, limit, offset).await?;
+        let events = events
+            .into_iter()
+            .map(AuditEvent::try_from)
+            .collect::<Result<_, _>>()?;
+        Ok(GetAuditEventsResponseSuccess {
+            events,
+            page,
+            per_page,
+            total: total as u64,
+        })
+    }
+    .await;
+
+    match res {
+        Ok(resp) => GetAuditEventsResponse::Success(resp),
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(list),
+                err = e,
+            );
+            GetAuditEventsResponse::InternalServerError
+        }
+    }
+}
+
+/// The `limit` most recent events about the account of the user, without the IDs of the admins
+/// involved.
+pub(crate) async fn security_activity_events(
+    db: &Db,
+    user_id: mnln_core_items::id::UserId,
+    limit: i64,
+) -> anyhow::Result<Vec<SecurityActivityEvent>> {
+    let events = db::audit::recent_for_target(db, user_id.into(), limit).await?;
+    events
+        .into_iter()
+        .map(|event| {
+            let AuditEvent {
+                occurred_at,
+                actor_id,
+                ip,
+                user_agent,
+                details,
+                ..
+            } = AuditEvent::try_from(event)?;
+            Ok(SecurityActivityEvent {
+                occurred_at,
+                ip,
+                user_agent,
+                by_admin: actor_id.is_some_and(|actor_id| actor_id.0 != user_id.0),
+                details,
+            })
+        })
+        .collect()
+}
+
+pub(crate) async fn security_activity(
+    ctx: &Context,
+    claims: JwtClaims,
+) -> GetSecurityActivityResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+
+    let res = security_activity_events(&ctx.db, user_id, SECURITY_ACTIVITY_LIMIT).await;
+
+    match res {
+        Ok(events) => {
+            GetSecurityActivityResponse::Success(GetSecurityActivityResponseSuccess { events })
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed for user with ID {user_id}: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(security_activity),
+                err = e,
+            );
+            GetSecurityActivityResponse::InternalServerError
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shared_items_lib::Role;
+    use shared_items_lib::service_responses::{LoginFailureReason, LoginMethod};
+
+    use super::*;
+
+    #[test]
+    fn kind_matches_the_serialized_tag() {
+        let events = [
+            AuditEventDetails::Registered,
+            AuditEventDetails::LoginFailed {
+                method: LoginMethod::Password,
+                reason: LoginFailureReason::WrongPassword,
+                username: Some("alice".to_string()),
+            },
+            AuditEventDetails::RoleChanged { role: Role::Admin },
+            AuditEventDetails::PasswordResetForced,
+        ];
+        for details in events {
+            let json = serde_json::to_value(&details).unwrap();
+            assert_eq!(json["kind"], format!("{:?}", kind(&details)));
+        }
+    }
+}
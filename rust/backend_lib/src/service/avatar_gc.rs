@@ -0,0 +1,129 @@
+//! Garbage collection of the avatar bucket.
+//!
+//! The replaced avatars are deleted right after the upload, see
+//! [`crate::service::user::upload_user_avatar`], but the deletion may fail, and the objects of
+//! the failed uploads may be left behind as well. So the bucket is periodically reconciled
+//! with `users.avatar_s3_key`, and the objects that no user points to are deleted once they are
+//! older than [`mnln_env::AvatarEnv::orphan_grace_secs`], unless in the dry-run mode.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use mnln_env::Env;
+use object_storage::StoredAvatarObject;
+
+use crate::avatar;
+use crate::db::{self, Db, user::get_avatar};
+
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The keys of the objects that none of the `avatars` accounts for and that were last modified
+/// before `cutoff_ms`.
+fn orphans(
+    objects: Vec<StoredAvatarObject>,
+    avatars: &[get_avatar::Avatar],
+    cutoff_ms: i64,
+) -> Vec<String> {
+    let referenced: HashSet<String> = avatars
+        .iter()
+        .flat_map(|avatar| {
+            let rendition_sizes: &[u32] = if avatar.has_renditions {
+                &avatar::RENDITION_SIZES
+            } else {
+                &[]
+            };
+            rendition_sizes
+                .iter()
+                .map(|size| object_storage::avatar_rendition_key(&avatar.s3_key, *size))
+                .chain([avatar.s3_key.clone()])
+        })
+        .collect();
+
+    objects
+        .into_iter()
+        .filter(|object| object.last_modified_ms < cutoff_ms && !referenced.contains(&object.key))
+        .map(|object| object.key)
+        .collect()
+}
+
+/// Returns the number of the orphaned objects, which are deleted unless `dry_run`.
+async fn reconcile(env: &Env, db: &Db, dry_run: bool) -> anyhow::Result<usize> {
+    let grace = chrono::Duration::seconds(env.avatar.orphan_grace_secs.try_into()?);
+    let cutoff_ms = (chrono::Utc::now() - grace).timestamp_millis();
+
+    // Listed before the avatars are read, so that an object can't be uploaded and pointed to
+    // in between without either being older than the cutoff or being pointed to
+    let objects = object_storage::list_avatar_objects(env).await?;
+    let avatars = db::user::list_avatars(db).await?;
+
+    let orphans = orphans(objects, &avatars, cutoff_ms);
+    for key in &orphans {
+        if dry_run {
+            tracing::info!("Would delete the orphaned avatar object `{key}` (dry run)");
+        } else {
+            object_storage::delete_avatar(env, key, &[]).await?;
+            tracing::info!("Deleted the orphaned avatar object `{key}`");
+        }
+    }
+    Ok(orphans.len())
+}
+
+pub(crate) fn spawn_reconciler(env: Env, db: Db) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RECONCILE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            match reconcile(&env, &db, env.avatar.orphan_gc_dry_run).await {
+                Ok(orphans) => tracing::debug!("Found {orphans} orphaned avatar objects"),
+                Err(err) => tracing::error!("Failed to reconcile the avatar bucket: {err:#}"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(key: &str, last_modified_ms: i64) -> StoredAvatarObject {
+        StoredAvatarObject {
+            key: key.to_string(),
+            last_modified_ms,
+        }
+    }
+
+    #[test]
+    fn only_old_unreferenced_objects_are_orphans() {
+        let avatars = [
+            get_avatar::Avatar {
+                s3_key: "avatars/1/100.png".to_string(),
+                has_renditions: true,
+            },
+            get_avatar::Avatar {
+                s3_key: "avatars/2/100.svg".to_string(),
+                has_renditions: false,
+            },
+        ];
+        let objects = vec![
+            object("avatars/1/100.png", 0),
+            object("avatars/1/100_64px.webp", 0),
+            object("avatars/1/100_256px.webp", 0),
+            object("avatars/1/50.png", 0),
+            object("avatars/1/50_64px.webp", 0),
+            object("avatars/2/100.svg", 0),
+            object("avatars/2/100_64px.webp", 0),
+            // Possibly an upload in progress
+            object("avatars/3/200.png", 2000),
+        ];
+
+        assert_eq!(
+            orphans(objects, &avatars, 1000),
+            [
+                "avatars/1/50.png",
+                "avatars/1/50_64px.webp",
+                "avatars/2/100_64px.webp"
+            ]
+        );
+    }
+}
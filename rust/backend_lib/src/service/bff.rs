@@ -36,7 +36,7 @@ pub(crate) async fn get_user_page_data(
 
     let avatar_url = if avatar_s3_key.is_some() {
         let user_id: mnln_core_items::id::UserId = user_id.into();
-        let avatar_url = links::avatar_url(&ctx.env, user_id);
+        let avatar_url = links::avatar_url(&ctx.env, user_id, None);
         Some(avatar_url)
     } else {
         None
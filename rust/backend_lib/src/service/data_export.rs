@@ -0,0 +1,360 @@
+//! Personal data exports: a ZIP archive of everything we hold about the user.
+//!
+//! Requesting an export only enqueues it. A background worker assembles the archive and stores it
+//! in the export bucket, while the client polls the status. Once the export is ready, the status
+//! comes with a short-lived download link, signed like the access tokens but for another audience,
+//! so the archive can be downloaded without the access token, e.g. by the browser itself.
+
+use std::io::Write as _;
+use std::time::Duration;
+
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse as _, Response};
+use mnln_env::Env;
+use shared_items_lib::id::UserId;
+use shared_items_lib::service_responses::{
+    DataExport, DataExportStatus, GetDataExportResponse, PostDataExportResponse,
+};
+use shared_items_lib::{JwtClaims, NumericDate, Timestamp};
+
+use crate::db::{self, Db};
+use crate::{Context, links, service, util};
+
+const AUDIENCE: &str = "data-export";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a running export may take before another worker takes it over.
+fn lease() -> chrono::Duration {
+    chrono::Duration::minutes(30)
+}
+
+/// How long the archive is kept after it's assembled.
+fn retention() -> chrono::Duration {
+    chrono::Duration::days(7)
+}
+
+/// How long the failed exports are kept for troubleshooting.
+fn failed_retention() -> chrono::Duration {
+    chrono::Duration::days(7)
+}
+
+fn download_url_ttl() -> chrono::Duration {
+    chrono::Duration::hours(1)
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DownloadClaims {
+    iss: String,
+    aud: String,
+    sub: UserId,
+    export_id: i64,
+    exp: NumericDate,
+}
+
+fn download_url(
+    ctx: &Context,
+    user_id: mnln_core_items::id::UserId,
+    export_id: i64,
+) -> anyhow::Result<(String, Timestamp)> {
+    let exp = util::time_from_now(download_url_ttl());
+    let claims = DownloadClaims {
+        iss: ctx.env.jwt.issuer.clone(),
+        aud: AUDIENCE.to_string(),
+        sub: user_id.into(),
+        export_id,
+        exp: exp.into(),
+    };
+    let token = ctx.key_ring.sign(&claims)?;
+    Ok((links::data_export_download_url(&ctx.env, &token), exp))
+}
+
+fn data_export(
+    ctx: &Context,
+    user_id: mnln_core_items::id::UserId,
+    export: db::data_export::get::Export,
+) -> anyhow::Result<DataExport> {
+    let db::data_export::get::Export {
+        id,
+        status,
+        created_at_ms,
+        expires_at_ms,
+    } = export;
+
+    let expires_at = expires_at_ms.map(|ms| Timestamp(ms as u64));
+    let status = match status {
+        db::data_export::Status::Pending => DataExportStatus::Pending,
+        db::data_export::Status::Running => DataExportStatus::Running,
+        db::data_export::Status::Ready if expires_at.is_some_and(|exp| exp.0 <= util::now().0) => {
+            DataExportStatus::Expired
+        }
+        db::data_export::Status::Ready => DataExportStatus::Ready,
+        db::data_export::Status::Failed => DataExportStatus::Failed,
+    };
+    let (download_url, download_url_expires_at) = match status {
+        DataExportStatus::Ready => {
+            let (url, exp) = download_url(ctx, user_id, id)?;
+            (Some(url), Some(exp))
+        }
+        _ => (None, None),
+    };
+
+    Ok(DataExport {
+        id,
+        status,
+        created_at: Timestamp(created_at_ms as u64),
+        download_url,
+        download_url_expires_at,
+        expires_at,
+    })
+}
+
+async fn get_data_export(
+    ctx: &Context,
+    user_id: mnln_core_items::id::UserId,
+    export_id: i64,
+) -> anyhow::Result<Option<DataExport>> {
+    match db::data_export::get(&ctx.db, user_id.into(), export_id).await? {
+        Some(export) => Ok(Some(data_export(ctx, user_id, export)?)),
+        None => Ok(None),
+    }
+}
+
+pub(crate) async fn request(ctx: &Context, claims: JwtClaims) -> PostDataExportResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+
+    let res = match db::data_export::create(&ctx.db, user_id.into()).await {
+        Ok(export_id) => get_data_export(ctx, user_id, export_id).await,
+        Err(e) => Err(e.into()),
+    };
+    match res {
+        Ok(Some(export)) => PostDataExportResponse::Accepted(export),
+        Ok(None) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: the export of user with ID {user_id} vanished",
+                mod_path = module_path!(),
+                fn_name = stringify!(request),
+            );
+            PostDataExportResponse::InternalServerError
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(request),
+                err = e,
+            );
+            PostDataExportResponse::InternalServerError
+        }
+    }
+}
+
+pub(crate) async fn status(
+    ctx: &Context,
+    claims: JwtClaims,
+    export_id: i64,
+) -> GetDataExportResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+
+    match get_data_export(ctx, user_id, export_id).await {
+        Ok(Some(export)) => GetDataExportResponse::Success(export),
+        Ok(None) => GetDataExportResponse::NotFound,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(status),
+                err = e,
+            );
+            GetDataExportResponse::InternalServerError
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct DownloadQueryParams {
+    /// The `token` query parameter of the `download_url`
+    token: String,
+}
+
+pub(crate) async fn download(ctx: &Context, params: DownloadQueryParams) -> Response {
+    let validation = util::jwt_validation(&ctx.env.jwt, AUDIENCE);
+    let claims: DownloadClaims = match ctx.key_ring.verify(&params.token, validation) {
+        Ok(claims) => claims,
+        Err(err) => {
+            tracing::warn!("Failed to verify the data export download token: {err}");
+            return StatusCode::FORBIDDEN.into_response();
+        }
+    };
+    let DownloadClaims { sub, export_id, .. } = claims;
+
+    let user_id: mnln_core_items::id::UserId = sub.into();
+    let key = match db::data_export::get_archive_key(&ctx.db, user_id.into(), export_id).await {
+        Ok(Some(key)) => key,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(download),
+                err = e,
+            );
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let archive = match object_storage::get_export(&ctx.env, &key).await {
+        Ok(archive) => archive,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(download),
+                err = e,
+            );
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let content_disposition = format!(r#"attachment; filename="main-line-export-{export_id}.zip""#);
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, content_disposition),
+            (header::CACHE_CONTROL, "private, no-store".to_string()),
+        ],
+        archive,
+    )
+        .into_response()
+}
+
+const README: &str = "\
+This archive contains everything main-line holds about your account:
+
+- user.json: your account, except for the password hash and the other secrets;
+- sessions.json: the devices you logged in from, except for the refresh tokens;
+- passkeys.json: the passkeys you registered, except for the keys themselves;
+- personal_access_tokens.json: the tokens you created, except for the tokens themselves;
+- security_activity.json: the logins and the other changes to your account,
+  with the IP addresses and the user agents they came from;
+- avatars/: every avatar you uploaded.
+
+The times are UNIX timestamps in milliseconds.
+";
+
+/// Assembles the archive of the export in memory.
+///
+/// Every entry is stored uncompressed because the bulk of the archive are images,
+/// which are compressed already.
+async fn assemble(
+    env: &Env,
+    db: &Db,
+    user_id: mnln_core_items::id::UserId,
+) -> anyhow::Result<Vec<u8>> {
+    let user = db::data_export::user(db, user_id.into())
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("The user no longer exists"))?;
+    let sessions = db::data_export::sessions(db, user_id.into()).await?;
+    let passkeys = db::data_export::passkeys(db, user_id.into()).await?;
+    let personal_access_tokens =
+        db::data_export::personal_access_tokens(db, user_id.into()).await?;
+    // All of it rather than the most recent events the security activity view is limited to
+    let security_activity = service::audit::security_activity_events(db, user_id, i64::MAX).await?;
+    let avatars = object_storage::get_user_avatars(env, user_id).await?;
+
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+    zip.start_file("README.txt", options)?;
+    zip.write_all(README.as_bytes())?;
+    let json_files: [(&str, serde_json::Value); 5] = [
+        ("user.json", serde_json::to_value(user)?),
+        ("sessions.json", serde_json::to_value(sessions)?),
+        ("passkeys.json", serde_json::to_value(passkeys)?),
+        (
+            "personal_access_tokens.json",
+            serde_json::to_value(personal_access_tokens)?,
+        ),
+        (
+            "security_activity.json",
+            serde_json::to_value(security_activity)?,
+        ),
+    ];
+    for (name, json) in json_files {
+        zip.start_file(name, options)?;
+        serde_json::to_writer_pretty(&mut zip, &json)?;
+    }
+    for (key, avatar) in avatars {
+        // The keys are `avatars/{user_id}/{file_name}`
+        let file_name = key.rsplit('/').next().unwrap_or(&key);
+        zip.start_file(format!("avatars/{file_name}"), options)?;
+        zip.write_all(&avatar)?;
+    }
+
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Assembles the oldest pending export, returning `false` if there was none.
+async fn process_next(env: &Env, db: &Db) -> anyhow::Result<bool> {
+    let Some(db::data_export::claim::ClaimedExport { id, user_id }) =
+        db::data_export::claim(db, lease()).await?
+    else {
+        return Ok(false);
+    };
+    let user_id: mnln_core_items::id::UserId = user_id.into();
+
+    let res = match assemble(env, db, user_id).await {
+        Ok(archive) => object_storage::save_export(env, user_id, id, &archive).await,
+        Err(e) => Err(e),
+    };
+    match res {
+        Ok(key) => {
+            db::data_export::mark_ready(db, id, &key, retention()).await?;
+            tracing::info!("Assembled the data export {id} of user with ID {user_id}");
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to assemble the data export {id} of user with ID {user_id}: {e:#}"
+            );
+            db::data_export::mark_failed(db, id, &format!("{e:#}")).await?;
+        }
+    }
+    Ok(true)
+}
+
+async fn prune_expired(env: &Env, db: &Db) -> anyhow::Result<()> {
+    for export in db::data_export::take_expired(db, failed_retention(), 100).await? {
+        if let Some(key) = &export.object_key {
+            object_storage::delete_export(env, key).await?;
+        }
+        db::data_export::delete(db, export.id).await?;
+    }
+    Ok(())
+}
+
+/// Assembles the pending exports and deletes the expired ones in the background.
+pub(crate) fn spawn_worker(env: Env, db: Db) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            loop {
+                match process_next(&env, &db).await {
+                    Ok(true) => (),
+                    Ok(false) => break,
+                    Err(err) => {
+                        tracing::error!("Failed to process a data export: {err:#}");
+                        break;
+                    }
+                }
+            }
+            if let Err(err) = prune_expired(&env, &db).await {
+                tracing::error!("Failed to delete the expired data exports: {err:#}");
+            }
+        }
+    });
+}
@@ -0,0 +1,154 @@
+//! Proving the ownership of an email address with a signed, single-use link.
+//!
+//! The token is a JWT signed with the access token keys but meant for a different audience,
+//! so neither kind of token is accepted in place of the other. Only the `jti` of the most recently
+//! issued token is stored, which makes the tokens single-use and voids the older ones.
+
+use shared_items_lib::id::UserId;
+use shared_items_lib::service_responses::{
+    PostSendEmailVerificationResponse, PostVerifyEmailResponse,
+};
+use shared_items_lib::{JwtClaims, NumericDate};
+
+use crate::db::outbox::enqueue::NewMail;
+use crate::{Context, db, links, token, util};
+
+const AUDIENCE: &str = "email-verification";
+
+fn token_ttl() -> chrono::Duration {
+    chrono::Duration::hours(24)
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct EmailVerificationClaims {
+    iss: String,
+    aud: String,
+    sub: UserId,
+    /// The email address being verified
+    email: String,
+    exp: NumericDate,
+    jti: String,
+}
+
+/// Issues a verification token for `email` along with the mail delivering it.
+///
+/// The token becomes redeemable once the returned value is stored, e.g. with
+/// [`db::user::start_email_verification`].
+pub(crate) fn prepare(
+    ctx: &Context,
+    user_id: mnln_core_items::id::UserId,
+    email: &str,
+) -> anyhow::Result<db::user::EmailVerification> {
+    let jti = token::generate();
+    let claims = EmailVerificationClaims {
+        iss: ctx.env.jwt.issuer.clone(),
+        aud: AUDIENCE.to_string(),
+        sub: user_id.into(),
+        email: email.to_string(),
+        exp: util::time_from_now(token_ttl()).into(),
+        jti: jti.clone(),
+    };
+    let token = ctx.key_ring.sign(&claims)?;
+    let url = links::verify_email_url(&ctx.env, &token);
+
+    let mail = NewMail {
+        recipient: email.to_string(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hi,\n\
+             \n\
+             Please confirm that {email} is your email address by opening the link below \
+             within {hours} hours:\n\
+             \n\
+             {url}\n\
+             \n\
+             If you didn't add this email address to your main-line account, \
+             you can ignore this message.\n",
+            hours = token_ttl().num_hours(),
+        ),
+    };
+    Ok(db::user::EmailVerification { jti, mail })
+}
+
+/// (Re)sends the verification link for the current email address of the user.
+pub(crate) async fn send(ctx: &Context, claims: JwtClaims) -> PostSendEmailVerificationResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+
+    let profile = match db::user::get_profile(&ctx.db, user_id.into()).await {
+        Ok(Some(profile)) => profile,
+        Ok(None) => return PostSendEmailVerificationResponse::NotFound,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(send),
+                err = e,
+            );
+            return PostSendEmailVerificationResponse::InternalServerError;
+        }
+    };
+    let Some(email) = profile.email else {
+        return PostSendEmailVerificationResponse::NoEmail;
+    };
+    if profile.email_verified {
+        return PostSendEmailVerificationResponse::AlreadyVerified;
+    }
+
+    let res = match prepare(ctx, user_id, &email) {
+        Ok(verification) => {
+            db::user::start_email_verification(&ctx.db, user_id.into(), &email, &verification)
+                .await
+                .map_err(anyhow::Error::from)
+        }
+        Err(e) => Err(e),
+    };
+    match res {
+        Ok(true) => PostSendEmailVerificationResponse::Success,
+        // The email address has been changed or verified since it was read
+        Ok(false) => PostSendEmailVerificationResponse::Conflict,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(send),
+                err = e,
+            );
+            PostSendEmailVerificationResponse::InternalServerError
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct VerifyEmailRequest {
+    /// The `token` query parameter of the verification link
+    token: String,
+}
+
+pub(crate) async fn verify(ctx: &Context, request: VerifyEmailRequest) -> PostVerifyEmailResponse {
+    let validation = util::jwt_validation(&ctx.env.jwt, AUDIENCE);
+    let claims: EmailVerificationClaims = match ctx.key_ring.verify(&request.token, validation) {
+        Ok(claims) => claims,
+        Err(err) => {
+            tracing::warn!("Failed to verify the email verification token: {err}");
+            return PostVerifyEmailResponse::InvalidToken;
+        }
+    };
+    let EmailVerificationClaims {
+        sub, email, jti, ..
+    } = claims;
+
+    let user_id: mnln_core_items::id::UserId = sub.into();
+    match db::user::verify_email(&ctx.db, user_id.into(), &email, &jti).await {
+        Ok(true) => PostVerifyEmailResponse::Success,
+        Ok(false) => PostVerifyEmailResponse::InvalidToken,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(verify),
+                err = e,
+            );
+            PostVerifyEmailResponse::InternalServerError
+        }
+    }
+}
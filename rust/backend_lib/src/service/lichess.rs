@@ -0,0 +1,350 @@
+//! "Sign in with Lichess" and linking the Lichess account to an existing user.
+//!
+//! Either flow starts with an authorization URL, to which the frontend sends the user. Lichess
+//! redirects back to the frontend with the authorization code and the state, which the frontend
+//! posts to the matching callback endpoint. The state is single-use and remembers the PKCE code
+//! verifier and, when linking, the user who started it, so a user can't be tricked into linking
+//! their Lichess account to someone else's.
+//!
+//! The self-declared `lichess_username` is never trusted for signing in: only the Lichess
+//! accounts verified through OAuth are recorded in `lichess_id`.
+
+use shared_items_lib::JwtClaims;
+use shared_items_lib::service_responses::{
+    AuditEventDetails, LoginFailureReason, LoginMethod, PostLichessAuthorizeResponse,
+    PostLichessAuthorizeResponseSuccess, PostLichessLinkResponse, PostLichessSignInResponse,
+    PostLoginResponseSuccess,
+};
+
+use crate::request_origin::RequestOrigin;
+use crate::service::{self, audit, session};
+use crate::{Context, db, lichess, links, password, token, username};
+
+fn state_ttl() -> chrono::Duration {
+    chrono::Duration::minutes(10)
+}
+
+fn redirect_uri(ctx: &Context, link_user_id: Option<mnln_core_items::id::UserId>) -> String {
+    match link_user_id {
+        Some(_) => links::lichess_link_callback_url(&ctx.env),
+        None => links::lichess_sign_in_callback_url(&ctx.env),
+    }
+}
+
+/// Starts signing in, or linking the Lichess account to `link_user_id`.
+pub(crate) async fn authorize(
+    ctx: &Context,
+    link_user_id: Option<mnln_core_items::id::UserId>,
+) -> PostLichessAuthorizeResponse {
+    let state = token::generate();
+    // 43 URL-safe characters, as required by <https://www.rfc-editor.org/rfc/rfc7636#section-4.1>
+    let code_verifier = token::generate();
+
+    let res = async {
+        db::lichess::create_state(
+            &ctx.db,
+            &token::hash(&state),
+            &code_verifier,
+            link_user_id.map(Into::into),
+            state_ttl(),
+        )
+        .await?;
+        lichess::authorization_url(
+            &ctx.env.lichess_oauth,
+            &redirect_uri(ctx, link_user_id),
+            &state,
+            &code_verifier,
+        )
+    }
+    .await;
+
+    match res {
+        Ok(authorization_url) => {
+            PostLichessAuthorizeResponse::Success(PostLichessAuthorizeResponseSuccess {
+                authorization_url,
+            })
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(authorize),
+                err = e,
+            );
+            PostLichessAuthorizeResponse::InternalServerError
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct LichessCallbackRequest {
+    /// The `code` query parameter of the redirect
+    code: String,
+    /// The `state` query parameter of the redirect
+    state: String,
+}
+
+/// Exchanges the authorization code and reads the Lichess account it grants access to.
+async fn verified_account(
+    ctx: &Context,
+    redirect_uri: &str,
+    code: &str,
+    code_verifier: &str,
+) -> anyhow::Result<lichess::Account> {
+    let env = &ctx.env.lichess_oauth;
+    let access_token =
+        lichess::exchange_code(&ctx.http, env, redirect_uri, code, code_verifier).await?;
+    let account = lichess::account(&ctx.http, env, &access_token).await;
+    if let Err(e) = lichess::revoke(&ctx.http, env, &access_token).await {
+        tracing::warn!("Failed to revoke the Lichess access token: {e}");
+    }
+    account
+}
+
+/// Starts the session, unless the user has TOTP enabled and has to enter a code first.
+async fn start_session(
+    ctx: &Context,
+    user_id: db::id::UserId,
+    role: db::user::Role,
+    origin: &RequestOrigin,
+) -> PostLichessSignInResponse {
+    match service::admin::login_restriction(ctx, user_id).await {
+        Ok(Some(restriction)) => {
+            let reason = LoginFailureReason::Restricted;
+            audit::login_failed(
+                ctx,
+                Some(user_id),
+                origin,
+                LoginMethod::Lichess,
+                reason,
+                None,
+            )
+            .await;
+            return PostLichessSignInResponse::Restricted(restriction);
+        }
+        Ok(None) => (),
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed while checking the restriction: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(start_session),
+                err = e,
+            );
+            return PostLichessSignInResponse::InternalServerError;
+        }
+    }
+
+    match service::totp::challenge(ctx, user_id).await {
+        Ok(Some(challenge)) => return PostLichessSignInResponse::SecondFactorRequired(challenge),
+        Ok(None) => (),
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed while checking the second factor: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(start_session),
+                err = e,
+            );
+            return PostLichessSignInResponse::InternalServerError;
+        }
+    }
+
+    match session::start(ctx, user_id, role, false).await {
+        Ok(session::Tokens { jwt, refresh_token }) => {
+            audit::login_succeeded(ctx, user_id, origin, LoginMethod::Lichess).await;
+            PostLichessSignInResponse::Success(PostLoginResponseSuccess { jwt, refresh_token })
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed while starting a session: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(start_session),
+                err = e,
+            );
+            PostLichessSignInResponse::InternalServerError
+        }
+    }
+}
+
+/// Registers the user of the Lichess account under its username, with a random password.
+///
+/// A username that [`username::validate`] rejects, e.g. a reserved one, is as unavailable as
+/// a taken one.
+async fn register(
+    ctx: &Context,
+    account: &lichess::Account,
+) -> anyhow::Result<db::user::register::Output> {
+    let Ok(name) = username::validate(&account.username) else {
+        return Ok(db::user::register::Output::AlreadyExists);
+    };
+    let secret = token::generate();
+    let client_salt = password::client_salt(&secret);
+    let password_hash = password::hash(&ctx.env.argon2, secret).await?;
+    Ok(db::lichess::register(
+        &ctx.db,
+        &name,
+        &username::normalize(&name),
+        &password_hash,
+        &client_salt,
+        &account.id,
+    )
+    .await)
+}
+
+pub(crate) async fn sign_in(
+    ctx: &Context,
+    request: LichessCallbackRequest,
+    origin: &RequestOrigin,
+) -> PostLichessSignInResponse {
+    let LichessCallbackRequest { code, state } = request;
+
+    let code_verifier = match db::lichess::take_state(&ctx.db, &token::hash(&state)).await {
+        Ok(Some(db::lichess::take_state::State {
+            code_verifier,
+            link_user_id: None,
+        })) => code_verifier,
+        Ok(Some(_) | None) => return PostLichessSignInResponse::InvalidState,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(sign_in),
+                err = e,
+            );
+            return PostLichessSignInResponse::InternalServerError;
+        }
+    };
+
+    let redirect_uri = redirect_uri(ctx, None);
+    let account = match verified_account(ctx, &redirect_uri, &code, &code_verifier).await {
+        Ok(account) => account,
+        Err(e) => {
+            tracing::warn!(
+                "The function {mod_path}::{fn_name}(...) failed to verify the Lichess account: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(sign_in),
+                err = e,
+            );
+            return PostLichessSignInResponse::LichessUnavailable;
+        }
+    };
+
+    // A concurrent sign-in may register the user in between, hence the second lookup.
+    for attempt in 0..2 {
+        match db::lichess::find_user(&ctx.db, &account.id).await {
+            Ok(Some(db::lichess::find_user::User { id, role })) => {
+                return start_session(ctx, id, role, origin).await;
+            }
+            Ok(None) if attempt > 0 => return PostLichessSignInResponse::UsernameTaken,
+            Ok(None) => (),
+            Err(e) => {
+                tracing::error!(
+                    "The function {mod_path}::{fn_name}(...) failed: {err}",
+                    mod_path = module_path!(),
+                    fn_name = stringify!(sign_in),
+                    err = e,
+                );
+                return PostLichessSignInResponse::InternalServerError;
+            }
+        }
+
+        match register(ctx, &account).await {
+            Ok(db::user::register::Output::Success { user_id }) => {
+                tracing::info!(
+                    "Registered user with ID {user_id} through the Lichess account {}",
+                    account.id
+                );
+                audit::record(
+                    ctx,
+                    Some(user_id),
+                    Some(user_id),
+                    origin,
+                    AuditEventDetails::Registered,
+                )
+                .await;
+                return start_session(ctx, user_id, db::user::Role::User, origin).await;
+            }
+            Ok(db::user::register::Output::AlreadyExists) => (),
+            Ok(db::user::register::Output::UnknownError { err }) => {
+                tracing::error!(
+                    "The function {mod_path}::{fn_name}(...) failed: {err}",
+                    mod_path = module_path!(),
+                    fn_name = stringify!(sign_in),
+                );
+                return PostLichessSignInResponse::InternalServerError;
+            }
+            Err(e) => {
+                tracing::error!(
+                    "The function {mod_path}::{fn_name}(...) failed: {err}",
+                    mod_path = module_path!(),
+                    fn_name = stringify!(sign_in),
+                    err = e,
+                );
+                return PostLichessSignInResponse::InternalServerError;
+            }
+        }
+    }
+    PostLichessSignInResponse::UsernameTaken
+}
+
+pub(crate) async fn link(
+    ctx: &Context,
+    claims: JwtClaims,
+    request: LichessCallbackRequest,
+) -> PostLichessLinkResponse {
+    let LichessCallbackRequest { code, state } = request;
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    let db_user_id: db::id::UserId = user_id.into();
+
+    let code_verifier = match db::lichess::take_state(&ctx.db, &token::hash(&state)).await {
+        Ok(Some(db::lichess::take_state::State {
+            code_verifier,
+            link_user_id: Some(link_user_id),
+        })) if mnln_core_items::id::UserId::from(link_user_id).0 == user_id.0 => code_verifier,
+        Ok(Some(_) | None) => return PostLichessLinkResponse::InvalidState,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(link),
+                err = e,
+            );
+            return PostLichessLinkResponse::InternalServerError;
+        }
+    };
+
+    let redirect_uri = redirect_uri(ctx, Some(user_id));
+    let account = match verified_account(ctx, &redirect_uri, &code, &code_verifier).await {
+        Ok(account) => account,
+        Err(e) => {
+            tracing::warn!(
+                "The function {mod_path}::{fn_name}(...) failed to verify the Lichess account: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(link),
+                err = e,
+            );
+            return PostLichessLinkResponse::LichessUnavailable;
+        }
+    };
+
+    match db::lichess::link(&ctx.db, db_user_id, &account.id, &account.username).await {
+        Ok(db::lichess::link::Output::Success) => {
+            tracing::info!(
+                "Linked the Lichess account {} to user with ID {db_user_id}",
+                account.id
+            );
+            PostLichessLinkResponse::Success
+        }
+        Ok(db::lichess::link::Output::AlreadyLinked) => PostLichessLinkResponse::AlreadyLinked,
+        // The state of a deleted user is deleted along with them, so this is a race
+        Ok(db::lichess::link::Output::NotFound) => PostLichessLinkResponse::InvalidState,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(link),
+                err = e,
+            );
+            PostLichessLinkResponse::InternalServerError
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Brute-force protection of the login and salt endpoints.
+//!
+//! Failures are counted per username and per client IP address, or IPv6 /64 network, in the
+//! database, so lockouts survive restarts and apply to every replica. While either the username or
+//! the IP address is locked out, the endpoints respond with `429 Too Many Requests` without
+//! checking the password.
+
+use std::net::{IpAddr, Ipv6Addr};
+use std::time::Duration;
+
+use mnln_env::{Env, LoginThrottleEnv};
+
+use crate::db::{self, Db, login_throttle::Scope};
+use crate::{Context, username};
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The address itself for IPv4, but the /64 network for IPv6, since that's what a single client
+/// is usually assigned and can pick the addresses from at will.
+fn ip_key(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => ip.to_string(),
+            None => {
+                let network = Ipv6Addr::from(u128::from(ip) & !u128::from(u64::MAX));
+                format!("{network}/64")
+            }
+        },
+    }
+}
+
+fn reset_after(env: &LoginThrottleEnv) -> chrono::Duration {
+    chrono::Duration::seconds(env.reset_after_secs.into())
+}
+
+/// The lockout after `failures` consecutive failures, if they are more than allowed.
+///
+/// Each failure past the allowed ones doubles the lockout, up to the maximum.
+fn lockout(
+    env: &LoginThrottleEnv,
+    allowed_failures: u32,
+    failures: u32,
+) -> Option<chrono::Duration> {
+    let excess = failures.checked_sub(allowed_failures)?.checked_sub(1)?;
+    let secs = 2u64
+        .checked_pow(excess)
+        .and_then(|factor| factor.checked_mul(env.base_lockout_secs.into()))
+        .map_or(env.max_lockout_secs.into(), |secs| {
+            secs.min(env.max_lockout_secs.into())
+        });
+    Some(chrono::Duration::seconds(secs as i64))
+}
+
+/// How many seconds the client has to wait before trying to log in as `username`,
+/// if it's locked out.
+pub(crate) async fn retry_after_secs(
+    ctx: &Context,
+    username: &str,
+    ip: IpAddr,
+) -> sqlx::Result<Option<u64>> {
+    let username = username::normalize(username);
+    let secs = db::login_throttle::retry_after_secs(&ctx.db, &username, &ip_key(ip)).await?;
+    Ok(secs.map(|secs| secs.max(1) as u64))
+}
+
+async fn record_failure_of(
+    ctx: &Context,
+    conn: &mut sqlx::PgConnection,
+    scope: Scope,
+    key: &str,
+    allowed_failures: u32,
+) -> sqlx::Result<()> {
+    let env = &ctx.env.login_throttle;
+    let failures = db::login_throttle::record_failure(conn, scope, key, reset_after(env)).await?;
+    if let Some(lockout) = lockout(env, allowed_failures, failures as u32) {
+        tracing::warn!(
+            "Locking out {scope:?} {key} for {secs} s after {failures} failed logins",
+            secs = lockout.num_seconds(),
+        );
+        db::login_throttle::lock_out(conn, scope, key, lockout).await?;
+    }
+    Ok(())
+}
+
+/// Counts a failed login as `username` from `ip` against both.
+///
+/// Unknown usernames are counted too, so the lockouts don't reveal which usernames are taken.
+pub(crate) async fn record_failure(ctx: &Context, username: &str, ip: IpAddr) -> sqlx::Result<()> {
+    let env = &ctx.env.login_throttle;
+    let mut tx = ctx.db.begin().await?;
+    record_failure_of(
+        ctx,
+        &mut tx,
+        Scope::Username,
+        &username::normalize(username),
+        env.username_allowed_failures,
+    )
+    .await?;
+    record_failure_of(
+        ctx,
+        &mut tx,
+        Scope::Ip,
+        &ip_key(ip),
+        env.ip_allowed_failures,
+    )
+    .await?;
+    tx.commit().await
+}
+
+/// Forgets the failures of the username after a successful login.
+///
+/// The failures from the IP address are kept: otherwise, an attacker could log into their own
+/// account every now and then to keep guessing the passwords of others.
+pub(crate) async fn record_success(ctx: &Context, username: &str) -> sqlx::Result<()> {
+    db::login_throttle::reset(&ctx.db, Scope::Username, &username::normalize(username)).await
+}
+
+/// Periodically deletes the counters that would start over anyway.
+pub(crate) fn spawn_pruner(env: Env, db: Db) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            match db::login_throttle::prune(&db, reset_after(&env.login_throttle)).await {
+                Ok(pruned) => tracing::debug!("Pruned {pruned} login throttling counters"),
+                Err(err) => tracing::error!("Failed to prune the login throttling counters: {err}"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> LoginThrottleEnv {
+        LoginThrottleEnv {
+            username_allowed_failures: 5,
+            ip_allowed_failures: 20,
+            base_lockout_secs: 30,
+            max_lockout_secs: 3600,
+            reset_after_secs: 86400,
+        }
+    }
+
+    #[test]
+    fn lockout_doubles_up_to_the_maximum() {
+        let env = env();
+        let secs = |failures| lockout(&env, 5, failures).map(|lockout| lockout.num_seconds());
+        assert_eq!(secs(0), None);
+        assert_eq!(secs(5), None);
+        assert_eq!(secs(6), Some(30));
+        assert_eq!(secs(7), Some(60));
+        assert_eq!(secs(12), Some(1920));
+        assert_eq!(secs(13), Some(3600));
+        assert_eq!(secs(100), Some(3600));
+    }
+
+    #[test]
+    fn ipv6_addresses_are_counted_per_64_network() {
+        let key = |ip: &str| ip_key(ip.parse().unwrap());
+        assert_eq!(key("203.0.113.7"), "203.0.113.7");
+        assert_eq!(key("::ffff:203.0.113.7"), "203.0.113.7");
+        assert_eq!(key("2001:db8:1:2:3:4:5:6"), "2001:db8:1:2::/64");
+        assert_eq!(key("2001:db8:1:2:ffff::1"), key("2001:db8:1:2::1"));
+        assert_ne!(key("2001:db8:1:2::1"), key("2001:db8:1:3::1"));
+    }
+}
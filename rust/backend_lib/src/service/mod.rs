@@ -1,2 +1,15 @@
+pub(crate) mod account_deletion;
+pub(crate) mod admin;
+pub(crate) mod audit;
+pub(crate) mod avatar_gc;
 pub(crate) mod bff;
+pub(crate) mod data_export;
+pub(crate) mod email_verification;
+pub(crate) mod lichess;
+pub(crate) mod login_throttle;
+pub(crate) mod passkey;
+pub(crate) mod password_reset;
+pub(crate) mod personal_access_token;
+pub(crate) mod session;
+pub(crate) mod totp;
 pub(crate) mod user;
@@ -0,0 +1,457 @@
+//! Passwordless login with passkeys, see [`crate::webauthn`].
+//!
+//! Both ceremonies start by handing out the options with a single-use challenge, which is stored
+//! hashed and consumed when the browser sends back the credential. The passkeys are discoverable,
+//! so the login doesn't ask for the username and the authenticator tells us the user instead.
+//!
+//! Since the authenticators verify the user, the sessions started with a passkey carry
+//! `second_factor` like those completed with a TOTP code.
+
+use shared_items_lib::JwtClaims;
+use shared_items_lib::Timestamp;
+use shared_items_lib::service_responses::{
+    DeletePasskeyResponse, GetPasskeysResponse, GetPasskeysResponseSuccess, LoginFailureReason,
+    LoginMethod, Passkey, PasskeyAuthenticatorSelection, PasskeyCreationOptions,
+    PasskeyCredentialDescriptor, PasskeyCredentialParameters, PasskeyRelyingParty,
+    PasskeyRequestOptions, PasskeyUser, PostLoginResponseSuccess, PostPasskeyLoginOptionsResponse,
+    PostPasskeyLoginResponse, PostPasskeyRegistrationOptionsResponse,
+    PostPasskeyRegistrationResponse,
+};
+
+use crate::request_origin::RequestOrigin;
+use crate::service::{admin, audit, session};
+use crate::webauthn::{self, Ceremony, Rejection};
+use crate::{Context, db, token};
+
+const PUBLIC_KEY: &str = "public-key";
+const MAX_NAME_LEN: usize = 64;
+
+fn challenge_ttl() -> chrono::Duration {
+    chrono::Duration::minutes(5)
+}
+
+fn timeout_ms() -> u32 {
+    challenge_ttl().num_milliseconds() as u32
+}
+
+/// The user handle that the authenticator stores along with the passkey.
+fn user_handle(user_id: db::id::UserId) -> String {
+    let user_id: mnln_core_items::id::UserId = user_id.into();
+    webauthn::base64url_encode(&user_id.0.to_be_bytes())
+}
+
+fn passkey(passkey: db::passkey::list::Passkey) -> Passkey {
+    let db::passkey::list::Passkey {
+        id,
+        name,
+        created_at_ms,
+        last_used_at_ms,
+    } = passkey;
+    Passkey {
+        id,
+        name,
+        created_at: Timestamp(created_at_ms as u64),
+        last_used_at: last_used_at_ms.map(|ms| Timestamp(ms as u64)),
+    }
+}
+
+pub(crate) async fn registration_options(
+    ctx: &Context,
+    claims: JwtClaims,
+) -> PostPasskeyRegistrationOptionsResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    let user_id: db::id::UserId = user_id.into();
+
+    let res: sqlx::Result<PostPasskeyRegistrationOptionsResponse> = async {
+        let Some(profile) = db::user::get_profile(&ctx.db, user_id).await? else {
+            return Ok(PostPasskeyRegistrationOptionsResponse::NotFound);
+        };
+        let exclude_credentials = db::passkey::credential_ids(&ctx.db, user_id)
+            .await?
+            .iter()
+            .map(|credential_id| PasskeyCredentialDescriptor {
+                type_: PUBLIC_KEY.to_string(),
+                id: webauthn::base64url_encode(credential_id),
+            })
+            .collect();
+
+        let challenge = token::generate();
+        db::passkey::create_challenge(
+            &ctx.db,
+            &token::hash(&challenge),
+            Some(user_id),
+            challenge_ttl(),
+        )
+        .await?;
+
+        let env = &ctx.env.webauthn;
+        Ok(PostPasskeyRegistrationOptionsResponse::Success(Box::new(
+            PasskeyCreationOptions {
+                challenge,
+                rp: PasskeyRelyingParty {
+                    id: env.rp_id.clone(),
+                    name: env.rp_name.clone(),
+                },
+                user: PasskeyUser {
+                    id: user_handle(user_id),
+                    name: profile.username.clone(),
+                    display_name: profile.username,
+                },
+                pub_key_cred_params: webauthn::ALGORITHMS
+                    .iter()
+                    .map(|&alg| PasskeyCredentialParameters {
+                        type_: PUBLIC_KEY.to_string(),
+                        alg: alg as i32,
+                    })
+                    .collect(),
+                timeout: timeout_ms(),
+                exclude_credentials,
+                authenticator_selection: PasskeyAuthenticatorSelection {
+                    resident_key: "required".to_string(),
+                    require_resident_key: true,
+                    user_verification: "required".to_string(),
+                },
+                attestation: "none".to_string(),
+            },
+        )))
+    }
+    .await;
+
+    res.unwrap_or_else(|e| {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(registration_options),
+            err = e,
+        );
+        PostPasskeyRegistrationOptionsResponse::InternalServerError
+    })
+}
+
+/// The `response` of a `PublicKeyCredential` created by the authenticator, see
+/// <https://www.w3.org/TR/webauthn-3/#dictdef-authenticatorattestationresponsejson>.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AttestationResponse {
+    /// base64url-encoded
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    /// base64url-encoded
+    attestation_object: String,
+}
+
+/// A `PublicKeyCredential` created by the authenticator, as serialized by its `toJSON()`.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct RegistrationCredential {
+    /// The base64url-encoded credential ID
+    id: String,
+    response: AttestationResponse,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct PasskeyRegistrationRequest {
+    /// The name to tell the passkey apart from the others, e.g. the device it's stored on
+    name: String,
+    credential: RegistrationCredential,
+}
+
+pub(crate) async fn register(
+    ctx: &Context,
+    claims: JwtClaims,
+    request: PasskeyRegistrationRequest,
+) -> PostPasskeyRegistrationResponse {
+    let PasskeyRegistrationRequest { name, credential } = request;
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    let user_id: db::id::UserId = user_id.into();
+
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return PostPasskeyRegistrationResponse::InvalidName;
+    }
+
+    let verified: Result<_, Rejection> = (|| {
+        let client_data_json = webauthn::base64url_decode(&credential.response.client_data_json)?;
+        let attestation_object =
+            webauthn::base64url_decode(&credential.response.attestation_object)?;
+        let challenge = webauthn::client_data_challenge(
+            &ctx.env.webauthn,
+            &client_data_json,
+            Ceremony::Registration,
+        )?;
+        let new_credential = webauthn::verify_registration(
+            &ctx.env.webauthn,
+            &client_data_json,
+            &attestation_object,
+        )?;
+        if webauthn::base64url_encode(&new_credential.credential_id) != credential.id {
+            return Err(Rejection(
+                "The credential ID doesn't match the attested one",
+            ));
+        }
+        Ok((challenge, new_credential))
+    })();
+    let (challenge, new_credential) = match verified {
+        Ok(verified) => verified,
+        Err(Rejection(reason)) => {
+            return PostPasskeyRegistrationResponse::InvalidCredential {
+                reason: reason.to_string(),
+            };
+        }
+    };
+
+    let res: sqlx::Result<PostPasskeyRegistrationResponse> = async {
+        if !db::passkey::take_challenge(&ctx.db, &token::hash(&challenge), Some(user_id)).await? {
+            return Ok(PostPasskeyRegistrationResponse::InvalidChallenge);
+        }
+        let webauthn::NewCredential {
+            credential_id,
+            public_key,
+            sign_count,
+        } = new_credential;
+        let output = db::passkey::create(
+            &ctx.db,
+            user_id,
+            &credential_id,
+            &public_key,
+            sign_count,
+            name,
+        )
+        .await?;
+        Ok(match output {
+            db::passkey::create::Output::Success(created) => {
+                tracing::info!("User with ID {user_id} registered a passkey");
+                PostPasskeyRegistrationResponse::Success(passkey(created))
+            }
+            db::passkey::create::Output::AlreadyRegistered => {
+                PostPasskeyRegistrationResponse::AlreadyRegistered
+            }
+        })
+    }
+    .await;
+
+    res.unwrap_or_else(|e| {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(register),
+            err = e,
+        );
+        PostPasskeyRegistrationResponse::InternalServerError
+    })
+}
+
+pub(crate) async fn list(ctx: &Context, claims: JwtClaims) -> GetPasskeysResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    let user_id: db::id::UserId = user_id.into();
+
+    match db::passkey::list(&ctx.db, user_id).await {
+        Ok(passkeys) => GetPasskeysResponse::Success(GetPasskeysResponseSuccess {
+            passkeys: passkeys.into_iter().map(passkey).collect(),
+        }),
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(list),
+                err = e,
+            );
+            GetPasskeysResponse::InternalServerError
+        }
+    }
+}
+
+pub(crate) async fn delete(
+    ctx: &Context,
+    claims: JwtClaims,
+    passkey_id: i32,
+) -> DeletePasskeyResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    let user_id: db::id::UserId = user_id.into();
+
+    match db::passkey::delete(&ctx.db, user_id, passkey_id).await {
+        Ok(true) => {
+            tracing::info!("User with ID {user_id} deleted the passkey with ID {passkey_id}");
+            DeletePasskeyResponse::Success
+        }
+        Ok(false) => DeletePasskeyResponse::NotFound,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(delete),
+                err = e,
+            );
+            DeletePasskeyResponse::InternalServerError
+        }
+    }
+}
+
+pub(crate) async fn login_options(ctx: &Context) -> PostPasskeyLoginOptionsResponse {
+    let challenge = token::generate();
+    let res =
+        db::passkey::create_challenge(&ctx.db, &token::hash(&challenge), None, challenge_ttl())
+            .await;
+    if let Err(e) = res {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(login_options),
+            err = e,
+        );
+        return PostPasskeyLoginOptionsResponse::InternalServerError;
+    }
+
+    PostPasskeyLoginOptionsResponse::Success(PasskeyRequestOptions {
+        challenge,
+        timeout: timeout_ms(),
+        rp_id: ctx.env.webauthn.rp_id.clone(),
+        allow_credentials: Vec::new(),
+        user_verification: "required".to_string(),
+    })
+}
+
+/// The `response` of a `PublicKeyCredential` asserted by the authenticator, see
+/// <https://www.w3.org/TR/webauthn-3/#dictdef-authenticatorassertionresponsejson>.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AssertionResponse {
+    /// base64url-encoded
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    /// base64url-encoded
+    authenticator_data: String,
+    /// base64url-encoded
+    signature: String,
+    /// base64url-encoded, which discoverable passkeys always return
+    user_handle: Option<String>,
+}
+
+/// A `PublicKeyCredential` asserted by the authenticator, as serialized by its `toJSON()`.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct PasskeyLoginRequest {
+    /// The base64url-encoded credential ID
+    id: String,
+    response: AssertionResponse,
+}
+
+fn invalid_credential(reason: &'static str) -> PostPasskeyLoginResponse {
+    PostPasskeyLoginResponse::InvalidCredential {
+        reason: reason.to_string(),
+    }
+}
+
+pub(crate) async fn login(
+    ctx: &Context,
+    request: PasskeyLoginRequest,
+    origin: &RequestOrigin,
+) -> PostPasskeyLoginResponse {
+    let PasskeyLoginRequest { id, response } = request;
+
+    let decoded: Result<_, Rejection> = (|| {
+        let credential_id = webauthn::base64url_decode(&id)?;
+        let client_data_json = webauthn::base64url_decode(&response.client_data_json)?;
+        let authenticator_data = webauthn::base64url_decode(&response.authenticator_data)?;
+        let signature = webauthn::base64url_decode(&response.signature)?;
+        let challenge = webauthn::client_data_challenge(
+            &ctx.env.webauthn,
+            &client_data_json,
+            Ceremony::Authentication,
+        )?;
+        Ok((
+            credential_id,
+            client_data_json,
+            authenticator_data,
+            signature,
+            challenge,
+        ))
+    })();
+    let (credential_id, client_data_json, authenticator_data, signature, challenge) = match decoded
+    {
+        Ok(decoded) => decoded,
+        Err(Rejection(reason)) => return invalid_credential(reason),
+    };
+
+    let res: sqlx::Result<PostPasskeyLoginResponse> = async {
+        if !db::passkey::take_challenge(&ctx.db, &token::hash(&challenge), None).await? {
+            return Ok(PostPasskeyLoginResponse::InvalidChallenge);
+        }
+        let Some(credential) = db::passkey::find(&ctx.db, &credential_id).await? else {
+            return Ok(invalid_credential("Unknown passkey"));
+        };
+        let db::passkey::find::Credential {
+            id: passkey_id,
+            user_id,
+            role,
+            public_key,
+            sign_count: stored_sign_count,
+        } = credential;
+
+        if let Some(handle) = &response.user_handle
+            && *handle != user_handle(user_id)
+        {
+            return Ok(invalid_credential("The passkey belongs to another user"));
+        }
+
+        let login_failed = |reason| {
+            audit::login_failed(ctx, Some(user_id), origin, LoginMethod::Passkey, reason, None)
+        };
+
+        let sign_count = match webauthn::verify_assertion(
+            &ctx.env.webauthn,
+            &public_key,
+            &client_data_json,
+            &authenticator_data,
+            &signature,
+        ) {
+            Ok(sign_count) => sign_count,
+            Err(Rejection(reason)) => {
+                login_failed(LoginFailureReason::InvalidPasskey).await;
+                return Ok(invalid_credential(reason));
+            }
+        };
+        // The stored counter came from a `u32` in the first place.
+        if !webauthn::sign_count_is_valid(stored_sign_count as u32, sign_count)
+            || !db::passkey::record_use(&ctx.db, passkey_id, stored_sign_count, sign_count).await?
+        {
+            tracing::warn!(
+                "The function {mod_path}::{fn_name}(...) failed: the signature counter of the passkey with ID {passkey_id} went from {stored_sign_count} to {sign_count}, so it may have been cloned",
+                mod_path = module_path!(),
+                fn_name = stringify!(login),
+            );
+            login_failed(LoginFailureReason::InvalidPasskey).await;
+            return Ok(invalid_credential("The signature counter didn't increase"));
+        }
+
+        if let Some(restriction) = admin::login_restriction(ctx, user_id).await? {
+            login_failed(LoginFailureReason::Restricted).await;
+            return Ok(PostPasskeyLoginResponse::Restricted(restriction));
+        }
+
+        Ok(match session::start(ctx, user_id, role, true).await {
+            Ok(session::Tokens { jwt, refresh_token }) => {
+                audit::login_succeeded(ctx, user_id, origin, LoginMethod::Passkey).await;
+                PostPasskeyLoginResponse::Success(PostLoginResponseSuccess { jwt, refresh_token })
+            }
+            Err(e) => {
+                tracing::error!(
+                    "The function {mod_path}::{fn_name}(...) failed while starting a session: {err}",
+                    mod_path = module_path!(),
+                    fn_name = stringify!(login),
+                    err = e,
+                );
+                PostPasskeyLoginResponse::InternalServerError
+            }
+        })
+    }
+    .await;
+
+    res.unwrap_or_else(|e| {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(login),
+            err = e,
+        );
+        PostPasskeyLoginResponse::InternalServerError
+    })
+}
@@ -0,0 +1,225 @@
+//! Recovering an account whose password is forgotten with a single-use link sent to the
+//! verified email address.
+//!
+//! Requesting a link responds the same whether or not the account exists, so the endpoint
+//! can't be used to find out who is registered.
+
+use shared_items_lib::JwtClaims;
+use shared_items_lib::service_responses::{
+    AuditEventDetails, PostAdminForcePasswordResetResponse, PostPasswordResetConfirmResponse,
+    PostPasswordResetRequestResponse,
+};
+
+use crate::db::outbox::enqueue::NewMail;
+use crate::request_origin::RequestOrigin;
+use crate::service::audit;
+use crate::{Context, db, links, password, token, validation};
+
+fn token_ttl() -> chrono::Duration {
+    chrono::Duration::hours(1)
+}
+
+/// The minimum time between two links sent to the same user.
+fn throttle() -> chrono::Duration {
+    chrono::Duration::minutes(1)
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct PasswordResetRequest {
+    email: String,
+}
+
+pub(crate) async fn request(
+    ctx: &Context,
+    request: PasswordResetRequest,
+) -> PostPasswordResetRequestResponse {
+    // Nobody can have an invalid email address, so there's nothing to send.
+    let Ok(email) = validation::email(&request.email) else {
+        return PostPasswordResetRequestResponse::Success;
+    };
+
+    let reset_token = token::generate();
+    let url = links::reset_password_url(&ctx.env, &reset_token);
+    let mail = NewMail {
+        recipient: email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi,\n\
+             \n\
+             Someone asked to reset the password of the main-line account with this email \
+             address. You can choose a new password by opening the link below within \
+             {minutes} minutes:\n\
+             \n\
+             {url}\n\
+             \n\
+             Resetting the password will log you out everywhere. If you didn't ask for this, \
+             you can ignore this message.\n",
+            minutes = token_ttl().num_minutes(),
+        ),
+    };
+
+    match db::password_reset::create(
+        &ctx.db,
+        &email,
+        &token::hash(&reset_token),
+        token_ttl(),
+        throttle(),
+        &mail,
+    )
+    .await
+    {
+        // Whether a link was sent must not be disclosed
+        Ok(_) => PostPasswordResetRequestResponse::Success,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(request),
+                err = e,
+            );
+            PostPasswordResetRequestResponse::InternalServerError
+        }
+    }
+}
+
+/// Sends the user a reset link and keeps them from logging in with the password until they
+/// use it, e.g. because the password is known to be compromised.
+pub(crate) async fn force(
+    ctx: &Context,
+    admin: JwtClaims,
+    user_id: mnln_core_items::id::UserId,
+    origin: &RequestOrigin,
+) -> PostAdminForcePasswordResetResponse {
+    let admin_id: mnln_core_items::id::UserId = admin.sub.into();
+    let user_id: db::id::UserId = user_id.into();
+
+    let res: sqlx::Result<PostAdminForcePasswordResetResponse> = async {
+        let email = match db::user::get_profile(&ctx.db, user_id).await? {
+            Some(db::user::get_profile::Profile {
+                email: Some(email),
+                email_verified: true,
+                ..
+            }) => email,
+            Some(_) => return Ok(PostAdminForcePasswordResetResponse::NoVerifiedEmail),
+            None => return Ok(PostAdminForcePasswordResetResponse::NotFound),
+        };
+
+        let reset_token = token::generate();
+        let url = links::reset_password_url(&ctx.env, &reset_token);
+        let mail = NewMail {
+            recipient: email,
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Hi,\n\
+                 \n\
+                 An administrator requires you to choose a new password for your main-line \
+                 account before you log in with the password again. You can choose it by \
+                 opening the link below within {minutes} minutes:\n\
+                 \n\
+                 {url}\n\
+                 \n\
+                 If the link expires, you can ask for another one on the login page.\n",
+                minutes = token_ttl().num_minutes(),
+            ),
+        };
+
+        let Some(revoked_at_ms) = db::password_reset::force(
+            &ctx.db,
+            user_id,
+            &token::hash(&reset_token),
+            token_ttl(),
+            &mail,
+        )
+        .await?
+        else {
+            return Ok(PostAdminForcePasswordResetResponse::NotFound);
+        };
+        ctx.revocations
+            .insert_sessions_revoked_at(user_id.into(), revoked_at_ms as u64);
+        tracing::info!(
+            "Admin with ID {admin_id} forced a password reset of user with ID {user_id}"
+        );
+        let details = AuditEventDetails::PasswordResetForced;
+        audit::record(ctx, Some(admin_id.into()), Some(user_id), origin, details).await;
+        Ok(PostAdminForcePasswordResetResponse::Success)
+    }
+    .await;
+
+    res.unwrap_or_else(|e| {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(force),
+            err = e,
+        );
+        PostAdminForcePasswordResetResponse::InternalServerError
+    })
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct PasswordResetConfirmRequest {
+    /// The `token` query parameter of the reset link
+    token: String,
+    /// The new password, pre-hashed like in [`crate::service::user::RegisterRequest`]
+    password_hash: String,
+}
+
+pub(crate) async fn confirm(
+    ctx: &Context,
+    request: PasswordResetConfirmRequest,
+) -> PostPasswordResetConfirmResponse {
+    let PasswordResetConfirmRequest {
+        token: reset_token,
+        password_hash,
+    } = request;
+
+    let client_salt: String = password::client_salt(&password_hash);
+    let password_hash = match password::hash(&ctx.env.argon2, password_hash).await {
+        Ok(password_hash) => password_hash,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed while hashing the password: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(confirm),
+                err = e,
+            );
+            return PostPasswordResetConfirmResponse::InternalServerError;
+        }
+    };
+
+    let redeemed = match db::password_reset::redeem(
+        &ctx.db,
+        &token::hash(&reset_token),
+        &password_hash,
+        &client_salt,
+    )
+    .await
+    {
+        Ok(Some(redeemed)) => redeemed,
+        Ok(None) => return PostPasswordResetConfirmResponse::InvalidToken,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(confirm),
+                err = e,
+            );
+            return PostPasswordResetConfirmResponse::InternalServerError;
+        }
+    };
+
+    let db::password_reset::redeem::Redeemed {
+        user_id,
+        sessions_revoked_at_ms,
+    } = redeemed;
+    ctx.revocations
+        .insert_sessions_revoked_at(user_id.into(), sessions_revoked_at_ms as u64);
+
+    tracing::info!(
+        "The function {mod_path}::{fn_name}(...) succeeded: reset the password of user with ID {user_id}",
+        mod_path = module_path!(),
+        fn_name = stringify!(confirm),
+    );
+
+    PostPasswordResetConfirmResponse::Success
+}
@@ -0,0 +1,217 @@
+//! Long-lived bearer tokens for scripted access to the API.
+//!
+//! Unlike the access tokens, a personal access token is accepted only by the endpoints that
+//! require one of its scopes, see [`crate::auth::RequireScope`]. It's stored hashed, shown to the
+//! user only once, and valid until it expires, if ever, or the user revokes it. Revoking all the
+//! sessions of the user, e.g. on a password reset or on logging out everywhere, deletes the
+//! tokens as well.
+
+use shared_items_lib::service_responses::{
+    DeletePersonalAccessTokenResponse, GetPersonalAccessTokensResponse,
+    GetPersonalAccessTokensResponseSuccess, PersonalAccessToken, PersonalAccessTokenScope,
+    PostPersonalAccessTokenResponse, PostPersonalAccessTokenResponseSuccess,
+};
+use shared_items_lib::{JwtClaims, NumericDate, Timestamp};
+
+use crate::{Context, db, token, util};
+
+/// Tells the personal access tokens apart from the access tokens, which are JWTs.
+const PREFIX: &str = "mnln_pat_";
+const MAX_TOKENS: i64 = 20;
+const MAX_NAME_LEN: usize = 64;
+
+pub(crate) fn is_personal_access_token(bearer_token: &str) -> bool {
+    bearer_token.starts_with(PREFIX)
+}
+
+impl From<db::personal_access_token::list::Token> for PersonalAccessToken {
+    fn from(value: db::personal_access_token::list::Token) -> Self {
+        let db::personal_access_token::list::Token {
+            id,
+            name,
+            scopes,
+            created_at_ms,
+            last_used_at_ms,
+            expires_at_ms,
+        } = value;
+        PersonalAccessToken {
+            id,
+            name,
+            scopes: scopes.into_iter().map(Into::into).collect(),
+            created_at: Timestamp(created_at_ms as u64),
+            last_used_at: last_used_at_ms.map(|ms| Timestamp(ms as u64)),
+            expires_at: expires_at_ms.map(|ms| Timestamp(ms as u64)),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct CreatePersonalAccessTokenRequest {
+    /// From 1 to 64 characters, e.g. what the token is used for
+    name: String,
+    /// At least one
+    scopes: Vec<PersonalAccessTokenScope>,
+    /// UNIX timestamp (ms) in the future, or `None` for a token that is valid until revoked
+    expires_at: Option<Timestamp>,
+}
+
+pub(crate) async fn create(
+    ctx: &Context,
+    claims: JwtClaims,
+    request: CreatePersonalAccessTokenRequest,
+) -> PostPersonalAccessTokenResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    let CreatePersonalAccessTokenRequest {
+        name,
+        mut scopes,
+        expires_at,
+    } = request;
+
+    let invalid_request = |reason: &str| PostPersonalAccessTokenResponse::InvalidRequest {
+        reason: reason.to_string(),
+    };
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return invalid_request("The name must be from 1 to 64 characters long");
+    }
+    let mut seen = Vec::with_capacity(scopes.len());
+    scopes.retain(|scope| {
+        let is_new = !seen.contains(scope);
+        seen.push(*scope);
+        is_new
+    });
+    if scopes.is_empty() {
+        return invalid_request("The token must have at least one scope");
+    }
+    let expires_at_ms = match expires_at {
+        Some(Timestamp(ms)) if ms <= util::now().0 || i64::try_from(ms).is_err() => {
+            return invalid_request("The expiry must be in the future");
+        }
+        expires_at => expires_at.map(|Timestamp(ms)| ms as i64),
+    };
+
+    let bearer_token = format!("{PREFIX}{}", token::generate());
+    let scopes: Vec<db::personal_access_token::Scope> =
+        scopes.into_iter().map(Into::into).collect();
+
+    let res = db::personal_access_token::create(
+        &ctx.db,
+        user_id.into(),
+        &token::hash(&bearer_token),
+        name,
+        &scopes,
+        expires_at_ms,
+        MAX_TOKENS,
+    )
+    .await;
+
+    match res {
+        Ok(db::personal_access_token::create::Output::Success(personal_access_token)) => {
+            tracing::info!(
+                "User with ID {user_id} created the personal access token with ID {}",
+                personal_access_token.id
+            );
+            PostPersonalAccessTokenResponse::Success(PostPersonalAccessTokenResponseSuccess {
+                token: bearer_token,
+                personal_access_token: personal_access_token.into(),
+            })
+        }
+        Ok(db::personal_access_token::create::Output::TooManyTokens) => {
+            PostPersonalAccessTokenResponse::TooManyTokens
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(create),
+                err = e,
+            );
+            PostPersonalAccessTokenResponse::InternalServerError
+        }
+    }
+}
+
+pub(crate) async fn list(ctx: &Context, claims: JwtClaims) -> GetPersonalAccessTokensResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+
+    match db::personal_access_token::list(&ctx.db, user_id.into()).await {
+        Ok(tokens) => {
+            GetPersonalAccessTokensResponse::Success(GetPersonalAccessTokensResponseSuccess {
+                personal_access_tokens: tokens.into_iter().map(Into::into).collect(),
+            })
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(list),
+                err = e,
+            );
+            GetPersonalAccessTokensResponse::InternalServerError
+        }
+    }
+}
+
+pub(crate) async fn delete(
+    ctx: &Context,
+    claims: JwtClaims,
+    token_id: i32,
+) -> DeletePersonalAccessTokenResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+
+    match db::personal_access_token::delete(&ctx.db, user_id.into(), token_id).await {
+        Ok(true) => {
+            tracing::info!(
+                "User with ID {user_id} revoked the personal access token with ID {token_id}"
+            );
+            DeletePersonalAccessTokenResponse::Success
+        }
+        Ok(false) => DeletePersonalAccessTokenResponse::NotFound,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(delete),
+                err = e,
+            );
+            DeletePersonalAccessTokenResponse::InternalServerError
+        }
+    }
+}
+
+/// The claims of the user that the valid personal access token belongs to, and its scopes.
+///
+/// The claims aren't signed, but they let the handlers treat both kinds of tokens alike. Their
+/// `jti` is derived from the ID of the token, and they never have the second factor.
+pub(crate) async fn authenticate(
+    ctx: &Context,
+    bearer_token: &str,
+) -> sqlx::Result<Option<(JwtClaims, Vec<PersonalAccessTokenScope>)>> {
+    let Some(token) =
+        db::personal_access_token::authenticate(&ctx.db, &token::hash(bearer_token)).await?
+    else {
+        return Ok(None);
+    };
+    let db::personal_access_token::authenticate::Token {
+        id,
+        user_id,
+        role,
+        scopes,
+        created_at_ms,
+        expires_at_ms,
+    } = token;
+
+    let user_id: mnln_core_items::id::UserId = user_id.into();
+    let claims = JwtClaims {
+        iss: ctx.env.jwt.issuer.clone(),
+        sub: user_id.into(),
+        aud: ctx.env.jwt.audience.clone(),
+        exp: NumericDate(expires_at_ms.map_or(u64::MAX, |ms| ms as u64 / 1000)),
+        nbf: Timestamp(created_at_ms as u64).into(),
+        iat: util::now().into(),
+        role: role.into(),
+        jti: format!("pat-{id}"),
+        second_factor: false,
+    };
+    Ok(Some((claims, scopes.into_iter().map(Into::into).collect())))
+}
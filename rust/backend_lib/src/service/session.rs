@@ -0,0 +1,87 @@
+//! Sessions made of a short-lived access JWT and a rotating, single-use refresh token.
+
+use mnln_env::JwtEnv;
+use shared_items_lib::id::UserId;
+use shared_items_lib::{JwtClaims, JwtString, NumericDate, RefreshTokenString, Role};
+
+use crate::{Context, db, token, util};
+
+pub(crate) fn access_token_ttl() -> chrono::Duration {
+    chrono::Duration::minutes(15)
+}
+
+pub(crate) fn refresh_token_ttl() -> chrono::Duration {
+    chrono::Duration::weeks(2)
+}
+
+/// How long a rotated refresh token still yields its successor, since the clients may send
+/// the same token in parallel requests, e.g. from several tabs, before they learn the new one.
+pub(crate) fn refresh_token_reuse_grace() -> chrono::Duration {
+    chrono::Duration::seconds(30)
+}
+
+pub(crate) struct Tokens {
+    pub(crate) jwt: JwtString,
+    pub(crate) refresh_token: RefreshTokenString,
+}
+
+fn create_jwt_claims(
+    jwt_env: &JwtEnv,
+    user_id: UserId,
+    role: Role,
+    second_factor: bool,
+) -> JwtClaims {
+    let iat = NumericDate::from(util::now());
+    let exp = NumericDate::from(util::time_from_now(access_token_ttl()));
+    JwtClaims {
+        iss: jwt_env.issuer.clone(),
+        sub: user_id,
+        aud: jwt_env.audience.clone(),
+        role,
+        iat,
+        nbf: iat,
+        exp,
+        jti: token::generate(),
+        second_factor,
+    }
+}
+
+pub(crate) fn sign_access_token(
+    ctx: &Context,
+    user_id: db::id::UserId,
+    role: db::user::Role,
+    second_factor: bool,
+) -> anyhow::Result<JwtString> {
+    let user_id: mnln_core_items::id::UserId = user_id.into();
+    let claims: JwtClaims =
+        create_jwt_claims(&ctx.env.jwt, user_id.into(), role.into(), second_factor);
+    util::sign_jwt(&claims, ctx)
+}
+
+/// Starts a new token family, e.g. after a successful login.
+///
+/// `second_factor` tells whether the user passed the second factor, which every access token
+/// of the family will carry.
+pub(crate) async fn start(
+    ctx: &Context,
+    user_id: db::id::UserId,
+    role: db::user::Role,
+    second_factor: bool,
+) -> anyhow::Result<Tokens> {
+    let refresh_token = token::generate();
+    db::session::create(
+        &ctx.db,
+        user_id,
+        &token::hash(&refresh_token),
+        refresh_token_ttl(),
+        second_factor,
+    )
+    .await?;
+
+    let jwt = sign_access_token(ctx, user_id, role, second_factor)?;
+
+    Ok(Tokens {
+        jwt,
+        refresh_token: RefreshTokenString(refresh_token),
+    })
+}
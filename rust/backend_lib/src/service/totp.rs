@@ -0,0 +1,397 @@
+//! Optional two-factor authentication with TOTP.
+//!
+//! The user enrolls by adding the secret to their authenticator app and confirming it with a code,
+//! which also hands out the recovery codes. From then on, the right password (or a sign-in with
+//! Lichess) only yields a short-lived challenge token, signed like the access tokens but for
+//! another audience, which is exchanged for the session along with a TOTP code or a recovery code.
+//!
+//! The sessions started this way carry `second_factor` in their access tokens, which the admin
+//! endpoints require unless `TOTP_REQUIRE_FOR_ADMINS` is off.
+
+use std::net::IpAddr;
+
+use shared_items_lib::id::UserId;
+use shared_items_lib::service_responses::{
+    LoginFailureReason, LoginMethod, PostLoginResponseSuccess, PostLoginSecondFactorResponse,
+    PostTotpConfirmResponse, PostTotpConfirmResponseSuccess, PostTotpDisableResponse,
+    PostTotpEnrollResponse, PostTotpEnrollResponseSuccess, SecondFactorChallenge,
+};
+use shared_items_lib::{JwtClaims, NumericDate};
+
+use crate::request_origin::RequestOrigin;
+use crate::service::{admin, audit, login_throttle, session, user};
+use crate::{Context, db, token, totp, util};
+
+const AUDIENCE: &str = "second-factor";
+
+fn challenge_ttl() -> chrono::Duration {
+    chrono::Duration::minutes(5)
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct ChallengeClaims {
+    iss: String,
+    aud: String,
+    sub: UserId,
+    exp: NumericDate,
+}
+
+/// The challenge to complete the login with, if the user has TOTP enabled.
+pub(crate) async fn challenge(
+    ctx: &Context,
+    user_id: db::id::UserId,
+) -> anyhow::Result<Option<SecondFactorChallenge>> {
+    match db::totp::get(&ctx.db, user_id).await? {
+        Some(db::totp::get::Totp { enabled: true, .. }) => (),
+        Some(_) | None => return Ok(None),
+    }
+    let user_id: mnln_core_items::id::UserId = user_id.into();
+    let expires_at = util::time_from_now(challenge_ttl());
+    let claims = ChallengeClaims {
+        iss: ctx.env.jwt.issuer.clone(),
+        aud: AUDIENCE.to_string(),
+        sub: user_id.into(),
+        exp: expires_at.into(),
+    };
+    let challenge_token = ctx.key_ring.sign(&claims)?;
+    Ok(Some(SecondFactorChallenge {
+        challenge_token,
+        expires_at,
+    }))
+}
+
+fn verify_challenge(ctx: &Context, challenge_token: &str) -> Option<db::id::UserId> {
+    let validation = util::jwt_validation(&ctx.env.jwt, AUDIENCE);
+    let claims: ChallengeClaims = match ctx.key_ring.verify(challenge_token, validation) {
+        Ok(claims) => claims,
+        Err(err) => {
+            tracing::warn!("Failed to verify the second factor challenge token: {err}");
+            return None;
+        }
+    };
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    Some(user_id.into())
+}
+
+/// Accepts either a TOTP code that wasn't used before or an unused recovery code.
+async fn verify_code(
+    ctx: &Context,
+    user_id: db::id::UserId,
+    secret: &[u8],
+    code: &str,
+) -> sqlx::Result<bool> {
+    let code = code.trim();
+    if totp::is_code(code) {
+        let unix_secs = util::now().0 / 1000;
+        let Some(step) = totp::matching_step(secret, code, unix_secs) else {
+            return Ok(false);
+        };
+        return db::totp::use_step(&ctx.db, user_id, step as i64).await;
+    }
+
+    let code_hash = token::hash(&totp::normalize_recovery_code(code));
+    let used = db::totp::use_recovery_code(&ctx.db, user_id, &code_hash).await?;
+    if used {
+        tracing::info!("User with ID {user_id} used a TOTP recovery code");
+    }
+    Ok(used)
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct SecondFactorLoginRequest {
+    /// The `challenge_token` of the `SecondFactorRequired` response
+    challenge_token: String,
+    /// The 6-digit TOTP code or a recovery code
+    code: String,
+}
+
+pub(crate) async fn login(
+    ctx: &Context,
+    request: SecondFactorLoginRequest,
+    origin: &RequestOrigin,
+) -> PostLoginSecondFactorResponse {
+    let SecondFactorLoginRequest {
+        challenge_token,
+        code,
+    } = request;
+    let ip = origin.ip;
+
+    let Some(user_id) = verify_challenge(ctx, &challenge_token) else {
+        return PostLoginSecondFactorResponse::InvalidChallenge;
+    };
+
+    let (username, role, secret) = match db::totp::get(&ctx.db, user_id).await {
+        Ok(Some(db::totp::get::Totp {
+            username,
+            role,
+            secret: Some(secret),
+            enabled: true,
+        })) => (username, role, secret),
+        // TOTP was disabled or the user was deleted since the password was checked
+        Ok(Some(_) | None) => return PostLoginSecondFactorResponse::InvalidChallenge,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(login),
+                err = e,
+            );
+            return PostLoginSecondFactorResponse::InternalServerError;
+        }
+    };
+
+    // The codes are guessed far more easily than the passwords, so they're throttled alike.
+    match user::check_login_throttle(ctx, &username, ip).await {
+        Ok(()) => (),
+        Err(Some(retry_after_secs)) => {
+            return PostLoginSecondFactorResponse::TooManyRequests { retry_after_secs };
+        }
+        Err(None) => return PostLoginSecondFactorResponse::InternalServerError,
+    }
+
+    match verify_code(ctx, user_id, &secret, &code).await {
+        Ok(true) => (),
+        Ok(false) => {
+            tracing::warn!(
+                "The function {mod_path}::{fn_name}(...) failed: wrong code for user with ID {user_id} from {ip}",
+                mod_path = module_path!(),
+                fn_name = stringify!(login),
+            );
+            user::record_login_failure(ctx, &username, ip).await;
+            audit::login_failed(
+                ctx,
+                Some(user_id),
+                origin,
+                LoginMethod::SecondFactor,
+                LoginFailureReason::WrongSecondFactorCode,
+                Some(&username),
+            )
+            .await;
+            return PostLoginSecondFactorResponse::InvalidCode;
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed while verifying the code: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(login),
+                err = e,
+            );
+            return PostLoginSecondFactorResponse::InternalServerError;
+        }
+    }
+
+    if let Err(e) = login_throttle::record_success(ctx, &username).await {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed to reset the failed logins: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(login),
+            err = e,
+        );
+    }
+
+    match admin::login_restriction(ctx, user_id).await {
+        Ok(Some(restriction)) => {
+            audit::login_failed(
+                ctx,
+                Some(user_id),
+                origin,
+                LoginMethod::SecondFactor,
+                LoginFailureReason::Restricted,
+                Some(&username),
+            )
+            .await;
+            return PostLoginSecondFactorResponse::Restricted(restriction);
+        }
+        Ok(None) => (),
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed while checking the restriction: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(login),
+                err = e,
+            );
+            return PostLoginSecondFactorResponse::InternalServerError;
+        }
+    }
+
+    match session::start(ctx, user_id, role, true).await {
+        Ok(session::Tokens { jwt, refresh_token }) => {
+            audit::login_succeeded(ctx, user_id, origin, LoginMethod::SecondFactor).await;
+            PostLoginSecondFactorResponse::Success(PostLoginResponseSuccess { jwt, refresh_token })
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed while starting a session: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(login),
+                err = e,
+            );
+            PostLoginSecondFactorResponse::InternalServerError
+        }
+    }
+}
+
+pub(crate) async fn enroll(ctx: &Context, claims: JwtClaims) -> PostTotpEnrollResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    let user_id: db::id::UserId = user_id.into();
+
+    let res: sqlx::Result<PostTotpEnrollResponse> = async {
+        let username = match db::totp::get(&ctx.db, user_id).await? {
+            Some(db::totp::get::Totp { enabled: true, .. }) => {
+                return Ok(PostTotpEnrollResponse::AlreadyEnabled);
+            }
+            Some(db::totp::get::Totp { username, .. }) => username,
+            None => return Ok(PostTotpEnrollResponse::NotFound),
+        };
+
+        let secret = totp::generate_secret();
+        if !db::totp::begin_enrollment(&ctx.db, user_id, &secret).await? {
+            return Ok(PostTotpEnrollResponse::AlreadyEnabled);
+        }
+
+        Ok(PostTotpEnrollResponse::Success(
+            PostTotpEnrollResponseSuccess {
+                provisioning_uri: totp::provisioning_uri(&ctx.env.totp, &username, secret.clone()),
+                secret: totp::secret_base32(secret),
+            },
+        ))
+    }
+    .await;
+
+    res.unwrap_or_else(|e| {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(enroll),
+            err = e,
+        );
+        PostTotpEnrollResponse::InternalServerError
+    })
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct TotpCodeRequest {
+    /// The 6-digit TOTP code, or a recovery code where it's accepted
+    code: String,
+}
+
+pub(crate) async fn confirm(
+    ctx: &Context,
+    claims: JwtClaims,
+    request: TotpCodeRequest,
+) -> PostTotpConfirmResponse {
+    let TotpCodeRequest { code } = request;
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    let user_id: db::id::UserId = user_id.into();
+
+    let res: sqlx::Result<PostTotpConfirmResponse> = async {
+        let secret = match db::totp::get(&ctx.db, user_id).await? {
+            Some(db::totp::get::Totp {
+                secret: Some(secret),
+                enabled: false,
+                ..
+            }) => secret,
+            Some(_) | None => return Ok(PostTotpConfirmResponse::NotEnrolling),
+        };
+
+        let unix_secs = util::now().0 / 1000;
+        let Some(step) = totp::matching_step(&secret, code.trim(), unix_secs) else {
+            return Ok(PostTotpConfirmResponse::InvalidCode);
+        };
+
+        let recovery_codes: Vec<String> = (0..totp::RECOVERY_CODE_COUNT)
+            .map(|_| totp::generate_recovery_code())
+            .collect();
+        let recovery_code_hashes: Vec<String> = recovery_codes
+            .iter()
+            .map(|code| token::hash(&totp::normalize_recovery_code(code)))
+            .collect();
+
+        if !db::totp::enable(&ctx.db, user_id, step as i64, &recovery_code_hashes).await? {
+            return Ok(PostTotpConfirmResponse::NotEnrolling);
+        }
+
+        Ok(PostTotpConfirmResponse::Success(
+            PostTotpConfirmResponseSuccess { recovery_codes },
+        ))
+    }
+    .await;
+
+    res.unwrap_or_else(|e| {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(confirm),
+            err = e,
+        );
+        PostTotpConfirmResponse::InternalServerError
+    })
+}
+
+/// Disables TOTP, which takes a code so that a stolen access token isn't enough.
+pub(crate) async fn disable(
+    ctx: &Context,
+    claims: JwtClaims,
+    request: TotpCodeRequest,
+    ip: IpAddr,
+) -> PostTotpDisableResponse {
+    let TotpCodeRequest { code } = request;
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    let user_id: db::id::UserId = user_id.into();
+
+    let (username, secret) = match db::totp::get(&ctx.db, user_id).await {
+        Ok(Some(db::totp::get::Totp {
+            username,
+            secret: Some(secret),
+            enabled: true,
+            ..
+        })) => (username, secret),
+        Ok(Some(_) | None) => return PostTotpDisableResponse::NotEnabled,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(disable),
+                err = e,
+            );
+            return PostTotpDisableResponse::InternalServerError;
+        }
+    };
+
+    match user::check_login_throttle(ctx, &username, ip).await {
+        Ok(()) => (),
+        Err(Some(retry_after_secs)) => {
+            return PostTotpDisableResponse::TooManyRequests { retry_after_secs };
+        }
+        Err(None) => return PostTotpDisableResponse::InternalServerError,
+    }
+
+    let res: sqlx::Result<bool> = async {
+        if !verify_code(ctx, user_id, &secret, &code).await? {
+            return Ok(false);
+        }
+        db::totp::disable(&ctx.db, user_id).await?;
+        Ok(true)
+    }
+    .await;
+
+    match res {
+        Ok(true) => {
+            tracing::info!("User with ID {user_id} disabled TOTP");
+            PostTotpDisableResponse::Success
+        }
+        Ok(false) => {
+            user::record_login_failure(ctx, &username, ip).await;
+            PostTotpDisableResponse::InvalidCode
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(disable),
+                err = e,
+            );
+            PostTotpDisableResponse::InternalServerError
+        }
+    }
+}
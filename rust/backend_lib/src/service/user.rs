@@ -1,6 +1,8 @@
 use crate::links;
 use axum::response::IntoResponse as _;
 
+use std::net::IpAddr;
+
 use axum::http::StatusCode;
 use axum::response::Response;
 
@@ -8,19 +10,39 @@ use browser_supported_img_format::BrowserSupportedImgFormat;
 use futures_core::Stream;
 use shared_items_lib::JwtClaims;
 use shared_items_lib::JwtString;
-use shared_items_lib::Role;
-use shared_items_lib::id::UserId;
+use shared_items_lib::RefreshTokenString;
+use shared_items_lib::Timestamp;
+use shared_items_lib::service_responses::AuditEventDetails;
+use shared_items_lib::service_responses::GetMeResponse;
+use shared_items_lib::service_responses::LoginFailureReason;
+use shared_items_lib::service_responses::LoginMethod;
+use shared_items_lib::service_responses::PatchMeResponse;
 use shared_items_lib::service_responses::PostLoginResponse;
 use shared_items_lib::service_responses::PostLoginResponseSuccess;
+use shared_items_lib::service_responses::PostLogoutEverywhereResponse;
+use shared_items_lib::service_responses::PostLogoutResponse;
+use shared_items_lib::service_responses::PostRefreshResponse;
+use shared_items_lib::service_responses::PostRefreshResponseSuccess;
 use shared_items_lib::service_responses::PostRegisterResponse;
 use shared_items_lib::service_responses::PostSaltResponse;
 use shared_items_lib::service_responses::PostSaltResponseSuccess;
 use shared_items_lib::service_responses::PostUploadUserAvatarResponse;
 use shared_items_lib::service_responses::PostUploadUserAvatarSuccess;
+use shared_items_lib::service_responses::ProfileField;
+use shared_items_lib::service_responses::UserProfile;
 
 use crate::Context;
-use crate::db;
+use crate::avatar;
+use crate::db::{self, Db};
+use crate::password;
+use crate::request_origin::RequestOrigin;
+use crate::service;
+use crate::service::login_throttle;
+use crate::service::session;
+use crate::token;
+use crate::username;
 use crate::util;
+use crate::validation;
 
 #[derive(serde::Deserialize, utoipa::ToSchema)]
 pub(crate) struct RegisterRequest {
@@ -40,13 +62,44 @@ impl From<db::user::register::Output> for PostRegisterResponse {
     }
 }
 
-pub(crate) async fn register(ctx: &Context, request: RegisterRequest) -> PostRegisterResponse {
+pub(crate) async fn register(
+    ctx: &Context,
+    request: RegisterRequest,
+    origin: &RequestOrigin,
+) -> PostRegisterResponse {
     let RegisterRequest {
         username,
         password_hash,
     } = request;
-    let output: db::user::register::Output =
-        db::user::register(&ctx.db, &username, &password_hash).await;
+    let username = match username::validate(&username) {
+        Ok(username) => username,
+        Err(reason) => return PostRegisterResponse::InvalidUsername { reason },
+    };
+    let client_salt: String = password::client_salt(&password_hash);
+    let password_hash = match password::hash(&ctx.env.argon2, password_hash).await {
+        Ok(password_hash) => password_hash,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed while hashing the password: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(register),
+                err = e,
+            );
+            return PostRegisterResponse::InternalServerError;
+        }
+    };
+    let output: db::user::register::Output = db::user::register(
+        &ctx.db,
+        &username,
+        &username::normalize(&username),
+        &password_hash,
+        &client_salt,
+    )
+    .await;
+    if let db::user::register::Output::Success { user_id } = output {
+        let details = AuditEventDetails::Registered;
+        service::audit::record(ctx, Some(user_id), Some(user_id), origin, details).await;
+    }
     PostRegisterResponse::from(output)
 }
 
@@ -56,51 +109,238 @@ pub(crate) struct LoginRequest {
     password_hash: String,
 }
 
-fn create_jwt_claims(user_id: UserId, role: Role) -> JwtClaims {
-    let iat = util::now();
-    let exp = util::time_from_now(chrono::Duration::weeks(2));
-    JwtClaims {
-        sub: user_id,
-        role,
-        iat,
-        exp,
+/// Fills in the normalized usernames of the accounts that predate the username policy.
+///
+/// It's done here rather than in the migration, since SQL `LOWER` doesn't lowercase every
+/// username like [`username::normalize`] does.
+pub(crate) async fn normalize_legacy_usernames(db: &Db) -> anyhow::Result<()> {
+    let users = db::user::without_username_normalized(db).await?;
+    let mut colliding = 0;
+    for db::user::without_username_normalized::User { id, username } in users {
+        match db::user::set_username_normalized(db, id, &username::normalize(&username)).await {
+            Ok(true) => (),
+            Ok(false) => colliding += 1,
+            Err(err) => {
+                tracing::warn!("Failed to normalize the username of user with ID {id}: {err}");
+                colliding += 1;
+            }
+        }
+    }
+    if colliding > 0 {
+        tracing::info!(
+            "{colliding} users have no normalized username and log in by their exact usernames"
+        );
+    }
+    Ok(())
+}
+
+// A failed rehash is not fatal: the old hash stays valid and we'll retry on the next login.
+async fn rehash_password(ctx: &Context, user_id: db::id::UserId, secret: String) {
+    let password_hash = match password::hash(&ctx.env.argon2, secret).await {
+        Ok(password_hash) => password_hash,
+        Err(e) => {
+            tracing::warn!(
+                "The function {mod_path}::{fn_name}(...) failed for user with ID {user_id}: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(rehash_password),
+                err = e,
+            );
+            return;
+        }
+    };
+
+    match db::user::set_password_hash(&ctx.db, user_id, &password_hash).await {
+        Ok(()) => tracing::info!(
+            "The function {mod_path}::{fn_name}(...) succeeded: rehashed the password of user with ID {user_id}",
+            mod_path = module_path!(),
+            fn_name = stringify!(rehash_password),
+        ),
+        Err(e) => tracing::warn!(
+            "The function {mod_path}::{fn_name}(...) failed for user with ID {user_id}: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(rehash_password),
+            err = e,
+        ),
+    }
+}
+
+/// Checks whether the username or the IP address is locked out.
+///
+/// Returns `Err` with the number of seconds to wait, or with `None` if the check itself failed.
+pub(crate) async fn check_login_throttle(
+    ctx: &Context,
+    username: &str,
+    ip: IpAddr,
+) -> Result<(), Option<u64>> {
+    match login_throttle::retry_after_secs(ctx, username, ip).await {
+        Ok(None) => Ok(()),
+        Ok(Some(retry_after_secs)) => {
+            tracing::warn!(
+                "Rejected a login attempt for {username} from {ip}, locked out for {retry_after_secs} s",
+            );
+            Err(Some(retry_after_secs))
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(check_login_throttle),
+                err = e,
+            );
+            Err(None)
+        }
+    }
+}
+
+// A failure to count the failure is logged but doesn't change the response.
+pub(crate) async fn record_login_failure(ctx: &Context, username: &str, ip: IpAddr) {
+    if let Err(e) = login_throttle::record_failure(ctx, username, ip).await {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(record_login_failure),
+            err = e,
+        );
     }
 }
 
-pub(crate) async fn login(ctx: &Context, request: LoginRequest) -> PostLoginResponse {
+pub(crate) async fn login(
+    ctx: &Context,
+    request: LoginRequest,
+    origin: &RequestOrigin,
+) -> PostLoginResponse {
     let LoginRequest {
         username,
         password_hash,
     } = request;
+    let ip = origin.ip;
+    let login_failed = |user_id, reason| {
+        let method = LoginMethod::Password;
+        service::audit::login_failed(ctx, user_id, origin, method, reason, Some(&username))
+    };
 
-    let (user_id, role) =
-        match db::user::check_credentials(&ctx.db, &username, &password_hash).await {
-            db::user::check_credentials::Output::UserNotFound
-            | db::user::check_credentials::Output::WrongPassword => {
-                return PostLoginResponse::InvalidCredentials;
-            }
-            db::user::check_credentials::Output::DbError { err } => {
-                tracing::error!(
-                    "The function {mod_path}::{fn_name}(...) failed: {err}",
-                    mod_path = module_path!(),
-                    fn_name = stringify!(login),
-                    err = err,
-                );
-                return PostLoginResponse::InternalServerError;
-            }
-            db::user::check_credentials::Output::Valid { user_id, role } => (user_id, role),
+    match check_login_throttle(ctx, &username, ip).await {
+        Ok(()) => (),
+        Err(Some(retry_after_secs)) => {
+            return PostLoginResponse::TooManyRequests { retry_after_secs };
+        }
+        Err(None) => return PostLoginResponse::InternalServerError,
+    }
+
+    let username_normalized = username::normalize(&username);
+    let credentials =
+        match db::user::get_credentials(&ctx.db, &username, &username_normalized).await {
+            Ok(credentials) => credentials,
+            Err(_) => return PostLoginResponse::InternalServerError,
         };
 
-    let user_id: mnln_core_items::id::UserId = user_id.into();
-    let user_id: UserId = user_id.into();
-    let role: Role = role.into();
+    let Some(db::user::get_credentials::Credentials {
+        id: user_id,
+        role,
+        password_hash: stored_hash,
+        legacy_password_hash,
+        password_reset_required,
+    }) = credentials
+    else {
+        if let Err(e) = password::verify_dummy(&ctx.env.argon2, password_hash).await {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(login),
+                err = e,
+            );
+        }
+        record_login_failure(ctx, &username, ip).await;
+        login_failed(None, LoginFailureReason::UnknownUsername).await;
+        return PostLoginResponse::InvalidCredentials;
+    };
 
-    let claims: JwtClaims = create_jwt_claims(user_id, role);
-    let jwt: JwtString = match util::sign_jwt(&claims, ctx) {
-        Ok(jwt) => jwt,
+    let verification = password::verify(
+        &ctx.env.argon2,
+        password_hash.clone(),
+        stored_hash,
+        legacy_password_hash,
+    )
+    .await;
+
+    let needs_rehash = match verification {
+        Ok(password::Verification::Valid { needs_rehash }) => needs_rehash,
+        Ok(password::Verification::Invalid) => {
+            tracing::warn!(
+                "The function {mod_path}::{fn_name}(...) failed: wrong password for user with ID {user_id} from {ip}",
+                mod_path = module_path!(),
+                fn_name = stringify!(login),
+            );
+            record_login_failure(ctx, &username, ip).await;
+            login_failed(Some(user_id), LoginFailureReason::WrongPassword).await;
+            return PostLoginResponse::InvalidCredentials;
+        }
         Err(e) => {
             tracing::error!(
-                "The function {mod_path}::{fn_name}(...) failed while signing a JWT: {err}",
+                "The function {mod_path}::{fn_name}(...) failed while verifying the password: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(login),
+                err = e,
+            );
+            return PostLoginResponse::InternalServerError;
+        }
+    };
+
+    if let Err(e) = login_throttle::record_success(ctx, &username).await {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed to reset the failed logins: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(login),
+            err = e,
+        );
+    }
+
+    if needs_rehash {
+        rehash_password(ctx, user_id, password_hash).await;
+    }
+
+    match service::admin::login_restriction(ctx, user_id).await {
+        Ok(Some(restriction)) => {
+            login_failed(Some(user_id), LoginFailureReason::Restricted).await;
+            return PostLoginResponse::Restricted(restriction);
+        }
+        Ok(None) => (),
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed while checking the restriction: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(login),
+                err = e,
+            );
+            return PostLoginResponse::InternalServerError;
+        }
+    }
+
+    if password_reset_required {
+        return PostLoginResponse::PasswordResetRequired;
+    }
+
+    match service::totp::challenge(ctx, user_id).await {
+        Ok(Some(challenge)) => return PostLoginResponse::SecondFactorRequired(challenge),
+        Ok(None) => (),
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed while checking the second factor: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(login),
+                err = e,
+            );
+            return PostLoginResponse::InternalServerError;
+        }
+    }
+
+    let session::Tokens { jwt, refresh_token } = match session::start(ctx, user_id, role, false)
+        .await
+    {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed while starting a session: {err}",
                 mod_path = module_path!(),
                 fn_name = stringify!(login),
                 err = e,
@@ -109,64 +349,373 @@ pub(crate) async fn login(ctx: &Context, request: LoginRequest) -> PostLoginResp
         }
     };
 
-    PostLoginResponse::Success(PostLoginResponseSuccess { jwt })
+    service::audit::login_succeeded(ctx, user_id, origin, LoginMethod::Password).await;
+
+    PostLoginResponse::Success(PostLoginResponseSuccess { jwt, refresh_token })
 }
 
 #[derive(serde::Deserialize, utoipa::ToSchema)]
-pub(crate) struct SaltRequest {
-    username: String,
+pub(crate) struct RefreshRequest {
+    refresh_token: RefreshTokenString,
 }
 
-pub(crate) async fn salt(ctx: &Context, request: SaltRequest) -> PostSaltResponse {
-    let SaltRequest { username } = request;
-    let hash = match db::user::get_password_hash(&ctx.db, &username).await {
-        Ok(hash) => hash,
+pub(crate) async fn refresh(ctx: &Context, request: RefreshRequest) -> PostRefreshResponse {
+    let RefreshRequest {
+        refresh_token: RefreshTokenString(refresh_token),
+    } = request;
+    let successor_seed = token::generate();
+
+    let output = db::session::rotate(
+        &ctx.db,
+        &token::hash(&refresh_token),
+        &successor_seed,
+        &token::hash(&token::successor(&refresh_token, &successor_seed)),
+        session::refresh_token_ttl(),
+        session::refresh_token_reuse_grace(),
+    )
+    .await;
+
+    let (user_id, role, second_factor, successor_seed) = match output {
+        db::session::rotate::Output::Rotated {
+            user_id,
+            role,
+            second_factor,
+            successor_seed,
+        } => (user_id, role, second_factor, successor_seed),
+        db::session::rotate::Output::Reused { user_id: _ }
+        | db::session::rotate::Output::Invalid => {
+            return PostRefreshResponse::InvalidToken;
+        }
+        db::session::rotate::Output::DbError { err: _ } => {
+            return PostRefreshResponse::InternalServerError;
+        }
+    };
+
+    let jwt: JwtString = match session::sign_access_token(ctx, user_id, role, second_factor) {
+        Ok(jwt) => jwt,
         Err(e) => {
             tracing::error!(
-                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                "The function {mod_path}::{fn_name}(...) failed while signing a JWT: {err}",
                 mod_path = module_path!(),
-                fn_name = stringify!(salt),
+                fn_name = stringify!(refresh),
                 err = e,
             );
-            return PostSaltResponse::InternalServerError;
+            return PostRefreshResponse::InternalServerError;
         }
     };
 
-    let Some(ref hash) = hash else {
-        return PostSaltResponse::UserNotFound;
-    };
+    PostRefreshResponse::Success(PostRefreshResponseSuccess {
+        jwt,
+        refresh_token: RefreshTokenString(token::successor(&refresh_token, &successor_seed)),
+    })
+}
 
-    tracing::trace!(
-        "The function {mod_path}::{fn_name}(...) progress: retrieved password hash for user {username}. Hash: {hash}",
-        mod_path = module_path!(),
-        fn_name = stringify!(salt),
-        username = username,
-    );
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct LogoutRequest {
+    /// The refresh token of the session to end. Without it, only the access token is revoked.
+    refresh_token: Option<RefreshTokenString>,
+}
 
-    let hash: argon2::PasswordHash = match hash.try_into() {
-        Ok(hash) => hash,
-        Err(e) => {
+pub(crate) async fn logout(
+    ctx: &Context,
+    claims: JwtClaims,
+    request: LogoutRequest,
+) -> PostLogoutResponse {
+    let LogoutRequest { refresh_token } = request;
+
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    let user_id: db::id::UserId = user_id.into();
+
+    if let Some(RefreshTokenString(refresh_token)) = refresh_token {
+        let refresh_token_hash = token::hash(&refresh_token);
+        if let Err(e) =
+            db::revocation::revoke_session_family(&ctx.db, user_id, &refresh_token_hash).await
+        {
             tracing::error!(
                 "The function {mod_path}::{fn_name}(...) failed: {err}",
                 mod_path = module_path!(),
-                fn_name = stringify!(salt),
+                fn_name = stringify!(logout),
                 err = e,
             );
-            return PostSaltResponse::InternalServerError;
+            return PostLogoutResponse::InternalServerError;
         }
-    };
+    }
 
-    let Some(salt) = hash.salt else {
+    if let Err(e) = db::revocation::revoke_access_token(
+        &ctx.db,
+        &claims.jti,
+        user_id,
+        Timestamp::from(claims.exp).0 as i64,
+    )
+    .await
+    {
         tracing::error!(
-            "The function {mod_path}::{fn_name}(...) failed: no salt found in password hash \
-            for user {username}",
+            "The function {mod_path}::{fn_name}(...) failed: {err}",
             mod_path = module_path!(),
-            fn_name = stringify!(salt),
+            fn_name = stringify!(logout),
+            err = e,
         );
-        return PostSaltResponse::InternalServerError;
+        return PostLogoutResponse::InternalServerError;
+    }
+    ctx.revocations.insert_jti(claims.jti);
+
+    PostLogoutResponse::Success
+}
+
+pub(crate) async fn logout_everywhere(
+    ctx: &Context,
+    claims: JwtClaims,
+) -> PostLogoutEverywhereResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+
+    let revoked_at_ms = match db::revocation::revoke_all_sessions(&ctx.db, user_id.into()).await {
+        Ok(revoked_at_ms) => revoked_at_ms,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(logout_everywhere),
+                err = e,
+            );
+            return PostLogoutEverywhereResponse::InternalServerError;
+        }
+    };
+    ctx.revocations
+        .insert_sessions_revoked_at(user_id, revoked_at_ms as u64);
+
+    PostLogoutEverywhereResponse::Success
+}
+
+impl From<db::user::get_profile::Profile> for UserProfile {
+    fn from(value: db::user::get_profile::Profile) -> Self {
+        let db::user::get_profile::Profile {
+            username,
+            email,
+            email_verified,
+            lichess_username,
+            chess_dot_com_username,
+            profile_version,
+            deletion_scheduled_at_ms,
+        } = value;
+        UserProfile {
+            username,
+            email,
+            email_verified,
+            lichess_username,
+            chess_dot_com_username,
+            version: profile_version,
+            deletion_scheduled_at: deletion_scheduled_at_ms.map(|ms| Timestamp(ms as u64)),
+        }
+    }
+}
+
+pub(crate) async fn get_me(ctx: &Context, claims: JwtClaims) -> GetMeResponse {
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+
+    match db::user::get_profile(&ctx.db, user_id.into()).await {
+        Ok(Some(profile)) => GetMeResponse::Success(profile.into()),
+        Ok(None) => GetMeResponse::NotFound,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(get_me),
+                err = e,
+            );
+            GetMeResponse::InternalServerError
+        }
+    }
+}
+
+/// Every field except `version` can be omitted to keep the current value or set to `null` to clear it.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct PatchMeRequest {
+    /// The `version` of the profile that the edit is based on
+    version: i32,
+    #[serde(default, deserialize_with = "util::deserialize_some")]
+    #[schema(value_type = Option<String>)]
+    email: Option<Option<String>>,
+    #[serde(default, deserialize_with = "util::deserialize_some")]
+    #[schema(value_type = Option<String>)]
+    lichess_username: Option<Option<String>>,
+    #[serde(default, deserialize_with = "util::deserialize_some")]
+    #[schema(value_type = Option<String>)]
+    chess_dot_com_username: Option<Option<String>>,
+}
+
+/// Validates the new value of the field, if any, failing with the field and the reason.
+fn validate_change(
+    field: ProfileField,
+    change: Option<Option<String>>,
+    validate: fn(&str) -> Result<String, String>,
+) -> Result<Option<Option<String>>, (ProfileField, String)> {
+    let Some(Some(value)) = change else {
+        return Ok(change);
+    };
+    match validate(&value) {
+        Ok(value) => Ok(Some(Some(value))),
+        Err(reason) => Err((field, reason)),
+    }
+}
+
+fn validate_changes(
+    email: Option<Option<String>>,
+    lichess_username: Option<Option<String>>,
+    chess_dot_com_username: Option<Option<String>>,
+) -> Result<db::user::update_profile::Changes, (ProfileField, String)> {
+    Ok(db::user::update_profile::Changes {
+        email: validate_change(ProfileField::Email, email, validation::email)?,
+        lichess_username: validate_change(
+            ProfileField::LichessUsername,
+            lichess_username,
+            validation::lichess_username,
+        )?,
+        chess_dot_com_username: validate_change(
+            ProfileField::ChessDotComUsername,
+            chess_dot_com_username,
+            validation::chess_dot_com_username,
+        )?,
+    })
+}
+
+impl From<db::user::update_profile::UniqueField> for ProfileField {
+    fn from(value: db::user::update_profile::UniqueField) -> Self {
+        match value {
+            db::user::update_profile::UniqueField::Email => ProfileField::Email,
+            db::user::update_profile::UniqueField::LichessUsername => ProfileField::LichessUsername,
+            db::user::update_profile::UniqueField::ChessDotComUsername => {
+                ProfileField::ChessDotComUsername
+            }
+        }
+    }
+}
+
+impl From<db::user::update_profile::Output> for PatchMeResponse {
+    fn from(value: db::user::update_profile::Output) -> Self {
+        match value {
+            db::user::update_profile::Output::Success(profile) => {
+                PatchMeResponse::Success(profile.into())
+            }
+            db::user::update_profile::Output::VersionMismatch(profile) => {
+                PatchMeResponse::VersionMismatch {
+                    current: profile.into(),
+                }
+            }
+            db::user::update_profile::Output::AlreadyExists { field } => {
+                PatchMeResponse::AlreadyTaken {
+                    field: field.into(),
+                }
+            }
+            db::user::update_profile::Output::NotFound => PatchMeResponse::NotFound,
+            db::user::update_profile::Output::UnknownError { err: _ } => {
+                PatchMeResponse::InternalServerError
+            }
+        }
+    }
+}
+
+pub(crate) async fn patch_me(
+    ctx: &Context,
+    claims: JwtClaims,
+    request: PatchMeRequest,
+) -> PatchMeResponse {
+    let PatchMeRequest {
+        version,
+        email,
+        lichess_username,
+        chess_dot_com_username,
+    } = request;
+
+    let changes = match validate_changes(email, lichess_username, chess_dot_com_username) {
+        Ok(changes) => changes,
+        Err((field, reason)) => return PatchMeResponse::InvalidField { field, reason },
+    };
+
+    let user_id: mnln_core_items::id::UserId = claims.sub.into();
+
+    // The username of a linked Lichess account is verified, so it can't be edited.
+    if let Some(lichess_username) = &changes.lichess_username {
+        match db::lichess::linked_username(&ctx.db, user_id.into()).await {
+            Ok(Some(linked)) if lichess_username.as_ref() != Some(&linked) => {
+                return PatchMeResponse::InvalidField {
+                    field: ProfileField::LichessUsername,
+                    reason: "The Lichess account is linked through Sign in with Lichess"
+                        .to_string(),
+                };
+            }
+            Ok(_) => (),
+            Err(e) => {
+                tracing::error!(
+                    "The function {mod_path}::{fn_name}(...) failed: {err}",
+                    mod_path = module_path!(),
+                    fn_name = stringify!(patch_me),
+                    err = e,
+                );
+                return PatchMeResponse::InternalServerError;
+            }
+        }
+    }
+
+    // It's only started if the email address actually changes.
+    let email_verification = match &changes.email {
+        Some(Some(email)) => match service::email_verification::prepare(ctx, user_id, email) {
+            Ok(verification) => Some(verification),
+            Err(e) => {
+                tracing::error!(
+                    "The function {mod_path}::{fn_name}(...) failed: {err}",
+                    mod_path = module_path!(),
+                    fn_name = stringify!(patch_me),
+                    err = e,
+                );
+                return PatchMeResponse::InternalServerError;
+            }
+        },
+        Some(None) | None => None,
     };
 
-    let salt = salt.as_str().to_string();
+    db::user::update_profile(
+        &ctx.db,
+        user_id.into(),
+        version,
+        changes,
+        email_verification.as_ref(),
+    )
+    .await
+    .into()
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub(crate) struct SaltRequest {
+    username: String,
+}
+
+pub(crate) async fn salt(ctx: &Context, request: SaltRequest, ip: IpAddr) -> PostSaltResponse {
+    let SaltRequest { username } = request;
+
+    match check_login_throttle(ctx, &username, ip).await {
+        Ok(()) => (),
+        Err(Some(retry_after_secs)) => {
+            return PostSaltResponse::TooManyRequests { retry_after_secs };
+        }
+        Err(None) => return PostSaltResponse::InternalServerError,
+    }
+    // The fake salt is derived even for the existing users, so that both paths take equally long.
+    // It's derived from the normalized username too, so that, like the real salt, it doesn't
+    // depend on the case, which would otherwise tell the unknown usernames apart.
+    let username_normalized = username::normalize(&username);
+    let fake_salt = password::fake_client_salt(&ctx.env.fake_salt, &username_normalized);
+    let salt = match db::user::get_client_salt(&ctx.db, &username, &username_normalized).await {
+        Ok(salt) => salt.unwrap_or(fake_salt),
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(salt),
+                err = e,
+            );
+            return PostSaltResponse::InternalServerError;
+        }
+    };
 
     tracing::trace!(
         "The function {mod_path}::{fn_name}(...) succeeded: retrieved salt for user {username}. Salt: {salt}",
@@ -187,8 +736,8 @@ pub struct UploadUserAvatarRequest {
     pub avatar: bytes::Bytes,
 }
 
-async fn avatar_byte_stream_from_multipart<'m>(
-    multipart: &'m mut axum::extract::Multipart,
+async fn avatar_byte_stream_from_multipart(
+    multipart: &mut axum::extract::Multipart,
 ) -> Result<
     (
         impl Stream<Item = Result<bytes::Bytes, std::io::Error>> + Send + Unpin,
@@ -259,36 +808,125 @@ async fn avatar_byte_stream_from_multipart<'m>(
             detail: format!("Unsupported image format for the file: `{file_name}`"),
         });
     };
+    let file_name = file_name.to_string();
+
+    let mut stream = field.map_err(std::io::Error::other);
+
+    // The extension is merely declared by the client, so the content has to agree with it.
+    // The first chunk may be shorter than what's needed to sniff the format, hence the loop.
+    let mut head = bytes::BytesMut::new();
+    while head.len() < BrowserSupportedImgFormat::SNIFF_LEN {
+        match stream.try_next().await {
+            Ok(Some(chunk)) => head.extend_from_slice(&chunk),
+            Ok(None) => break,
+            Err(err) => {
+                tracing::error!(
+                    "The function {mod_path}::{fn_name}(...) failed: {err}",
+                    mod_path = module_path!(),
+                    fn_name = stringify!(avatar_byte_stream_from_multipart),
+                    err = err,
+                );
+                return Err(PostUploadUserAvatarResponse::InternalServerError { detail: None });
+            }
+        }
+    }
+
+    let sniffed_format = BrowserSupportedImgFormat::sniff(&head);
+    if sniffed_format != Some(file_format) {
+        tracing::warn!(
+            "The function {mod_path}::{fn_name}(...) failed: the content of `{file_name}` was sniffed as {sniffed_format:?}",
+            mod_path = module_path!(),
+            fn_name = stringify!(avatar_byte_stream_from_multipart),
+        );
+        return Err(PostUploadUserAvatarResponse::BadRequest {
+            detail: format!(
+                "The content of the file `{file_name}` is not a {} image",
+                file_format.content_type()
+            ),
+        });
+    }
 
-    let stream = field.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e));
+    let head = futures_util::stream::once(std::future::ready(Ok(head.freeze())));
+    let stream = futures_util::StreamExt::chain(head, stream);
 
     Ok((stream, file_format))
 }
 
+/// Reads the whole avatar, unless it's larger than `max_bytes`, in which case it's `None` and
+/// the rest of it isn't even received.
+async fn collect_avatar(
+    mut stream: impl Stream<Item = Result<bytes::Bytes, std::io::Error>> + Unpin,
+    max_bytes: u64,
+) -> std::io::Result<Option<bytes::Bytes>> {
+    use futures_util::stream::TryStreamExt as _;
+
+    let mut avatar = bytes::BytesMut::new();
+    while let Some(chunk) = stream.try_next().await? {
+        if (avatar.len() + chunk.len()) as u64 > max_bytes {
+            return Ok(None);
+        }
+        avatar.extend_from_slice(&chunk);
+    }
+    Ok(Some(avatar.freeze()))
+}
+
+/// Deletes the objects of the avatar that won't be used and stops counting them against the quota.
+/// The failures are only logged, since the upload has failed anyway.
+async fn discard_avatar(
+    ctx: &Context,
+    user_id: db::id::UserId,
+    s3_key: Option<&str>,
+    storage_bytes: i64,
+) {
+    if let Some(s3_key) = s3_key
+        && let Err(e) =
+            object_storage::delete_avatar(&ctx.env, s3_key, &avatar::RENDITION_SIZES).await
+    {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed to delete `{s3_key}`: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(discard_avatar),
+            err = e,
+        );
+    }
+    if let Err(e) = db::user::release_avatar_storage(&ctx.db, user_id, storage_bytes).await {
+        tracing::error!(
+            "The function {mod_path}::{fn_name}(...) failed for user with ID {user_id}: {err}",
+            mod_path = module_path!(),
+            fn_name = stringify!(discard_avatar),
+            err = e,
+        );
+    }
+}
+
 pub(crate) async fn upload_user_avatar(
     ctx: &Context,
-    claims: Option<JwtClaims>,
+    claims: JwtClaims,
+    origin: &RequestOrigin,
     mut multipart: axum::extract::Multipart,
 ) -> PostUploadUserAvatarResponse {
-    let Some(claims) = claims else {
-        tracing::warn!("post_upload_user_avatar: Missing JWT claims");
-        return PostUploadUserAvatarResponse::Unauthorized {
-            detail: "Missing or invalid JWT".to_string(),
-        };
-    };
-
     let user_id: mnln_core_items::id::UserId = claims.sub.into();
+    let limits = &ctx.env.avatar;
 
     let (stream, file_format) = match avatar_byte_stream_from_multipart(&mut multipart).await {
         Ok((stream, file_format)) => (stream, file_format),
         Err(err_resp) => return err_resp,
     };
 
-    let s3_key = match object_storage::save_avatar(&ctx.env, user_id, stream, file_format).await {
-        Ok(s3_key) => s3_key,
+    // The whole avatar is needed to decode it anyway
+    let avatar = match collect_avatar(stream, limits.max_upload_bytes).await {
+        Ok(Some(avatar)) => avatar,
+        Ok(None) => {
+            return PostUploadUserAvatarResponse::PayloadTooLarge {
+                detail: format!(
+                    "The avatar must be at most {} bytes large",
+                    limits.max_upload_bytes
+                ),
+            };
+        }
         Err(e) => {
             tracing::error!(
-                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                "The function {mod_path}::{fn_name}(...) failed while reading the avatar: {err}",
                 mod_path = module_path!(),
                 fn_name = stringify!(upload_user_avatar),
                 err = e,
@@ -297,20 +935,7 @@ pub(crate) async fn upload_user_avatar(
         }
     };
 
-    let user_id: db::id::UserId = user_id.into();
-
-    if let Err(e) = db::user::set_avatar(&ctx.db, user_id, &s3_key).await {
-        tracing::error!(
-            "The function {mod_path}::{fn_name}(...) failed: {err}",
-            mod_path = module_path!(),
-            fn_name = stringify!(upload_user_avatar),
-            err = e,
-        );
-        return PostUploadUserAvatarResponse::InternalServerError {
-            detail: Some("Failed to set the uploaded avatar".to_string()),
-        };
-    };
-
+    // The avatar must be the only field, which is checked before anything is stored
     // TODO: find a way to do this via a drop guard
     match multipart.next_field().await {
         Ok(Some(_)) => {
@@ -330,20 +955,181 @@ pub(crate) async fn upload_user_avatar(
         }
     };
 
-    let user_id: mnln_core_items::id::UserId = user_id.into();
+    let svg_policy = limits.svg_policy;
+    let max_dimension = limits.max_dimension;
+    let processed = tokio::task::spawn_blocking(move || {
+        avatar::process(avatar, file_format, svg_policy, max_dimension)
+    })
+    .await;
+    let avatar::Processed {
+        avatar,
+        format: file_format,
+        renditions,
+    } = match processed {
+        Ok(Ok(processed)) => processed,
+        Ok(Err(e))
+            if matches!(
+                e.downcast_ref::<image::ImageError>(),
+                Some(image::ImageError::Limits(_))
+            ) =>
+        {
+            return PostUploadUserAvatarResponse::PayloadTooLarge {
+                detail: format!(
+                    "The avatar must be at most {max_dimension}x{max_dimension} pixels large"
+                ),
+            };
+        }
+        Ok(Err(e)) => {
+            tracing::warn!(
+                "The function {mod_path}::{fn_name}(...) failed to process the avatar: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(upload_user_avatar),
+                err = e,
+            );
+            return PostUploadUserAvatarResponse::BadRequest {
+                detail: format!("Failed to process the image: {e}"),
+            };
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(upload_user_avatar),
+                err = e,
+            );
+            return PostUploadUserAvatarResponse::InternalServerError { detail: None };
+        }
+    };
+
+    let db_user_id: db::id::UserId = user_id.into();
+    let storage_bytes =
+        (avatar.len() + renditions.iter().map(|r| r.webp.len()).sum::<usize>()) as i64;
+    let quota_bytes = i64::try_from(limits.storage_quota_bytes).unwrap_or(i64::MAX);
+    match db::user::reserve_avatar_storage(&ctx.db, db_user_id, storage_bytes, quota_bytes).await {
+        Ok(true) => (),
+        Ok(false) => {
+            return PostUploadUserAvatarResponse::PayloadTooLarge {
+                detail: format!(
+                    "The avatar would exceed the storage quota of {} bytes",
+                    limits.storage_quota_bytes
+                ),
+            };
+        }
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(upload_user_avatar),
+                err = e,
+            );
+            return PostUploadUserAvatarResponse::InternalServerError { detail: None };
+        }
+    }
+
+    let stream = futures_util::stream::once(std::future::ready(Ok::<_, std::io::Error>(avatar)));
+    let s3_key = match object_storage::save_avatar(&ctx.env, user_id, stream, file_format).await {
+        Ok(s3_key) => s3_key,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(upload_user_avatar),
+                err = e,
+            );
+            discard_avatar(ctx, db_user_id, None, storage_bytes).await;
+            return PostUploadUserAvatarResponse::InternalServerError { detail: None };
+        }
+    };
+
+    let res: anyhow::Result<Option<db::user::get_avatar::Avatar>> = async {
+        for avatar::Rendition { size, webp } in &renditions {
+            object_storage::save_avatar_rendition(&ctx.env, &s3_key, *size, webp).await?;
+        }
+        let has_renditions = avatar::has_renditions(file_format);
+        let previous =
+            db::user::set_avatar(&ctx.db, db_user_id, &s3_key, has_renditions, storage_bytes)
+                .await?;
+        Ok(previous)
+    }
+    .await;
+    let previous = match res {
+        Ok(previous) => previous,
+        Err(e) => {
+            tracing::error!(
+                "The function {mod_path}::{fn_name}(...) failed: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(upload_user_avatar),
+                err = e,
+            );
+            discard_avatar(ctx, db_user_id, Some(&s3_key), storage_bytes).await;
+            return PostUploadUserAvatarResponse::InternalServerError {
+                detail: Some("Failed to set the uploaded avatar".to_string()),
+            };
+        }
+    };
+
+    // The replaced avatar no longer counts against the quota, and whatever is left of it if
+    // this fails is deleted by `service::avatar_gc`
+    if let Some(previous) = previous {
+        let rendition_sizes: &[u32] = if previous.has_renditions {
+            &avatar::RENDITION_SIZES
+        } else {
+            &[]
+        };
+        if let Err(e) =
+            object_storage::delete_avatar(&ctx.env, &previous.s3_key, rendition_sizes).await
+        {
+            tracing::warn!(
+                "The function {mod_path}::{fn_name}(...) failed to delete the replaced avatar `{s3_key}`: {err}",
+                mod_path = module_path!(),
+                fn_name = stringify!(upload_user_avatar),
+                s3_key = previous.s3_key,
+                err = e,
+            );
+        }
+    }
+
+    let details = AuditEventDetails::AvatarChanged;
+    service::audit::record(ctx, Some(db_user_id), Some(db_user_id), origin, details).await;
 
-    let url = links::avatar_url(&ctx.env, user_id);
+    let url = links::avatar_url(&ctx.env, user_id, None);
 
     PostUploadUserAvatarResponse::Success(PostUploadUserAvatarSuccess { url })
 }
 
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct AvatarQueryParams {
+    /// The side length (px) of the square WebP rendition: 64, 128 or 256. The original avatar
+    /// by default, which is also served for the avatars without renditions, e.g. the SVG ones
+    size: Option<u32>,
+}
+
 pub(crate) async fn get_user_avatar(
     ctx: &Context,
     user_id: mnln_core_items::id::UserId,
+    params: AvatarQueryParams,
 ) -> Response {
+    let AvatarQueryParams { size } = params;
+    if let Some(size) = size
+        && !avatar::RENDITION_SIZES.contains(&size)
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported avatar size: {size}"),
+        )
+            .into_response();
+    }
+
     let user_id: db::id::UserId = user_id.into();
     let avatar_s3_key = match db::user::get_avatar(&ctx.db, user_id).await {
-        Ok(Some(avatar_s3_key)) => avatar_s3_key,
+        Ok(Some(db::user::get_avatar::Avatar {
+            s3_key,
+            has_renditions,
+        })) => match size {
+            Some(size) if has_renditions => object_storage::avatar_rendition_key(&s3_key, size),
+            _ => s3_key,
+        },
         Ok(None) => {
             return StatusCode::NOT_FOUND.into_response();
         }
@@ -385,8 +1171,58 @@ pub(crate) async fn get_user_avatar(
 
     (
         StatusCode::OK,
-        [(axum::http::header::CONTENT_TYPE, content_type)],
+        [
+            (axum::http::header::CONTENT_TYPE, content_type),
+            (axum::http::header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+            // In case an SVG avatar is opened directly, which would run its scripts on our origin
+            // if one slipped through the sanitization or predates it
+            (
+                axum::http::header::CONTENT_SECURITY_POLICY,
+                "default-src 'none'; style-src 'unsafe-inline'; sandbox",
+            ),
+        ],
         avatar,
     )
         .into_response()
 }
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt as _;
+
+    use super::*;
+
+    /// The chunks, followed by an error that fails the test if it's ever read.
+    fn stream_of(
+        chunks: &[&'static [u8]],
+    ) -> impl Stream<Item = Result<bytes::Bytes, std::io::Error>> + Unpin {
+        let chunks = chunks
+            .iter()
+            .map(|&chunk| Ok(bytes::Bytes::from_static(chunk)))
+            .chain(std::iter::once(Err(std::io::Error::other(
+                "read past the end",
+            ))));
+        futures_util::stream::iter(chunks)
+    }
+
+    #[tokio::test]
+    async fn avatars_up_to_the_limit_are_collected() {
+        let stream = stream_of(&[b"abc", b"def"]).take(2);
+        let avatar = collect_avatar(stream, 6).await.unwrap();
+        assert_eq!(avatar.as_deref(), Some(&b"abcdef"[..]));
+    }
+
+    #[tokio::test]
+    async fn oversized_avatars_are_abandoned_at_the_first_chunk_over_the_limit() {
+        // The error after the chunks would fail the collection if it read on
+        let avatar = collect_avatar(stream_of(&[b"abc", b"def"]), 5)
+            .await
+            .unwrap();
+        assert_eq!(avatar, None);
+    }
+
+    #[tokio::test]
+    async fn stream_errors_are_passed_on() {
+        assert!(collect_avatar(stream_of(&[b"abc"]), 10).await.is_err());
+    }
+}
@@ -0,0 +1,294 @@
+//! Making the uploaded SVG avatars safe to serve from the API origin,
+//! see [`mnln_env::SvgAvatarPolicy`].
+//!
+//! The sanitizer rewrites the document keeping only the allowlisted elements, which draw and
+//! nothing else, and their attributes that can't run scripts or reference anything outside of
+//! the document. Whatever it can't parse is rejected rather than passed through.
+
+use anyhow::Context as _;
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use resvg::{tiny_skia, usvg};
+
+const SVG_NAMESPACE: &str = "http://www.w3.org/2000/svg";
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+/// The longer side (px) of the rasterized avatars
+const RASTER_SIZE: f32 = 512.0;
+
+/// Notably without `script`, `style`, `foreignObject`, `image`, `feImage`, `a` and the
+/// animation elements, which can change the attributes after the sanitization.
+const ALLOWED_ELEMENTS: &[&str] = &[
+    "svg",
+    "g",
+    "defs",
+    "symbol",
+    "use",
+    "title",
+    "desc",
+    "path",
+    "rect",
+    "circle",
+    "ellipse",
+    "line",
+    "polyline",
+    "polygon",
+    "text",
+    "tspan",
+    "textPath",
+    "linearGradient",
+    "radialGradient",
+    "stop",
+    "pattern",
+    "clipPath",
+    "mask",
+    "marker",
+    "filter",
+    "feBlend",
+    "feColorMatrix",
+    "feComponentTransfer",
+    "feComposite",
+    "feConvolveMatrix",
+    "feDiffuseLighting",
+    "feDisplacementMap",
+    "feDistantLight",
+    "feDropShadow",
+    "feFlood",
+    "feFuncA",
+    "feFuncB",
+    "feFuncG",
+    "feFuncR",
+    "feGaussianBlur",
+    "feMerge",
+    "feMergeNode",
+    "feMorphology",
+    "feOffset",
+    "fePointLight",
+    "feSpecularLighting",
+    "feSpotLight",
+    "feTile",
+    "feTurbulence",
+];
+
+/// Whether every `url(...)` in the value points into the document, e.g. `fill="url(#gradient)"`.
+fn references_are_local(value: &str) -> bool {
+    let value = value.to_ascii_lowercase();
+    value.match_indices("url(").all(|(i, _)| {
+        value[i + "url(".len()..]
+            .trim_start()
+            .trim_start_matches(['"', '\''])
+            .starts_with('#')
+    })
+}
+
+fn is_allowed_attribute(key: &str, value: &str) -> bool {
+    if key.to_ascii_lowercase().starts_with("on") {
+        // Event handlers
+        return false;
+    }
+    match key {
+        "xmlns" => value == SVG_NAMESPACE,
+        "xmlns:xlink" => value == XLINK_NAMESPACE,
+        "href" | "xlink:href" => value.trim_start().starts_with('#'),
+        "xml:space" | "xml:lang" => true,
+        // CSS escapes could spell `url(` in a way that `references_are_local` doesn't see
+        "style" => !value.contains('\\') && references_are_local(value),
+        _ => !key.contains(':') && references_are_local(value),
+    }
+}
+
+fn sanitized_element(
+    element: &BytesStart,
+    is_root: bool,
+) -> anyhow::Result<Option<BytesStart<'static>>> {
+    let name = std::str::from_utf8(element.name().into_inner())?;
+    if is_root {
+        anyhow::ensure!(
+            name == "svg",
+            "The root element is `{name}` rather than `svg`"
+        );
+    }
+    if !ALLOWED_ELEMENTS.contains(&name) {
+        return Ok(None);
+    }
+
+    let mut sanitized = BytesStart::new(name.to_string());
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let key = std::str::from_utf8(attribute.key.into_inner())?;
+        let value = attribute.unescape_value()?;
+        if is_allowed_attribute(key, &value) {
+            sanitized.push_attribute((key, value.as_ref()));
+        }
+    }
+    Ok(Some(sanitized))
+}
+
+/// Rewrites the SVG document without anything that could run scripts or load external resources.
+///
+/// The disallowed elements are dropped along with their contents, and the disallowed attributes
+/// on their own. The declarations, doctypes, processing instructions and comments are dropped
+/// too, so entities other than the predefined ones make the document invalid.
+pub(crate) fn sanitize(svg: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let svg = std::str::from_utf8(svg).context("The SVG isn't valid UTF-8")?;
+    let mut reader = Reader::from_str(svg);
+    let mut writer = Writer::new(Vec::with_capacity(svg.len()));
+
+    // The number of the open elements that are kept, and that are dropped with their contents
+    let mut depth = 0usize;
+    let mut dropped_depth = 0usize;
+    let mut seen_root = false;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(_) if dropped_depth > 0 => dropped_depth += 1,
+            Event::Start(element) => {
+                anyhow::ensure!(depth > 0 || !seen_root, "The SVG has several root elements");
+                match sanitized_element(&element, !seen_root)? {
+                    Some(sanitized) => {
+                        writer.write_event(Event::Start(sanitized))?;
+                        depth += 1;
+                    }
+                    None => dropped_depth = 1,
+                }
+                seen_root = true;
+            }
+            Event::Empty(_) if dropped_depth > 0 => (),
+            Event::Empty(element) => {
+                anyhow::ensure!(depth > 0 || !seen_root, "The SVG has several root elements");
+                if let Some(sanitized) = sanitized_element(&element, !seen_root)? {
+                    writer.write_event(Event::Empty(sanitized))?;
+                }
+                seen_root = true;
+            }
+            Event::End(_) if dropped_depth > 0 => dropped_depth -= 1,
+            Event::End(element) => {
+                writer.write_event(Event::End(element))?;
+                depth -= 1;
+            }
+            Event::Text(text) if dropped_depth == 0 => {
+                let text = text.unescape()?;
+                writer.write_event(Event::Text(BytesText::new(&text)))?;
+            }
+            Event::CData(text) if dropped_depth == 0 => {
+                let text = text.decode()?;
+                writer.write_event(Event::Text(BytesText::new(&text)))?;
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+    }
+    anyhow::ensure!(seen_root, "The SVG has no root element");
+    anyhow::ensure!(depth == 0 && dropped_depth == 0, "The SVG is truncated");
+
+    Ok(writer.into_inner())
+}
+
+/// Renders the SVG document into a PNG image whose longer side is 512 px.
+///
+/// Neither the local files nor the network are accessed, so the external images are left blank.
+pub(crate) fn rasterize(svg: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let options = usvg::Options {
+        image_href_resolver: usvg::ImageHrefResolver {
+            resolve_data: usvg::ImageHrefResolver::default_data_resolver(),
+            resolve_string: Box::new(|_href, _options| None),
+        },
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_data(svg, &options)?;
+
+    let size = tree.size();
+    let scale = RASTER_SIZE / size.width().max(size.height());
+    let width = (size.width() * scale).round().max(1.0) as u32;
+    let height = (size.height() * scale).round().max(1.0) as u32;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .with_context(|| format!("Couldn't allocate a {width}x{height} pixmap"))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    Ok(pixmap.encode_png()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use browser_supported_img_format::BrowserSupportedImgFormat;
+
+    use super::*;
+
+    const MALICIOUS: &[&str] = &[
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><script>alert(1)</script></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><script><![CDATA[alert(1)]]></script></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" onload="alert(1)"/>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><rect OnClick="alert(1)"/></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink"><a xlink:href="javascript:alert(1)"><rect/></a></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><use href="data:image/svg+xml;base64,PHN2Zz48c2NyaXB0PmFsZXJ0KDEpPC9zY3JpcHQ+PC9zdmc+#x"/></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><use href=" javascript:alert(1)"/></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><image href="https://evil.example/track.png"/></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><foreignObject><iframe xmlns="http://www.w3.org/1999/xhtml" src="javascript:alert(1)"/></foreignObject></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><set attributeName="onmouseover" to="alert(1)"/></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><style>@import url(https://evil.example/x.css);</style></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><rect style="fill: url( 'https://evil.example/x#y')"/></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><rect style="fill: u\72l(https://evil.example/x)"/></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><rect filter="URL(https://evil.example/f.svg#f)"/></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:h="http://www.w3.org/1999/xhtml"><h:script>alert(1)</h:script></svg>"#,
+        r#"<svg xmlns="http://www.w3.org/2000/svg"><SCRIPT>alert(1)</SCRIPT></svg>"#,
+    ];
+
+    #[test]
+    fn strips_scripts_handlers_and_external_references() {
+        for svg in MALICIOUS {
+            let sanitized = sanitize(svg.as_bytes()).unwrap();
+            let sanitized = String::from_utf8(sanitized).unwrap().to_ascii_lowercase();
+            for needle in ["script", "alert", "javascript", "evil.example", "data:"] {
+                assert!(!sanitized.contains(needle), "{svg} -> {sanitized}");
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_what_it_cant_sanitize() {
+        let entity = r#"<!DOCTYPE svg [<!ENTITY xxe SYSTEM "file:///etc/passwd">]>
+            <svg xmlns="http://www.w3.org/2000/svg"><text>&xxe;</text></svg>"#;
+        let not_svg = r#"<html><script>alert(1)</script></html>"#;
+        let several_roots = r#"<svg/><script>alert(1)</script>"#;
+        for svg in [entity, not_svg, several_roots, "", "<svg>"] {
+            assert!(sanitize(svg.as_bytes()).is_err(), "{svg}");
+        }
+    }
+
+    #[test]
+    fn keeps_the_drawing() {
+        let svg = r##"<?xml version="1.0"?>
+            <svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+                <defs><linearGradient id="g"><stop offset="0" stop-color="#fff"/></linearGradient></defs>
+                <path d="M0 0L10 10" fill="url(#g)" style="stroke: red"/>
+                <use href="#g"/>
+                <text>1 &lt; 2</text>
+            </svg>"##;
+        let sanitized = String::from_utf8(sanitize(svg.as_bytes()).unwrap()).unwrap();
+        for needle in [
+            r#"viewBox="0 0 10 10""#,
+            r#"fill="url(#g)""#,
+            r#"style="stroke: red""#,
+            r##"<use href="#g"/>"##,
+            "1 &lt; 2",
+        ] {
+            assert!(sanitized.contains(needle), "{sanitized}");
+        }
+    }
+
+    #[test]
+    fn rasterizes_into_png() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10"><script>alert(1)</script><image href="/etc/passwd" width="20" height="10"/><rect width="20" height="10" fill="red"/></svg>"#;
+        let png = rasterize(svg.as_bytes()).unwrap();
+        assert_eq!(
+            BrowserSupportedImgFormat::sniff(&png),
+            Some(BrowserSupportedImgFormat::Png)
+        );
+        let png = image::load_from_memory(&png).unwrap();
+        assert_eq!((png.width(), png.height()), (512, 256));
+    }
+}
@@ -0,0 +1,49 @@
+//! Opaque bearer tokens (e.g. refresh tokens) that are handed out once and stored hashed.
+
+use base64::Engine as _;
+use hmac::Mac as _;
+use rand::RngCore as _;
+use sha2::Digest as _;
+
+const TOKEN_LEN: usize = 32;
+
+/// Generates a random URL-safe token with 256 bits of entropy.
+pub(crate) fn generate() -> String {
+    let mut bytes = [0u8; TOKEN_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hex-encoded SHA-256 of the token.
+///
+/// A fast hash is fine here because, unlike passwords, the tokens have full entropy.
+pub(crate) fn hash(token: &str) -> String {
+    format!("{:x}", sha2::Sha256::digest(token.as_bytes()))
+}
+
+/// The token that succeeds `token` on rotation, derived from it and a random `seed`.
+///
+/// Only the holder of `token` can derive the successor from the stored `seed`, so a refresh token
+/// presented twice in quick succession can get the same successor without it being stored.
+pub(crate) fn successor(token: &str, seed: &str) -> String {
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(token.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(seed.as_bytes());
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_successor_depends_on_both_the_token_and_the_seed() {
+        let (token, seed) = (generate(), generate());
+        let successor = successor(&token, &seed);
+
+        assert_eq!(successor, super::successor(&token, &seed));
+        assert_ne!(successor, super::successor(&generate(), &seed));
+        assert_ne!(successor, super::successor(&token, &generate()));
+        assert_eq!(successor.len(), generate().len());
+    }
+}
@@ -0,0 +1,127 @@
+//! Time-based one-time passwords for two-factor authentication, as shown by authenticator apps.
+//!
+//! See <https://www.rfc-editor.org/rfc/rfc6238> and
+//! <https://github.com/google/google-authenticator/wiki/Key-Uri-Format>.
+
+use rand::Rng as _;
+use rand::RngCore as _;
+use subtle::ConstantTimeEq as _;
+use totp_rs::{Algorithm, TOTP};
+
+use mnln_env::TotpEnv;
+
+/// The length of the secret, as recommended by <https://www.rfc-editor.org/rfc/rfc4226#section-4>
+const SECRET_LEN: usize = 20;
+const DIGITS: usize = 6;
+const STEP_SECS: u64 = 30;
+/// How many steps a code may be off, to tolerate clock drift and slow typing
+const SKEW: u8 = 1;
+
+pub(crate) const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghijkmnpqrstuvwxyz23456789";
+const RECOVERY_CODE_GROUP_LEN: usize = 5;
+
+fn totp(secret: Vec<u8>, issuer: Option<String>, account_name: String) -> TOTP {
+    // The checked constructor would reject none of our secrets but also the usernames with `:`
+    TOTP::new_unchecked(
+        Algorithm::SHA1,
+        DIGITS,
+        SKEW,
+        STEP_SECS,
+        secret,
+        issuer,
+        account_name,
+    )
+}
+
+pub(crate) fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; SECRET_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+/// The `otpauth://` URI that the authenticator app scans as a QR code.
+pub(crate) fn provisioning_uri(env: &TotpEnv, username: &str, secret: Vec<u8>) -> String {
+    totp(secret, Some(env.issuer.clone()), username.to_string()).get_url()
+}
+
+/// The secret as typed into the authenticator app when the QR code can't be scanned.
+pub(crate) fn secret_base32(secret: Vec<u8>) -> String {
+    totp(secret, None, String::new()).get_secret_base32()
+}
+
+/// Whether the code looks like a TOTP code rather than a recovery code.
+pub(crate) fn is_code(code: &str) -> bool {
+    code.len() == DIGITS && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// The time step at which the code is valid, if it's valid at `unix_secs` give or take [`SKEW`].
+///
+/// The caller must remember the step, so that the code can't be used twice.
+pub(crate) fn matching_step(secret: &[u8], code: &str, unix_secs: u64) -> Option<u64> {
+    let totp = totp(secret.to_vec(), None, String::new());
+    let current = unix_secs / STEP_SECS;
+    (current.saturating_sub(SKEW.into())..=current + u64::from(SKEW)).find(|step| {
+        let expected = totp.generate(step * STEP_SECS);
+        bool::from(expected.as_bytes().ct_eq(code.as_bytes()))
+    })
+}
+
+/// Generates a recovery code like `x7kqm-2hd9p`, with 50 bits of entropy.
+///
+/// The alphabet leaves out the characters that are easy to mistake for one another.
+pub(crate) fn generate_recovery_code() -> String {
+    let mut rng = rand::rngs::OsRng;
+    let mut code = String::with_capacity(2 * RECOVERY_CODE_GROUP_LEN + 1);
+    for i in 0..2 * RECOVERY_CODE_GROUP_LEN {
+        if i == RECOVERY_CODE_GROUP_LEN {
+            code.push('-');
+        }
+        let index = rng.gen_range(0..RECOVERY_CODE_ALPHABET.len());
+        code.push(RECOVERY_CODE_ALPHABET[index].into());
+    }
+    code
+}
+
+/// The recovery code as it's hashed, so that the case and the separators don't matter.
+pub(crate) fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // See <https://www.rfc-editor.org/rfc/rfc6238#appendix-B>, truncated to 6 digits
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn matching_step_accepts_rfc_6238_codes_within_the_skew() {
+        assert_eq!(matching_step(RFC_SECRET, "287082", 59), Some(1));
+        assert_eq!(
+            matching_step(RFC_SECRET, "081804", 1111111109),
+            Some(37037036)
+        );
+        assert_eq!(
+            matching_step(RFC_SECRET, "081804", 1111111109 + 30),
+            Some(37037036)
+        );
+        assert_eq!(matching_step(RFC_SECRET, "081804", 1111111109 + 60), None);
+        assert_eq!(matching_step(RFC_SECRET, "000000", 59), None);
+    }
+
+    #[test]
+    fn recovery_codes_are_normalized() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 11);
+        assert!(!is_code(&code));
+        assert_eq!(
+            normalize_recovery_code(&code.to_uppercase()),
+            code.replace('-', "")
+        );
+        assert_eq!(normalize_recovery_code(" X7kqm 2hd9P "), "x7kqm2hd9p");
+    }
+}
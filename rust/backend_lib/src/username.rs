@@ -0,0 +1,91 @@
+//! The policy for the usernames of the accounts.
+//!
+//! Usernames are restricted to latin letters, digits, `_` and `-`, so that they can't contain
+//! Unicode confusables, and are unique regardless of case: `users.username_normalized` holds
+//! the [`normalize`]d username under a unique index.
+
+/// Shorter than `users.username VARCHAR(50)`, and long enough for any Lichess username
+const LEN: std::ops::RangeInclusive<usize> = 2..=30;
+
+/// The names that could be mistaken for the site itself or clash with its routes
+const RESERVED: &[&str] = &[
+    "admin",
+    "administrator",
+    "anonymous",
+    "api",
+    "mainline",
+    "main-line",
+    "me",
+    "mod",
+    "moderator",
+    "null",
+    "root",
+    "staff",
+    "support",
+    "system",
+    "undefined",
+];
+
+/// The form of the username that is unique across the accounts.
+pub(crate) fn normalize(username: &str) -> String {
+    username.to_lowercase()
+}
+
+/// Checks the username against the policy and returns it trimmed.
+pub(crate) fn validate(username: &str) -> Result<String, String> {
+    let username = username.trim();
+    if !LEN.contains(&username.len()) {
+        return Err(format!(
+            "must be {} to {} characters long",
+            LEN.start(),
+            LEN.end()
+        ));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("may only contain latin letters, digits, `_` and `-`".to_string());
+    }
+    if !username.starts_with(|c: char| c.is_ascii_alphanumeric())
+        || !username.ends_with(|c: char| c.is_ascii_alphanumeric())
+    {
+        return Err("must start and end with a letter or a digit".to_string());
+    }
+    if RESERVED.contains(&normalize(username).as_str()) {
+        return Err("is reserved".to_string());
+    }
+    Ok(username.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_usernames_within_the_policy() {
+        assert_eq!(
+            validate(" Magnus_Carlsen ").as_deref(),
+            Ok("Magnus_Carlsen")
+        );
+        assert_eq!(validate("dr-nykterstein").as_deref(), Ok("dr-nykterstein"));
+        assert_eq!(validate("DK").as_deref(), Ok("DK"));
+    }
+
+    #[test]
+    fn rejects_usernames_outside_the_policy() {
+        assert!(validate("a").is_err());
+        assert!(validate(&"a".repeat(31)).is_err());
+        // Cyrillic `а`
+        assert!(validate("m\u{430}gnus").is_err());
+        assert!(validate("magnus carlsen").is_err());
+        assert!(validate("_magnus").is_err());
+        assert!(validate("magnus-").is_err());
+        assert!(validate("Admin").is_err());
+    }
+
+    #[test]
+    fn normalization_ignores_case() {
+        assert_eq!(normalize("Magnus"), normalize("mAGNUS"));
+    }
+}
@@ -1,9 +1,4 @@
-use hmac::Mac as _;
-use jwt::SignWithKey as _;
-
-use hmac::Hmac;
-use sha2::Sha256;
-
+use mnln_env::JwtEnv;
 use shared_items_lib::{JwtClaims, JwtString, Timestamp};
 
 use crate::Context;
@@ -14,23 +9,76 @@ pub(crate) fn now() -> Timestamp {
     Timestamp(timestamp as u64)
 }
 
+/// Use with `#[serde(default, deserialize_with = "...")]` on an `Option<Option<T>>` field
+/// to tell an absent field (`None`) from an explicit `null` (`Some(None)`).
+pub(crate) fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: serde::Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
 pub(crate) fn time_from_now(duration: chrono::Duration) -> Timestamp {
     let later = chrono::Utc::now() + duration;
     let timestamp = later.timestamp_millis();
     Timestamp(timestamp as u64)
 }
 
-// See <https://docs.rs/jwt/latest/jwt/#signing>
 pub(crate) fn sign_jwt(claims: &JwtClaims, ctx: &Context) -> anyhow::Result<JwtString> {
-    let key: Hmac<Sha256> = Hmac::new_from_slice(ctx.env.jwt_signing_key.as_bytes())?;
-    let token_str = claims.sign_with_key(&key)?;
+    let token_str = ctx.key_ring.sign(claims)?;
     Ok(JwtString(token_str))
 }
 
-pub(crate) fn verify_jwt(token: &str, ctx: &Context) -> anyhow::Result<JwtClaims> {
-    use jwt::VerifyWithKey as _;
+#[derive(Debug, derive_more::Display)]
+pub(crate) enum JwtVerificationError {
+    #[display("malformed token: {_0}")]
+    Malformed(jsonwebtoken::errors::Error),
+    #[display("unknown signing key: `{_0}`")]
+    UnknownKey(String),
+    #[display("invalid signature")]
+    BadSignature,
+    #[display("the token has expired")]
+    Expired,
+    #[display("the token is not valid yet")]
+    NotYetValid,
+    #[display("wrong issuer")]
+    WrongIssuer,
+    #[display("wrong audience")]
+    WrongAudience,
+}
+
+impl From<jsonwebtoken::errors::Error> for JwtVerificationError {
+    fn from(value: jsonwebtoken::errors::Error) -> Self {
+        use jsonwebtoken::errors::ErrorKind;
+
+        match value.kind() {
+            ErrorKind::InvalidSignature | ErrorKind::InvalidAlgorithm => Self::BadSignature,
+            ErrorKind::ExpiredSignature => Self::Expired,
+            ErrorKind::ImmatureSignature => Self::NotYetValid,
+            ErrorKind::InvalidIssuer => Self::WrongIssuer,
+            ErrorKind::InvalidAudience => Self::WrongAudience,
+            _ => Self::Malformed(value),
+        }
+    }
+}
+
+/// Requires the tokens to be issued by us for `audience` and not to be expired,
+/// allowing for the clock skew configured in [`mnln_env::JwtEnv`].
+pub(crate) fn jwt_validation(jwt_env: &JwtEnv, audience: &str) -> jsonwebtoken::Validation {
+    // The algorithm is replaced by the one of the key, see [`crate::key_ring::KeyRing::verify`]
+    let mut validation = jsonwebtoken::Validation::default();
+    validation.leeway = jwt_env.leeway_secs;
+    validation.validate_nbf = true;
+    validation.set_issuer(&[&jwt_env.issuer]);
+    validation.set_audience(&[audience]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+    validation
+}
 
-    let key: Hmac<Sha256> = Hmac::new_from_slice(ctx.env.jwt_signing_key.as_bytes())?;
-    let claims: JwtClaims = token.verify_with_key(&key)?;
-    Ok(claims)
+pub(crate) fn verify_jwt(token: &str, ctx: &Context) -> Result<JwtClaims, JwtVerificationError> {
+    let jwt_env = &ctx.env.jwt;
+    let mut validation = jwt_validation(jwt_env, &jwt_env.audience);
+    validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+    ctx.key_ring.verify(token, validation)
 }
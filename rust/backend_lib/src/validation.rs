@@ -0,0 +1,96 @@
+//! Validation of the user-provided profile fields.
+//!
+//! The length limits match the sizes of the corresponding `VARCHAR` columns of the `users` table.
+
+/// `users.email VARCHAR(100)`
+const EMAIL_MAX_LEN: usize = 100;
+/// See <https://www.rfc-editor.org/rfc/rfc5321#section-4.5.3.1.1>
+const EMAIL_LOCAL_PART_MAX_LEN: usize = 64;
+/// See <https://www.rfc-editor.org/rfc/rfc1035#section-2.3.4>
+const DOMAIN_LABEL_MAX_LEN: usize = 63;
+
+/// `users.lichess_username VARCHAR(50)`
+const LICHESS_USERNAME_LEN: std::ops::RangeInclusive<usize> = 2..=50;
+/// `users.chess_dot_com_username VARCHAR(50)`
+const CHESS_DOT_COM_USERNAME_LEN: std::ops::RangeInclusive<usize> = 3..=50;
+
+fn is_email_local_part_char(c: char) -> bool {
+    c.is_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(c)
+}
+
+fn validate_domain_label(label: &str) -> Result<(), String> {
+    if label.is_empty() || label.chars().count() > DOMAIN_LABEL_MAX_LEN {
+        return Err(format!(
+            "every part of the domain must be 1 to {DOMAIN_LABEL_MAX_LEN} characters long"
+        ));
+    }
+    if !label.chars().all(|c| c.is_alphanumeric() || c == '-') {
+        return Err("the domain may only contain letters, digits, hyphens and dots".to_string());
+    }
+    if label.starts_with('-') || label.ends_with('-') {
+        return Err("the parts of the domain must not start or end with a hyphen".to_string());
+    }
+    Ok(())
+}
+
+/// Checks the syntax of the email address (without quoted local parts and IP literals)
+/// and returns it with the domain lowercased.
+pub(crate) fn email(email: &str) -> Result<String, String> {
+    let email = email.trim();
+    if email.chars().count() > EMAIL_MAX_LEN {
+        return Err(format!("must be at most {EMAIL_MAX_LEN} characters long"));
+    }
+    let Some((local_part, domain)) = email.rsplit_once('@') else {
+        return Err("must contain `@`".to_string());
+    };
+
+    if local_part.is_empty() || local_part.chars().count() > EMAIL_LOCAL_PART_MAX_LEN {
+        return Err(format!(
+            "the part before `@` must be 1 to {EMAIL_LOCAL_PART_MAX_LEN} characters long"
+        ));
+    }
+    if !local_part.chars().all(is_email_local_part_char) {
+        return Err("the part before `@` contains a forbidden character".to_string());
+    }
+    if local_part.starts_with('.') || local_part.ends_with('.') || local_part.contains("..") {
+        return Err(
+            "the part before `@` must not start or end with a dot or contain `..`".to_string(),
+        );
+    }
+
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.len() < 2 {
+        return Err("the domain must contain a dot".to_string());
+    }
+    labels.into_iter().try_for_each(validate_domain_label)?;
+
+    Ok(format!("{local_part}@{}", domain.to_lowercase()))
+}
+
+fn chess_username(username: &str, len: std::ops::RangeInclusive<usize>) -> Result<String, String> {
+    let username = username.trim();
+    if !len.contains(&username.len()) {
+        return Err(format!(
+            "must be {} to {} characters long",
+            len.start(),
+            len.end()
+        ));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err("may only contain latin letters, digits, `_` and `-`".to_string());
+    }
+    Ok(username.to_string())
+}
+
+/// See <https://lichess.org/signup>
+pub(crate) fn lichess_username(username: &str) -> Result<String, String> {
+    chess_username(username, LICHESS_USERNAME_LEN)
+}
+
+/// See <https://www.chess.com/register>
+pub(crate) fn chess_dot_com_username(username: &str) -> Result<String, String> {
+    chess_username(username, CHESS_DOT_COM_USERNAME_LEN)
+}
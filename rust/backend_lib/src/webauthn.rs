@@ -0,0 +1,566 @@
+//! Verification of the WebAuthn ceremonies that register passkeys and log in with them.
+//!
+//! Only what passkeys need is supported: ES256, EdDSA and RS256 credentials, and the `none` and
+//! self-attested `packed` attestation statements, since we don't ask for attestation anyway.
+//! User verification is always required, so a passkey is both factors at once.
+//!
+//! See <https://www.w3.org/TR/webauthn-3/#sctn-rp-operations>.
+
+use std::fmt;
+
+use base64::Engine as _;
+use ciborium::Value;
+use sha2::Digest as _;
+
+use mnln_env::WebAuthnEnv;
+
+/// See <https://www.iana.org/assignments/cose/cose.xhtml#algorithms>
+pub(crate) const ES256: i64 = -7;
+pub(crate) const EDDSA: i64 = -8;
+pub(crate) const RS256: i64 = -257;
+
+/// The supported algorithms, in the order of preference
+pub(crate) const ALGORITHMS: [i64; 3] = [ES256, EDDSA, RS256];
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+const MAX_CREDENTIAL_ID_LEN: usize = 1023;
+
+/// Why the credential was rejected.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Rejection(pub(crate) &'static str);
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+pub(crate) fn base64url_encode(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub(crate) fn base64url_decode(value: &str) -> Result<Vec<u8>, Rejection> {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| Rejection("Malformed base64url"))
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Ceremony {
+    Registration,
+    Authentication,
+}
+
+impl Ceremony {
+    fn client_data_type(self) -> &'static str {
+        match self {
+            Ceremony::Registration => "webauthn.create",
+            Ceremony::Authentication => "webauthn.get",
+        }
+    }
+}
+
+/// See <https://www.w3.org/TR/webauthn-3/#dictionary-client-data>
+#[derive(serde::Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    type_: String,
+    challenge: String,
+    origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    cross_origin: bool,
+}
+
+/// Checks the client data of the ceremony and returns its challenge, which the caller must
+/// consume to make sure it was issued by us and is used once.
+pub(crate) fn client_data_challenge(
+    env: &WebAuthnEnv,
+    client_data_json: &[u8],
+    ceremony: Ceremony,
+) -> Result<String, Rejection> {
+    let client_data: ClientData =
+        serde_json::from_slice(client_data_json).map_err(|_| Rejection("Malformed client data"))?;
+    if client_data.type_ != ceremony.client_data_type() {
+        return Err(Rejection("Wrong ceremony type"));
+    }
+    if !env.origins.contains(&client_data.origin) || client_data.cross_origin {
+        return Err(Rejection("Unexpected origin"));
+    }
+    Ok(client_data.challenge)
+}
+
+/// See <https://www.w3.org/TR/webauthn-3/#sctn-authenticator-data>
+struct AuthenticatorData {
+    sign_count: u32,
+    /// The credential ID and the COSE public key, present when registering
+    attested_credential: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+fn split(data: &[u8], len: usize) -> Result<(&[u8], &[u8]), Rejection> {
+    if data.len() < len {
+        return Err(Rejection("Truncated authenticator data"));
+    }
+    Ok(data.split_at(len))
+}
+
+fn parse_authenticator_data(
+    env: &WebAuthnEnv,
+    data: &[u8],
+) -> Result<AuthenticatorData, Rejection> {
+    let (rp_id_hash, rest) = split(data, 32)?;
+    let (flags, rest) = split(rest, 1)?;
+    let (sign_count, rest) = split(rest, 4)?;
+
+    if rp_id_hash != &sha2::Sha256::digest(env.rp_id.as_bytes())[..] {
+        return Err(Rejection(
+            "The credential is bound to another relying party",
+        ));
+    }
+    let flags = flags[0];
+    if flags & FLAG_USER_PRESENT == 0 {
+        return Err(Rejection("The user wasn't present"));
+    }
+    if flags & FLAG_USER_VERIFIED == 0 {
+        return Err(Rejection("The user wasn't verified"));
+    }
+    let sign_count = u32::from_be_bytes(sign_count.try_into().expect("4 bytes"));
+
+    let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+        let (_aaguid, rest) = split(rest, 16)?;
+        let (credential_id_len, rest) = split(rest, 2)?;
+        let credential_id_len =
+            u16::from_be_bytes(credential_id_len.try_into().expect("2 bytes")).into();
+        if credential_id_len > MAX_CREDENTIAL_ID_LEN {
+            return Err(Rejection("The credential ID is too long"));
+        }
+        let (credential_id, rest) = split(rest, credential_id_len)?;
+        // The public key is followed by the extensions, if any, so it's measured by decoding it.
+        let mut remaining = rest;
+        let _: Value = ciborium::from_reader(&mut remaining)
+            .map_err(|_| Rejection("Malformed credential public key"))?;
+        let public_key = &rest[..rest.len() - remaining.len()];
+        Some((credential_id.to_vec(), public_key.to_vec()))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        sign_count,
+        attested_credential,
+    })
+}
+
+fn map_get<'a>(map: &'a [(Value, Value)], key: &str) -> Option<&'a Value> {
+    map.iter()
+        .find(|(k, _)| k.as_text() == Some(key))
+        .map(|(_, v)| v)
+}
+
+fn cose_get(map: &[(Value, Value)], label: i64) -> Option<&Value> {
+    map.iter()
+        .find(|(k, _)| k.as_integer().map(i128::from) == Some(label.into()))
+        .map(|(_, v)| v)
+}
+
+fn cose_int(map: &[(Value, Value)], label: i64) -> Result<i128, Rejection> {
+    cose_get(map, label)
+        .and_then(Value::as_integer)
+        .map(i128::from)
+        .ok_or(Rejection("Malformed credential public key"))
+}
+
+fn cose_bytes(map: &[(Value, Value)], label: i64) -> Result<&[u8], Rejection> {
+    cose_get(map, label)
+        .and_then(Value::as_bytes)
+        .map(Vec::as_slice)
+        .ok_or(Rejection("Malformed credential public key"))
+}
+
+/// A credential public key, see <https://www.w3.org/TR/webauthn-3/#sctn-encoded-credPubKey-examples>
+pub(crate) enum PublicKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    EdDsa(ed25519_dalek::VerifyingKey),
+    Rs256(rsa::pkcs1v15::VerifyingKey<sha2::Sha256>),
+}
+
+impl PublicKey {
+    /// Parses the key in the COSE_Key format, as stored along with the passkey.
+    pub(crate) fn from_cose(cose_key: &[u8]) -> Result<Self, Rejection> {
+        let value: Value = ciborium::from_reader(cose_key)
+            .map_err(|_| Rejection("Malformed credential public key"))?;
+        let map = value
+            .as_map()
+            .ok_or(Rejection("Malformed credential public key"))?;
+
+        // See <https://www.rfc-editor.org/rfc/rfc9053#section-7>
+        const KTY: i64 = 1;
+        const ALG: i64 = 3;
+        const CRV_OR_N: i64 = -1;
+        const X_OR_E: i64 = -2;
+        const Y: i64 = -3;
+        const KTY_OKP: i128 = 1;
+        const KTY_EC2: i128 = 2;
+        const KTY_RSA: i128 = 3;
+        const CRV_P256: i128 = 1;
+        const CRV_ED25519: i128 = 6;
+
+        let kty = cose_int(map, KTY)?;
+        let alg = cose_int(map, ALG)?;
+        match (kty, i64::try_from(alg).unwrap_or_default()) {
+            (KTY_EC2, ES256) if cose_int(map, CRV_OR_N)? == CRV_P256 => {
+                let mut point = vec![0x04];
+                point.extend_from_slice(cose_bytes(map, X_OR_E)?);
+                point.extend_from_slice(cose_bytes(map, Y)?);
+                p256::ecdsa::VerifyingKey::from_sec1_bytes(&point)
+                    .map(PublicKey::Es256)
+                    .map_err(|_| Rejection("Invalid P-256 public key"))
+            }
+            (KTY_OKP, EDDSA) if cose_int(map, CRV_OR_N)? == CRV_ED25519 => {
+                let x: &[u8; 32] = cose_bytes(map, X_OR_E)?
+                    .try_into()
+                    .map_err(|_| Rejection("Invalid Ed25519 public key"))?;
+                ed25519_dalek::VerifyingKey::from_bytes(x)
+                    .map(PublicKey::EdDsa)
+                    .map_err(|_| Rejection("Invalid Ed25519 public key"))
+            }
+            (KTY_RSA, RS256) => {
+                let n = rsa::BigUint::from_bytes_be(cose_bytes(map, CRV_OR_N)?);
+                let e = rsa::BigUint::from_bytes_be(cose_bytes(map, X_OR_E)?);
+                rsa::RsaPublicKey::new(n, e)
+                    .map(|key| PublicKey::Rs256(rsa::pkcs1v15::VerifyingKey::new(key)))
+                    .map_err(|_| Rejection("Invalid RSA public key"))
+            }
+            _ => Err(Rejection("Unsupported credential algorithm")),
+        }
+    }
+
+    fn alg(&self) -> i64 {
+        match self {
+            PublicKey::Es256(_) => ES256,
+            PublicKey::EdDsa(_) => EDDSA,
+            PublicKey::Rs256(_) => RS256,
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), Rejection> {
+        use rsa::signature::Verifier as _;
+
+        let verified = match self {
+            PublicKey::Es256(key) => p256::ecdsa::Signature::from_der(signature)
+                .is_ok_and(|signature| key.verify(message, &signature).is_ok()),
+            PublicKey::EdDsa(key) => ed25519_dalek::Signature::from_slice(signature)
+                .is_ok_and(|signature| key.verify_strict(message, &signature).is_ok()),
+            PublicKey::Rs256(key) => rsa::pkcs1v15::Signature::try_from(signature)
+                .is_ok_and(|signature| key.verify(message, &signature).is_ok()),
+        };
+        verified.then_some(()).ok_or(Rejection("Invalid signature"))
+    }
+}
+
+/// What the authenticator signs: the authenticator data followed by the hash of the client data.
+fn signed_message(authenticator_data: &[u8], client_data_json: &[u8]) -> Vec<u8> {
+    let mut message = authenticator_data.to_vec();
+    message.extend_from_slice(&sha2::Sha256::digest(client_data_json));
+    message
+}
+
+/// A passkey that passed the registration ceremony.
+pub(crate) struct NewCredential {
+    pub(crate) credential_id: Vec<u8>,
+    /// In the COSE_Key format
+    pub(crate) public_key: Vec<u8>,
+    pub(crate) sign_count: u32,
+}
+
+/// Verifies the attestation object of the registration, whose client data was checked with
+/// [`client_data_challenge`].
+///
+/// See <https://www.w3.org/TR/webauthn-3/#sctn-registering-a-new-credential>.
+pub(crate) fn verify_registration(
+    env: &WebAuthnEnv,
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+) -> Result<NewCredential, Rejection> {
+    let value: Value = ciborium::from_reader(attestation_object)
+        .map_err(|_| Rejection("Malformed attestation object"))?;
+    let map = value
+        .as_map()
+        .ok_or(Rejection("Malformed attestation object"))?;
+    let (Some(fmt), Some(att_stmt), Some(authenticator_data)) = (
+        map_get(map, "fmt").and_then(Value::as_text),
+        map_get(map, "attStmt").and_then(Value::as_map),
+        map_get(map, "authData").and_then(Value::as_bytes),
+    ) else {
+        return Err(Rejection("Malformed attestation object"));
+    };
+
+    let AuthenticatorData {
+        sign_count,
+        attested_credential,
+    } = parse_authenticator_data(env, authenticator_data)?;
+    let (credential_id, cose_key) =
+        attested_credential.ok_or(Rejection("Missing attested credential data"))?;
+    let public_key = PublicKey::from_cose(&cose_key)?;
+
+    // See <https://www.w3.org/TR/webauthn-3/#sctn-defined-attestation-formats>
+    match fmt {
+        "none" if att_stmt.is_empty() => (),
+        "packed" => {
+            if map_get(att_stmt, "x5c").is_some() {
+                return Err(Rejection("Unsupported attestation certificate"));
+            }
+            let alg = map_get(att_stmt, "alg")
+                .and_then(Value::as_integer)
+                .map(i128::from);
+            let signature = map_get(att_stmt, "sig").and_then(Value::as_bytes);
+            let (Some(alg), Some(signature)) = (alg, signature) else {
+                return Err(Rejection("Malformed attestation statement"));
+            };
+            // Self attestation, signed with the credential private key
+            if alg != public_key.alg().into() {
+                return Err(Rejection("Malformed attestation statement"));
+            }
+            public_key.verify(
+                &signed_message(authenticator_data, client_data_json),
+                signature,
+            )?;
+        }
+        _ => return Err(Rejection("Unsupported attestation statement format")),
+    }
+
+    Ok(NewCredential {
+        credential_id,
+        public_key: cose_key,
+        sign_count,
+    })
+}
+
+/// Verifies the assertion of the authentication, whose client data was checked with
+/// [`client_data_challenge`], and returns the signature counter.
+///
+/// See <https://www.w3.org/TR/webauthn-3/#sctn-verifying-assertion>.
+pub(crate) fn verify_assertion(
+    env: &WebAuthnEnv,
+    public_key: &[u8],
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    signature: &[u8],
+) -> Result<u32, Rejection> {
+    let AuthenticatorData { sign_count, .. } = parse_authenticator_data(env, authenticator_data)?;
+    PublicKey::from_cose(public_key)?.verify(
+        &signed_message(authenticator_data, client_data_json),
+        signature,
+    )?;
+    Ok(sign_count)
+}
+
+/// Whether the signature counter moved forward, as it does unless the authenticator was cloned.
+///
+/// Authenticators that don't count, like the synced passkeys, always report 0.
+pub(crate) fn sign_count_is_valid(stored: u32, received: u32) -> bool {
+    received > stored || (stored == 0 && received == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::signature::Signer as _;
+
+    use super::*;
+
+    const ORIGIN: &str = "http://localhost:3001";
+
+    fn env() -> WebAuthnEnv {
+        WebAuthnEnv {
+            rp_id: "localhost".to_string(),
+            rp_name: "Main Line".to_string(),
+            origins: vec![ORIGIN.to_string()],
+        }
+    }
+
+    fn cbor(value: &Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).unwrap();
+        bytes
+    }
+
+    /// A software authenticator with a single ES256 passkey.
+    struct Authenticator {
+        key: p256::ecdsa::SigningKey,
+        credential_id: Vec<u8>,
+        sign_count: u32,
+    }
+
+    impl Authenticator {
+        fn new() -> Self {
+            Authenticator {
+                key: p256::ecdsa::SigningKey::random(&mut rand::rngs::OsRng),
+                credential_id: b"software-credential".to_vec(),
+                sign_count: 0,
+            }
+        }
+
+        fn cose_key(&self) -> Vec<u8> {
+            let point = self.key.verifying_key().to_encoded_point(false);
+            cbor(&Value::Map(vec![
+                (1.into(), 2.into()),
+                (3.into(), ES256.into()),
+                ((-1).into(), 1.into()),
+                ((-2).into(), Value::Bytes(point.x().unwrap().to_vec())),
+                ((-3).into(), Value::Bytes(point.y().unwrap().to_vec())),
+            ]))
+        }
+
+        fn authenticator_data(&self, rp_id: &str, attested: bool) -> Vec<u8> {
+            let mut flags = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+            if attested {
+                flags |= FLAG_ATTESTED_CREDENTIAL_DATA;
+            }
+            let mut data = sha2::Sha256::digest(rp_id.as_bytes()).to_vec();
+            data.push(flags);
+            data.extend_from_slice(&self.sign_count.to_be_bytes());
+            if attested {
+                data.extend_from_slice(&[0; 16]);
+                data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+                data.extend_from_slice(&self.credential_id);
+                data.extend_from_slice(&self.cose_key());
+            }
+            data
+        }
+
+        fn client_data(ceremony: Ceremony, challenge: &str, origin: &str) -> Vec<u8> {
+            serde_json::json!({
+                "type": ceremony.client_data_type(),
+                "challenge": challenge,
+                "origin": origin,
+            })
+            .to_string()
+            .into_bytes()
+        }
+
+        fn sign(&self, authenticator_data: &[u8], client_data_json: &[u8]) -> Vec<u8> {
+            let signature: p256::ecdsa::Signature = self
+                .key
+                .sign(&signed_message(authenticator_data, client_data_json));
+            signature.to_der().as_bytes().to_vec()
+        }
+
+        /// Returns the client data and the attestation object.
+        fn register(&self, challenge: &str, packed: bool) -> (Vec<u8>, Vec<u8>) {
+            let client_data = Self::client_data(Ceremony::Registration, challenge, ORIGIN);
+            let authenticator_data = self.authenticator_data("localhost", true);
+            let (fmt, att_stmt) = if packed {
+                let sig = self.sign(&authenticator_data, &client_data);
+                (
+                    "packed",
+                    vec![
+                        ("alg".into(), ES256.into()),
+                        ("sig".into(), Value::Bytes(sig)),
+                    ],
+                )
+            } else {
+                ("none", vec![])
+            };
+            let attestation_object = cbor(&Value::Map(vec![
+                ("fmt".into(), fmt.into()),
+                ("attStmt".into(), Value::Map(att_stmt)),
+                ("authData".into(), Value::Bytes(authenticator_data)),
+            ]));
+            (client_data, attestation_object)
+        }
+
+        /// Returns the client data, the authenticator data and the signature.
+        fn authenticate(&mut self, challenge: &str) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+            self.sign_count += 1;
+            let client_data = Self::client_data(Ceremony::Authentication, challenge, ORIGIN);
+            let authenticator_data = self.authenticator_data("localhost", false);
+            let signature = self.sign(&authenticator_data, &client_data);
+            (client_data, authenticator_data, signature)
+        }
+    }
+
+    #[test]
+    fn software_authenticator_registers_and_authenticates() {
+        let env = env();
+        let mut authenticator = Authenticator::new();
+
+        for packed in [false, true] {
+            let (client_data, attestation_object) = authenticator.register("register", packed);
+            assert_eq!(
+                client_data_challenge(&env, &client_data, Ceremony::Registration).as_deref(),
+                Ok("register")
+            );
+            let credential = verify_registration(&env, &client_data, &attestation_object).unwrap();
+            assert_eq!(credential.credential_id, authenticator.credential_id);
+            assert_eq!(credential.public_key, authenticator.cose_key());
+            assert_eq!(credential.sign_count, 0);
+        }
+
+        let public_key = authenticator.cose_key();
+        let (client_data, authenticator_data, signature) = authenticator.authenticate("login");
+        assert_eq!(
+            client_data_challenge(&env, &client_data, Ceremony::Authentication).as_deref(),
+            Ok("login")
+        );
+        assert_eq!(
+            verify_assertion(
+                &env,
+                &public_key,
+                &client_data,
+                &authenticator_data,
+                &signature
+            ),
+            Ok(1)
+        );
+
+        let mut tampered = signature.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(
+            verify_assertion(
+                &env,
+                &public_key,
+                &client_data,
+                &authenticator_data,
+                &tampered
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn ceremonies_are_bound_to_the_origin_and_the_relying_party() {
+        let env = env();
+        let authenticator = Authenticator::new();
+
+        let client_data =
+            Authenticator::client_data(Ceremony::Registration, "c", "https://evil.example");
+        assert!(client_data_challenge(&env, &client_data, Ceremony::Registration).is_err());
+
+        let client_data = Authenticator::client_data(Ceremony::Registration, "c", ORIGIN);
+        assert!(client_data_challenge(&env, &client_data, Ceremony::Authentication).is_err());
+
+        let authenticator_data = authenticator.authenticator_data("evil.example", false);
+        let signature = authenticator.sign(&authenticator_data, &client_data);
+        assert!(
+            verify_assertion(
+                &env,
+                &authenticator.cose_key(),
+                &client_data,
+                &authenticator_data,
+                &signature
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn sign_count_must_increase_unless_unsupported() {
+        assert!(sign_count_is_valid(0, 0));
+        assert!(sign_count_is_valid(0, 1));
+        assert!(sign_count_is_valid(5, 6));
+        assert!(!sign_count_is_valid(5, 5));
+        assert!(!sign_count_is_valid(5, 0));
+    }
+}
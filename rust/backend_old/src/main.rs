@@ -17,6 +17,8 @@ async fn main() -> anyhow::Result<()> {
 
     info!("Serving the app...");
 
+    // The peer addresses are needed to throttle the logins per IP address
+    let app = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
     axum::serve(listener, app).await?;
 
     Ok(())
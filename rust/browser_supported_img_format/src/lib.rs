@@ -1,4 +1,4 @@
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum BrowserSupportedImgFormat {
     Bmp,
     Png,
@@ -9,6 +9,9 @@ pub enum BrowserSupportedImgFormat {
 }
 
 impl BrowserSupportedImgFormat {
+    /// How many leading bytes [`Self::sniff`] needs to tell any supported format apart.
+    pub const SNIFF_LEN: usize = 1024;
+
     pub fn ext(&self) -> &'static str {
         match self {
             Self::Bmp => "bmp",
@@ -40,6 +43,46 @@ impl BrowserSupportedImgFormat {
         }
     }
 
+    /// Detects the format from the leading bytes of the file, regardless of its name.
+    ///
+    /// The raster formats are recognized by their magic bytes. SVG, being text, is recognized
+    /// by an `<svg` tag within the first [`Self::SNIFF_LEN`] bytes, which may be preceded by an
+    /// XML declaration, comments or a doctype.
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+        const JPEG: &[u8] = b"\xff\xd8\xff";
+
+        if bytes.starts_with(PNG) {
+            Some(Self::Png)
+        } else if bytes.starts_with(JPEG) {
+            Some(Self::Jpeg)
+        } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+            Some(Self::Gif)
+        } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+            Some(Self::Webp)
+        } else if bytes.starts_with(b"BM") {
+            Some(Self::Bmp)
+        } else if Self::looks_like_svg(bytes) {
+            Some(Self::Svg)
+        } else {
+            None
+        }
+    }
+
+    fn looks_like_svg(bytes: &[u8]) -> bool {
+        let bytes = &bytes[..bytes.len().min(Self::SNIFF_LEN)];
+        let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+        let Some(start) = bytes.iter().position(|b| !b.is_ascii_whitespace()) else {
+            return false;
+        };
+        let bytes = &bytes[start..];
+        bytes.starts_with(b"<")
+            && !bytes.contains(&0)
+            && bytes
+                .windows(4)
+                .any(|window| window.eq_ignore_ascii_case(b"<svg"))
+    }
+
     /// The value for the `accept` attribute of the HTML `<input type="file" ...>` element.
     ///
     /// See <https://developer.mozilla.org/en-US/docs/Web/HTML/Reference/Attributes/accept>.
@@ -58,3 +101,41 @@ impl BrowserSupportedImgFormat {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_the_magic_bytes() {
+        let cases: [(&[u8], _); 6] = [
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", BrowserSupportedImgFormat::Png),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF", BrowserSupportedImgFormat::Jpeg),
+            (b"GIF89a\x01\0\x01\0", BrowserSupportedImgFormat::Gif),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", BrowserSupportedImgFormat::Webp),
+            (b"BM\x36\0\0\0\0\0", BrowserSupportedImgFormat::Bmp),
+            (
+                b"\xef\xbb\xbf<?xml version=\"1.0\"?>\n<!-- logo -->\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>",
+                BrowserSupportedImgFormat::Svg,
+            ),
+        ];
+        for (bytes, format) in cases {
+            assert_eq!(BrowserSupportedImgFormat::sniff(bytes), Some(format));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_content() {
+        assert_eq!(BrowserSupportedImgFormat::sniff(b""), None);
+        assert_eq!(BrowserSupportedImgFormat::sniff(b"MZ\x90\0\x03\0"), None);
+        assert_eq!(BrowserSupportedImgFormat::sniff(b"\x7fELF\x02\x01"), None);
+        assert_eq!(
+            BrowserSupportedImgFormat::sniff(b"<html><body></body></html>"),
+            None
+        );
+        assert_eq!(
+            BrowserSupportedImgFormat::sniff(b"RIFF\x24\0\0\0WAVEfmt "),
+            None
+        );
+    }
+}
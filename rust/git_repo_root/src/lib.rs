@@ -16,7 +16,7 @@ pub fn git_repo_root() -> anyhow::Result<String> {
     check_git_installation()?;
 
     let output = Command::new("git")
-        .args(&["rev-parse", "--show-toplevel"])
+        .args(["rev-parse", "--show-toplevel"])
         .output()?;
     if !output.status.success() {
         anyhow::bail!("Failed to get git repository root.");
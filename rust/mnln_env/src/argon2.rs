@@ -0,0 +1,36 @@
+use crate::var_or;
+
+/// Cost parameters of the server-side Argon2id password hashing.
+///
+/// The defaults follow the [OWASP recommendation](https://cheatsheetseries.owasp.org/cheatsheets/Password_Storage_Cheat_Sheet.html#argon2id).
+/// Changing them is safe: existing hashes are transparently rehashed on the next successful login.
+#[derive(Debug, Clone)]
+pub struct Argon2Env {
+    /// Memory size in KiB
+    pub m_cost: u32,
+    /// Number of iterations
+    pub t_cost: u32,
+    /// Degree of parallelism
+    pub p_cost: u32,
+}
+
+impl Argon2Env {
+    const DEFAULT_M_COST: u32 = 19 * 1024;
+    const DEFAULT_T_COST: u32 = 2;
+    const DEFAULT_P_COST: u32 = 1;
+
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        let m_cost = var_or("ARGON2_M_COST", Self::DEFAULT_M_COST)?;
+        let t_cost = var_or("ARGON2_T_COST", Self::DEFAULT_T_COST)?;
+        let p_cost = var_or("ARGON2_P_COST", Self::DEFAULT_P_COST)?;
+        Ok(Argon2Env {
+            m_cost,
+            t_cost,
+            p_cost,
+        })
+    }
+
+    pub(crate) fn dev() -> anyhow::Result<Self> {
+        Self::from_env()
+    }
+}
@@ -0,0 +1,82 @@
+use crate::var_or;
+
+/// How the uploaded SVG avatars are made safe to serve from the API origin, where a script
+/// inside of one would run with access to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SvgAvatarPolicy {
+    /// Stored as SVG without scripts, event handlers, external references and foreign objects
+    Sanitize,
+    /// Stored as PNG, so that nothing of the original markup is ever served
+    Rasterize,
+}
+
+impl std::str::FromStr for SvgAvatarPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sanitize" => Ok(Self::Sanitize),
+            "rasterize" => Ok(Self::Rasterize),
+            _ => {
+                anyhow::bail!("Unsupported SVG avatar policy `{s}`, expected sanitize or rasterize")
+            }
+        }
+    }
+}
+
+/// Limits on the uploaded avatars, see also [`SvgAvatarPolicy`].
+#[derive(Debug, Clone)]
+pub struct AvatarEnv {
+    pub svg_policy: SvgAvatarPolicy,
+    /// The larger uploads are aborted while they are being received
+    pub max_upload_bytes: u64,
+    /// The width and the height of the decoded images must not exceed it
+    pub max_dimension: u32,
+    /// How many bytes the avatars of a user, with their renditions, may take in the object
+    /// storage altogether
+    pub storage_quota_bytes: u64,
+    /// The objects in the avatar bucket that no user points to are deleted once they are older
+    /// than this, which leaves enough time for the uploads in progress to point to theirs
+    pub orphan_grace_secs: u64,
+    /// Whether the orphaned objects are only logged instead of being deleted
+    pub orphan_gc_dry_run: bool,
+}
+
+impl AvatarEnv {
+    const DEFAULT_SVG_POLICY: SvgAvatarPolicy = SvgAvatarPolicy::Sanitize;
+    const DEFAULT_MAX_UPLOAD_BYTES: u64 = 5 * 1024 * 1024;
+    const DEFAULT_MAX_DIMENSION: u32 = 8192;
+    const DEFAULT_STORAGE_QUOTA_BYTES: u64 = 20 * 1024 * 1024;
+    const DEFAULT_ORPHAN_GRACE_SECS: u64 = 24 * 60 * 60;
+    const DEFAULT_ORPHAN_GC_DRY_RUN: bool = false;
+
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        let svg_policy = var_or("AVATAR_SVG_POLICY", Self::DEFAULT_SVG_POLICY)?;
+        let max_upload_bytes = var_or("AVATAR_MAX_UPLOAD_BYTES", Self::DEFAULT_MAX_UPLOAD_BYTES)?;
+        let max_dimension = var_or("AVATAR_MAX_DIMENSION", Self::DEFAULT_MAX_DIMENSION)?;
+        let storage_quota_bytes = var_or(
+            "AVATAR_STORAGE_QUOTA_BYTES",
+            Self::DEFAULT_STORAGE_QUOTA_BYTES,
+        )?;
+        let orphan_grace_secs =
+            var_or("AVATAR_ORPHAN_GRACE_SECS", Self::DEFAULT_ORPHAN_GRACE_SECS)?;
+        let orphan_gc_dry_run =
+            var_or("AVATAR_ORPHAN_GC_DRY_RUN", Self::DEFAULT_ORPHAN_GC_DRY_RUN)?;
+        anyhow::ensure!(
+            max_upload_bytes <= storage_quota_bytes,
+            "AVATAR_MAX_UPLOAD_BYTES must not exceed AVATAR_STORAGE_QUOTA_BYTES"
+        );
+        Ok(AvatarEnv {
+            svg_policy,
+            max_upload_bytes,
+            max_dimension,
+            storage_quota_bytes,
+            orphan_grace_secs,
+            orphan_gc_dry_run,
+        })
+    }
+
+    pub(crate) fn dev() -> anyhow::Result<Self> {
+        Self::from_env()
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::Context as _;
+use std::env;
+
+/// The key that the fake client salts of unknown usernames are derived from.
+///
+/// `/api/user/salt` responds with such a salt instead of revealing that the username is free.
+/// The salt must be the same for every request, or the responses would tell the fake salts from
+/// the real ones, so rotating the key does reveal the unknown usernames to anyone who recorded
+/// their salts before.
+#[derive(Clone)]
+pub struct FakeSaltEnv {
+    pub key: Vec<u8>,
+}
+
+impl std::fmt::Debug for FakeSaltEnv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FakeSaltEnv").finish_non_exhaustive()
+    }
+}
+
+impl FakeSaltEnv {
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        let key = env::var("FAKE_SALT_KEY").context("Missing FAKE_SALT_KEY")?;
+        anyhow::ensure!(
+            key.len() >= 32,
+            "FAKE_SALT_KEY must be at least 32 characters long"
+        );
+        Ok(FakeSaltEnv {
+            key: key.into_bytes(),
+        })
+    }
+
+    // This function is meant to be used for tests happening
+    // as a part of local development only.
+    pub(crate) fn dev() -> anyhow::Result<Self> {
+        let repo_root: String = git_repo_root::git_repo_root()?;
+        let repo_root: std::path::PathBuf = repo_root.into();
+        let fake_salt_secrets_path = repo_root.join("secrets").join("fake_salt_key.env");
+
+        dotenv::from_path(fake_salt_secrets_path)?;
+        Self::from_env()
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::Context as _;
+use base64::Engine as _;
+use std::env;
+
+use crate::var_or;
+
+/// Registered claims that every access JWT is issued with and validated against.
+///
+/// See <https://www.rfc-editor.org/rfc/rfc7519#section-4.1>.
+#[derive(Debug, Clone)]
+pub struct JwtEnv {
+    /// The value of the `iss` claim
+    pub issuer: String,
+    /// The value of the `aud` claim
+    pub audience: String,
+    /// Allowed clock skew between the issuer and the verifier when validating
+    /// the `exp` and `nbf` claims
+    pub leeway_secs: u64,
+    pub key_ring: JwtKeyRing,
+}
+
+impl JwtEnv {
+    const DEFAULT_ISSUER: &str = "main-line";
+    const DEFAULT_AUDIENCE: &str = "main-line";
+    const DEFAULT_LEEWAY_SECS: u64 = 30;
+
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        let issuer = var_or("JWT_ISSUER", Self::DEFAULT_ISSUER.to_string())?;
+        let audience = var_or("JWT_AUDIENCE", Self::DEFAULT_AUDIENCE.to_string())?;
+        let leeway_secs = var_or("JWT_LEEWAY_SECS", Self::DEFAULT_LEEWAY_SECS)?;
+        let key_ring = JwtKeyRing::from_env()?;
+        Ok(JwtEnv {
+            issuer,
+            audience,
+            leeway_secs,
+            key_ring,
+        })
+    }
+
+    // This function is meant to be used for tests happening
+    // as a part of local development only.
+    pub(crate) fn dev() -> anyhow::Result<Self> {
+        let repo_root: String = git_repo_root::git_repo_root()?;
+        let repo_root: std::path::PathBuf = repo_root.into();
+        let jwt_secrets_path = repo_root.join("secrets").join("jwt_signing_key.env");
+
+        dotenv::from_path(jwt_secrets_path)?;
+        Self::from_env()
+    }
+}
+
+/// <https://www.rfc-editor.org/rfc/rfc7518#section-3.1>
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JwtAlgorithm {
+    /// HMAC using SHA-256
+    HS256,
+    /// ECDSA using P-256 and SHA-256
+    ES256,
+    /// EdDSA using Ed25519
+    EdDSA,
+}
+
+impl std::str::FromStr for JwtAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HS256" => Ok(Self::HS256),
+            "ES256" => Ok(Self::ES256),
+            "EdDSA" => Ok(Self::EdDSA),
+            _ => anyhow::bail!("Unsupported JWT algorithm `{s}`, expected HS256, ES256 or EdDSA"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct JwtKey {
+    /// The value of the `kid` header of the tokens signed with this key
+    pub kid: String,
+    pub algorithm: JwtAlgorithm,
+    /// The shared secret for [`JwtAlgorithm::HS256`] or the PKCS#8 DER-encoded private key otherwise
+    pub material: Vec<u8>,
+}
+
+// The key material must never end up in the logs.
+impl std::fmt::Debug for JwtKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtKey")
+            .field("kid", &self.kid)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Every key that access JWTs may be signed with.
+///
+/// All of the keys are accepted during verification but only the active one is used for
+/// signing, which allows rotating keys without logging everyone out:
+///
+/// 1. add the new key to `JWT_KEYS`;
+/// 2. once every replica knows it, make it active with `JWT_ACTIVE_KEY_ID`;
+/// 3. once the access tokens signed with the old key have expired, remove the old key.
+///
+/// The keys are configured with `JWT_KEYS`, a comma-separated list of `<kid>:<alg>:<material>`
+/// entries, where the material is the secret itself for HS256 and the base64-encoded PKCS#8 DER
+/// private key for ES256 and EdDSA:
+///
+/// ```sh
+/// openssl genpkey -algorithm ed25519 -outform DER | base64 -w0
+/// openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-256 \
+///     | openssl pkcs8 -topk8 -nocrypt -outform DER | base64 -w0
+/// ```
+///
+/// For backwards compatibility, `JWT_SIGNING_KEY` is loaded as an HS256 key with the `kid` "default".
+#[derive(Debug, Clone)]
+pub struct JwtKeyRing {
+    pub keys: Vec<JwtKey>,
+    /// The `kid` of the key that new tokens are signed with
+    pub active_kid: String,
+}
+
+impl JwtKeyRing {
+    const LEGACY_KID: &str = "default";
+
+    fn parse_key(entry: &str) -> anyhow::Result<JwtKey> {
+        let mut parts = entry.trim().splitn(3, ':');
+        let (Some(kid), Some(algorithm), Some(material)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("Expected a `<kid>:<alg>:<material>` entry");
+        };
+        anyhow::ensure!(!kid.is_empty(), "The key ID is empty");
+        let algorithm: JwtAlgorithm = algorithm.parse()?;
+        let material = match algorithm {
+            JwtAlgorithm::HS256 => material.as_bytes().to_vec(),
+            JwtAlgorithm::ES256 | JwtAlgorithm::EdDSA => base64::engine::general_purpose::STANDARD
+                .decode(material)
+                .with_context(|| format!("Couldn't decode the private key `{kid}` as base64"))?,
+        };
+        anyhow::ensure!(!material.is_empty(), "The key `{kid}` is empty");
+        Ok(JwtKey {
+            kid: kid.to_string(),
+            algorithm,
+            material,
+        })
+    }
+
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        let mut keys = Vec::new();
+
+        match env::var("JWT_SIGNING_KEY") {
+            Ok(secret) => keys.push(JwtKey {
+                kid: Self::LEGACY_KID.to_string(),
+                algorithm: JwtAlgorithm::HS256,
+                material: secret.into_bytes(),
+            }),
+            Err(env::VarError::NotPresent) => (),
+            Err(err) => return Err(err).context("Couldn't read JWT_SIGNING_KEY"),
+        }
+
+        match env::var("JWT_KEYS") {
+            Ok(entries) => {
+                for entry in entries.split(',').filter(|entry| !entry.trim().is_empty()) {
+                    let key = Self::parse_key(entry).context("Couldn't parse JWT_KEYS")?;
+                    anyhow::ensure!(
+                        keys.iter().all(|k| k.kid != key.kid),
+                        "Duplicate JWT key ID `{}`",
+                        key.kid
+                    );
+                    keys.push(key);
+                }
+            }
+            Err(env::VarError::NotPresent) => (),
+            Err(err) => return Err(err).context("Couldn't read JWT_KEYS"),
+        }
+
+        let active_kid = match env::var("JWT_ACTIVE_KEY_ID") {
+            Ok(kid) => kid,
+            Err(env::VarError::NotPresent) => match keys.as_slice() {
+                [] => anyhow::bail!("Missing JWT_KEYS or JWT_SIGNING_KEY"),
+                [key] => key.kid.clone(),
+                _ => anyhow::bail!("Missing JWT_ACTIVE_KEY_ID"),
+            },
+            Err(err) => return Err(err).context("Couldn't read JWT_ACTIVE_KEY_ID"),
+        };
+        anyhow::ensure!(
+            keys.iter().any(|key| key.kid == active_kid),
+            "JWT_ACTIVE_KEY_ID `{active_kid}` doesn't match any of the JWT keys"
+        );
+
+        Ok(JwtKeyRing { keys, active_kid })
+    }
+}
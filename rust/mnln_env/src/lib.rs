@@ -1,22 +1,84 @@
 use anyhow::Context as _;
 use std::env;
 
+mod argon2;
+mod avatar;
+mod fake_salt;
+mod jwt;
+mod lichess;
+mod login_throttle;
+mod mail;
 mod minio;
 mod pg;
+mod totp;
+mod webauthn;
 
+pub use argon2::Argon2Env;
+pub use avatar::{AvatarEnv, SvgAvatarPolicy};
+pub use fake_salt::FakeSaltEnv;
+pub use jwt::{JwtAlgorithm, JwtEnv, JwtKey, JwtKeyRing};
+pub use lichess::LichessOAuthEnv;
+pub use login_throttle::LoginThrottleEnv;
+pub use mail::{MailEnv, MailTransportEnv, SmtpEnv, SmtpSecurity};
 use minio::MinioEnv;
 pub use pg::PgEnv;
+pub use totp::TotpEnv;
+pub use webauthn::WebAuthnEnv;
+
+/// Reads an optional environment variable, falling back to `default` when it is not set.
+pub(crate) fn var_or<T>(key: &str, default: T) -> anyhow::Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    match env::var(key) {
+        Ok(value) => value
+            .parse::<T>()
+            .map_err(Into::into)
+            .with_context(|| format!("Couldn't parse {key}")),
+        Err(env::VarError::NotPresent) => Ok(default),
+        Err(err) => Err(err).with_context(|| format!("Couldn't read {key}")),
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Env {
     pub base_api_url: String,
     pub base_frontend_url: String,
     /// <https://www.jwt.io/introduction>
-    pub jwt_signing_key: String,
+    pub jwt: JwtEnv,
     /// <https://www.postgresql.org/>
     pub pg: PgEnv,
     /// <https://github.com/minio/minio>
     pub minio: MinioEnv,
+    /// <https://en.wikipedia.org/wiki/Argon2>
+    pub argon2: Argon2Env,
+    pub fake_salt: FakeSaltEnv,
+    pub mail: MailEnv,
+    pub lichess_oauth: LichessOAuthEnv,
+    pub login_throttle: LoginThrottleEnv,
+    pub totp: TotpEnv,
+    pub webauthn: WebAuthnEnv,
+    pub avatar: AvatarEnv,
+    /// The networks of the reverse proxies whose `X-Forwarded-For` is trusted,
+    /// as comma-separated CIDRs in `TRUSTED_PROXIES`, e.g. `10.0.0.0/8,127.0.0.1/32`
+    pub trusted_proxies: Vec<ipnet::IpNet>,
+}
+
+fn trusted_proxies_from_env() -> anyhow::Result<Vec<ipnet::IpNet>> {
+    match env::var("TRUSTED_PROXIES") {
+        Ok(value) => value
+            .split(',')
+            .map(str::trim)
+            .filter(|cidr| !cidr.is_empty())
+            .map(|cidr| {
+                cidr.parse()
+                    .with_context(|| format!("Couldn't parse TRUSTED_PROXIES entry `{cidr}`"))
+            })
+            .collect(),
+        Err(env::VarError::NotPresent) => Ok(Vec::new()),
+        Err(err) => Err(err).context("Couldn't read TRUSTED_PROXIES"),
+    }
 }
 
 impl Env {
@@ -24,35 +86,66 @@ impl Env {
         let base_api_url = env::var("BASE_API_URL").context("Missing BASE_API_URL")?;
         let base_frontend_url =
             env::var("BASE_FRONTEND_URL").context("Missing BASE_FRONTEND_URL")?;
-        let jwt_signing_key = env::var("JWT_SIGNING_KEY").context("Missing JWT_SIGNING_KEY")?;
         let pg = PgEnv::from_env()?;
         let minio = MinioEnv::from_env()?;
+        let argon2 = Argon2Env::from_env()?;
+        let fake_salt = FakeSaltEnv::from_env()?;
+        let jwt = JwtEnv::from_env()?;
+        let mail = MailEnv::from_env()?;
+        let lichess_oauth = LichessOAuthEnv::from_env()?;
+        let login_throttle = LoginThrottleEnv::from_env()?;
+        let totp = TotpEnv::from_env()?;
+        let webauthn = WebAuthnEnv::from_env()?;
+        let avatar = AvatarEnv::from_env()?;
+        let trusted_proxies = trusted_proxies_from_env()?;
         Ok(Env {
             pg,
             base_api_url,
             base_frontend_url,
-            jwt_signing_key,
+            jwt,
             minio,
+            argon2,
+            fake_salt,
+            mail,
+            lichess_oauth,
+            login_throttle,
+            totp,
+            webauthn,
+            avatar,
+            trusted_proxies,
         })
     }
 
     pub fn dev() -> anyhow::Result<Self> {
-        let repo_root = git_repo_root::git_repo_root()?;
-        let repo_root: std::path::PathBuf = repo_root.into();
-        let jwt_secrets_path = repo_root.join("secrets").join("jwt_signing_key.env");
-
         let base_api_url = "http://localhost:3000".to_string();
         let base_frontend_url = "http://localhost:3001".to_string();
-        dotenv::from_path(jwt_secrets_path)?;
-        let jwt_signing_key = env::var("JWT_SIGNING_KEY").context("Missing JWT_SIGNING_KEY")?;
         let pg = PgEnv::dev()?;
         let minio = MinioEnv::dev()?;
+        let argon2 = Argon2Env::dev()?;
+        let fake_salt = FakeSaltEnv::dev()?;
+        let jwt = JwtEnv::dev()?;
+        let mail = MailEnv::dev()?;
+        let lichess_oauth = LichessOAuthEnv::dev()?;
+        let login_throttle = LoginThrottleEnv::dev()?;
+        let totp = TotpEnv::dev()?;
+        let webauthn = WebAuthnEnv::dev()?;
+        let avatar = AvatarEnv::dev()?;
+        let trusted_proxies = trusted_proxies_from_env()?;
         Ok(Env {
             pg,
             base_api_url,
             base_frontend_url,
-            jwt_signing_key,
+            jwt,
             minio,
+            argon2,
+            fake_salt,
+            mail,
+            lichess_oauth,
+            login_throttle,
+            totp,
+            webauthn,
+            avatar,
+            trusted_proxies,
         })
     }
 }
@@ -0,0 +1,50 @@
+use crate::var_or;
+
+/// "Sign in with Lichess" through OAuth2 authorization code flow with PKCE.
+///
+/// Lichess doesn't require registering the client, so the `client_id` is any name shown to the
+/// user on the consent screen. The endpoints can point to a mock authorization server in tests.
+/// See <https://lichess.org/api#tag/OAuth>.
+#[derive(Debug, Clone)]
+pub struct LichessOAuthEnv {
+    pub client_id: String,
+    /// Where the user is sent to grant the access
+    pub authorize_url: String,
+    /// Where the authorization code is exchanged for an access token
+    pub token_url: String,
+    /// Where the account of the access token is read from
+    pub account_url: String,
+}
+
+impl LichessOAuthEnv {
+    const DEFAULT_CLIENT_ID: &str = "main-line";
+    const DEFAULT_AUTHORIZE_URL: &str = "https://lichess.org/oauth";
+    const DEFAULT_TOKEN_URL: &str = "https://lichess.org/api/token";
+    const DEFAULT_ACCOUNT_URL: &str = "https://lichess.org/api/account";
+
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        let client_id = var_or(
+            "LICHESS_OAUTH_CLIENT_ID",
+            Self::DEFAULT_CLIENT_ID.to_string(),
+        )?;
+        let authorize_url = var_or(
+            "LICHESS_OAUTH_AUTHORIZE_URL",
+            Self::DEFAULT_AUTHORIZE_URL.to_string(),
+        )?;
+        let token_url = var_or(
+            "LICHESS_OAUTH_TOKEN_URL",
+            Self::DEFAULT_TOKEN_URL.to_string(),
+        )?;
+        let account_url = var_or("LICHESS_ACCOUNT_URL", Self::DEFAULT_ACCOUNT_URL.to_string())?;
+        Ok(LichessOAuthEnv {
+            client_id,
+            authorize_url,
+            token_url,
+            account_url,
+        })
+    }
+
+    pub(crate) fn dev() -> anyhow::Result<Self> {
+        Self::from_env()
+    }
+}
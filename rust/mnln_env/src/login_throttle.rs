@@ -0,0 +1,55 @@
+use crate::var_or;
+
+/// Limits on failed logins, counted both per username and per client IP address.
+///
+/// Once a counter exceeds its number of allowed failures, every further failure locks the
+/// username or the IP address out for twice as long as the previous one, starting at
+/// `base_lockout_secs` and up to `max_lockout_secs`.
+#[derive(Debug, Clone)]
+pub struct LoginThrottleEnv {
+    /// Failures of a username that don't lock it out
+    pub username_allowed_failures: u32,
+    /// Failures from an IP address that don't lock it out, higher because of shared NATs
+    pub ip_allowed_failures: u32,
+    pub base_lockout_secs: u32,
+    pub max_lockout_secs: u32,
+    /// The counter starts over when there was no failure for this long
+    pub reset_after_secs: u32,
+}
+
+impl LoginThrottleEnv {
+    const DEFAULT_USERNAME_ALLOWED_FAILURES: u32 = 5;
+    const DEFAULT_IP_ALLOWED_FAILURES: u32 = 20;
+    const DEFAULT_BASE_LOCKOUT_SECS: u32 = 30;
+    const DEFAULT_MAX_LOCKOUT_SECS: u32 = 60 * 60;
+    const DEFAULT_RESET_AFTER_SECS: u32 = 24 * 60 * 60;
+
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        let username_allowed_failures = var_or(
+            "LOGIN_USERNAME_ALLOWED_FAILURES",
+            Self::DEFAULT_USERNAME_ALLOWED_FAILURES,
+        )?;
+        let ip_allowed_failures = var_or(
+            "LOGIN_IP_ALLOWED_FAILURES",
+            Self::DEFAULT_IP_ALLOWED_FAILURES,
+        )?;
+        let base_lockout_secs = var_or("LOGIN_BASE_LOCKOUT_SECS", Self::DEFAULT_BASE_LOCKOUT_SECS)?;
+        let max_lockout_secs = var_or("LOGIN_MAX_LOCKOUT_SECS", Self::DEFAULT_MAX_LOCKOUT_SECS)?;
+        let reset_after_secs = var_or("LOGIN_RESET_AFTER_SECS", Self::DEFAULT_RESET_AFTER_SECS)?;
+        anyhow::ensure!(
+            base_lockout_secs <= max_lockout_secs,
+            "LOGIN_BASE_LOCKOUT_SECS must not exceed LOGIN_MAX_LOCKOUT_SECS"
+        );
+        Ok(LoginThrottleEnv {
+            username_allowed_failures,
+            ip_allowed_failures,
+            base_lockout_secs,
+            max_lockout_secs,
+            reset_after_secs,
+        })
+    }
+
+    pub(crate) fn dev() -> anyhow::Result<Self> {
+        Self::from_env()
+    }
+}
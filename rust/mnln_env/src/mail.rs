@@ -0,0 +1,128 @@
+use anyhow::Context as _;
+use std::env;
+
+use crate::var_or;
+
+/// How the connection to the SMTP server is secured.
+#[derive(Debug, Clone, Copy)]
+pub enum SmtpSecurity {
+    /// Plain text, e.g. for a local mail catcher
+    None,
+    /// Upgraded with the `STARTTLS` command, see <https://www.rfc-editor.org/rfc/rfc3207>
+    StartTls,
+    /// Implicit TLS, see <https://www.rfc-editor.org/rfc/rfc8314>
+    Tls,
+}
+
+impl SmtpSecurity {
+    fn default_port(self) -> u16 {
+        match self {
+            SmtpSecurity::None => 25,
+            SmtpSecurity::StartTls => 587,
+            SmtpSecurity::Tls => 465,
+        }
+    }
+}
+
+impl std::str::FromStr for SmtpSecurity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Tls),
+            _ => anyhow::bail!("Unsupported SMTP security `{s}`, expected none, starttls or tls"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpEnv {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl SmtpEnv {
+    fn from_env() -> anyhow::Result<Self> {
+        let host = env::var("SMTP_HOST").context("Missing SMTP_HOST")?;
+        let security = var_or("SMTP_SECURITY", SmtpSecurity::StartTls)?;
+        let port = var_or("SMTP_PORT", security.default_port())?;
+        let username = env::var("SMTP_USERNAME").ok();
+        let password = env::var("SMTP_PASSWORD").ok();
+        anyhow::ensure!(
+            username.is_some() == password.is_some(),
+            "SMTP_USERNAME and SMTP_PASSWORD must be set together"
+        );
+        anyhow::ensure!(
+            username.is_none() || !matches!(security, SmtpSecurity::None),
+            "SMTP_USERNAME and SMTP_PASSWORD require SMTP_SECURITY=starttls or tls, \
+            so that they aren't sent in plain text"
+        );
+        Ok(SmtpEnv {
+            host,
+            port,
+            security,
+            username,
+            password,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MailTransportEnv {
+    Smtp(SmtpEnv),
+    /// Every mail is written to a `.eml` file in the directory
+    File {
+        dir: std::path::PathBuf,
+    },
+    /// Every mail is kept in memory and logged
+    Memory,
+}
+
+/// Outgoing mail, e.g. for email verification.
+///
+/// `MAIL_TRANSPORT` is one of `smtp` (configured with `SMTP_*`), `file` (configured with
+/// `MAIL_FILE_DIR`) or `memory`.
+#[derive(Debug, Clone)]
+pub struct MailEnv {
+    /// The `From` header, e.g. `main-line <no-reply@example.com>`
+    pub from: String,
+    pub transport: MailTransportEnv,
+}
+
+impl MailEnv {
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        let from = env::var("MAIL_FROM").context("Missing MAIL_FROM")?;
+        let transport = env::var("MAIL_TRANSPORT").context("Missing MAIL_TRANSPORT")?;
+        let transport = match transport.as_str() {
+            "smtp" => MailTransportEnv::Smtp(SmtpEnv::from_env()?),
+            "file" => MailTransportEnv::File {
+                dir: env::var("MAIL_FILE_DIR")
+                    .context("Missing MAIL_FILE_DIR")?
+                    .into(),
+            },
+            "memory" => MailTransportEnv::Memory,
+            _ => anyhow::bail!(
+                "Unsupported MAIL_TRANSPORT `{transport}`, expected smtp, file or memory"
+            ),
+        };
+        Ok(MailEnv { from, transport })
+    }
+
+    // This function is meant to be used for tests happening
+    // as a part of local development only.
+    pub(crate) fn dev() -> anyhow::Result<Self> {
+        let repo_root: String = git_repo_root::git_repo_root()?;
+        let repo_root: std::path::PathBuf = repo_root.into();
+
+        let from = "main-line <no-reply@localhost>".to_string();
+        let transport = MailTransportEnv::File {
+            dir: repo_root.join("data").join("mail"),
+        };
+        Ok(MailEnv { from, transport })
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::Context as _;
 use std::env;
 
+use crate::var_or;
+
 #[derive(Debug, Clone)]
 pub struct MinioEnv {
     pub user: String,
@@ -8,9 +10,13 @@ pub struct MinioEnv {
     pub host: String,
     pub port: String,
     pub avatar_bucket: String,
+    /// Where the personal data exports are stored until they expire
+    pub export_bucket: String,
 }
 
 impl MinioEnv {
+    const DEFAULT_EXPORT_BUCKET: &str = "exports";
+
     pub(crate) fn from_env() -> anyhow::Result<Self> {
         let host = env::var("MINIO_HOST").context("Missing MINIO_HOST")?;
         let port = env::var("MINIO_PORT").context("Missing MINIO_PORT")?;
@@ -18,12 +24,17 @@ impl MinioEnv {
         let password = env::var("MINIO_ROOT_PASSWORD").context("Missing MINIO_PASSWORD")?;
         let avatar_bucket =
             env::var("MINIO_AVATAR_BUCKET").context("Missing MINIO_AVATAR_BUCKET")?;
+        let export_bucket = var_or(
+            "MINIO_EXPORT_BUCKET",
+            Self::DEFAULT_EXPORT_BUCKET.to_string(),
+        )?;
         Ok(MinioEnv {
             host,
             port,
             user,
             password,
             avatar_bucket,
+            export_bucket,
         })
     }
 
@@ -42,12 +53,17 @@ impl MinioEnv {
         let password = env::var("MINIO_ROOT_PASSWORD").context("Missing MINIO_PASSWORD")?;
         let avatar_bucket =
             env::var("MINIO_AVATAR_BUCKET").context("Missing MINIO_AVATAR_BUCKET")?;
+        let export_bucket = var_or(
+            "MINIO_EXPORT_BUCKET",
+            Self::DEFAULT_EXPORT_BUCKET.to_string(),
+        )?;
         Ok(MinioEnv {
             host,
             port,
             user,
             password,
             avatar_bucket,
+            export_bucket,
         })
     }
 }
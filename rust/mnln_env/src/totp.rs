@@ -0,0 +1,32 @@
+use crate::var_or;
+
+/// Two-factor authentication with time-based one-time passwords.
+/// See <https://www.rfc-editor.org/rfc/rfc6238>.
+#[derive(Debug, Clone)]
+pub struct TotpEnv {
+    /// The issuer shown by the authenticator app next to the username
+    pub issuer: String,
+    /// Whether the admin endpoints reject the access tokens of admins who didn't log in
+    /// with the second factor, which also requires them to enroll
+    pub require_for_admins: bool,
+}
+
+impl TotpEnv {
+    const DEFAULT_ISSUER: &str = "Main Line";
+    const DEFAULT_REQUIRE_FOR_ADMINS: bool = true;
+
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        let issuer = var_or("TOTP_ISSUER", Self::DEFAULT_ISSUER.to_string())?;
+        anyhow::ensure!(!issuer.contains(':'), "TOTP_ISSUER must not contain `:`");
+        let require_for_admins =
+            var_or("TOTP_REQUIRE_FOR_ADMINS", Self::DEFAULT_REQUIRE_FOR_ADMINS)?;
+        Ok(TotpEnv {
+            issuer,
+            require_for_admins,
+        })
+    }
+
+    pub(crate) fn dev() -> anyhow::Result<Self> {
+        Self::from_env()
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Context as _;
+use std::env;
+
+use crate::var_or;
+
+/// The relying party of the passkeys, see <https://www.w3.org/TR/webauthn-3/#relying-party>.
+#[derive(Debug, Clone)]
+pub struct WebAuthnEnv {
+    /// The registrable domain of the frontend, e.g. `example.com`, which the passkeys are bound to
+    pub rp_id: String,
+    /// The name shown by the authenticator next to the username
+    pub rp_name: String,
+    /// The origins that may run the ceremonies, as comma-separated `WEBAUTHN_ORIGINS`
+    pub origins: Vec<String>,
+}
+
+impl WebAuthnEnv {
+    const DEFAULT_RP_ID: &str = "localhost";
+    const DEFAULT_RP_NAME: &str = "Main Line";
+    const DEFAULT_ORIGINS: &str = "http://localhost:3001";
+
+    pub(crate) fn from_env() -> anyhow::Result<Self> {
+        let rp_id = var_or("WEBAUTHN_RP_ID", Self::DEFAULT_RP_ID.to_string())?;
+        let rp_name = var_or("WEBAUTHN_RP_NAME", Self::DEFAULT_RP_NAME.to_string())?;
+        let origins = match env::var("WEBAUTHN_ORIGINS") {
+            Ok(value) => value,
+            Err(env::VarError::NotPresent) => Self::DEFAULT_ORIGINS.to_string(),
+            Err(err) => return Err(err).context("Couldn't read WEBAUTHN_ORIGINS"),
+        };
+        let origins: Vec<String> = origins
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(str::to_string)
+            .collect();
+        anyhow::ensure!(!origins.is_empty(), "WEBAUTHN_ORIGINS must not be empty");
+        Ok(WebAuthnEnv {
+            rp_id,
+            rp_name,
+            origins,
+        })
+    }
+
+    pub(crate) fn dev() -> anyhow::Result<Self> {
+        Self::from_env()
+    }
+}
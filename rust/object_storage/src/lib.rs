@@ -29,44 +29,50 @@ fn default_region(env: &Env) -> Region {
     }
 }
 
-pub async fn init(env: &Env) -> anyhow::Result<()> {
-    let avatar_bucket = avatar_bucket(env).await?;
-    if !avatar_bucket.exists().await? {
-        tracing::info!(
-            "Avatar bucket `{}` does not exist. Creating...",
-            &env.minio.avatar_bucket
-        );
-        let mut opts =
-            CreateBucketOptions::new(&env.minio.avatar_bucket, default_region(env), creds(env)?);
+async fn ensure_bucket(env: &Env, name: &str) -> anyhow::Result<()> {
+    if !bucket(env, name)?.exists().await? {
+        tracing::info!("Bucket `{name}` does not exist. Creating...");
+        let mut opts = CreateBucketOptions::new(name, default_region(env), creds(env)?);
         opts.path_style = true;
         opts.set_dangerous_config(true, true);
         let _create_bucket_response = Bucket::create_with_opts(opts).await?;
-        tracing::info!("Created avatar bucket: `{}`", &env.minio.avatar_bucket);
+        tracing::info!("Created bucket: `{name}`");
     } else {
-        tracing::info!(
-            "Avatar bucket `{}` already exists. Skipping creation.",
-            &env.minio.avatar_bucket
-        );
+        tracing::info!("Bucket `{name}` already exists. Skipping creation.");
     };
     Ok(())
 }
 
-async fn avatar_bucket(env: &Env) -> anyhow::Result<Box<Bucket>> {
+pub async fn init(env: &Env) -> anyhow::Result<()> {
+    ensure_bucket(env, &env.minio.avatar_bucket).await?;
+    ensure_bucket(env, &env.minio.export_bucket).await?;
+    Ok(())
+}
+
+fn bucket(env: &Env, name: &str) -> anyhow::Result<Box<Bucket>> {
     let creds = creds(env)?;
-    let bucket = Bucket::new(
-        &env.minio.avatar_bucket.as_str(),
-        default_region(env),
-        creds,
-    )?
-    .set_dangereous_config(true, true)?
-    .with_path_style();
+    let bucket = Bucket::new(name, default_region(env), creds)?
+        .set_dangereous_config(true, true)?
+        .with_path_style();
     Ok(bucket)
 }
 
+async fn avatar_bucket(env: &Env) -> anyhow::Result<Box<Bucket>> {
+    bucket(env, &env.minio.avatar_bucket)
+}
+
+/// Every avatar is stored under this prefix.
+const AVATARS_PREFIX: &str = "avatars/";
+
+/// Every avatar of the user is stored under this prefix.
+fn avatar_prefix(user_id: UserId) -> String {
+    format!("{AVATARS_PREFIX}{user_id}/")
+}
+
 // TODO: implement extras, such as S3Key and S3Path
 fn avatar_key(user_id: UserId, format: BrowserSupportedImgFormat) -> String {
     let timestamp: Timestamp = mnln_time::now();
-    format!("avatars/{user_id}/{timestamp}.{}", format.ext())
+    format!("{}{timestamp}.{}", avatar_prefix(user_id), format.ext())
 }
 
 pub async fn save_avatar<B, E>(
@@ -85,12 +91,101 @@ where
     let key = avatar_key(user_id, avatar_format);
 
     let mut reader = tokio_util::io::StreamReader::new(avatar);
+    if let Err(e) = bucket.put_object_stream(&mut reader, &key).await {
+        // A failed stream may leave a partial object behind
+        if let Err(delete_err) = bucket.delete_object(&key).await {
+            tracing::warn!("Failed to delete the partial avatar `{key}`: {delete_err}");
+        }
+        return Err(anyhow::anyhow!(e).context("put_object_stream failed"));
+    }
+
+    Ok(key)
+}
+
+/// The key of the WebP rendition of the avatar stored under `avatar_key`.
+///
+/// The renditions share the prefix of the avatar, so they go wherever it goes.
+pub fn avatar_rendition_key(avatar_key: &str, size: u32) -> String {
+    let stem = avatar_key
+        .rsplit_once('.')
+        .map_or(avatar_key, |(stem, _ext)| stem);
+    format!("{stem}_{size}px.{}", BrowserSupportedImgFormat::Webp.ext())
+}
+
+pub async fn save_avatar_rendition(
+    env: &Env,
+    avatar_key: &str,
+    size: u32,
+    webp: &[u8],
+) -> anyhow::Result<()> {
+    let bucket = avatar_bucket(env)
+        .await
+        .context("Failed to get avatar bucket")?;
+    let key = avatar_rendition_key(avatar_key, size);
     bucket
-        .put_object_stream(&mut reader, &key)
+        .put_object_with_content_type(&key, webp, BrowserSupportedImgFormat::Webp.content_type())
         .await
-        .context("put_object_stream failed")?;
+        .context("put_object failed")?;
+    Ok(())
+}
 
-    Ok(key)
+/// Deletes the avatar stored under `avatar_key` along with its renditions of the sizes.
+///
+/// The objects that don't exist are skipped, so it also cleans up after a partial upload.
+pub async fn delete_avatar(
+    env: &Env,
+    avatar_key: &str,
+    rendition_sizes: &[u32],
+) -> anyhow::Result<()> {
+    let bucket = avatar_bucket(env)
+        .await
+        .context("Failed to get avatar bucket")?;
+    let keys = rendition_sizes
+        .iter()
+        .map(|size| avatar_rendition_key(avatar_key, *size))
+        .chain([avatar_key.to_string()]);
+    for key in keys {
+        bucket
+            .delete_object(&key)
+            .await
+            .with_context(|| format!("delete_object failed for `{key}`"))?;
+    }
+    Ok(())
+}
+
+pub struct StoredAvatarObject {
+    pub key: String,
+    /// UNIX timestamp (ms)
+    pub last_modified_ms: i64,
+}
+
+/// Lists every object in the avatar bucket, both the avatars and their renditions.
+pub async fn list_avatar_objects(env: &Env) -> anyhow::Result<Vec<StoredAvatarObject>> {
+    let bucket = avatar_bucket(env)
+        .await
+        .context("Failed to get avatar bucket")?;
+    let pages = bucket
+        .list(AVATARS_PREFIX.to_string(), None)
+        .await
+        .context("list failed")?;
+
+    pages
+        .into_iter()
+        .flat_map(|page| page.contents)
+        .map(|object| {
+            let last_modified = chrono::DateTime::parse_from_rfc3339(&object.last_modified)
+                .with_context(|| {
+                    format!(
+                        "Invalid last modification time `{}` of `{}`",
+                        object.last_modified, object.key
+                    )
+                })?;
+            Ok(StoredAvatarObject {
+                key: object.key,
+                last_modified_ms: last_modified.timestamp_millis(),
+            })
+        })
+        .collect()
 }
 
 pub async fn get_avatar(env: &Env, key: &str) -> anyhow::Result<bytes::Bytes> {
@@ -103,6 +198,98 @@ pub async fn get_avatar(env: &Env, key: &str) -> anyhow::Result<bytes::Bytes> {
     }
 }
 
+/// Deletes every object under `prefix`, returning how many there were.
+async fn delete_prefix(bucket: &Bucket, prefix: String) -> anyhow::Result<usize> {
+    let pages = bucket.list(prefix, None).await.context("list failed")?;
+
+    let mut deleted = 0;
+    for object in pages.into_iter().flat_map(|page| page.contents) {
+        bucket
+            .delete_object(&object.key)
+            .await
+            .with_context(|| format!("delete_object failed for `{}`", object.key))?;
+        deleted += 1;
+    }
+    Ok(deleted)
+}
+
+/// Deletes every object of the user, e.g. when the account is deleted.
+///
+/// Returns the number of deleted objects.
+pub async fn delete_user_objects(env: &Env, user_id: UserId) -> anyhow::Result<usize> {
+    let avatar_bucket = avatar_bucket(env)
+        .await
+        .context("Failed to get avatar bucket")?;
+    let export_bucket =
+        bucket(env, &env.minio.export_bucket).context("Failed to get export bucket")?;
+
+    let avatars = delete_prefix(&avatar_bucket, avatar_prefix(user_id)).await?;
+    let exports = delete_prefix(&export_bucket, export_prefix(user_id)).await?;
+    Ok(avatars + exports)
+}
+
+/// Every personal data export of the user is stored under this prefix.
+fn export_prefix(user_id: UserId) -> String {
+    format!("exports/{user_id}/")
+}
+
+/// Stores the archive of a personal data export, returning its key.
+pub async fn save_export(
+    env: &Env,
+    user_id: UserId,
+    export_id: i64,
+    archive: &[u8],
+) -> anyhow::Result<String> {
+    let bucket = bucket(env, &env.minio.export_bucket).context("Failed to get export bucket")?;
+    let key = format!("{}{export_id}.zip", export_prefix(user_id));
+    bucket
+        .put_object_with_content_type(&key, archive, "application/zip")
+        .await
+        .context("put_object failed")?;
+    Ok(key)
+}
+
+pub async fn get_export(env: &Env, key: &str) -> anyhow::Result<bytes::Bytes> {
+    let bucket = bucket(env, &env.minio.export_bucket).context("Failed to get export bucket")?;
+    match bucket.get_object(key).await {
+        Ok(data) => Ok(data.into_bytes()),
+        Err(e) => Err(anyhow::anyhow!(e).context("get_object failed")),
+    }
+}
+
+pub async fn delete_export(env: &Env, key: &str) -> anyhow::Result<()> {
+    let bucket = bucket(env, &env.minio.export_bucket).context("Failed to get export bucket")?;
+    bucket
+        .delete_object(key)
+        .await
+        .context("delete_object failed")?;
+    Ok(())
+}
+
+/// The avatars of the user as `(key, content)` pairs, e.g. for a personal data export.
+pub async fn get_user_avatars(
+    env: &Env,
+    user_id: UserId,
+) -> anyhow::Result<Vec<(String, bytes::Bytes)>> {
+    let bucket = avatar_bucket(env)
+        .await
+        .context("Failed to get avatar bucket")?;
+    let pages = bucket
+        .list(avatar_prefix(user_id), None)
+        .await
+        .context("list failed")?;
+
+    let mut avatars = Vec::new();
+    for object in pages.into_iter().flat_map(|page| page.contents) {
+        let data = bucket
+            .get_object(&object.key)
+            .await
+            .with_context(|| format!("get_object failed for `{}`", object.key))?;
+        avatars.push((object.key, data.into_bytes()));
+    }
+    Ok(avatars)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
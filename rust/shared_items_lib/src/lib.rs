@@ -5,8 +5,16 @@ pub mod service_responses;
 #[serde(transparent)]
 pub struct JwtString(pub String);
 
+/// An opaque, single-use token that can be exchanged for a new access JWT
+/// and a new refresh token.
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(transparent)]
+pub struct RefreshTokenString(pub String);
+
 /// A UNIX timestamp in milliseconds (UTC).
-#[derive(specta::Type, serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+#[derive(
+    specta::Type, serde::Serialize, serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug,
+)]
 #[serde(transparent)]
 #[repr(transparent)]
 pub struct Timestamp(pub u64);
@@ -23,19 +31,64 @@ impl From<Timestamp> for mnln_core_items::Timestamp {
     }
 }
 
+/// A UNIX timestamp in seconds (UTC), the `NumericDate` of the registered JWT claims,
+/// see <https://www.rfc-editor.org/rfc/rfc7519#section-2>
+#[derive(
+    specta::Type,
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[serde(transparent)]
+#[repr(transparent)]
+pub struct NumericDate(pub u64);
+
+/// Rounds down to the second.
+impl From<Timestamp> for NumericDate {
+    fn from(value: Timestamp) -> Self {
+        NumericDate(value.0 / 1000)
+    }
+}
+
+impl From<NumericDate> for Timestamp {
+    fn from(value: NumericDate) -> Self {
+        Timestamp(value.0.saturating_mul(1000))
+    }
+}
+
 #[derive(specta::Type, serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct JwtClaims {
+    /// Issuer of the JWT
+    pub iss: String,
     /// Subject of the JWT (the user)
     pub sub: id::UserId,
+    /// Audience of the JWT
+    pub aud: String,
     /// Expiration time
-    pub exp: Timestamp,
+    pub exp: NumericDate,
+    /// Time before which the JWT must not be accepted
+    pub nbf: NumericDate,
     /// Issued at
-    pub iat: Timestamp,
+    pub iat: NumericDate,
     /// Role of the user
     pub role: Role,
+    /// Unique ID of the JWT, used for revoking it before it expires
+    pub jti: String,
+    /// Whether the user logged in with the second factor besides the password, or with a passkey,
+    /// which is both factors at once
+    #[serde(default)]
+    pub second_factor: bool,
 }
 
-#[derive(specta::Type, serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+#[derive(
+    specta::Type, serde::Serialize, serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug,
+)]
 pub enum Role {
     Admin,
     User,
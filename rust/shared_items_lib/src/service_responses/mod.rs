@@ -1,12 +1,17 @@
-use crate::JwtString;
+use crate::id::UserId;
+use crate::{JwtString, RefreshTokenString, Role, Timestamp};
 
 /// Responses for user registration
-#[derive(specta::Type)]
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
 pub enum PostRegisterResponse {
     /// User registered successfully
     Success,
-    /// User already exists
+    /// User already exists, possibly under the same username in a different case
     AlreadyExists,
+    /// The username doesn't satisfy the policy, e.g. it's too long, contains characters other
+    /// than latin letters, digits, `_` and `-`, or is reserved
+    InvalidUsername { reason: String },
     /// Internal server error
     InternalServerError,
 }
@@ -14,15 +19,67 @@ pub enum PostRegisterResponse {
 #[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
 pub struct PostLoginResponseSuccess {
     pub jwt: JwtString,
+    pub refresh_token: RefreshTokenString,
 }
 
-#[derive(specta::Type)]
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
 #[serde(tag = "kind")]
 pub enum PostLoginResponse {
     /// Login successful
     Success(PostLoginResponseSuccess),
+    /// The password is right, but the user has TOTP enabled, so the login has to be completed
+    /// with a code
+    SecondFactorRequired(SecondFactorChallenge),
     /// Invalid credentials
     InvalidCredentials,
+    /// The password is right, but the account is suspended or banned
+    Restricted(AccountRestriction),
+    /// The password is right, but an admin requires it to be reset through the emailed link
+    PasswordResetRequired,
+    /// Too many failed logins for the username or from the IP address
+    TooManyRequests { retry_after_secs: u64 },
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct PostRefreshResponseSuccess {
+    pub jwt: JwtString,
+    pub refresh_token: RefreshTokenString,
+}
+
+/// Responses for exchanging a refresh token
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostRefreshResponse {
+    /// Tokens rotated successfully
+    Success(PostRefreshResponseSuccess),
+    /// The refresh token is unknown, expired, revoked or was already used
+    InvalidToken,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for logging out of the current session
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostLogoutResponse {
+    /// Logged out successfully
+    Success,
+    /// Missing or invalid JWT
+    Unauthorized,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for logging out of every session of the user
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostLogoutEverywhereResponse {
+    /// Logged out of every session successfully
+    Success,
+    /// Missing or invalid JWT
+    Unauthorized,
     /// Internal server error
     InternalServerError,
 }
@@ -38,8 +95,8 @@ pub struct PostSaltResponseSuccess {
 pub enum PostSaltResponse {
     /// Salt retrieved successfully
     Success(PostSaltResponseSuccess),
-    /// User not found
-    UserNotFound,
+    /// Too many failed logins for the username or from the IP address
+    TooManyRequests { retry_after_secs: u64 },
     /// Internal server error
     InternalServerError,
 }
@@ -89,6 +146,10 @@ pub enum PostUploadUserAvatarResponse {
     BadRequest {
         detail: String,
     },
+    /// The avatar is too large in bytes or in pixels, or it would exceed the storage quota
+    PayloadTooLarge {
+        detail: String,
+    },
     InternalServerError {
         #[serde(skip_serializing_if = "Option::is_none")]
         detail: Option<String>,
@@ -97,3 +158,839 @@ pub enum PostUploadUserAvatarResponse {
         detail: String,
     },
 }
+
+/// The editable profile of the current user
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct UserProfile {
+    pub username: String,
+    pub email: Option<String>,
+    /// Whether the ownership of `email` has been proven, see [`PostVerifyEmailResponse`]
+    pub email_verified: bool,
+    pub lichess_username: Option<String>,
+    pub chess_dot_com_username: Option<String>,
+    /// Must be sent back when editing the profile, see [`PatchMeResponse::VersionMismatch`]
+    pub version: i32,
+    /// When the account will be deleted unless the deletion is cancelled
+    pub deletion_scheduled_at: Option<Timestamp>,
+}
+
+/// Responses for retrieving the profile of the current user
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum GetMeResponse {
+    /// Profile retrieved successfully
+    Success(UserProfile),
+    /// The user no longer exists
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema, Clone, Copy, Debug)]
+pub enum ProfileField {
+    Email,
+    LichessUsername,
+    ChessDotComUsername,
+}
+
+/// Responses for editing the profile of the current user
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
+pub enum PatchMeResponse {
+    /// Profile edited successfully
+    Success(UserProfile),
+    /// The value of the field is invalid
+    InvalidField { field: ProfileField, reason: String },
+    /// The value of the field is already used by another user
+    AlreadyTaken { field: ProfileField },
+    /// The profile has been edited since it was read (e.g. in another tab),
+    /// so the edit has to be redone on top of the current profile
+    VersionMismatch { current: UserProfile },
+    /// The user no longer exists
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for (re)sending the verification link for the email address of the current user
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
+pub enum PostSendEmailVerificationResponse {
+    /// The verification link will be sent shortly
+    Success,
+    /// The user has no email address
+    NoEmail,
+    /// The email address is already verified
+    AlreadyVerified,
+    /// The email address was changed or verified while the link was being sent
+    Conflict,
+    /// The user no longer exists
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for redeeming an email verification link
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostVerifyEmailResponse {
+    /// The email address is verified
+    Success,
+    /// The token is malformed, expired, already redeemed or superseded by a newer one,
+    /// or the email address has changed since it was issued
+    InvalidToken,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for requesting a password reset link.
+///
+/// The response is the same whether or not an account with the email address exists.
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostPasswordResetRequestResponse {
+    /// If the email address belongs to an account and is verified, a reset link will be sent shortly
+    Success,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for setting a new password with a reset link
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostPasswordResetConfirmResponse {
+    /// The password has been changed and every session has been logged out
+    Success,
+    /// The token is unknown, expired or already used
+    InvalidToken,
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct DeleteUserResponseScheduled {
+    /// The deletion can be cancelled until then
+    pub deletion_scheduled_at: Timestamp,
+}
+
+/// Responses for deleting an account
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum DeleteUserResponse {
+    /// The account, its data and its files will be deleted after the grace period
+    Scheduled(DeleteUserResponseScheduled),
+    /// The user doesn't exist
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for cancelling the deletion of an account
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostCancelUserDeletionResponse {
+    /// The account will be kept
+    Success,
+    /// The user doesn't exist or no deletion is scheduled
+    NotScheduled,
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub enum DataExportStatus {
+    /// Waiting for a worker
+    Pending,
+    /// Being assembled
+    Running,
+    /// The archive can be downloaded with the `download_url`
+    Ready,
+    /// The archive has been deleted, a new export has to be requested
+    Expired,
+    /// Assembling the archive failed, a new export has to be requested
+    Failed,
+}
+
+/// A personal data export of the current user
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct DataExport {
+    pub id: i64,
+    pub status: DataExportStatus,
+    pub created_at: Timestamp,
+    /// A link to the ZIP archive that works until `download_url_expires_at`, if the export is ready
+    pub download_url: Option<String>,
+    pub download_url_expires_at: Option<Timestamp>,
+    /// When the archive will be deleted, if the export is ready
+    pub expires_at: Option<Timestamp>,
+}
+
+/// Responses for requesting a personal data export
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostDataExportResponse {
+    /// The export has been requested, or was already pending, and its status can be polled
+    Accepted(DataExport),
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for polling the status of a personal data export
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum GetDataExportResponse {
+    Success(DataExport),
+    /// The export doesn't exist, belongs to someone else or has been deleted
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct PostLichessAuthorizeResponseSuccess {
+    /// The Lichess page where the user grants the access, which then redirects back
+    pub authorization_url: String,
+}
+
+/// Responses for starting "Sign in with Lichess" or linking the Lichess account
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostLichessAuthorizeResponse {
+    Success(PostLichessAuthorizeResponseSuccess),
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for completing "Sign in with Lichess"
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
+pub enum PostLichessSignInResponse {
+    /// Signed into the account linked to the Lichess account, which is created if there's none
+    Success(PostLoginResponseSuccess),
+    /// The linked account has TOTP enabled, so the sign-in has to be completed with a code
+    SecondFactorRequired(SecondFactorChallenge),
+    /// The linked account is suspended or banned
+    Restricted(AccountRestriction),
+    /// The state is unknown, expired or already used, or the authorization was denied
+    InvalidState,
+    /// There's no linked account and the username of the Lichess account is taken or not allowed,
+    /// so the user has to log in with the password and link the Lichess account instead
+    UsernameTaken,
+    /// Lichess couldn't be reached or rejected the authorization code
+    LichessUnavailable,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for completing the linking of the Lichess account
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostLichessLinkResponse {
+    /// The Lichess account is linked and can be used to sign in
+    Success,
+    /// The state is unknown, expired, already used or was issued to another user
+    InvalidState,
+    /// The Lichess account is linked to another user
+    AlreadyLinked,
+    /// Lichess couldn't be reached or rejected the authorization code
+    LichessUnavailable,
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct SecondFactorChallenge {
+    /// Sent back along with the code to complete the login
+    pub challenge_token: String,
+    pub expires_at: Timestamp,
+}
+
+/// Responses for completing the login with a TOTP code or a recovery code
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
+pub enum PostLoginSecondFactorResponse {
+    /// Login successful
+    Success(PostLoginResponseSuccess),
+    /// The account was suspended or banned since the password was checked
+    Restricted(AccountRestriction),
+    /// The challenge token is invalid or expired, so the login has to start over
+    InvalidChallenge,
+    /// The code is wrong, already used or the recovery code is unknown
+    InvalidCode,
+    /// Too many failed logins for the username or from the IP address
+    TooManyRequests { retry_after_secs: u64 },
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct PostTotpEnrollResponseSuccess {
+    /// The `otpauth://` URI to show as a QR code to the authenticator app
+    pub provisioning_uri: String,
+    /// The base32-encoded secret, for typing it into the authenticator app instead
+    pub secret: String,
+}
+
+/// Responses for starting the TOTP enrollment
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostTotpEnrollResponse {
+    /// The enrollment has to be confirmed with a code from the authenticator app
+    Success(PostTotpEnrollResponseSuccess),
+    /// TOTP is already enabled and has to be disabled before enrolling again
+    AlreadyEnabled,
+    /// User not found
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct PostTotpConfirmResponseSuccess {
+    /// Single-use codes for logging in without the authenticator app, shown only this once
+    pub recovery_codes: Vec<String>,
+}
+
+/// Responses for confirming the TOTP enrollment
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostTotpConfirmResponse {
+    /// TOTP is enabled and will be required on every login
+    Success(PostTotpConfirmResponseSuccess),
+    /// The code is wrong
+    InvalidCode,
+    /// There's no enrollment to confirm, either because it wasn't started or because TOTP is
+    /// already enabled
+    NotEnrolling,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for disabling TOTP
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostTotpDisableResponse {
+    /// TOTP is disabled and the recovery codes are deleted
+    Success,
+    /// The TOTP code or the recovery code is wrong
+    InvalidCode,
+    /// TOTP isn't enabled
+    NotEnabled,
+    /// Too many failed logins for the username or from the IP address
+    TooManyRequests { retry_after_secs: u64 },
+    /// Internal server error
+    InternalServerError,
+}
+
+/// See <https://www.w3.org/TR/webauthn-3/#dictdef-publickeycredentialrpentity>
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct PasskeyRelyingParty {
+    pub id: String,
+    pub name: String,
+}
+
+/// See <https://www.w3.org/TR/webauthn-3/#dictdef-publickeycredentialuserentityjson>
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyUser {
+    /// The base64url-encoded user handle
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+/// See <https://www.w3.org/TR/webauthn-3/#dictdef-publickeycredentialparameters>
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct PasskeyCredentialParameters {
+    /// Always `public-key`
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// The COSE algorithm identifier
+    pub alg: i32,
+}
+
+/// See <https://www.w3.org/TR/webauthn-3/#dictdef-publickeycredentialdescriptorjson>
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct PasskeyCredentialDescriptor {
+    /// Always `public-key`
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// The base64url-encoded credential ID
+    pub id: String,
+}
+
+/// See <https://www.w3.org/TR/webauthn-3/#dictdef-authenticatorselectioncriteria>
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyAuthenticatorSelection {
+    pub resident_key: String,
+    pub require_resident_key: bool,
+    pub user_verification: String,
+}
+
+/// The options for `navigator.credentials.create()`, in the format of
+/// `PublicKeyCredential.parseCreationOptionsFromJSON()`.
+///
+/// See <https://www.w3.org/TR/webauthn-3/#dictdef-publickeycredentialcreationoptionsjson>
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyCreationOptions {
+    /// The base64url-encoded challenge
+    pub challenge: String,
+    pub rp: PasskeyRelyingParty,
+    pub user: PasskeyUser,
+    pub pub_key_cred_params: Vec<PasskeyCredentialParameters>,
+    /// In milliseconds
+    pub timeout: u32,
+    /// The passkeys that the user has registered already
+    pub exclude_credentials: Vec<PasskeyCredentialDescriptor>,
+    pub authenticator_selection: PasskeyAuthenticatorSelection,
+    pub attestation: String,
+}
+
+/// The options for `navigator.credentials.get()`, in the format of
+/// `PublicKeyCredential.parseRequestOptionsFromJSON()`.
+///
+/// See <https://www.w3.org/TR/webauthn-3/#dictdef-publickeycredentialrequestoptionsjson>
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRequestOptions {
+    /// The base64url-encoded challenge
+    pub challenge: String,
+    /// In milliseconds
+    pub timeout: u32,
+    pub rp_id: String,
+    /// Empty, so that the authenticator offers the discoverable passkeys of any user
+    pub allow_credentials: Vec<PasskeyCredentialDescriptor>,
+    pub user_verification: String,
+}
+
+/// A passkey of the current user
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct Passkey {
+    pub id: i32,
+    pub name: String,
+    pub created_at: Timestamp,
+    pub last_used_at: Option<Timestamp>,
+}
+
+/// Responses for starting the registration of a passkey
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostPasskeyRegistrationOptionsResponse {
+    /// The options to create the passkey with, whose challenge expires after the timeout
+    Success(Box<PasskeyCreationOptions>),
+    /// User not found
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for completing the registration of a passkey
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
+pub enum PostPasskeyRegistrationResponse {
+    /// The passkey can be used to log in
+    Success(Passkey),
+    /// The name is empty or longer than 64 characters
+    InvalidName,
+    /// The challenge is unknown, expired, already used or was issued to another user
+    InvalidChallenge,
+    /// The credential failed the verification
+    InvalidCredential { reason: String },
+    /// The passkey is registered already
+    AlreadyRegistered,
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct GetPasskeysResponseSuccess {
+    pub passkeys: Vec<Passkey>,
+}
+
+/// Responses for listing the passkeys of the current user
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum GetPasskeysResponse {
+    Success(GetPasskeysResponseSuccess),
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for deleting a passkey
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum DeletePasskeyResponse {
+    /// The passkey can no longer be used to log in
+    Success,
+    /// The passkey doesn't exist or belongs to someone else
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for starting the login with a passkey
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostPasskeyLoginOptionsResponse {
+    /// The options to get the passkey with, whose challenge expires after the timeout
+    Success(PasskeyRequestOptions),
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for completing the login with a passkey
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
+pub enum PostPasskeyLoginResponse {
+    /// Login successful
+    Success(PostLoginResponseSuccess),
+    /// The challenge is unknown, expired or already used
+    InvalidChallenge,
+    /// The passkey is unknown or failed the verification
+    InvalidCredential { reason: String },
+    /// The account is suspended or banned
+    Restricted(AccountRestriction),
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(
+    specta::Type, serde::Serialize, serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug,
+)]
+pub enum AccountRestrictionKind {
+    /// Temporarily, until the restriction expires
+    Suspended,
+    /// Until an admin lifts the ban, unless it's given an expiry
+    Banned,
+}
+
+/// Why and until when the user can't log in
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct AccountRestriction {
+    pub kind: AccountRestrictionKind,
+    pub reason: String,
+    /// `None` for a ban that doesn't expire
+    pub expires_at: Option<Timestamp>,
+}
+
+/// A user as seen by the admins
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct AdminUser {
+    pub id: UserId,
+    pub username: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub role: Role,
+    /// `None` for the accounts created before the registration time was recorded
+    pub created_at: Option<Timestamp>,
+    /// The active restriction, if any
+    pub restriction: Option<AccountRestriction>,
+    /// Whether the user has to reset the password before logging in with it
+    pub password_reset_required: bool,
+    pub totp_enabled: bool,
+    pub passkey_count: u32,
+    /// When the account will be deleted unless the deletion is cancelled
+    pub deletion_scheduled_at: Option<Timestamp>,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct GetAdminUsersResponseSuccess {
+    /// Ordered by ID
+    pub users: Vec<AdminUser>,
+    pub page: u32,
+    pub per_page: u32,
+    /// The number of users matching the filters across all pages
+    pub total: u64,
+}
+
+/// Responses for listing the users
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
+pub enum GetAdminUsersResponse {
+    Success(GetAdminUsersResponseSuccess),
+    /// The filters or the pagination are out of range
+    InvalidQuery {
+        reason: String,
+    },
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for viewing a user
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum GetAdminUserResponse {
+    Success(AdminUser),
+    /// User not found
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for promoting or demoting a user
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PutAdminUserRoleResponse {
+    /// The role is changed and the user is logged out everywhere, so that it takes effect
+    Success,
+    /// Admins can't change their own role, so that the last admin can't demote themselves
+    CannotModifySelf,
+    /// User not found
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for suspending or banning a user
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
+pub enum PutAdminUserRestrictionResponse {
+    /// The restriction replaces the previous one, if any, and the user is logged out everywhere
+    Success,
+    /// The reason is empty or too long, or the expiry is missing or in the past
+    InvalidRestriction { reason: String },
+    /// Admins can't restrict themselves
+    CannotModifySelf,
+    /// User not found
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for lifting the suspension or the ban of a user
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum DeleteAdminUserRestrictionResponse {
+    /// The user can log in again
+    Success,
+    /// The user doesn't exist or isn't restricted
+    NotRestricted,
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for forcing a user to reset their password
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum PostAdminForcePasswordResetResponse {
+    /// The reset link is sent, the user is logged out everywhere and can't log in with the
+    /// password until resetting it
+    Success,
+    /// The user has no verified email address to send the reset link to
+    NoVerifiedEmail,
+    /// User not found
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(
+    specta::Type, serde::Serialize, serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug,
+)]
+pub enum AuditEventKind {
+    Registered,
+    LoginSucceeded,
+    LoginFailed,
+    AvatarChanged,
+    RoleChanged,
+    Restricted,
+    RestrictionLifted,
+    PasswordResetForced,
+}
+
+#[derive(
+    specta::Type, serde::Serialize, serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug,
+)]
+pub enum LoginMethod {
+    Password,
+    /// The TOTP code or a recovery code after the password
+    SecondFactor,
+    Passkey,
+    Lichess,
+}
+
+#[derive(
+    specta::Type, serde::Serialize, serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug,
+)]
+pub enum LoginFailureReason {
+    UnknownUsername,
+    WrongPassword,
+    WrongSecondFactorCode,
+    /// The passkey of the account failed the verification, e.g. it may have been cloned
+    InvalidPasskey,
+    /// The credentials are right, but the account is suspended or banned
+    Restricted,
+}
+
+/// What happened, with the details depending on the kind
+#[derive(specta::Type, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
+pub enum AuditEventDetails {
+    Registered,
+    LoginSucceeded {
+        method: LoginMethod,
+    },
+    LoginFailed {
+        method: LoginMethod,
+        reason: LoginFailureReason,
+        /// As typed, for the logins with a username. It matters when there's no such user
+        username: Option<String>,
+    },
+    AvatarChanged,
+    RoleChanged {
+        role: Role,
+    },
+    Restricted {
+        restriction: AccountRestriction,
+    },
+    RestrictionLifted,
+    PasswordResetForced,
+}
+
+/// A security-relevant event, as seen by the admins
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct AuditEvent {
+    pub id: u64,
+    pub occurred_at: Timestamp,
+    /// Who did it, `None` if nobody was logged in
+    pub actor_id: Option<UserId>,
+    /// Whose account it's about, `None` if there's no such account
+    pub target_id: Option<UserId>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: AuditEventDetails,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct GetAuditEventsResponseSuccess {
+    /// The most recent first
+    pub events: Vec<AuditEvent>,
+    pub page: u32,
+    pub per_page: u32,
+    /// The number of events matching the filters across all pages
+    pub total: u64,
+}
+
+/// Responses for querying the audit log
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
+pub enum GetAuditEventsResponse {
+    Success(GetAuditEventsResponseSuccess),
+    /// The filters or the pagination are out of range
+    InvalidQuery {
+        reason: String,
+    },
+    /// Internal server error
+    InternalServerError,
+}
+
+/// A security-relevant event concerning the account, as seen by its owner
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct SecurityActivityEvent {
+    pub occurred_at: Timestamp,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    /// Whether an admin did it rather than the user
+    pub by_admin: bool,
+    pub details: AuditEventDetails,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct GetSecurityActivityResponseSuccess {
+    /// The most recent first
+    pub events: Vec<SecurityActivityEvent>,
+}
+
+/// Responses for viewing the recent security activity of the account
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum GetSecurityActivityResponse {
+    Success(GetSecurityActivityResponseSuccess),
+    /// Internal server error
+    InternalServerError,
+}
+
+/// What a personal access token may be used for
+#[derive(
+    specta::Type,
+    serde::Serialize,
+    serde::Deserialize,
+    utoipa::ToSchema,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+)]
+pub enum PersonalAccessTokenScope {
+    /// Uploading the avatar
+    #[serde(rename = "avatar:write")]
+    AvatarWrite,
+    /// Reading the games, once they're exposed through the API
+    #[serde(rename = "games:read")]
+    GamesRead,
+    /// Reading the profile
+    #[serde(rename = "profile:read")]
+    ProfileRead,
+}
+
+/// A personal access token of the current user, without the token itself
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct PersonalAccessToken {
+    pub id: i32,
+    pub name: String,
+    pub scopes: Vec<PersonalAccessTokenScope>,
+    pub created_at: Timestamp,
+    pub last_used_at: Option<Timestamp>,
+    /// `None` for a token that is valid until revoked
+    pub expires_at: Option<Timestamp>,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct PostPersonalAccessTokenResponseSuccess {
+    /// The bearer token, which is shown only this once
+    pub token: String,
+    pub personal_access_token: PersonalAccessToken,
+}
+
+/// Responses for creating a personal access token
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+#[serde(tag = "kind")]
+pub enum PostPersonalAccessTokenResponse {
+    Success(PostPersonalAccessTokenResponseSuccess),
+    /// The name, the scopes or the expiry are invalid
+    InvalidRequest {
+        reason: String,
+    },
+    /// The user has as many tokens as allowed, so one has to be revoked first
+    TooManyTokens,
+    /// Internal server error
+    InternalServerError,
+}
+
+#[derive(specta::Type, serde::Serialize, utoipa::ToSchema)]
+pub struct GetPersonalAccessTokensResponseSuccess {
+    pub personal_access_tokens: Vec<PersonalAccessToken>,
+}
+
+/// Responses for listing the personal access tokens of the current user
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum GetPersonalAccessTokensResponse {
+    Success(GetPersonalAccessTokensResponseSuccess),
+    /// Internal server error
+    InternalServerError,
+}
+
+/// Responses for revoking a personal access token
+#[derive(specta::Type)]
+#[serde(tag = "kind")]
+pub enum DeletePersonalAccessTokenResponse {
+    /// The token is no longer accepted
+    Success,
+    /// The token doesn't exist or belongs to someone else
+    NotFound,
+    /// Internal server error
+    InternalServerError,
+}
@@ -14,3 +14,13 @@ if __name__ == "__main__":
             print(f"Generated new JWT signing key and saved to {git_root}/secrets/jwt_signing_key.env")
         else:
             print(f"JWT signing key already exists in {git_root}/secrets/jwt_signing_key.env")
+    # create $(git_root)/secrets/fake_salt_key.env if it doesn't exist
+    with open(f"{git_root}/secrets/fake_salt_key.env", "a+") as f:
+        f.seek(0)
+        content = f.read().strip()
+        if not content:
+            secret = secrets.token_urlsafe(64)
+            f.write(f"FAKE_SALT_KEY={secret}\n")
+            print(f"Generated new fake salt key and saved to {git_root}/secrets/fake_salt_key.env")
+        else:
+            print(f"Fake salt key already exists in {git_root}/secrets/fake_salt_key.env")